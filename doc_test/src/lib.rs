@@ -73,13 +73,28 @@ mod test_readme {
     external_doc_test!(include_str!("../README.md"));
 }
 
-use std::cmp;
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::{cmp, fs};
 
-use proc_macro2::TokenStream;
+use proc_macro2::{Delimiter, Group, TokenStream, TokenTree};
 use quote::{quote, ToTokens};
 use rust_format::Formatter as _;
 
 const MIN_BUFF_SIZE: usize = 128;
+const HIDDEN: &str = "_hidden_";
+// Mark where hidden lines start and end (as comments, so they survive formatting)
+const HIDDEN_START: &str = "__quote_doctest_hidden_start__";
+const HIDDEN_END: &str = "__quote_doctest_hidden_end__";
+// Drops (`None`) or rewrites each line of a doctest
+type LineFilter = Arc<dyn Fn(&str) -> Option<String> + Send + Sync>;
+// Hidden lines that capture everything printed by `print!` and `println!`
+const CAPTURE_OUTPUT: [&str; 3] = [
+    "let mut __output = String::new();",
+    "macro_rules! print { ($($arg:tt)*) => { ::std::fmt::Write::write_fmt(&mut __output, format_args!($($arg)*)).unwrap() }; }",
+    "macro_rules! println { () => { print!(\"\\n\") }; ($($arg:tt)*) => {{ print!($($arg)*); print!(\"\\n\"); }}; }",
+];
 
 /// The default amount of formatter indent to remove (when generating `main`)
 pub const FORMATTER_INDENT: usize = 4;
@@ -101,7 +116,49 @@ macro_rules! doc_test {
     };
 }
 
-pub use rust_format::{Error, _blank_, _comment_};
+pub use rust_format::{_blank_, _comment_, Edition, Error, ErrorKind};
+
+#[cfg(feature = "source_macro")]
+#[cfg_attr(docsrs, doc(cfg(feature = "source_macro")))]
+pub use quote_doctest_macros::doc_test_source;
+
+// Lets the paths generated by `doc_test_source` resolve inside this crate as well
+#[cfg(all(test, feature = "source_macro"))]
+extern crate self as quote_doctest;
+
+/// A "marker" macro used to mark code in a doctest that should be hidden from the rendered docs
+/// (while still being compiled and run), such as setup boilerplate. Each line of the formatted code
+/// inside it is prefixed with `# ` in the doctest.
+///
+/// It is important to understand this is NOT actually a macro that is executed. In fact, it is just
+/// here for documentation purposes. Instead, this works as a raw set of tokens in the source code
+/// that we match against verbatim. This means it cannot be renamed on import for example, and it MUST be
+/// invoked as `_hidden_!(`, then any statements, and then `)` (optionally followed by `;`).
+/// Without formatting, only top level markers are hidden (each on its own line)
+///
+/// ```
+/// use quote::quote;
+/// use quote_doctest::{doc_test, FormatDocTest};
+///
+/// let test = doc_test!(quote! {
+///     _hidden_!(let x = 5;);
+///     assert_eq!(x, 5);
+/// }).unwrap();
+///
+/// let expected = quote! {
+///     /// ```
+///     /// # let x = 5;
+///     /// assert_eq!(x, 5);
+///     /// ```
+/// };
+/// assert_eq!(expected.format_tokens().unwrap(), test.format_tokens().unwrap());
+/// ```
+///
+/// Actually executing this macro has no effect and it is not meant to even be imported.
+#[macro_export]
+macro_rules! _hidden_ {
+    ($($tt:tt)*) => {};
+}
 
 // *** Formatter ***
 
@@ -126,6 +183,15 @@ impl Formatter {
         Formatter::RustFmt(rust_fmt)
     }
 
+    /// Creates a basic default `rustfmt` `Formatter` instance that automatically strips markers
+    /// from the source code and converts doc blocks (`#[doc = ""]`) into doc comments (`///`)
+    pub fn new_rust_fmt_doc_blocks() -> Self {
+        let config = rust_format::Config::new_str()
+            .post_proc(rust_format::PostProcess::ReplaceMarkersAndDocBlocks);
+        let rust_fmt = rust_format::RustFmt::from_config(config);
+        Formatter::RustFmt(rust_fmt)
+    }
+
     /// Creates a basic default `prettyplease` `Formatter` instance that automatically strips
     /// markers from the source code
     #[cfg(feature = "pretty_please")]
@@ -154,8 +220,241 @@ impl Default for Formatter {
     }
 }
 
-/// Optional enum passed to [doc_test] for different configuration options
+// *** Fence Attributes ***
+
+/// An attribute added to the opening code fence of a doctest, which changes how `rustdoc` tests it
+#[derive(Clone, Copy, Debug)]
+pub enum FenceAttr {
+    /// The doctest is compiled, but not run (`no_run`)
+    NoRun,
+    /// The doctest is neither compiled nor run (`ignore`)
+    Ignore,
+    /// The doctest must panic when run (`should_panic`)
+    ShouldPanic,
+    /// The doctest must fail to compile (`compile_fail`)
+    CompileFail,
+    /// The doctest is compiled with the given edition (such as `edition2021`)
+    Edition(Edition),
+}
+
+impl FenceAttr {
+    fn as_str(self) -> Cow<'static, str> {
+        match self {
+            FenceAttr::NoRun => "no_run".into(),
+            FenceAttr::Ignore => "ignore".into(),
+            FenceAttr::ShouldPanic => "should_panic".into(),
+            FenceAttr::CompileFail => "compile_fail".into(),
+            FenceAttr::Edition(Edition::Rust2015) => "edition2015".into(),
+            FenceAttr::Edition(Edition::Rust2018) => "edition2018".into(),
+            FenceAttr::Edition(Edition::Rust2021) => "edition2021".into(),
+            FenceAttr::Edition(Edition::Rust2024) => "edition2024".into(),
+            FenceAttr::Edition(Edition::Custom(edition)) => format!("edition{edition}").into(),
+        }
+    }
+}
+
+// *** Doc Test Options ***
+
+/// How a `main` function is generated around the doctest code (or handled, if already written).
+/// It is only generated when formatting, since that is the only way it can be removed
+#[derive(Clone)]
+pub enum GenMain {
+    /// No main function is generated
+    No,
+    /// `fn main()` is generated and then stripped (along with its indentation) after formatting
+    Strip,
+    /// `fn main() -> Result<(), Box<dyn std::error::Error>>` (ending with `Ok(())`) is generated,
+    /// so `?` can be used. It is hidden (prefixed with `#`) after formatting
+    Fallible,
+    /// `async fn main()` is generated with the given attribute (such as `#[tokio::main]`). It is
+    /// hidden (prefixed with `#`) after formatting
+    Async(TokenStream),
+}
+
+impl GenMain {
+    #[inline]
+    fn is_hidden(&self) -> bool {
+        matches!(self, GenMain::Fallible | GenMain::Async(_))
+    }
+}
+
+/// Builds [DocTestOptions] one option at a time. This is the preferred way to configure [doc_test]
+///
+/// ```
+/// use quote::quote;
+/// use quote_doctest::{doc_test, DocTestOptions, FenceAttr, FormatDocTest, GenMain};
+///
+/// let options = DocTestOptions::builder()
+///     .gen_main(GenMain::Fallible)
+///     .fence_attrs([FenceAttr::NoRun]);
+/// let actual = doc_test!(quote! { run()?; }, options).unwrap();
+///
+/// let expected = quote! {
+///     /// ```no_run
+///     /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+///     /// run()?;
+///     /// # Ok(())
+///     /// # }
+///     /// ```
+/// };
+/// assert_eq!(expected.format_tokens().unwrap(), actual.format_tokens().unwrap());
+/// ```
+#[derive(Clone)]
+pub struct DocTestBuilder {
+    fmt: Option<Formatter>,
+    gen_main: GenMain,
+    strip_indent: Option<usize>,
+    attrs: Vec<FenceAttr>,
+    expected_output: Option<String>,
+    filters: Vec<LineFilter>,
+}
+
+impl DocTestBuilder {
+    /// Creates a new builder that formats with the default formatter, generates a main function
+    /// that is stripped afterwards, and detects the indentation to strip
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            fmt: Some(Formatter::default()),
+            gen_main: GenMain::Strip,
+            strip_indent: None,
+            attrs: Vec::new(),
+            expected_output: None,
+            filters: Vec::new(),
+        }
+    }
+
+    // Options that don't set fence attributes or expected output
+    #[inline]
+    fn with(fmt: Option<Formatter>, gen_main: GenMain, strip_indent: Option<usize>) -> Self {
+        Self {
+            fmt,
+            gen_main,
+            strip_indent,
+            attrs: Vec::new(),
+            expected_output: None,
+            filters: Vec::new(),
+        }
+    }
+
+    /// Sets the formatter used to format the doctest
+    #[inline]
+    pub fn formatter(mut self, fmt: Formatter) -> Self {
+        self.fmt = Some(fmt);
+        self
+    }
+
+    /// The doctest is not formatted and will be a single line (no main function is generated)
+    #[inline]
+    pub fn no_format(mut self) -> Self {
+        self.fmt = None;
+        self
+    }
+
+    /// Sets how the main function is generated
+    #[inline]
+    pub fn gen_main(mut self, gen_main: GenMain) -> Self {
+        self.gen_main = gen_main;
+        self
+    }
+
+    /// Sets the number of indent spaces to strip from each line inside the generated main function
+    /// (instead of detecting it from the first line)
+    #[inline]
+    pub fn strip_indent(mut self, strip_indent: usize) -> Self {
+        self.strip_indent = Some(strip_indent);
+        self
+    }
+
+    /// Adds attributes to the opening code fence of the doctest (such as ```` ```no_run ````)
+    #[inline]
+    pub fn fence_attrs(mut self, attrs: impl IntoIterator<Item = FenceAttr>) -> Self {
+        self.attrs.extend(attrs);
+        self
+    }
+
+    /// Verifies the output of the doctest. Everything printed with `print!` and `println!` is
+    /// captured (instead of going to stdout) by hidden lines at the start of the doctest, and a
+    /// hidden `assert_eq!` at the end compares it to `output`
+    ///
+    /// ```
+    /// use quote::quote;
+    /// use quote_doctest::{doc_test, DocTestOptions, FormatDocTest};
+    ///
+    /// let options = DocTestOptions::builder().expect_output("Hello World!\n");
+    /// let actual = doc_test!(quote! { println!("Hello World!"); }, options).unwrap();
+    /// let actual = actual.format_tokens().unwrap();
+    ///
+    /// assert!(actual.contains("/// println!(\"Hello World!\");\n"));
+    /// assert!(actual.contains("/// # assert_eq!(__output, \"Hello World!\\n\");\n"));
+    /// ```
+    #[inline]
+    pub fn expect_output(mut self, output: impl Into<String>) -> Self {
+        self.expected_output = Some(output.into());
+        self
+    }
+
+    /// Adds a filter that is called with each line of the doctest (after formatting and with the
+    /// indentation of `main` stripped) and returns the line to use instead, or `None` to drop it.
+    /// Filters are called in the order they were added, and the lines of the generated `main`
+    /// function and output capture are never filtered
+    ///
+    /// ```
+    /// use quote::quote;
+    /// use quote_doctest::{doc_test, DocTestOptions, FormatDocTest};
+    ///
+    /// let options = DocTestOptions::builder()
+    ///     .filter_lines(|line| (!line.starts_with("#[allow")).then(|| line.to_string()))
+    ///     .filter_lines(|line| Some(line.replace("internal::", "my_crate::")));
+    /// let code = quote! {
+    ///     #[allow(unused)]
+    ///     let x = internal::run();
+    /// };
+    /// let actual = doc_test!(code, options).unwrap();
+    ///
+    /// let expected = quote! {
+    ///     /// ```
+    ///     /// let x = my_crate::run();
+    ///     /// ```
+    /// };
+    /// assert_eq!(expected.format_tokens().unwrap(), actual.format_tokens().unwrap());
+    /// ```
+    #[inline]
+    pub fn filter_lines(
+        mut self,
+        filter: impl Fn(&str) -> Option<String> + Send + Sync + 'static,
+    ) -> Self {
+        self.filters.push(Arc::new(filter));
+        self
+    }
+
+    /// Builds the [DocTestOptions]
+    #[inline]
+    pub fn build(self) -> DocTestOptions {
+        DocTestOptions::Builder(self)
+    }
+}
+
+impl Default for DocTestBuilder {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<DocTestBuilder> for DocTestOptions {
+    #[inline]
+    fn from(builder: DocTestBuilder) -> Self {
+        builder.build()
+    }
+}
+
+/// Optional enum passed to [doc_test] for different configuration options. The variants other than
+/// [Builder](DocTestOptions::Builder) are kept for compatibility, but
+/// [builder](DocTestOptions::builder) is the preferred way to create it (and the only way to use
+/// options such as fence attributes or a fallible/async main)
 #[derive(Clone)]
+#[non_exhaustive]
 pub enum DocTestOptions {
     /// TokenStream is not formatted and no main function is generated. The doctest will be a single line
     NoFormatOrGenMain,
@@ -166,14 +465,24 @@ pub enum DocTestOptions {
     /// later stripped after formatting. The `usize` parameter is the number of indent spaces to be
     /// stripped (typically this number should be 4)
     FormatAndGenMain(Formatter, usize),
+    /// Options created by a [DocTestBuilder]
+    Builder(DocTestBuilder),
 }
 
 impl DocTestOptions {
+    /// Creates a [DocTestBuilder] using the defaults, which can then be customized
+    #[inline]
+    pub fn builder() -> DocTestBuilder {
+        DocTestBuilder::new()
+    }
+
     /// Creates a basic default `rustfmt` `DocTestOptions` instance that generates main,
     /// formats, and then strips the main function
     #[inline]
     pub fn new_rust_fmt() -> Self {
-        DocTestOptions::FormatAndGenMain(Formatter::new_rust_fmt(), FORMATTER_INDENT)
+        DocTestBuilder::new()
+            .formatter(Formatter::new_rust_fmt())
+            .build()
     }
 
     /// Creates a basic default `prettyplease` `DocTestOptions` instance that generates main,
@@ -182,15 +491,91 @@ impl DocTestOptions {
     #[cfg_attr(docsrs, doc(cfg(feature = "pretty_please")))]
     #[inline]
     pub fn new_pretty_please() -> Self {
-        DocTestOptions::FormatAndGenMain(Formatter::new_pretty_please(), FORMATTER_INDENT)
+        DocTestBuilder::new()
+            .formatter(Formatter::new_pretty_please())
+            .build()
+    }
+
+    /// Creates a `DocTestOptions` instance that formats with the default formatter inside a
+    /// generated `#[tokio::main] async fn main()` function, which is hidden in the doctest
+    ///
+    /// ```
+    /// use quote::quote;
+    /// use quote_doctest::{doc_test, DocTestOptions, FormatDocTest};
+    ///
+    /// let actual = doc_test!(quote! { run().await; }, DocTestOptions::tokio_main()).unwrap();
+    ///
+    /// let expected = quote! {
+    ///     /// ```
+    ///     /// # #[tokio::main]
+    ///     /// # async fn main() {
+    ///     /// run().await;
+    ///     /// # }
+    ///     /// ```
+    /// };
+    /// assert_eq!(expected.format_tokens().unwrap(), actual.format_tokens().unwrap());
+    /// ```
+    #[inline]
+    pub fn tokio_main() -> Self {
+        DocTestBuilder::new()
+            .gen_main(GenMain::Async(quote! { #[tokio::main] }))
+            .build()
     }
 
+    /// Creates a `DocTestOptions` instance that formats with the default formatter inside a
+    /// generated `#[async_std::main] async fn main()` function, which is hidden in the doctest
     #[inline]
-    fn options(self) -> (Option<Formatter>, bool, usize) {
+    pub fn async_std_main() -> Self {
+        DocTestBuilder::new()
+            .gen_main(GenMain::Async(quote! { #[async_std::main] }))
+            .build()
+    }
+
+    /// Add an attribute to the opening code fence of the doctest
+    ///
+    /// ```
+    /// use quote::quote;
+    /// use quote_doctest::{doc_test, DocTestOptions, FenceAttr, FormatDocTest};
+    ///
+    /// let options = DocTestOptions::default().fence(FenceAttr::NoRun);
+    /// let actual = doc_test!(quote! { loop {} }, options).unwrap();
+    ///
+    /// let expected = quote! {
+    ///     /// ```no_run
+    ///     /// loop {}
+    ///     /// ```
+    /// };
+    /// assert_eq!(expected.format_tokens().unwrap(), actual.format_tokens().unwrap());
+    /// ```
+    pub fn fence(self, attr: FenceAttr) -> Self {
+        self.settings().fence_attrs([attr]).build()
+    }
+
+    // The indent to strip is `None` if it should be detected
+    fn options(self) -> DocTestBuilder {
+        let mut options = self.settings();
+
+        // We can't remove main unless we are formatting
+        if options.fmt.is_none() {
+            options.gen_main = GenMain::No;
+        }
+        if let GenMain::No = options.gen_main {
+            options.strip_indent = Some(0);
+        }
+        options
+    }
+
+    // The settings as given (even if they can't all be used when creating a doctest)
+    fn settings(self) -> DocTestBuilder {
         match self {
-            DocTestOptions::NoFormatOrGenMain => (None, false, 0),
-            DocTestOptions::FormatOnly(fmt) => (Some(fmt), false, 0),
-            DocTestOptions::FormatAndGenMain(fmt, strip_indent) => (Some(fmt), true, strip_indent),
+            DocTestOptions::NoFormatOrGenMain => DocTestBuilder::with(None, GenMain::No, Some(0)),
+            DocTestOptions::FormatOnly(fmt) => {
+                DocTestBuilder::with(Some(fmt), GenMain::No, Some(0))
+            }
+            DocTestOptions::FormatAndGenMain(fmt, strip_indent) => {
+                DocTestBuilder::with(Some(fmt), GenMain::Strip, Some(strip_indent))
+            }
+            DocTestOptions::Builder(builder) => builder,
         }
     }
 }
@@ -216,7 +601,7 @@ impl Default for DocTestOptions {
 /// of the formatted code (or a single line of unformatted text, if `fmt` is `None`) or an [Error]
 /// error, if one occurred.
 #[inline]
-fn tokens_to_string(tokens: TokenStream, fmt: Option<Formatter>) -> Result<String, Error> {
+fn tokens_to_string(tokens: TokenStream, fmt: Option<&Formatter>) -> Result<String, Error> {
     match fmt {
         #[cfg(feature = "pretty_please")]
         Some(Formatter::PrettyPlease(pp)) => pp.format_tokens(tokens),
@@ -267,158 +652,1396 @@ pub fn doc_comment(comment: impl AsRef<str>) -> TokenStream {
     quote! { #( #[doc = #doc_comment] )* }
 }
 
-#[doc(hidden)]
-pub fn make_doc_test(
-    mut tokens: TokenStream,
-    options: DocTestOptions,
-) -> Result<TokenStream, Error> {
-    let (fmt, gen_main, strip_indent) = options.options();
-
-    // Surround with main, if needed (we can't remove it unless we are formatting)
-    if gen_main {
-        tokens = quote! {
-            fn main() { #tokens }
-        };
-    }
+/// Reads a markdown file and turns it into doc comments (the same as [doc_comment]), so long-form
+/// documentation can be kept in its own file. Line endings can be either LF or CRLF, and trailing
+/// whitespace (including a byte order mark and any blank lines at the end) is removed. An error is
+/// returned if the file can't be read or isn't valid UTF-8
+///
+/// ```no_run
+/// use quote::quote;
+/// use quote_doctest::doc_comment_from_file;
+///
+/// let comment = doc_comment_from_file("docs/fibonacci.md").unwrap();
+/// let actual = quote! {
+///     #comment
+///     fn fibonacci(n: u64) -> u64 {
+///         unimplemented!()
+///     }
+/// };
+/// ```
+pub fn doc_comment_from_file(path: impl AsRef<Path>) -> Result<TokenStream, Error> {
+    let text = fs::read_to_string(path)?;
+    let text = text.strip_prefix('\u{feff}').unwrap_or(&text);
+
+    let text: String = text
+        .lines()
+        .flat_map(|line| [line.trim_end(), "\n"])
+        .collect();
+    Ok(doc_comment(text.trim_end()))
+}
 
-    // Format, if required, and then break into lines
-    let src = tokens_to_string(tokens, fmt)?;
-    let lines = to_source_lines(&src, gen_main);
+/// The same as [doc_comment], except the prose is reflowed so no line is longer than `max_width`
+/// characters (not counting the `///`). Paragraphs are separated by blank lines, and code fences,
+/// indented code, headings, tables, and quotes are kept as is. Each list item is wrapped on its
+/// own with its continuation lines indented under it. Inline code spans are never split across
+/// lines, and a single word (or code span) wider than `max_width` is placed on its own line
+///
+/// ```
+/// use quote::quote;
+/// use quote_doctest::{doc_comment_wrapped, FormatDocTest};
+///
+/// let text = "This is a long sentence with `some code` in it.\n\nA new paragraph";
+/// let actual = doc_comment_wrapped(text, 20);
+/// let expected = quote! {
+///     /// This is a long
+///     /// sentence with
+///     /// `some code` in it.
+///     ///
+///     /// A new paragraph
+/// };
+///
+/// assert_eq!(expected.format_tokens().unwrap(), actual.format_tokens().unwrap());
+/// ```
+pub fn doc_comment_wrapped(text: impl AsRef<str>, max_width: usize) -> TokenStream {
+    doc_comment(wrap_text(text.as_ref(), max_width))
+}
 
-    // Assemble the lines back into a string while indenting
-    // NOTE: strip_indent will be zero unless gen_main was set
-    let indent = " ".repeat(strip_indent);
-    let doc_test = assemble_doc_test(lines, src.len(), indent);
-    let doc_test: Vec<_> = doc_test.lines().collect();
+/// Creates a markdown bullet list doc comment with one item per entry. Items that span multiple
+/// lines have their continuation lines indented under the item
+///
+/// ```
+/// use quote::quote;
+/// use quote_doctest::{doc_list, FormatDocTest};
+///
+/// let actual = doc_list(["First", "Second\nline"]);
+/// let expected = quote! {
+///     /// - First
+///     /// - Second
+///     ///   line
+/// };
+///
+/// assert_eq!(expected.format_tokens().unwrap(), actual.format_tokens().unwrap());
+/// ```
+pub fn doc_list<I, S>(items: I) -> TokenStream
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let mut buffer = String::with_capacity(MIN_BUFF_SIZE);
+
+    for item in items {
+        for (idx, line) in item.as_ref().trim_end().lines().enumerate() {
+            buffer.push_str(if idx == 0 { "- " } else { "  " });
+            buffer.push_str(line);
+            buffer.push('\n');
+        }
+    }
 
-    // Turn back into a token stream and into a doc test
-    Ok(quote! {
-        /// ```
-        #( #[doc = #doc_test] )*
-        /// ```
-    })
+    doc_comment(buffer)
 }
 
-fn to_source_lines(src: &str, gen_main: bool) -> Vec<&str> {
-    // Split string source code into lines
-    let lines = src.lines();
+/// Creates a markdown table doc comment with the given headers and rows. The columns are padded
+/// so they line up, `|` characters are escaped, and line breaks inside a cell become spaces. Rows
+/// are padded with empty cells (or truncated) to the number of headers
+///
+/// ```
+/// use quote::quote;
+/// use quote_doctest::{doc_table, FormatDocTest};
+///
+/// let actual = doc_table(["Name", "Value"], [["a", "1"], ["long name", "a|b"]]);
+/// let expected = quote! {
+///     /// | Name      | Value |
+///     /// |-----------|-------|
+///     /// | a         | 1     |
+///     /// | long name | a\|b  |
+/// };
+///
+/// assert_eq!(expected.format_tokens().unwrap(), actual.format_tokens().unwrap());
+/// ```
+pub fn doc_table<H, S, R, C, T>(headers: H, rows: R) -> TokenStream
+where
+    H: IntoIterator<Item = S>,
+    S: AsRef<str>,
+    R: IntoIterator<Item = C>,
+    C: IntoIterator<Item = T>,
+    T: AsRef<str>,
+{
+    let headers: Vec<_> = headers
+        .into_iter()
+        .map(|header| table_cell(header.as_ref()))
+        .collect();
+    let rows: Vec<Vec<_>> = rows
+        .into_iter()
+        .map(|row| {
+            let mut row: Vec<_> = row
+                .into_iter()
+                .take(headers.len())
+                .map(|cell| table_cell(cell.as_ref()))
+                .collect();
+            row.resize(headers.len(), String::new());
+            row
+        })
+        .collect();
+
+    // The separator needs at least 3 dashes
+    let widths: Vec<_> = (0..headers.len())
+        .map(|col| {
+            rows.iter()
+                .map(|row| &row[col])
+                .chain([&headers[col]])
+                .map(|cell| cell.chars().count())
+                .fold(3, cmp::max)
+        })
+        .collect();
+
+    let mut buffer = String::with_capacity(MIN_BUFF_SIZE);
+    let push_row = |buffer: &mut String, cells: &[String]| {
+        for (cell, width) in cells.iter().zip(&widths) {
+            let padding = width - cell.chars().count();
+            buffer.push_str("| ");
+            buffer.push_str(cell);
+            buffer.push_str(&" ".repeat(padding + 1));
+        }
+        buffer.push_str("|\n");
+    };
 
-    // Remove `fn main () {`, if we added it
-    if gen_main {
-        // Skip 'fn main {'
-        let mut lines = lines.skip(1).collect::<Vec<_>>();
-        // Remove the trailing `}`
-        lines.pop();
-        lines
-    } else {
-        lines.collect()
+    push_row(&mut buffer, &headers);
+    for width in &widths {
+        buffer.push('|');
+        buffer.push_str(&"-".repeat(width + 2));
+    }
+    buffer.push_str("|\n");
+    for row in &rows {
+        push_row(&mut buffer, row);
     }
+
+    doc_comment(buffer)
 }
 
-fn assemble_doc_test(lines: Vec<&str>, cap: usize, prefix: String) -> String {
-    // Unlikely to be this big, but better than reallocating
-    let mut buffer = String::with_capacity(cmp::max(cap * 2, MIN_BUFF_SIZE));
+// Escape a table cell so it can't end the cell early or break the row
+fn table_cell(cell: &str) -> String {
+    cell.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace('|', "\\|")
+}
 
-    // Build code from lines
-    for mut line in lines {
-        // Strip whitespace left over from main, if any (else noop)
-        line = line.strip_prefix(&prefix).unwrap_or(line);
+// Returns the length of the backtick run that is still open at the end of the word (if any),
+// given the one open before it
+fn open_code_span(word: &str, mut open: Option<usize>) -> Option<usize> {
+    let mut chars = word.chars().peekable();
 
-        // Except for empty lines, all lines should get a space at the front
-        if !line.is_empty() {
-            buffer.push(' ');
+    while let Some(ch) = chars.next() {
+        if ch == '`' {
+            let mut len = 1;
+            while chars.next_if_eq(&'`').is_some() {
+                len += 1;
+            }
+
+            open = match open {
+                None => Some(len),
+                // Only a run of the same length closes the span
+                Some(open_len) if open_len == len => None,
+                open => open,
+            };
         }
-        buffer.push_str(line);
-        buffer.push('\n');
     }
 
-    buffer
+    open
 }
 
-#[cfg(not(feature = "pretty_please"))]
-#[inline]
-fn doc_test_formatter() -> impl rust_format::Formatter {
-    let config = rust_format::Config::new_str()
-        .post_proc(rust_format::PostProcess::ReplaceMarkersAndDocBlocks);
-    rust_format::RustFmt::from_config(config)
+// Returns the length of the list item marker (including any indent and the following space) if
+// the line starts a list item
+fn list_marker(line: &str) -> Option<usize> {
+    let rest = line.trim_start_matches(' ');
+    let indent = line.len() - rest.len();
+
+    if rest.starts_with("- ") || rest.starts_with("* ") || rest.starts_with("+ ") {
+        return Some(indent + 2);
+    }
+
+    let digits = rest.chars().take_while(char::is_ascii_digit).count();
+    match &rest[digits..] {
+        rest if (1..=9).contains(&digits) && (rest.starts_with(". ") || rest.starts_with(") ")) => {
+            Some(indent + digits + 2)
+        }
+        _ => None,
+    }
 }
 
-#[cfg(feature = "pretty_please")]
 #[inline]
-fn doc_test_formatter() -> impl rust_format::Formatter {
-    rust_format::PrettyPlease::default()
+fn is_fence(line: &str) -> bool {
+    let line = line.trim_start();
+    line.starts_with("```") || line.starts_with("~~~")
 }
 
-/// Trait for converting [doc_test] results into a well formatted `String`
-pub trait FormatDocTest: ToTokens {
-    /// Convert results of a [doc_test] (or any other value that implements `ToTokens` that is valid
-    /// Rust source) into a formatted `String`. This will also convert doc blocks (`#[doc = ""]`) into
-    /// doc comments (`///`). This can be useful for display or equality testing in a unit test. An
-    /// error is returned if an issue occurs during the formatting process
-    ///
-    /// NOTE: If `pretty_please` is not enabled then `rustfmt` will be used via the `rust_format` crate
-    /// and when translating doc blocks will also translate any [`_comment_!`] or [`_blank_!`] markers.
-    /// If the source of this function came from [doc_test], these will already be translated anyway,
-    /// but this is mentioned for awareness.
-    fn format_tokens(self) -> Result<String, Error>
-    where
-        Self: Sized,
-    {
-        // We need a function - doc blocks alone won't pass the formatter
-        let doc_test = quote! {
-            #self
-            fn main() {}
-        };
+// Lines that are never joined with others (blank lines, headings, tables, quotes, HTML, and
+// thematic breaks/setext underlines)
+fn is_verbatim(line: &str) -> bool {
+    let line = line.trim();
 
-        // Format (and translate doc blocks to doc comments
-        let formatter = doc_test_formatter();
-        let source = formatter.format_tokens(doc_test)?;
+    line.is_empty()
+        || line.starts_with(['#', '|', '>', '<'])
+        || line.chars().all(|ch| matches!(ch, '-' | '=' | '*' | '_'))
+}
 
-        // Convert into lines so we trim off the last line (our added main function)
-        let mut lines: Vec<_> = source.lines().collect();
-        // All in one line because there will never be anything inside
-        lines.pop(); // fn main() {}
+// A paragraph or list item made up of one or more lines
+struct Paragraph<'a> {
+    lines: Vec<&'a str>,
+    // The text before the first word on the first line (indent and list marker)
+    lead: &'a str,
+}
 
-        // Unlikely to be this big, but better than reallocating
-        let mut buffer = String::with_capacity(cmp::max(source.len() * 2, MIN_BUFF_SIZE));
+impl Paragraph<'_> {
+    #[inline]
+    fn is_list(&self) -> bool {
+        !self.lead.trim().is_empty()
+    }
 
-        for line in lines {
-            buffer.push_str(line);
-            buffer.push('\n');
+    // Reflow the paragraph into lines no wider than `max_width`. Continuation lines are indented
+    // under the lead (so list items stay list items)
+    fn wrap(&self, max_width: usize, result: &mut String) {
+        let text = self.lines.iter().enumerate().map(|(idx, line)| match idx {
+            0 => &line[self.lead.len()..],
+            _ => line,
+        });
+
+        // Words inside a code span are joined so the span stays on one line
+        let mut words = Vec::new();
+        let mut open = None;
+        for word in text.flat_map(str::split_whitespace) {
+            match words.last_mut() {
+                Some(last) if open.is_some() => {
+                    *last = format!("{last} {word}");
+                }
+                _ => words.push(word.to_string()),
+            }
+            open = open_code_span(word, open);
         }
 
-        buffer.shrink_to_fit();
-        Ok(buffer)
+        let lead_width = self.lead.chars().count();
+        let mut width = lead_width;
+        let mut empty = true;
+        result.push_str(self.lead);
+
+        for word in words {
+            let word_width = word.chars().count();
+
+            if !empty {
+                if width + 1 + word_width > max_width {
+                    result.push('\n');
+                    result.push_str(&" ".repeat(lead_width));
+                    width = lead_width;
+                } else {
+                    result.push(' ');
+                    width += 1;
+                }
+            }
+            result.push_str(&word);
+            width += word_width;
+            empty = false;
+        }
+        result.push('\n');
     }
 }
 
-impl<T> FormatDocTest for T where T: ToTokens {}
-
-#[cfg(test)]
-mod tests {
-    use pretty_assertions::assert_eq;
-    use quote::quote;
+fn wrap_text(text: &str, max_width: usize) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut para: Option<Paragraph> = None;
+    let mut in_fence = false;
 
-    use crate::{
-        tokens_to_string, DocTestOptions, Error, FormatDocTest, Formatter, FORMATTER_INDENT,
+    let flush = |para: &mut Option<Paragraph>, result: &mut String| {
+        if let Some(para) = para.take() {
+            para.wrap(max_width, result);
+        }
     };
 
-    #[test]
-    fn doctest_format() {
-        let actual = quote! {
-            /// ```
-            /// assert_eq!(fibonacci(10), 55);
-            /// assert_eq!(fibonacci(1), 1);
-            /// ```
-        };
+    for line in text.lines() {
+        if in_fence || is_fence(line) || is_verbatim(line) {
+            flush(&mut para, &mut result);
+            if is_fence(line) {
+                in_fence = !in_fence;
+            }
+            result.push_str(line);
+            result.push('\n');
+            continue;
+        }
 
-        let expected = r#"/// ```
+        let indent = line.len() - line.trim_start_matches(' ').len();
+
+        if let Some(marker_len) = list_marker(line) {
+            flush(&mut para, &mut result);
+            para = Some(Paragraph {
+                lines: vec![line],
+                lead: &line[..marker_len],
+            });
+        } else if let Some(p) = &mut para {
+            // List item continuations must be indented under the item
+            if p.is_list() && indent < p.lead.len() {
+                flush(&mut para, &mut result);
+                para = Some(Paragraph {
+                    lines: vec![line],
+                    lead: &line[..indent],
+                });
+            } else {
+                p.lines.push(line);
+            }
+        } else if indent >= 4 {
+            // Indented code block
+            result.push_str(line);
+            result.push('\n');
+        } else {
+            para = Some(Paragraph {
+                lines: vec![line],
+                lead: &line[..indent],
+            });
+        }
+    }
+
+    flush(&mut para, &mut result);
+    result
+}
+
+// *** Doc Comment Builder ***
+
+/// Assembles a doc comment from the standard rustdoc sections. Sections are emitted in the order
+/// used by `std` (summary, description, `# Errors`, `# Panics`, `# Safety`, and then `# Examples`)
+/// with a blank line between each, and any that aren't set are skipped
+///
+/// ```
+/// use quote::quote;
+/// use quote_doctest::{doc_test, DocCommentBuilder, FormatDocTest};
+///
+/// let example = doc_test!(quote! { assert_eq!(div(4, 2), 2); }).unwrap();
+/// let actual = DocCommentBuilder::new("Divides two numbers")
+///     .panics("If `b` is zero")
+///     .example(example)
+///     .build();
+///
+/// let expected = quote! {
+///     /// Divides two numbers
+///     ///
+///     /// # Panics
+///     ///
+///     /// If `b` is zero
+///     ///
+///     /// # Examples
+///     ///
+///     /// ```
+///     /// assert_eq!(div(4, 2), 2);
+///     /// ```
+/// };
+/// assert_eq!(expected.format_tokens().unwrap(), actual.format_tokens().unwrap());
+/// ```
+#[derive(Clone, Default)]
+pub struct DocCommentBuilder {
+    summary: String,
+    description: Option<String>,
+    errors: Option<String>,
+    panics: Option<String>,
+    safety: Option<String>,
+    examples: Vec<TokenStream>,
+}
+
+impl DocCommentBuilder {
+    /// Creates a new builder with the given summary line
+    #[inline]
+    pub fn new(summary: impl Into<String>) -> Self {
+        Self {
+            summary: summary.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Sets the extended description that follows the summary
+    #[inline]
+    pub fn description(mut self, text: impl Into<String>) -> Self {
+        self.description = Some(text.into());
+        self
+    }
+
+    /// Sets the text of the `# Errors` section
+    #[inline]
+    pub fn errors(mut self, text: impl Into<String>) -> Self {
+        self.errors = Some(text.into());
+        self
+    }
+
+    /// Sets the text of the `# Panics` section
+    #[inline]
+    pub fn panics(mut self, text: impl Into<String>) -> Self {
+        self.panics = Some(text.into());
+        self
+    }
+
+    /// Sets the text of the `# Safety` section
+    #[inline]
+    pub fn safety(mut self, text: impl Into<String>) -> Self {
+        self.safety = Some(text.into());
+        self
+    }
+
+    /// Adds an example (typically the output of [doc_test]) to the `# Examples` section. Multiple
+    /// examples are separated by a blank line
+    #[inline]
+    pub fn example(mut self, example: TokenStream) -> Self {
+        self.examples.push(example);
+        self
+    }
+
+    /// Builds the doc comment as a [TokenStream](proc_macro2::TokenStream) of `#[doc]` attributes
+    pub fn build(self) -> TokenStream {
+        let mut tokens = doc_comment(&self.summary);
+
+        let push_text = |tokens: &mut TokenStream, text: &str| {
+            tokens.extend(doc_comment(format!("\n{text}")));
+        };
+
+        if let Some(description) = &self.description {
+            push_text(&mut tokens, description);
+        }
+
+        let sections = [
+            ("Errors", &self.errors),
+            ("Panics", &self.panics),
+            ("Safety", &self.safety),
+        ];
+        for (heading, text) in sections {
+            if let Some(text) = text {
+                push_text(&mut tokens, &format!("# {heading}\n\n{text}"));
+            }
+        }
+
+        if !self.examples.is_empty() {
+            push_text(&mut tokens, "# Examples\n\n");
+            for (idx, example) in self.examples.into_iter().enumerate() {
+                if idx > 0 {
+                    tokens.extend(doc_comment("\n"));
+                }
+                tokens.extend(example);
+            }
+        }
+
+        tokens
+    }
+}
+
+// A single part of a doc comment built by `DocBuilder`
+#[derive(Clone)]
+enum DocPart {
+    Text(String),
+    Code(TokenStream, DocTestOptions),
+    Source(String, DocTestOptions),
+}
+
+/// Builds a doc comment from any number of prose sections and independent code blocks, each code
+/// block with its own options. The parts are emitted in the order they are added, separated by
+/// a blank line
+///
+/// ```
+/// use quote::quote;
+/// use quote_doctest::{DocBuilder, DocTestOptions, FenceAttr, FormatDocTest};
+///
+/// let actual = DocBuilder::new()
+///     .text("First create it:")
+///     .code(quote! { let x = 5; })
+///     .text("Then panic:")
+///     .code_with(quote! { panic!(); }, DocTestOptions::default().fence(FenceAttr::ShouldPanic))
+///     .build()
+///     .unwrap();
+///
+/// let expected = quote! {
+///     /// First create it:
+///     ///
+///     /// ```
+///     /// let x = 5;
+///     /// ```
+///     ///
+///     /// Then panic:
+///     ///
+///     /// ```should_panic
+///     /// panic!();
+///     /// ```
+/// };
+/// assert_eq!(expected.format_tokens().unwrap(), actual.format_tokens().unwrap());
+/// ```
+#[derive(Clone, Default)]
+pub struct DocBuilder {
+    parts: Vec<DocPart>,
+}
+
+impl DocBuilder {
+    /// Creates a new empty builder
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds prose (the same as [doc_comment])
+    #[inline]
+    pub fn text(mut self, text: impl Into<String>) -> Self {
+        self.parts.push(DocPart::Text(text.into()));
+        self
+    }
+
+    /// Adds a code block created from the tokens using the default options (the same as [doc_test])
+    #[inline]
+    pub fn code(self, tokens: TokenStream) -> Self {
+        self.code_with(tokens, DocTestOptions::default())
+    }
+
+    /// Adds a code block created from the tokens using the given options (the same as [doc_test])
+    #[inline]
+    pub fn code_with(mut self, tokens: TokenStream, options: impl Into<DocTestOptions>) -> Self {
+        self.parts.push(DocPart::Code(tokens, options.into()));
+        self
+    }
+
+    /// Adds a code block created from source code using the given options (the same as
+    /// [doc_test_str])
+    #[inline]
+    pub fn code_str(
+        mut self,
+        source: impl Into<String>,
+        options: impl Into<DocTestOptions>,
+    ) -> Self {
+        self.parts
+            .push(DocPart::Source(source.into(), options.into()));
+        self
+    }
+
+    /// Builds the doc comment as a [TokenStream](proc_macro2::TokenStream) of `#[doc]` attributes.
+    /// An error is returned if any code block can't be formatted
+    pub fn build(self) -> Result<TokenStream, Error> {
+        let mut tokens = TokenStream::new();
+
+        for (idx, part) in self.parts.into_iter().enumerate() {
+            if idx > 0 {
+                tokens.extend(doc_comment("\n"));
+            }
+
+            tokens.extend(match part {
+                DocPart::Text(text) => doc_comment(text.trim_end()),
+                DocPart::Code(code, options) => make_doc_test(code, options)?,
+                DocPart::Source(source, options) => doc_test_str(&source, options),
+            });
+        }
+
+        Ok(tokens)
+    }
+}
+
+#[doc(hidden)]
+pub fn make_doc_test(
+    tokens: TokenStream,
+    options: impl Into<DocTestOptions>,
+) -> Result<TokenStream, Error> {
+    let options = options.into().options();
+
+    // Surround with main, if needed (we can't remove it unless we are formatting)
+    let tokens = wrap_main(tokens, &options.gen_main);
+
+    // Format, if required, and then break into lines
+    let src = match &options.fmt {
+        Some(fmt) => tokens_to_string(mark_hidden(tokens), Some(fmt))?,
+        None => unformatted_lines(tokens),
+    };
+    let gen_main = &options.gen_main;
+    let (header, lines) = to_source_lines(&src, !matches!(gen_main, GenMain::No));
+    let hidden_main = gen_main.is_hidden().then_some(&*header);
+
+    // NOTE: nothing is stripped unless gen_main was set
+    Ok(lines_to_doc_test(
+        lines,
+        src.len(),
+        options.strip_indent,
+        hidden_main,
+        &options,
+    ))
+}
+
+// Surround the tokens with the main function to generate (if any)
+fn wrap_main(tokens: TokenStream, gen_main: &GenMain) -> TokenStream {
+    match gen_main {
+        GenMain::No => tokens,
+        GenMain::Strip => quote! {
+            fn main() { #tokens }
+        },
+        GenMain::Fallible => quote! {
+            fn main() -> Result<(), Box<dyn std::error::Error>> {
+                #tokens
+                Ok(())
+            }
+        },
+        GenMain::Async(attr) => quote! {
+            #attr
+            async fn main() { #tokens }
+        },
+    }
+}
+
+/// Creates a doctest from source code that is already written, so comments, blank lines, and
+/// formatting are kept exactly as is. Nothing is formatted (any formatter in the options is
+/// ignored), but if the options generate `main` and the source is wrapped in `fn main() { ... }`,
+/// that function is stripped along with the given indent (or hidden, if the options generate a
+/// fallible or async main). Any fence attributes are used
+///
+/// ```
+/// use quote::quote;
+/// use quote_doctest::{doc_test_str, DocTestOptions, FormatDocTest};
+///
+/// let source = r#"fn main() {
+///     // Comments are kept
+///     let x = 5;
+///
+///     assert_eq!(x, 5);
+/// }
+/// "#;
+/// let actual = doc_test_str(source, DocTestOptions::default());
+///
+/// let expected = quote! {
+///     /// ```
+///     /// // Comments are kept
+///     /// let x = 5;
+///     ///
+///     /// assert_eq!(x, 5);
+///     /// ```
+/// };
+/// assert_eq!(expected.format_tokens().unwrap(), actual.format_tokens().unwrap());
+/// ```
+pub fn doc_test_str(source: &str, options: impl Into<DocTestOptions>) -> TokenStream {
+    let options = options.into().options();
+    let gen_main = &options.gen_main;
+
+    // Trailing whitespace (such as a final newline) would otherwise hide the closing brace
+    let source = source.trim_end();
+    let (header, lines) = to_source_lines(source, !matches!(gen_main, GenMain::No));
+
+    let (strip_indent, hidden_main) = match header.is_empty() {
+        true => (Some(0), None),
+        false => (
+            options.strip_indent,
+            gen_main.is_hidden().then_some(&*header),
+        ),
+    };
+    lines_to_doc_test(lines, source.len(), strip_indent, hidden_main, &options)
+}
+
+// Returns the number of lines before the body of the `main` function the lines are wrapped in (its
+// attributes and signature), if they are wrapped in one, and whether it is an empty `main` that
+// fits on its signature line (`fn main() {}`)
+fn main_header_len(lines: &[&str]) -> Option<(usize, bool)> {
+    let idx = lines
+        .iter()
+        .position(|line| !line.trim_start().starts_with("#["))?;
+    let sig = lines[idx].trim();
+    if !sig.starts_with("fn main(") && !sig.starts_with("async fn main(") {
+        return None;
+    }
+
+    if sig.ends_with("{}") && idx + 1 == lines.len() {
+        Some((idx + 1, true))
+    } else {
+        let is_main = sig.ends_with('{') && lines.last()?.trim() == "}" && idx + 1 < lines.len();
+        is_main.then_some((idx + 1, false))
+    }
+}
+
+// Returns the indentation (spaces and/or tabs) of the first line that isn't blank
+fn detect_indent<'a>(lines: &[&'a str]) -> &'a str {
+    match lines.iter().find(|line| !line.trim().is_empty()) {
+        Some(line) => {
+            let len = line.len() - line.trim_start_matches([' ', '\t']).len();
+            &line[..len]
+        }
+        None => "",
+    }
+}
+
+// Assemble the lines back into a string while indenting and turn them into a doc test. If the
+// header of a main function is given, the lines are wrapped in that function (hidden). If the
+// options expect output, the printed output is captured and compared to it (hidden)
+fn lines_to_doc_test(
+    mut lines: Vec<&str>,
+    cap: usize,
+    strip_indent: Option<usize>,
+    hidden_main: Option<&[&str]>,
+    options: &DocTestBuilder,
+) -> TokenStream {
+    // The `Ok(())` that ends a fallible main is hidden along with the rest of it
+    let hidden_ok = hidden_main.is_some() && lines.last().map(|line| line.trim()) == Some("Ok(())");
+    if hidden_ok {
+        lines.pop();
+    }
+
+    let indent = match strip_indent {
+        Some(strip_indent) => " ".repeat(strip_indent),
+        None => detect_indent(&lines).to_string(),
+    };
+    let mut doc_test = assemble_doc_test(lines, cap, indent, &options.filters);
+
+    if let Some(expected) = &options.expected_output {
+        let capture: String = CAPTURE_OUTPUT
+            .iter()
+            .map(|line| format!(" # {line}\n"))
+            .collect();
+        doc_test = format!("{capture}{doc_test} # assert_eq!(__output, {expected:?});\n");
+    }
+
+    if let Some(header) = hidden_main {
+        let header: String = header
+            .iter()
+            .map(|line| format!(" # {}\n", line.trim()))
+            .collect();
+        let ok = if hidden_ok { " # Ok(())\n" } else { "" };
+        doc_test = format!("{header}{doc_test}{ok} # }}\n");
+    }
+    let doc_test: Vec<_> = doc_test.lines().collect();
+    let fence = fence(&options.attrs);
+
+    // Turn back into a token stream and into a doc test
+    quote! {
+        #[doc = #fence]
+        #( #[doc = #doc_test] )*
+        /// ```
+    }
+}
+
+// Returns the tokens inside a `_hidden_!(...)` marker starting at `idx` along with the number of
+// tokens the marker spans (including any trailing `;`)
+fn hidden_marker(tokens: &[TokenTree], idx: usize) -> Option<(TokenStream, usize)> {
+    match &tokens[idx..] {
+        [TokenTree::Ident(ident), TokenTree::Punct(bang), TokenTree::Group(group), rest @ ..]
+            if ident == HIDDEN
+                && bang.as_char() == '!'
+                && group.delimiter() == Delimiter::Parenthesis =>
+        {
+            let len = match rest.first() {
+                Some(TokenTree::Punct(punct)) if punct.as_char() == ';' => 4,
+                _ => 3,
+            };
+            Some((group.stream(), len))
+        }
+        _ => None,
+    }
+}
+
+// Replace each `_hidden_!(...)` marker with its tokens surrounded by comment markers (which are
+// formatted into comment lines that mark where the hidden lines start and end)
+fn mark_hidden(tokens: TokenStream) -> TokenStream {
+    let tokens: Vec<_> = tokens.into_iter().collect();
+    let mut result = TokenStream::new();
+    let mut idx = 0;
+
+    while idx < tokens.len() {
+        match hidden_marker(&tokens, idx) {
+            Some((hidden, len)) => {
+                result.extend(quote! {
+                    _comment_!(#HIDDEN_START);
+                    #hidden
+                    _comment_!(#HIDDEN_END);
+                });
+                idx += len;
+            }
+            None => {
+                match &tokens[idx] {
+                    TokenTree::Group(group) => {
+                        let mut marked = Group::new(group.delimiter(), mark_hidden(group.stream()));
+                        marked.set_span(group.span());
+                        result.extend([TokenTree::Group(marked)]);
+                    }
+                    tt => result.extend([tt.clone()]),
+                }
+                idx += 1;
+            }
+        }
+    }
+
+    result
+}
+
+// Unformatted source is a single line, except top level `_hidden_!(...)` markers are each put on
+// their own (hidden) line
+fn unformatted_lines(tokens: TokenStream) -> String {
+    let tokens: Vec<_> = tokens.into_iter().collect();
+    let mut lines = Vec::new();
+    let mut visible = TokenStream::new();
+    let mut idx = 0;
+
+    while idx < tokens.len() {
+        match hidden_marker(&tokens, idx) {
+            Some((hidden, len)) => {
+                if !visible.is_empty() {
+                    lines.push(std::mem::take(&mut visible).to_string());
+                }
+                lines.push(format!("# {hidden}"));
+                idx += len;
+            }
+            None => {
+                visible.extend([tokens[idx].clone()]);
+                idx += 1;
+            }
+        }
+    }
+    if !visible.is_empty() {
+        lines.push(visible.to_string());
+    }
+
+    lines.join("\n")
+}
+
+// Returns true if the line is the comment a hidden marker was formatted into
+fn is_hidden_comment(line: &str, marker: &str) -> bool {
+    line.trim().strip_prefix("// ") == Some(marker)
+}
+
+// The opening code fence along with any attributes (comma separated)
+fn fence(attrs: &[FenceAttr]) -> String {
+    let attrs: Vec<_> = attrs.iter().map(|attr| attr.as_str()).collect();
+    format!(" ```{}", attrs.join(","))
+}
+
+// Split the source code into the header of its `main` function (if there is one and it should be
+// removed) and the lines of code inside it
+fn to_source_lines(src: &str, gen_main: bool) -> (Vec<&str>, Vec<&str>) {
+    // Split string source code into lines
+    let mut lines: Vec<_> = src.lines().collect();
+
+    // Remove `fn main () {` (and any attributes), if we added it
+    match main_header_len(&lines) {
+        Some((len, empty)) if gen_main => {
+            let mut body = lines.split_off(len);
+            if empty {
+                // Panic safety: the header always ends with the signature (`fn main() {}`)
+                let sig = lines.last_mut().unwrap();
+                *sig = sig.trim_end().strip_suffix('}').unwrap_or(sig);
+            } else {
+                // Remove the trailing `}`
+                body.pop();
+            }
+            (lines, body)
+        }
+        _ => (Vec::new(), lines),
+    }
+}
+
+fn assemble_doc_test(
+    lines: Vec<&str>,
+    cap: usize,
+    prefix: String,
+    filters: &[LineFilter],
+) -> String {
+    // Unlikely to be this big, but better than reallocating
+    let mut buffer = String::with_capacity(cmp::max(cap * 2, MIN_BUFF_SIZE));
+
+    let mut hidden = false;
+
+    // Build code from lines
+    'lines: for line in lines {
+        if is_hidden_comment(line, HIDDEN_START) {
+            hidden = true;
+            continue;
+        } else if is_hidden_comment(line, HIDDEN_END) {
+            hidden = false;
+            continue;
+        }
+
+        // Strip whitespace left over from main, if any (else noop)
+        let mut line = Cow::Borrowed(line.strip_prefix(&prefix).unwrap_or(line));
+
+        for filter in filters {
+            match filter(&line) {
+                Some(filtered) => line = Cow::Owned(filtered),
+                None => continue 'lines,
+            }
+        }
+
+        if hidden {
+            buffer.push_str(if line.is_empty() { " #" } else { " # " });
+        // Except for empty lines, all lines should get a space at the front
+        } else if !line.is_empty() {
+            buffer.push(' ');
+        }
+        buffer.push_str(&line);
+        buffer.push('\n');
+    }
+
+    buffer
+}
+
+// *** Examples ***
+
+/// Creates the source code of a standalone runnable example (such as one in the `examples`
+/// directory) from the same tokens and options given to [doc_test], so a doctest and an example
+/// can be generated from one definition. The main function the options generate is kept (a plain
+/// `fn main()` is generated even if the options don't generate one, unless they only format), and
+/// hidden code is included like any other code. Fence attributes, expected output, and line
+/// filters are ignored. The formatter in the options is used (or the default formatter, if they
+/// don't format)
+///
+/// ```
+/// use quote::quote;
+/// use quote_doctest::{make_example, DocTestOptions};
+///
+/// let code = quote! {
+///     _hidden_!(let x = 5;);
+///     assert_eq!(x, 5);
+/// };
+/// let actual = make_example(code, DocTestOptions::default()).unwrap();
+///
+/// assert_eq!("fn main() {\n    let x = 5;\n    assert_eq!(x, 5);\n}\n", actual);
+/// ```
+pub fn make_example(
+    tokens: TokenStream,
+    options: impl Into<DocTestOptions>,
+) -> Result<String, Error> {
+    let options = options.into().settings();
+
+    // Code that is only formatted must already have its own main function
+    let tokens = match (&options.fmt, options.gen_main) {
+        (Some(_), GenMain::No) => tokens,
+        (_, GenMain::No) => wrap_main(tokens, &GenMain::Strip),
+        (_, gen_main) => wrap_main(tokens, &gen_main),
+    };
+
+    let fmt = options.fmt.unwrap_or_default();
+    let src = tokens_to_string(mark_hidden(tokens), Some(&fmt))?;
+
+    Ok(src
+        .lines()
+        .filter(|line| {
+            !is_hidden_comment(line, HIDDEN_START) && !is_hidden_comment(line, HIDDEN_END)
+        })
+        .flat_map(|line| [line, "\n"])
+        .collect())
+}
+
+/// Writes a standalone runnable example created by [make_example] to `examples/<name>.rs` inside
+/// the given crate directory (creating the `examples` directory, if needed). The path of the
+/// example file is returned
+pub fn write_example(
+    crate_dir: impl AsRef<Path>,
+    name: &str,
+    tokens: TokenStream,
+    options: impl Into<DocTestOptions>,
+) -> Result<PathBuf, Error> {
+    let src = make_example(tokens, options)?;
+
+    let dir = crate_dir.as_ref().join("examples");
+    fs::create_dir_all(&dir)?;
+    let path = dir.join(format!("{name}.rs"));
+    fs::write(&path, src)?;
+    Ok(path)
+}
+
+#[cfg(not(feature = "pretty_please"))]
+#[inline]
+fn doc_test_formatter() -> Formatter {
+    Formatter::new_rust_fmt_doc_blocks()
+}
+
+#[cfg(feature = "pretty_please")]
+#[inline]
+fn doc_test_formatter() -> Formatter {
+    Formatter::PrettyPlease(rust_format::PrettyPlease::default())
+}
+
+/// Trait for converting [doc_test] results into a well formatted `String`
+pub trait FormatDocTest: ToTokens {
+    /// Convert results of a [doc_test] (or any other value that implements `ToTokens` that is valid
+    /// Rust source) into a formatted `String`. This will also convert doc blocks (`#[doc = ""]`) into
+    /// doc comments (`///`). This can be useful for display or equality testing in a unit test. An
+    /// error is returned if an issue occurs during the formatting process
+    ///
+    /// NOTE: If `pretty_please` is not enabled then `rustfmt` will be used via the `rust_format` crate
+    /// and when translating doc blocks will also translate any [`_comment_!`] or [`_blank_!`] markers.
+    /// If the source of this function came from [doc_test], these will already be translated anyway,
+    /// but this is mentioned for awareness.
+    fn format_tokens(self) -> Result<String, Error>
+    where
+        Self: Sized,
+    {
+        self.format_tokens_with(&doc_test_formatter())
+    }
+
+    /// The same as [format_tokens](FormatDocTest::format_tokens), except the given formatter is
+    /// always used (regardless of which features are enabled), so the results are the same for every
+    /// feature combination. The formatter's own settings are used, so to convert doc blocks to doc
+    /// comments with `rustfmt` it must post process them (as
+    /// [new_rust_fmt_doc_blocks](Formatter::new_rust_fmt_doc_blocks) does)
+    ///
+    /// ```
+    /// use quote::quote;
+    /// use quote_doctest::{doc_test, FormatDocTest, Formatter};
+    ///
+    /// let actual = doc_test!(quote! { let x = 5; }).unwrap();
+    /// let actual = actual.format_tokens_with(&Formatter::new_rust_fmt_doc_blocks()).unwrap();
+    ///
+    /// assert_eq!("/// ```\n/// let x = 5;\n/// ```\n", actual);
+    /// ```
+    fn format_tokens_with(self, fmt: &Formatter) -> Result<String, Error>
+    where
+        Self: Sized,
+    {
+        // We need a function - doc blocks alone won't pass the formatter
+        let doc_test = quote! {
+            #self
+            fn main() {}
+        };
+
+        // Format (and translate doc blocks to doc comments
+        let source = tokens_to_string(doc_test, Some(fmt))?;
+
+        // Convert into lines so we trim off the last line (our added main function)
+        let mut lines: Vec<_> = source.lines().collect();
+        // All in one line because there will never be anything inside
+        lines.pop(); // fn main() {}
+
+        // Unlikely to be this big, but better than reallocating
+        let mut buffer = String::with_capacity(cmp::max(source.len() * 2, MIN_BUFF_SIZE));
+
+        for line in lines {
+            buffer.push_str(line);
+            buffer.push('\n');
+        }
+
+        buffer.shrink_to_fit();
+        Ok(buffer)
+    }
+}
+
+impl<T> FormatDocTest for T where T: ToTokens {}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use quote::quote;
+
+    use crate::{
+        doc_comment_from_file, doc_comment_wrapped, doc_list, doc_table, doc_test_str,
+        tokens_to_string, write_example, DocBuilder, DocCommentBuilder, DocTestOptions, Edition,
+        Error, ErrorKind, FenceAttr, FormatDocTest, Formatter, GenMain, FORMATTER_INDENT,
+    };
+
+    #[test]
+    fn doctest_format() {
+        let actual = quote! {
+            /// ```
+            /// assert_eq!(fibonacci(10), 55);
+            /// assert_eq!(fibonacci(1), 1);
+            /// ```
+        };
+
+        let expected = r#"/// ```
 /// assert_eq!(fibonacci(10), 55);
 /// assert_eq!(fibonacci(1), 1);
 /// ```
 "#;
 
-        assert_eq!(expected, actual.format_tokens().unwrap());
+        assert_eq!(expected, actual.format_tokens().unwrap());
+    }
+
+    #[test]
+    fn wrapped_comment() {
+        let text = "A ``code `span` that`` is not split, and a_very_long_word.\n\n```\nlet s = \"Code blocks are kept as is\";\n```\n";
+        let actual = doc_comment_wrapped(text, 12);
+        let expected = quote! {
+            /// A
+            /// ``code `span` that``
+            /// is not
+            /// split, and
+            /// a_very_long_word.
+            ///
+            /// ```
+            /// let s = "Code blocks are kept as is";
+            /// ```
+        };
+
+        assert_eq!(
+            expected.format_tokens().unwrap(),
+            actual.format_tokens().unwrap()
+        );
+    }
+
+    // Headings, list items, and indented code are never joined with the surrounding text
+    #[test]
+    fn wrapped_comment_structure() {
+        let text = "# Errors\nText\n- a\n- b\n\n    let x = 5;\n    let y = 6;\n\n1. A numbered item that wraps";
+        let actual = doc_comment_wrapped(text, 16);
+        let expected = quote! {
+            /// # Errors
+            /// Text
+            /// - a
+            /// - b
+            ///
+            ///     let x = 5;
+            ///     let y = 6;
+            ///
+            /// 1. A numbered
+            ///    item that
+            ///    wraps
+        };
+
+        assert_eq!(
+            expected.format_tokens().unwrap(),
+            actual.format_tokens().unwrap()
+        );
+    }
+
+    #[test]
+    fn filter_lines() {
+        let options = || {
+            DocTestOptions::builder()
+                .filter_lines(|line| (!line.contains("internal")).then(|| line.to_string()))
+                .filter_lines(|line| Some(line.to_uppercase()))
+        };
+
+        let code = quote! {
+            _hidden_!(let a = internal();  let b = 1;);
+            let c = b;
+        };
+        let actual = doc_test!(code, options()).unwrap();
+        let expected = quote! {
+            /// ```
+            /// # LET B = 1;
+            /// LET C = B;
+            /// ```
+        };
+        assert_eq!(
+            expected.format_tokens().unwrap(),
+            actual.format_tokens().unwrap()
+        );
+
+        let actual = doc_test_str("fn main() {\n    internal();\n    run();\n}", options());
+        let expected = quote! {
+            /// ```
+            /// RUN();
+            /// ```
+        };
+        assert_eq!(
+            expected.format_tokens().unwrap(),
+            actual.format_tokens().unwrap()
+        );
+    }
+
+    #[test]
+    fn expect_output() {
+        let code = quote! {
+            let x = 5;
+            println!("{x}");
+        };
+
+        let options = DocTestOptions::builder()
+            .gen_main(GenMain::Fallible)
+            .expect_output("5\n");
+        let actual = doc_test!(code, options).unwrap();
+        let expected = quote! {
+            /// ```
+            /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+            /// # let mut __output = String::new();
+            /// # macro_rules! print { ($($arg:tt)*) => { ::std::fmt::Write::write_fmt(&mut __output, format_args!($($arg)*)).unwrap() }; }
+            /// # macro_rules! println { () => { print!("\n") }; ($($arg:tt)*) => {{ print!($($arg)*); print!("\n"); }}; }
+            /// let x = 5;
+            /// println!("{x}");
+            /// # assert_eq!(__output, "5\n");
+            /// # Ok(())
+            /// # }
+            /// ```
+        };
+
+        assert_eq!(
+            expected.format_tokens().unwrap(),
+            actual.format_tokens().unwrap()
+        );
+    }
+
+    #[cfg(feature = "pretty_please")]
+    #[test]
+    fn format_tokens_with() {
+        let actual = doc_test!(quote! { let x = 5; }).unwrap();
+        let expected = "/// ```\n/// let x = 5;\n/// ```\n";
+
+        let rust_fmt = Formatter::new_rust_fmt_doc_blocks();
+        assert_eq!(
+            expected,
+            actual.clone().format_tokens_with(&rust_fmt).unwrap()
+        );
+        let pretty_please = Formatter::new_pretty_please();
+        assert_eq!(expected, actual.format_tokens_with(&pretty_please).unwrap());
+    }
+
+    #[cfg(feature = "source_macro")]
+    #[test]
+    fn source_macro() {
+        let actual = crate::doc_test_source! { let x = 5;
+            // Indented less than the first line
+            assert_eq!(x, 5);
+
+
+        };
+        let expected = quote! {
+            /// ```
+            /// let x = 5;
+            /// // Indented less than the first line
+            /// assert_eq!(x, 5);
+            /// ```
+        };
+
+        assert_eq!(
+            expected.format_tokens().unwrap(),
+            actual.format_tokens().unwrap()
+        );
+    }
+
+    #[test]
+    fn example_file() {
+        let code = quote! {
+            _hidden_!(let x: u32 = "5".parse()?;);
+            assert_eq!(x, 5);
+        };
+        let options = DocTestOptions::builder()
+            .gen_main(GenMain::Fallible)
+            .fence_attrs([FenceAttr::NoRun])
+            .expect_output("");
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_example(dir.path(), "parse", code, options).unwrap();
+        let actual = std::fs::read_to_string(&path).unwrap();
+
+        assert_eq!(dir.path().join("examples").join("parse.rs"), path);
+        let expected = r#"fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let x: u32 = "5".parse()?;
+    assert_eq!(x, 5);
+    Ok(())
+}
+"#;
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn doc_builder() {
+        let source = "fn main() {\n    // Kept\n    let y = 2;\n}\n";
+
+        let actual = DocBuilder::new()
+            .code_with(quote! { let x = 1; }, DocTestOptions::NoFormatOrGenMain)
+            .text("Between\n\n")
+            .code_str(source, DocTestOptions::default())
+            .build()
+            .unwrap();
+        let expected = quote! {
+            /// ```
+            /// let x = 1 ;
+            /// ```
+            ///
+            /// Between
+            ///
+            /// ```
+            /// // Kept
+            /// let y = 2;
+            /// ```
+        };
+
+        assert_eq!(
+            expected.format_tokens().unwrap(),
+            actual.format_tokens().unwrap()
+        );
+    }
+
+    #[test]
+    fn comment_from_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("comment.md");
+        std::fs::write(
+            &path,
+            "\u{feff}# Title  \r\n\r\nSome text\t\r\nmore\r\n\r\n\r\n",
+        )
+        .unwrap();
+
+        let actual = doc_comment_from_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let expected = quote! {
+            /// # Title
+            ///
+            /// Some text
+            /// more
+        };
+        assert_eq!(
+            expected.format_tokens().unwrap(),
+            actual.format_tokens().unwrap()
+        );
+
+        let err = doc_comment_from_file(&path).unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::IOError(_)));
+    }
+
+    #[test]
+    fn list_and_table() {
+        let actual = doc_list(["One\n", "Two\nlines"]);
+        let expected = quote! {
+            /// - One
+            /// - Two
+            ///   lines
+        };
+        assert_eq!(
+            expected.format_tokens().unwrap(),
+            actual.format_tokens().unwrap()
+        );
+
+        let rows = [vec!["a\nb", "|", "extra"], vec!["ü"]];
+        let actual = doc_table(["A", "B"], rows);
+        let expected = quote! {
+            /// | A   | B   |
+            /// |-----|-----|
+            /// | a b | \|  |
+            /// | ü   |     |
+        };
+        assert_eq!(
+            expected.format_tokens().unwrap(),
+            actual.format_tokens().unwrap()
+        );
+    }
+
+    #[test]
+    fn doc_comment_builder() {
+        let first = doc_test!(quote! { let x = 1; }).unwrap();
+        let second = doc_test!(quote! { let y = 2; }).unwrap();
+
+        let actual = DocCommentBuilder::new("Summary")
+            .description("Line one\nLine two")
+            .errors("Errors")
+            .safety("Safety")
+            .example(first)
+            .example(second)
+            .build();
+        let expected = quote! {
+            /// Summary
+            ///
+            /// Line one
+            /// Line two
+            ///
+            /// # Errors
+            ///
+            /// Errors
+            ///
+            /// # Safety
+            ///
+            /// Safety
+            ///
+            /// # Examples
+            ///
+            /// ```
+            /// let x = 1;
+            /// ```
+            ///
+            /// ```
+            /// let y = 2;
+            /// ```
+        };
+
+        assert_eq!(
+            expected.format_tokens().unwrap(),
+            actual.format_tokens().unwrap()
+        );
     }
 
     #[test]
@@ -479,6 +2102,294 @@ mod tests {
         );
     }
 
+    #[test]
+    fn fence_attrs() {
+        let code = quote! {
+            assert_eq!(fibonacci(10), 55);
+        };
+
+        let options = DocTestOptions::NoFormatOrGenMain
+            .fence(FenceAttr::ShouldPanic)
+            .fence(FenceAttr::Edition(Edition::Rust2021));
+        let actual = doc_test!(code, options).unwrap();
+        let expected = quote! {
+            /// ```should_panic,edition2021
+            /// assert_eq ! (fibonacci (10) , 55) ;
+            /// ```
+        };
+
+        assert_eq!(
+            expected.format_tokens().unwrap(),
+            actual.format_tokens().unwrap()
+        );
+    }
+
+    #[test]
+    fn builder() {
+        let code = quote! {
+            assert_eq!(fibonacci(10), 55);
+        };
+
+        // Without formatting, main can't be stripped so it is never generated
+        let options = DocTestOptions::builder()
+            .no_format()
+            .gen_main(GenMain::Strip)
+            .fence_attrs([FenceAttr::Ignore]);
+        let actual = doc_test!(code.clone(), options).unwrap();
+        let expected = quote! {
+            /// ```ignore
+            /// assert_eq ! (fibonacci (10) , 55) ;
+            /// ```
+        };
+        assert_eq!(
+            expected.format_tokens().unwrap(),
+            actual.format_tokens().unwrap()
+        );
+
+        let options = DocTestOptions::builder()
+            .formatter(Formatter::new_rust_fmt())
+            .strip_indent(FORMATTER_INDENT)
+            .build()
+            .fence(FenceAttr::NoRun);
+        let actual = doc_test!(code, options).unwrap();
+        let expected = quote! {
+            /// ```no_run
+            /// assert_eq!(fibonacci(10), 55);
+            /// ```
+        };
+        assert_eq!(
+            expected.format_tokens().unwrap(),
+            actual.format_tokens().unwrap()
+        );
+    }
+
+    #[test]
+    fn rustfmt_hidden_marker() {
+        hidden_marker(Formatter::new_rust_fmt());
+    }
+
+    #[cfg(feature = "pretty_please")]
+    #[test]
+    fn prettyplz_hidden_marker() {
+        hidden_marker(Formatter::new_pretty_please());
+    }
+
+    fn hidden_marker(fmt: Formatter) {
+        let code = quote! {
+            _hidden_!(
+                fn fibonacci(n: u64) -> u64 {
+                    n
+                }
+            );
+            assert_eq!(fibonacci(10), 10);
+            if true {
+                _hidden_!(let x = 1;);
+                assert_eq!(x, 1);
+            }
+        };
+
+        let actual = doc_test!(
+            code,
+            DocTestOptions::FormatAndGenMain(fmt, FORMATTER_INDENT)
+        )
+        .unwrap();
+
+        let expected = quote! {
+            /// ```
+            /// # fn fibonacci(n: u64) -> u64 {
+            /// #     n
+            /// # }
+            /// assert_eq!(fibonacci(10), 10);
+            /// if true {
+            /// #     let x = 1;
+            ///     assert_eq!(x, 1);
+            /// }
+            /// ```
+        };
+
+        assert_eq!(
+            expected.format_tokens().unwrap(),
+            actual.format_tokens().unwrap()
+        );
+    }
+
+    #[test]
+    fn unformatted_hidden_marker() {
+        let code = quote! {
+            _hidden_!(let x = 1;);
+            assert_eq!(x, 1);
+        };
+
+        let actual = doc_test!(code, DocTestOptions::NoFormatOrGenMain).unwrap();
+        let expected = quote! {
+            /// ```
+            /// # let x = 1 ;
+            /// assert_eq ! (x , 1) ;
+            /// ```
+        };
+
+        assert_eq!(
+            expected.format_tokens().unwrap(),
+            actual.format_tokens().unwrap()
+        );
+    }
+
+    #[test]
+    fn source_str() {
+        let source = "// Not wrapped in main\nlet x = 5;\n\n\nassert_eq!(x, 5);\n";
+
+        let options = DocTestOptions::NoFormatOrGenMain.fence(FenceAttr::NoRun);
+        let actual = doc_test_str(source, options);
+        let expected = r#"/// ```no_run
+/// // Not wrapped in main
+/// let x = 5;
+///
+///
+/// assert_eq!(x, 5);
+/// ```
+"#;
+        assert_eq!(expected, actual.format_tokens().unwrap());
+
+        // Without a `main` function there is nothing to strip
+        let actual = doc_test_str(source, DocTestOptions::default());
+        assert!(actual
+            .format_tokens()
+            .unwrap()
+            .contains("/// // Not wrapped in main\n"));
+    }
+
+    #[test]
+    fn detect_indent() {
+        let source = "fn main() {\n\n\tif true {\n\t\tlet x = 5;\n\t}\n}\n";
+
+        let actual = doc_test_str(source, DocTestOptions::default());
+        let expected = "/// ```\n///\n/// if true {\n/// \tlet x = 5;\n/// }\n/// ```\n";
+        assert_eq!(expected, actual.format_tokens().unwrap());
+    }
+
+    #[test]
+    fn rustfmt_hard_tabs() {
+        let config = rust_format::Config::new_str()
+            .option("hard_tabs", "true")
+            .post_proc(rust_format::PostProcess::ReplaceMarkers);
+        let fmt = Formatter::RustFmt(rust_format::RustFmt::from_config(config));
+        let code = quote! {
+            if true {
+                assert_eq!(fibonacci(1), 1);
+            }
+        };
+
+        let options = DocTestOptions::builder().formatter(fmt);
+        let actual = doc_test!(code, options).unwrap();
+        let expected =
+            "/// ```\n/// if true {\n/// \tassert_eq!(fibonacci(1), 1);\n/// }\n/// ```\n";
+        assert_eq!(expected, actual.format_tokens().unwrap());
+    }
+
+    // An empty main is formatted onto a single line, but is still stripped (or hidden)
+    #[test]
+    fn empty_main() {
+        let empty = quote! {
+            /// ```
+            /// ```
+        }
+        .format_tokens()
+        .unwrap();
+        let actual = doc_test!(quote! {}).unwrap();
+        assert_eq!(empty, actual.format_tokens().unwrap());
+
+        let actual = doc_test_str("fn main() {}\n", DocTestOptions::default());
+        assert_eq!(empty, actual.format_tokens().unwrap());
+
+        let options = DocTestOptions::builder().gen_main(GenMain::Async(quote! { #[tokio::main] }));
+        let actual = doc_test!(quote! {}, options).unwrap();
+        let expected = quote! {
+            /// ```
+            /// # #[tokio::main]
+            /// # async fn main() {
+            /// # }
+            /// ```
+        };
+        assert_eq!(
+            expected.format_tokens().unwrap(),
+            actual.format_tokens().unwrap()
+        );
+    }
+
+    #[test]
+    fn rustfmt_fallible_main() {
+        fallible_main(Formatter::new_rust_fmt());
+    }
+
+    #[cfg(feature = "pretty_please")]
+    #[test]
+    fn prettyplz_fallible_main() {
+        fallible_main(Formatter::new_pretty_please());
+    }
+
+    fn fallible_main(fmt: Formatter) {
+        let code = quote! {
+            let x: u32 = "5".parse()?;
+            assert_eq!(x, 5);
+        };
+
+        let options = DocTestOptions::builder()
+            .formatter(fmt)
+            .gen_main(GenMain::Fallible);
+        let actual = doc_test!(code, options).unwrap();
+        let expected = quote! {
+            /// ```
+            /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+            /// let x: u32 = "5".parse()?;
+            /// assert_eq!(x, 5);
+            /// # Ok(())
+            /// # }
+            /// ```
+        };
+
+        assert_eq!(
+            expected.format_tokens().unwrap(),
+            actual.format_tokens().unwrap()
+        );
+    }
+
+    #[test]
+    fn rustfmt_async_main() {
+        async_main(Formatter::new_rust_fmt());
+    }
+
+    #[cfg(feature = "pretty_please")]
+    #[test]
+    fn prettyplz_async_main() {
+        async_main(Formatter::new_pretty_please());
+    }
+
+    fn async_main(fmt: Formatter) {
+        let code = quote! {
+            let body = fetch().await;
+            assert_eq!(body, "ok");
+        };
+
+        let options = DocTestOptions::builder()
+            .formatter(fmt)
+            .gen_main(GenMain::Async(quote! { #[async_std::main] }));
+        let actual = doc_test!(code, options).unwrap();
+        let expected = quote! {
+            /// ```
+            /// # #[async_std::main]
+            /// # async fn main() {
+            /// let body = fetch().await;
+            /// assert_eq!(body, "ok");
+            /// # }
+            /// ```
+        };
+
+        assert_eq!(
+            expected.format_tokens().unwrap(),
+            actual.format_tokens().unwrap()
+        );
+    }
+
     #[test]
     fn rustfmt_bad_source_code() {
         bad_source_code(Formatter::new_rust_fmt());
@@ -491,8 +2402,8 @@ mod tests {
     }
 
     fn bad_source_code(fmt: Formatter) {
-        match tokens_to_string(quote! {"blah blah blah"}, Some(fmt)) {
-            Err(Error::BadSourceCode(_)) => {}
+        match tokens_to_string(quote! {"blah blah blah"}, Some(&fmt)).map_err(Error::into_kind) {
+            Err(ErrorKind::BadSourceCode(_)) => {}
             _ => panic!("'rustfmt' should have failed due to bad source code"),
         }
     }
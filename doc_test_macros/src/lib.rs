@@ -0,0 +1,145 @@
+#![warn(missing_docs)]
+
+//! Procedural macros for [quote-doctest](https://docs.rs/quote-doctest). These are re-exported by
+//! that crate (when its `source_macro` feature is enabled) and should be used from there
+
+use proc_macro::{Delimiter, Group, Literal, Punct, Spacing, Span, TokenStream, TokenTree};
+
+const DEFAULT_OPTIONS: &str = "::quote_doctest::DocTestOptions::default()";
+const DOC_TEST_STR: &str = "::quote_doctest::doc_test_str";
+
+/// Creates a doctest from the literal source code of its body, so comments, blank lines, and
+/// formatting are kept exactly as they were written (no `_comment_!` or `_blank_!` markers are
+/// needed). The body is un-indented and passed to `doc_test_str` along with the options, which
+/// are the defaults unless given before the body as `options => { ... }`
+///
+/// ```
+/// use quote::quote;
+/// use quote_doctest::{doc_test_source, DocTestOptions, FenceAttr, FormatDocTest};
+///
+/// let actual = doc_test_source! {
+///     // This comment is kept
+///     let x = 5;
+///
+///     assert_eq!(x, 5);
+/// };
+/// let expected = quote! {
+///     /// ```
+///     /// // This comment is kept
+///     /// let x = 5;
+///     ///
+///     /// assert_eq!(x, 5);
+///     /// ```
+/// };
+/// assert_eq!(expected.format_tokens().unwrap(), actual.format_tokens().unwrap());
+///
+/// let actual = doc_test_source!(DocTestOptions::default().fence(FenceAttr::NoRun) => {
+///     loop {}
+/// });
+/// let expected = quote! {
+///     /// ```no_run
+///     /// loop {}
+///     /// ```
+/// };
+/// assert_eq!(expected.format_tokens().unwrap(), actual.format_tokens().unwrap());
+/// ```
+///
+/// NOTE: The source text is only available when the macro is invoked directly in a source file.
+/// Otherwise (such as when invoked by another macro), the tokens are used instead, so comments and
+/// formatting are lost
+#[proc_macro]
+pub fn doc_test_source(input: TokenStream) -> TokenStream {
+    let tokens: Vec<_> = input.clone().into_iter().collect();
+
+    let (options, source) = match split_options(&tokens) {
+        Some((options, body)) => (options, group_source(body)),
+        None => (
+            DEFAULT_OPTIONS.parse().expect("Valid options tokens"),
+            invocation_source(input),
+        ),
+    };
+
+    let mut args = TokenStream::from(TokenTree::Literal(Literal::string(&unindent(&source))));
+    args.extend([TokenTree::Punct(Punct::new(',', Spacing::Alone))]);
+    args.extend(options);
+
+    let mut output: TokenStream = DOC_TEST_STR.parse().expect("Valid function path tokens");
+    output.extend([TokenTree::Group(Group::new(Delimiter::Parenthesis, args))]);
+    output
+}
+
+// Split `options => { ... }` into the options and the body (if in that form)
+fn split_options(tokens: &[TokenTree]) -> Option<(TokenStream, &Group)> {
+    match tokens {
+        [options @ .., TokenTree::Punct(eq), TokenTree::Punct(gt), TokenTree::Group(body)]
+            if !options.is_empty()
+                && eq.as_char() == '='
+                && eq.spacing() == Spacing::Joint
+                && gt.as_char() == '>'
+                && body.delimiter() == Delimiter::Brace =>
+        {
+            Some((options.iter().cloned().collect(), body))
+        }
+        _ => None,
+    }
+}
+
+// The source text inside the group's delimiters
+fn group_source(group: &Group) -> String {
+    match group.span().source_text() {
+        Some(text) if text.len() >= 2 => text[1..text.len() - 1].to_string(),
+        _ => group.stream().to_string(),
+    }
+}
+
+// The source text inside the delimiters of the macro invocation
+fn invocation_source(input: TokenStream) -> String {
+    let text = Span::call_site().source_text().and_then(|text| {
+        let bang = text.find('!')?;
+        let start = bang + text[bang..].find(['{', '(', '['])?;
+        // The closing delimiter is always the last character of the invocation
+        Some(text[start + 1..text.len() - 1].to_string())
+    });
+
+    text.unwrap_or_else(|| input.to_string())
+}
+
+// Remove leading and trailing blank lines and the indentation common to every line (except a first
+// line that started on the same line as the opening delimiter)
+fn unindent(source: &str) -> String {
+    // A first line that starts right after the delimiter has no meaningful indentation
+    let skip_first = source
+        .lines()
+        .next()
+        .is_some_and(|line| !line.trim().is_empty());
+
+    let mut lines: Vec<_> = source.lines().map(str::trim_end).collect();
+    while lines.last().is_some_and(|line| line.is_empty()) {
+        lines.pop();
+    }
+    let start = lines.iter().position(|line| !line.is_empty());
+    let lines = &mut lines[start.unwrap_or(0)..];
+    if skip_first {
+        lines[0] = lines[0].trim_start();
+    }
+
+    let indent = lines
+        .iter()
+        .skip(skip_first as usize)
+        .filter(|line| !line.is_empty())
+        .map(|line| line.len() - line.trim_start_matches([' ', '\t']).len())
+        .min()
+        .unwrap_or(0);
+
+    lines
+        .iter()
+        .enumerate()
+        .map(
+            |(idx, line)| match line.is_empty() || (skip_first && idx == 0) {
+                true => *line,
+                false => &line[indent..],
+            },
+        )
+        .collect::<Vec<_>>()
+        .join("\n")
+}
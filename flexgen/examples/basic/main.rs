@@ -1,13 +1,13 @@
 use flexgen::config::Config;
+use flexgen::doctest::attach_doc_test;
 use flexgen::var::TokenVars;
 use flexgen::{import_vars, register_fragments, CodeFragment, CodeGenerator, Error};
 use proc_macro2::TokenStream;
 use quote::quote;
-use quote_doctest::doc_test;
 
-struct DocTest;
+struct Function;
 
-impl CodeFragment for DocTest {
+impl CodeFragment for Function {
     fn generate(&self, vars: &TokenVars) -> Result<TokenStream, Error> {
         import_vars! { vars => fib, one };
 
@@ -16,21 +16,8 @@ impl CodeFragment for DocTest {
             assert_eq!(#fib(#one), #one);
         };
 
-        Ok(doc_test!(test)?)
-    }
-}
-
-struct Function;
-
-impl CodeFragment for Function {
-    fn generate(&self, vars: &TokenVars) -> Result<TokenStream, Error> {
-        import_vars! { vars => fib, one };
-
-        let doc_test = DocTest.generate(vars)?;
-
-        Ok(quote! {
+        let item = quote! {
             /// This will run a compare between fib inputs and the outputs
-            #doc_test
             #[inline]
             fn #fib(n: u64) -> u64 {
                 match n {
@@ -39,7 +26,9 @@ impl CodeFragment for Function {
                     n => #fib(n - 1) + #fib(n - 2),
                 }
             }
-        })
+        };
+
+        attach_doc_test(test, item)
     }
 }
 
@@ -80,5 +69,17 @@ fn main() -> Result<(), Error> {
     let fragments = register_fragments!(Function, Main);
     let config = Config::from_default_toml_file()?;
     let gen = CodeGenerator::new(fragments, config)?;
-    gen.generate_files()
+
+    // `cargo run --example basic -- list` prints the resolved configuration instead (and `graph`
+    // prints it as a Graphviz DOT graph). `timing` prints how long each file and fragment took
+    match std::env::args().nth(1).as_deref() {
+        Some("list") => print!("{}", gen.list()?),
+        Some("graph") => print!("{}", gen.list()?.to_dot()),
+        Some("timing") => print!("{}", gen.generate_files()?),
+        _ => {
+            gen.generate_files()?;
+        }
+    }
+
+    Ok(())
 }
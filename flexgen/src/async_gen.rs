@@ -0,0 +1,401 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::time::Instant;
+
+use async_trait::async_trait;
+use flexstr::SharedStr;
+use futures::future::join_all;
+use proc_macro2::TokenStream;
+use quote::quote;
+
+use crate::compare::FileStatus;
+use crate::config::{Config, Section};
+use crate::hook::Hooks;
+use crate::key::FileKey;
+use crate::list::Listing;
+use crate::sink::{FileSink, FsSink, MemorySink};
+use crate::timing::TimingReport;
+use crate::var::TokenVars;
+use crate::{CodeFragment, CodeFragments, CodeGenerator, Error, FileGenerator, FragmentPhase};
+
+// *** Async Code Fragment ***
+
+/// A map of all registered async code fragments
+pub type AsyncCodeFragments = HashMap<SharedStr, &'static (dyn AsyncCodeFragment + Send + Sync)>;
+
+/// A single code fragment whose generation can await its inputs (remote specs, database schemas,
+/// etc.). Token streams are not `Send`, so the returned futures are not either
+#[allow(unused_variables)]
+#[async_trait(?Send)]
+pub trait AsyncCodeFragment {
+    /// Generate the `use` section of the source file. See [CodeFragment::uses]
+    async fn uses(&self, vars: &TokenVars) -> Result<TokenStream, Error> {
+        Ok(quote! {})
+    }
+
+    /// Generate any portion of the source file that must be on the top. See
+    /// [CodeFragment::generate_top]
+    async fn generate_top(&self, vars: &TokenVars) -> Result<TokenStream, Error> {
+        Ok(quote! {})
+    }
+
+    /// Generate the general body of the source file. See [CodeFragment::generate]
+    async fn generate(&self, vars: &TokenVars) -> Result<TokenStream, Error> {
+        Ok(quote! {})
+    }
+
+    /// Generate the type definitions section of the source file. See
+    /// [CodeFragment::generate_types]
+    async fn generate_types(&self, vars: &TokenVars) -> Result<TokenStream, Error> {
+        Ok(quote! {})
+    }
+
+    /// Generate the impl blocks section of the source file. See [CodeFragment::generate_impls]
+    async fn generate_impls(&self, vars: &TokenVars) -> Result<TokenStream, Error> {
+        Ok(quote! {})
+    }
+
+    /// Generate the tests section of the source file. See [CodeFragment::generate_tests]
+    async fn generate_tests(&self, vars: &TokenVars) -> Result<TokenStream, Error> {
+        Ok(quote! {})
+    }
+}
+
+// *** Resolved Fragment ***
+
+// The awaited results of an async fragment for a single file, replayed through the sync pipeline
+#[derive(Default)]
+pub(crate) struct ResolvedFragment {
+    uses: TokenStream,
+    top: TokenStream,
+    types: TokenStream,
+    impls: TokenStream,
+    body: TokenStream,
+    tests: TokenStream,
+}
+
+impl ResolvedFragment {
+    async fn new(
+        name: &SharedStr,
+        fragment: &(dyn AsyncCodeFragment + Send + Sync),
+        vars: &TokenVars,
+        sections: &[Section],
+    ) -> Result<Self, Error> {
+        let in_phase = |phase| move |err: Error| err.with_fragment_context(name, phase);
+
+        let mut resolved = Self {
+            uses: fragment
+                .uses(vars)
+                .await
+                .map_err(in_phase(FragmentPhase::Uses))?,
+            top: fragment
+                .generate_top(vars)
+                .await
+                .map_err(in_phase(FragmentPhase::Top))?,
+            ..Default::default()
+        };
+
+        // Only the sections the file will actually emit are generated
+        for &section in sections {
+            let (tokens, result) = match section {
+                Section::Types => (&mut resolved.types, fragment.generate_types(vars)),
+                Section::Impls => (&mut resolved.impls, fragment.generate_impls(vars)),
+                Section::Body => (&mut resolved.body, fragment.generate(vars)),
+                Section::Tests => (&mut resolved.tests, fragment.generate_tests(vars)),
+            };
+            *tokens = result.await.map_err(in_phase(section.into()))?;
+        }
+
+        Ok(resolved)
+    }
+}
+
+impl CodeFragment for ResolvedFragment {
+    #[inline]
+    fn uses(&self, _vars: &TokenVars) -> Result<TokenStream, Error> {
+        Ok(self.uses.clone())
+    }
+
+    #[inline]
+    fn generate_top(&self, _vars: &TokenVars) -> Result<TokenStream, Error> {
+        Ok(self.top.clone())
+    }
+
+    #[inline]
+    fn generate(&self, _vars: &TokenVars) -> Result<TokenStream, Error> {
+        Ok(self.body.clone())
+    }
+
+    #[inline]
+    fn generate_types(&self, _vars: &TokenVars) -> Result<TokenStream, Error> {
+        Ok(self.types.clone())
+    }
+
+    #[inline]
+    fn generate_impls(&self, _vars: &TokenVars) -> Result<TokenStream, Error> {
+        Ok(self.impls.clone())
+    }
+
+    #[inline]
+    fn generate_tests(&self, _vars: &TokenVars) -> Result<TokenStream, Error> {
+        Ok(self.tests.clone())
+    }
+}
+
+impl FileGenerator<'_> {
+    async fn resolve(&mut self, async_code: &AsyncCodeFragments) -> Result<(), Error> {
+        let sections = self.config.file_sections(self.name)?;
+
+        for (name, scope) in self.config.file_fragment_scopes(self.name)? {
+            if let Some(&fragment) = async_code.get(&name) {
+                let resolved = async {
+                    let scoped_vars = self.scoped_vars(&scope)?;
+                    let vars = scoped_vars.as_ref().unwrap_or(&self.vars);
+                    ResolvedFragment::new(&name, fragment, vars, sections).await
+                };
+
+                let resolved = resolved
+                    .await
+                    .map_err(|err| err.with_file_context(self.name, self.config))?;
+                self.resolved.insert(name, resolved);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// *** Async Code Generator ***
+
+/// A code generator that accepts both regular and async code fragments. Files are generated
+/// concurrently on the calling task: the async fragments of each file are awaited, and then the
+/// file is assembled and formatted exactly like [CodeGenerator] would
+///
+/// NOTE: Only awaiting the async fragments yields to the executor. Assembling, formatting (which
+/// may run `rustfmt` as a subprocess), and reading or writing files all block the calling task,
+/// and the returned futures are not `Send` (token streams aren't). To keep an async runtime
+/// responsive, drive the generator on its own thread instead (for example, with
+/// `futures::executor::block_on` inside `tokio::task::spawn_blocking`)
+pub struct AsyncCodeGenerator {
+    inner: CodeGenerator,
+    async_code: AsyncCodeFragments,
+}
+
+impl AsyncCodeGenerator {
+    /// Create a new instance of the `AsyncCodeGenerator`. It will validate the [Config] against
+    /// both sets of fragments and return an [Error] if there are any issues
+    pub fn new(
+        code: CodeFragments,
+        async_code: AsyncCodeFragments,
+        mut config: Config,
+    ) -> Result<Self, Error> {
+        config
+            .build_and_validate(|name| code.contains_key(name) || async_code.contains_key(name))?;
+
+        Ok(Self {
+            inner: CodeGenerator {
+                code,
+                config,
+                hooks: Hooks::default(),
+            },
+            async_code,
+        })
+    }
+
+    /// Register a hook for the given file that transforms its assembled tokens before they are
+    /// formatted. See [CodeGenerator::add_token_hook]
+    #[inline]
+    pub fn add_token_hook<F>(&mut self, name: impl FileKey, hook: F) -> Result<(), Error>
+    where
+        F: Fn(TokenStream) -> Result<TokenStream, Error> + Send + Sync + 'static,
+    {
+        self.inner.add_token_hook(name, hook)
+    }
+
+    /// Register a hook for the given file that transforms its source after it has been formatted.
+    /// See [CodeGenerator::add_string_hook]
+    #[inline]
+    pub fn add_string_hook<F>(&mut self, name: impl FileKey, hook: F) -> Result<(), Error>
+    where
+        F: Fn(String) -> Result<String, Error> + Send + Sync + 'static,
+    {
+        self.inner.add_string_hook(name, hook)
+    }
+
+    /// Return the fully resolved configuration. See [CodeGenerator::list]
+    #[inline]
+    pub fn list(&self) -> Result<Listing, Error> {
+        Listing::new(
+            self.inner.code.keys().chain(self.async_code.keys()),
+            &self.inner.config,
+        )
+    }
+
+    // The fragments are awaited, but `f` (assembly, formatting, and any file I/O) runs synchronously
+    async fn generate_async<T, F>(&self, f: F) -> Result<HashMap<SharedStr, T>, Error>
+    where
+        F: Fn(&FileGenerator) -> Result<(SharedStr, T), Error>,
+    {
+        let config = &self.inner.config;
+        let f = &f;
+
+        let results = join_all(config.file_names().into_iter().map(|name| async move {
+            let result = async {
+                let mut file_gen = FileGenerator::new(name, &self.inner)?;
+                file_gen.resolve(&self.async_code).await?;
+                f(&file_gen)
+            };
+
+            result
+                .await
+                .map_err(|err| err.with_file_context(name, config))
+        }))
+        .await;
+
+        let results: HashMap<_, _> = CodeGenerator::parse_results(results)?.into_iter().collect();
+        Ok(results)
+    }
+
+    /// Generate the files listed in the [Config], but return them as a map of strings instead of
+    /// actually writing them to the filesystem. Formatting blocks the calling task (see
+    /// [AsyncCodeGenerator])
+    #[inline]
+    pub async fn generate_strings(&self) -> Result<HashMap<SharedStr, String>, Error> {
+        self.generate_async(|file_gen| file_gen.generate_string())
+            .await
+    }
+
+    /// Generate the files listed in the [Config] and compare each one against the file currently
+    /// on disk. See [CodeGenerator::compare]. Formatting and reading the files block the calling
+    /// task (see [AsyncCodeGenerator])
+    #[inline]
+    pub async fn compare(&self) -> Result<HashMap<SharedStr, FileStatus>, Error> {
+        self.generate_async(|file_gen| file_gen.compare_file())
+            .await
+    }
+
+    /// Generate the files listed in the [Config] into the given [FileSink]. It returns a
+    /// [TimingReport] like [CodeGenerator::generate_files_to] (the time spent awaiting async
+    /// fragments is only included in the total). Formatting and writing the files block the
+    /// calling task (see [AsyncCodeGenerator])
+    pub async fn generate_files_to(&self, sink: &dyn FileSink) -> Result<TimingReport, Error> {
+        let start = Instant::now();
+        let files = self
+            .generate_async(|file_gen| file_gen.generate_file(sink))
+            .await?;
+        Ok(TimingReport::new(
+            files.into_values().collect(),
+            start.elapsed(),
+        ))
+    }
+
+    /// Generate the files listed in the [Config] into memory instead of the filesystem. See
+    /// [CodeGenerator::generate_in_memory]. Formatting blocks the calling task (see
+    /// [AsyncCodeGenerator])
+    pub async fn generate_in_memory(&self) -> Result<BTreeMap<PathBuf, String>, Error> {
+        let sink = MemorySink::new();
+        self.generate_files_to(&sink).await?;
+        Ok(sink.into_files())
+    }
+
+    /// Generate the files listed in the [Config]. It returns a [TimingReport] like
+    /// [CodeGenerator::generate_files]. Formatting and writing the files block the calling task
+    /// (see [AsyncCodeGenerator])
+    #[inline]
+    pub async fn generate_files(&self) -> Result<TimingReport, Error> {
+        self.generate_files_to(&FsSink).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+    use std::path::Path;
+
+    use futures::executor::block_on;
+    use pretty_assertions::assert_eq;
+    use proc_macro2::TokenStream;
+    use quote::quote;
+
+    use crate::async_gen::{AsyncCodeFragment, AsyncCodeFragments, AsyncCodeGenerator};
+    use crate::config::Config;
+    use crate::var::TokenVars;
+    use crate::{make_key, register_fragments, CodeFragment, Error};
+
+    struct Blocking;
+
+    impl CodeFragment for Blocking {
+        fn generate(&self, _vars: &TokenVars) -> Result<TokenStream, Error> {
+            Ok(quote! { fn blocking() {} })
+        }
+    }
+
+    struct Fetched;
+
+    #[async_trait(?Send)]
+    impl AsyncCodeFragment for Fetched {
+        async fn generate_types(&self, _vars: &TokenVars) -> Result<TokenStream, Error> {
+            let name = futures::future::ready(quote! { Fetched }).await;
+            Ok(quote! { struct #name; })
+        }
+
+        async fn generate(&self, _vars: &TokenVars) -> Result<TokenStream, Error> {
+            Ok(quote! { fn fetched() {} })
+        }
+    }
+
+    const CONFIG: &str = r#"
+        [fragment_lists]
+        all = [ "blocking", "fetched" ]
+
+        [files.test]
+        path = "test.rs"
+        fragment_list = "all"
+        layout = [ "sections" ]
+        vars = {}
+    "#;
+
+    fn generator() -> AsyncCodeGenerator {
+        let code = register_fragments!(Blocking);
+        let mut async_code = AsyncCodeFragments::new();
+        async_code.insert(make_key("Fetched"), &Fetched);
+
+        let config = Config::from_toml_reader(CONFIG.as_bytes()).unwrap();
+        AsyncCodeGenerator::new(code, async_code, config).unwrap()
+    }
+
+    #[test]
+    fn generate_strings() {
+        let gen = generator();
+        let mut files = block_on(gen.generate_strings()).unwrap();
+
+        assert_eq!(
+            "struct Fetched;\n\nfn blocking() {}\n\nfn fetched() {}\n",
+            files.remove(&make_key("test")).unwrap()
+        );
+    }
+
+    // The generator itself can be moved to a dedicated thread to keep the blocking work off an
+    // async runtime
+    #[test]
+    fn generate_on_thread() {
+        let gen = generator();
+        let handle = std::thread::spawn(move || block_on(gen.generate_strings()));
+        let files = handle.join().unwrap().unwrap();
+
+        assert!(files[&make_key("test")].contains("struct Fetched;"));
+    }
+
+    #[test]
+    fn hooks_in_memory() {
+        let mut gen = generator();
+        gen.add_string_hook(make_key("test"), |source| {
+            Ok(format!("// Hooked\n{source}"))
+        })
+        .unwrap();
+
+        let files = block_on(gen.generate_in_memory()).unwrap();
+        let expected = "// Hooked\nstruct Fetched;\n\nfn blocking() {}\n\nfn fetched() {}\n";
+        assert_eq!(Some(&expected.to_string()), files.get(Path::new("test.rs")));
+    }
+}
@@ -0,0 +1,61 @@
+use std::io;
+use std::path::Path;
+
+use similar::TextDiff;
+
+use crate::Error;
+
+// *** File Status ***
+
+/// The status of a single generated file when compared against the file currently on disk
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FileStatus {
+    /// The file on disk is identical to the generated source
+    Unchanged,
+    /// The file does not yet exist on disk, but its directory does (generation would add it)
+    Added,
+    /// The file on disk differs from the generated source. It contains a unified diff from the
+    /// file on disk to the generated source
+    Modified {
+        /// A unified diff from the current file to the generated source
+        diff: String,
+    },
+    /// Neither the file nor its directory exist on disk (generation would fail to write it)
+    Missing,
+}
+
+impl FileStatus {
+    pub(crate) fn new(path: &Path, source: &str) -> Result<Self, Error> {
+        match std::fs::read_to_string(path) {
+            Ok(current) if current == source => Ok(FileStatus::Unchanged),
+            Ok(current) => {
+                let name = path.display().to_string();
+                let diff = TextDiff::from_lines(current.as_str(), source)
+                    .unified_diff()
+                    .header(&name, &name)
+                    .to_string();
+                Ok(FileStatus::Modified { diff })
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                let dir_exists = match path.parent() {
+                    Some(parent) if !parent.as_os_str().is_empty() => parent.is_dir(),
+                    // A bare file name is relative to the current directory
+                    _ => true,
+                };
+
+                Ok(if dir_exists {
+                    FileStatus::Added
+                } else {
+                    FileStatus::Missing
+                })
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Returns true if the file on disk does not match the generated source
+    #[inline]
+    pub fn is_stale(&self) -> bool {
+        !matches!(self, FileStatus::Unchanged)
+    }
+}
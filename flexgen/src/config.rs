@@ -1,16 +1,130 @@
 use std::collections::HashMap;
 use std::io::Read;
-use std::path::{Path, PathBuf};
-use std::{fs, io};
+use std::path::{Component, Path, PathBuf};
+use std::{env, fs, io};
 
 use flexstr::SharedStr;
+use indexmap::IndexMap;
 
+use proc_macro2::TokenStream;
+use rust_format::PostProcess;
+
+use crate::key::{FileKey, FragmentKey};
 use crate::var::Vars;
-use crate::{CodeFragments, Error, TokenVars};
+use crate::{CodeFragment, Error, TokenVars};
 
 const BUF_SIZE: usize = u16::MAX as usize;
 
-const DEFAULT_FILENAME: &str = "flexgen.toml";
+pub(crate) const DEFAULT_FILENAME: &str = "flexgen.toml";
+
+const DEFAULT_SECTIONS: &[Section] = &[
+    Section::Types,
+    Section::Impls,
+    Section::Body,
+    Section::Tests,
+];
+
+const DEFAULT_LAYOUT: &[LayoutPart] = &[
+    LayoutPart::Banner,
+    LayoutPart::Top,
+    LayoutPart::Uses,
+    LayoutPart::Sections,
+];
+
+// *** Layout ***
+
+/// A part of the overall structure of a generated file. The file's `layout` option gives the order in
+/// which these are emitted (default: banner, top, uses, sections). Parts not listed are omitted
+#[derive(Clone, Copy, Debug, serde::Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum LayoutPart {
+    /// The "auto-generated" warning banner comment
+    Banner,
+    /// The output of [generate_top](CodeFragment::generate_top) from all fragments
+    Top,
+    /// The merged std, external, and crate `use` sections
+    Uses,
+    /// All the [Section]s in the order given by the file's `sections` option
+    Sections,
+}
+
+// *** Line Ending ***
+
+/// The line ending used for the final output of a generated file
+#[derive(Clone, Copy, Debug, serde::Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LineEnding {
+    /// Unix style line feed (`\n`)
+    Lf,
+    /// Windows style carriage return and line feed (`\r\n`)
+    Crlf,
+    /// The line ending of the platform flexgen is running on
+    Native,
+}
+
+impl LineEnding {
+    #[inline]
+    fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::Crlf => "\r\n",
+            LineEnding::Native if cfg!(windows) => "\r\n",
+            LineEnding::Native => "\n",
+        }
+    }
+
+    /// Convert all line endings in the given source to this line ending
+    pub fn apply(self, source: String) -> String {
+        let ending = self.as_str();
+
+        // Normalize first so existing CRLF line endings aren't doubled up
+        let source = if source.contains('\r') {
+            source.replace("\r\n", "\n")
+        } else {
+            source
+        };
+
+        if ending == "\n" {
+            source
+        } else {
+            source.replace('\n', ending)
+        }
+    }
+}
+
+// *** Section ***
+
+/// A named section of a generated file. Each section is collected from all the fragments of a file
+/// and then the sections are concatenated in the order given by the file's `sections` option
+/// (default: types, impls, body, tests). Sections not listed are not generated
+#[derive(Clone, Copy, Debug, serde::Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Section {
+    /// Output of [generate_types](CodeFragment::generate_types)
+    Types,
+    /// Output of [generate_impls](CodeFragment::generate_impls)
+    Impls,
+    /// Output of [generate](CodeFragment::generate)
+    Body,
+    /// Output of [generate_tests](CodeFragment::generate_tests)
+    Tests,
+}
+
+impl Section {
+    #[inline]
+    pub(crate) fn generate(
+        self,
+        fragment: &dyn CodeFragment,
+        vars: &TokenVars,
+    ) -> Result<TokenStream, Error> {
+        match self {
+            Section::Types => fragment.generate_types(vars),
+            Section::Impls => fragment.generate_impls(vars),
+            Section::Body => fragment.generate(vars),
+            Section::Tests => fragment.generate_tests(vars),
+        }
+    }
+}
 
 // *** FragmentItem ***
 
@@ -27,21 +141,62 @@ pub enum FragmentItem {
 
 // *** Fragment Lists ***
 
+// A fragment list is either just the list of fragments, or a table with the list and vars that
+// apply to every fragment invoked via that list
+#[derive(Clone, Debug, serde::Deserialize, PartialEq)]
+#[serde(untagged)]
+enum FragmentListDef {
+    Fragments(Vec<FragmentItem>),
+    WithVars {
+        fragments: Vec<FragmentItem>,
+        #[serde(default)]
+        vars: Vars,
+    },
+}
+
 #[derive(Clone, Debug, Default, serde::Deserialize, PartialEq)]
-struct FragmentLists(HashMap<SharedStr, Vec<FragmentItem>>);
+#[serde(from = "IndexMap<SharedStr, FragmentListDef>")]
+struct FragmentLists {
+    lists: IndexMap<SharedStr, Vec<FragmentItem>>,
+    vars: HashMap<SharedStr, Vars>,
+}
+
+impl From<IndexMap<SharedStr, FragmentListDef>> for FragmentLists {
+    fn from(defs: IndexMap<SharedStr, FragmentListDef>) -> Self {
+        let mut lists = IndexMap::with_capacity(defs.len());
+        let mut list_vars = HashMap::new();
+
+        for (name, def) in defs {
+            match def {
+                FragmentListDef::Fragments(fragments) => {
+                    lists.insert(name, fragments);
+                }
+                FragmentListDef::WithVars { fragments, vars } => {
+                    list_vars.insert(name.clone(), vars);
+                    lists.insert(name, fragments);
+                }
+            }
+        }
+
+        Self {
+            lists,
+            vars: list_vars,
+        }
+    }
+}
 
 impl FragmentLists {
     pub fn build(&self) -> Self {
-        let mut lists = HashMap::with_capacity(self.0.len());
+        let mut lists = IndexMap::with_capacity(self.lists.len());
 
-        for (key, fragments) in &self.0 {
+        for (key, fragments) in &self.lists {
             let mut new_fragments = Vec::with_capacity(fragments.len());
 
             for fragment in fragments {
                 match fragment {
                     FragmentItem::Fragment(s) | FragmentItem::FragmentListRef(s) => {
                         // If it is also a key, that means it is a list reference
-                        if self.0.contains_key(s) {
+                        if self.lists.contains_key(s) {
                             new_fragments.push(FragmentItem::FragmentListRef(s.clone()));
                         } else {
                             new_fragments.push(FragmentItem::Fragment(s.clone()));
@@ -53,18 +208,24 @@ impl FragmentLists {
             lists.insert(key.clone(), new_fragments);
         }
 
-        Self(lists)
+        Self {
+            lists,
+            vars: self.vars.clone(),
+        }
     }
 
-    pub fn validate_code_fragments(&self, code: &CodeFragments) -> Result<(), Error> {
+    pub fn validate_code_fragments(
+        &self,
+        is_registered: impl Fn(&SharedStr) -> bool,
+    ) -> Result<(), Error> {
         let mut missing = Vec::new();
 
         // Loop over each fragment list searching for each item in the code fragments
-        for fragments in self.0.values() {
+        for fragments in self.lists.values() {
             let v: Vec<_> = fragments
                 .iter()
                 .filter_map(|fragment| match fragment {
-                    FragmentItem::Fragment(name) if !code.contains_key(name) => Some(name.clone()),
+                    FragmentItem::Fragment(name) if !is_registered(name) => Some(name.clone()),
                     _ => None,
                 })
                 .collect();
@@ -82,7 +243,7 @@ impl FragmentLists {
 
     pub fn validate_file(&self, name: &SharedStr, f: &File) -> Result<(), Error> {
         // Ensure the file's fragment list exists
-        if !self.0.contains_key(&f.fragment_list) {
+        if !self.lists.contains_key(&f.fragment_list) {
             return Err(Error::MissingFragmentList(
                 f.fragment_list.clone(),
                 name.clone(),
@@ -93,12 +254,12 @@ impl FragmentLists {
 
         'top: for exception in &f.fragment_list_exceptions {
             // If it is the name of a list, we can bypass the 2nd scan entirely
-            if self.0.contains_key(exception) {
+            if self.lists.contains_key(exception) {
                 continue;
             }
 
             // If it might be the name of an actual fragment we will need to scan them all
-            for fragment_list in self.0.values() {
+            for fragment_list in self.lists.values() {
                 // As soon as we find a match jump to looking for next exception
                 if fragment_list.iter().any(|fragment| match fragment {
                     FragmentItem::Fragment(name) => name == exception,
@@ -120,10 +281,79 @@ impl FragmentLists {
     }
 
     #[inline]
-    pub fn fragment_list(&self, name: &SharedStr) -> Result<&Vec<FragmentItem>, Error> {
-        self.0
+    pub fn fragment_list(&self, name: &str) -> Result<&Vec<FragmentItem>, Error> {
+        self.lists
             .get(name)
-            .ok_or_else(|| Error::FragmentListNotFound(name.clone()))
+            .ok_or_else(|| Error::FragmentListNotFound(name.into()))
+    }
+
+    #[inline]
+    pub fn names(&self) -> Vec<&SharedStr> {
+        self.lists.keys().collect()
+    }
+
+    // Collect every fragment and fragment list reachable from the given list (including itself)
+    pub fn reachable(&self, name: &SharedStr, reached: &mut Vec<SharedStr>) -> Result<(), Error> {
+        // Also guards against list cycles
+        if reached.contains(name) {
+            return Ok(());
+        }
+        reached.push(name.clone());
+
+        for fragment in self.fragment_list(name)? {
+            match fragment {
+                FragmentItem::FragmentListRef(name) => self.reachable(name, reached)?,
+                FragmentItem::Fragment(name) => {
+                    if !reached.contains(name) {
+                        reached.push(name.clone());
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Expands the given list in place. Each fragment is returned along with the lists (outermost
+    // first) that have vars and were used to reach it. `chain` holds the lists currently being
+    // expanded so a list that includes itself is reported instead of recursing forever
+    pub fn expand(
+        &self,
+        name: &SharedStr,
+        exceptions: &[SharedStr],
+        chain: &mut Vec<SharedStr>,
+        scope: &mut Vec<SharedStr>,
+        expanded: &mut Vec<(SharedStr, Vec<SharedStr>)>,
+    ) -> Result<(), Error> {
+        if let Some(start) = chain.iter().position(|list| list == name) {
+            let mut cycle = chain[start..].to_vec();
+            cycle.push(name.clone());
+            return Err(Error::FragmentListCycle(cycle));
+        }
+        chain.push(name.clone());
+
+        let has_vars = self.vars.contains_key(name);
+        if has_vars {
+            scope.push(name.clone());
+        }
+
+        for fragment in self.fragment_list(name)? {
+            match fragment {
+                FragmentItem::FragmentListRef(name) if !exceptions.contains(name) => {
+                    self.expand(name, exceptions, chain, scope, expanded)?;
+                }
+                FragmentItem::Fragment(name) if !exceptions.contains(name) => {
+                    expanded.push((name.clone(), scope.clone()));
+                }
+                _ => {}
+            }
+        }
+
+        if has_vars {
+            scope.pop();
+        }
+        chain.pop();
+        Ok(())
     }
 }
 
@@ -131,11 +361,17 @@ impl FragmentLists {
 
 #[derive(Clone, Debug, Default, serde::Deserialize, PartialEq)]
 struct General {
+    #[serde(default)]
+    flexgen_version: Option<SharedStr>,
     #[serde(default)]
     base_path: PathBuf,
     #[serde(default)]
     rust_fmt: RustFmt,
     #[serde(default)]
+    line_ending: Option<LineEnding>,
+    #[serde(default)]
+    api_summary: bool,
+    #[serde(default)]
     vars: Vars,
 }
 
@@ -144,6 +380,11 @@ impl General {
     fn build_rust_fmt(&self) -> Option<rust_format::RustFmt> {
         self.rust_fmt.build_rust_fmt()
     }
+
+    #[inline]
+    fn build_rust_fmt_only(&self) -> Option<rust_format::RustFmt> {
+        self.rust_fmt.build_rust_fmt_only()
+    }
 }
 
 #[derive(Clone, Debug, Default, serde::Deserialize, PartialEq)]
@@ -151,25 +392,39 @@ struct RustFmt {
     #[serde(default)]
     omit_final_format: bool,
     #[serde(default)]
+    skip_pretty_please: bool,
+    #[serde(default)]
     path: Option<PathBuf>,
     #[serde(default)]
     options: HashMap<SharedStr, SharedStr>,
 }
 
 impl RustFmt {
+    fn build(&self, post_proc: PostProcess) -> rust_format::RustFmt {
+        let mut config = if !self.options.is_empty() {
+            let map = self.options.iter().map(|(k, v)| (&**k, &**v)).collect();
+            rust_format::Config::from_hash_map(map)
+        } else {
+            rust_format::Config::new()
+        };
+        if let Some(path) = &self.path {
+            config = config.rust_fmt_path(path.clone())
+        }
+
+        rust_format::RustFmt::from_config(config.post_proc(post_proc))
+    }
+
     fn build_rust_fmt(&self) -> Option<rust_format::RustFmt> {
         if !self.omit_final_format {
-            let mut config = if !self.options.is_empty() {
-                let map = self.options.iter().map(|(k, v)| (&**k, &**v)).collect();
-                rust_format::Config::from_hash_map(map)
-            } else {
-                rust_format::Config::new()
-            };
-            if let Some(path) = &self.path {
-                config = config.rust_fmt_path(path.clone())
-            }
+            Some(self.build(PostProcess::None))
+        } else {
+            None
+        }
+    }
 
-            Some(rust_format::RustFmt::from_config(config))
+    fn build_rust_fmt_only(&self) -> Option<rust_format::RustFmt> {
+        if self.skip_pretty_please {
+            Some(self.build(PostProcess::ReplaceMarkers))
         } else {
             None
         }
@@ -182,6 +437,16 @@ struct File {
     fragment_list: SharedStr,
     #[serde(default)]
     fragment_list_exceptions: Vec<SharedStr>,
+    #[serde(default)]
+    transitive_exceptions: bool,
+    #[serde(default)]
+    sections: Option<Vec<Section>>,
+    #[serde(default)]
+    layout: Option<Vec<LayoutPart>>,
+    #[serde(default)]
+    line_ending: Option<LineEnding>,
+    #[serde(default)]
+    api_summary: Option<bool>,
     vars: Vars,
 }
 
@@ -201,7 +466,25 @@ impl Config {
         let mut buffer = String::with_capacity(BUF_SIZE);
         reader.read_to_string(&mut buffer)?;
 
-        Ok(toml::from_str(&buffer)?)
+        let config: Config = toml::from_str(&buffer)?;
+        config.check_version(env!("CARGO_PKG_VERSION"))?;
+        Ok(config)
+    }
+
+    // Ensure the running flexgen version satisfies the requirement in the config (if any)
+    fn check_version(&self, version: &str) -> Result<(), Error> {
+        if let Some(req) = &self.general.flexgen_version {
+            let version_req = semver::VersionReq::parse(req)
+                .map_err(|err| Error::DeserializeError(format!("flexgen_version: {err}")))?;
+            // Panic safety: Our own crate version is always valid
+            let version = semver::Version::parse(version).unwrap();
+
+            if !version_req.matches(&version) {
+                return Err(Error::IncompatibleVersion(req.clone(), version.to_string()));
+            }
+        }
+
+        Ok(())
     }
 
     /// Try to load the `Config` from the default TOML file (flexgen.toml)
@@ -216,13 +499,24 @@ impl Config {
         Self::from_toml_reader(f)
     }
 
-    pub(crate) fn build_and_validate(&mut self, code: &CodeFragments) -> Result<(), Error> {
+    pub(crate) fn build_and_validate(
+        &mut self,
+        is_registered: impl Fn(&SharedStr) -> bool,
+    ) -> Result<(), Error> {
         // Build and validate fragment lists against code fragments and files
         self.fragment_lists = self.fragment_lists.build();
 
-        self.fragment_lists.validate_code_fragments(code)?;
+        self.fragment_lists.validate_code_fragments(is_registered)?;
         for (name, file) in &self.files {
             self.fragment_lists.validate_file(name, file)?;
+
+            if let Some(layout) = &file.layout {
+                for (idx, part) in layout.iter().enumerate() {
+                    if layout[..idx].contains(part) {
+                        return Err(Error::DuplicateLayoutPart(*part, name.clone()));
+                    }
+                }
+            }
         }
 
         Ok(())
@@ -236,21 +530,65 @@ impl Config {
 
     /// Return the specified file configuration
     #[inline]
-    fn file(&self, name: &SharedStr) -> Result<&File, Error> {
+    fn file(&self, name: impl FileKey) -> Result<&File, Error> {
+        let name = name.file_name();
         self.files
             .get(name)
-            .ok_or_else(|| Error::FileNotFound(name.clone()))
+            .ok_or_else(|| Error::FileNotFound(name.into()))
+    }
+
+    /// Return the base path with a leading `$VAR` component (such as `$OUT_DIR` in build scripts)
+    /// replaced by the value of that environment variable at runtime
+    pub fn base_path(&self) -> Result<PathBuf, Error> {
+        match self.base_path_var() {
+            Some(var) => {
+                let mut path = PathBuf::from(
+                    env::var_os(var).ok_or_else(|| Error::MissingEnvVar(var.into()))?,
+                );
+                path.extend(self.general.base_path.components().skip(1));
+                Ok(path)
+            }
+            None => Ok(self.general.base_path.clone()),
+        }
+    }
+
+    // The name of the environment variable if the base path starts with a `$VAR` component
+    fn base_path_var(&self) -> Option<&str> {
+        match self.general.base_path.components().next() {
+            Some(Component::Normal(first)) => first.to_str()?.strip_prefix('$'),
+            _ => None,
+        }
+    }
+
+    /// Resolve a relative base path against the given directory (typically the one containing the
+    /// config file). Absolute base paths and those starting with a `$VAR` component are unchanged
+    pub fn relative_to(mut self, dir: impl AsRef<Path>) -> Self {
+        if self.general.base_path.is_relative() && self.base_path_var().is_none() {
+            self.general.base_path = dir.as_ref().join(&self.general.base_path);
+        }
+        self
     }
 
     /// Build the full file path to the file given as a parameter
-    pub fn file_path(&self, name: &SharedStr) -> Result<PathBuf, Error> {
+    pub fn file_path(&self, name: impl FileKey) -> Result<PathBuf, Error> {
         let file = self.file(name)?;
-        let base_path = self.general.base_path.as_os_str();
+        Ok(Self::join_path(&self.base_path()?, &file.path))
+    }
 
-        let mut path = PathBuf::with_capacity(base_path.len() + file.path.as_os_str().len());
+    // The full path to the file given as a parameter, with any leading `$VAR` component of the
+    // base path left as is
+    pub(crate) fn unresolved_file_path(&self, name: impl FileKey) -> Result<PathBuf, Error> {
+        let file = self.file(name)?;
+        Ok(Self::join_path(&self.general.base_path, &file.path))
+    }
+
+    #[inline]
+    fn join_path(base_path: &Path, file_path: &Path) -> PathBuf {
+        let mut path =
+            PathBuf::with_capacity(base_path.as_os_str().len() + file_path.as_os_str().len() + 1);
         path.push(base_path);
-        path.push(&file.path);
-        Ok(path)
+        path.push(file_path);
+        path
     }
 
     #[inline]
@@ -269,34 +607,148 @@ impl Config {
     }
 
     #[inline]
-    fn file_vars(&self, name: &SharedStr) -> Result<TokenVars, Error> {
+    fn file_vars(&self, name: impl FileKey) -> Result<TokenVars, Error> {
         Self::convert_vars(&self.file(name)?.vars)
     }
 
     /// Return the complete vars for the file name given as a parameter
     #[inline]
-    pub fn vars(&self, name: &SharedStr) -> Result<TokenVars, Error> {
+    pub fn vars(&self, name: impl FileKey) -> Result<TokenVars, Error> {
+        let mut vars = self.general_vars()?;
+        vars.extend(self.file_vars(name)?);
+        Ok(vars)
+    }
+
+    /// Return the complete vars for a fragment of the given file that was reached via the given
+    /// fragment lists (outermost first). List vars override general vars (inner lists taking
+    /// precedence over outer ones), and file vars override them all
+    pub fn scoped_vars(&self, name: impl FileKey, lists: &[SharedStr]) -> Result<TokenVars, Error> {
         let mut vars = self.general_vars()?;
+
+        for list in lists {
+            if let Some(list_vars) = self.fragment_lists.vars.get(list) {
+                vars.extend(Self::convert_vars(list_vars)?);
+            }
+        }
+
         vars.extend(self.file_vars(name)?);
         Ok(vars)
     }
 
     /// Return the given named fragment list
     #[inline]
-    pub fn fragment_list(&self, name: &SharedStr) -> Result<&Vec<FragmentItem>, Error> {
-        self.fragment_lists.fragment_list(name)
+    pub fn fragment_list(&self, name: impl FragmentKey) -> Result<&Vec<FragmentItem>, Error> {
+        self.fragment_lists.fragment_list(name.fragment_name())
+    }
+
+    /// Return the names of all the fragment lists specified in the config
+    #[inline]
+    pub fn fragment_list_names(&self) -> Vec<&SharedStr> {
+        self.fragment_lists.names()
+    }
+
+    /// Return the effective sequence of code fragment names for the file given as a parameter. All
+    /// fragment list references are expanded in place and the file's exceptions are removed.
+    ///
+    /// An exception naming a fragment list skips every reference to that list, no matter how deeply
+    /// nested. If the file sets `transitive_exceptions`, every fragment and list reachable from
+    /// that list is excluded as well, even when referenced directly or via some other list
+    pub fn file_fragments(&self, name: impl FileKey) -> Result<Vec<SharedStr>, Error> {
+        Ok(self
+            .file_fragment_scopes(name)?
+            .into_iter()
+            .map(|(fragment, _)| fragment)
+            .collect())
+    }
+
+    /// Return the same sequence as [file_fragments](Config::file_fragments), but each fragment
+    /// also includes the fragment lists with vars (outermost first) it was reached through. These
+    /// can be passed to [scoped_vars](Config::scoped_vars)
+    pub fn file_fragment_scopes(
+        &self,
+        name: impl FileKey,
+    ) -> Result<Vec<(SharedStr, Vec<SharedStr>)>, Error> {
+        let file = self.file(name)?;
+
+        let exceptions = if file.transitive_exceptions {
+            let mut reached = Vec::with_capacity(file.fragment_list_exceptions.len() * 4);
+
+            for exception in &file.fragment_list_exceptions {
+                if self.fragment_lists.lists.contains_key(exception) {
+                    self.fragment_lists.reachable(exception, &mut reached)?;
+                } else if !reached.contains(exception) {
+                    reached.push(exception.clone());
+                }
+            }
+
+            reached
+        } else {
+            file.fragment_list_exceptions.clone()
+        };
+
+        let mut expanded = Vec::with_capacity(self.fragment_list(&file.fragment_list)?.len());
+        self.fragment_lists.expand(
+            &file.fragment_list,
+            &exceptions,
+            &mut Vec::new(),
+            &mut Vec::new(),
+            &mut expanded,
+        )?;
+        Ok(expanded)
+    }
+
+    /// Return the order in which sections are concatenated for the file given as a parameter
+    #[inline]
+    pub fn file_sections(&self, name: impl FileKey) -> Result<&[Section], Error> {
+        Ok(self
+            .file(name)?
+            .sections
+            .as_deref()
+            .unwrap_or(DEFAULT_SECTIONS))
+    }
+
+    /// Return the layout (order of the parts of the overall file) for the file given as a parameter
+    #[inline]
+    pub fn file_layout(&self, name: impl FileKey) -> Result<&[LayoutPart], Error> {
+        Ok(self.file(name)?.layout.as_deref().unwrap_or(DEFAULT_LAYOUT))
+    }
+
+    /// Return the line ending for the file given as a parameter (falling back to the general line
+    /// ending). If `None`, the line endings produced by the formatter are left unchanged
+    #[inline]
+    pub fn file_line_ending(&self, name: impl FileKey) -> Result<Option<LineEnding>, Error> {
+        Ok(self.file(name)?.line_ending.or(self.general.line_ending))
+    }
+
+    /// Return the path of the markdown API summary for the file given as a parameter if one should
+    /// be written (falling back to the general setting). It sits alongside the file with an
+    /// `.api.md` extension
+    pub fn file_api_summary_path(&self, name: impl FileKey) -> Result<Option<PathBuf>, Error> {
+        let file = self.file(&name)?;
+
+        if file.api_summary.unwrap_or(self.general.api_summary) {
+            Ok(Some(self.file_path(name)?.with_extension("api.md")))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Return the name of the fragment list used by the file given as a parameter
+    #[inline]
+    pub fn file_fragment_list_name(&self, name: impl FileKey) -> Result<&SharedStr, Error> {
+        Ok(&self.file(name)?.fragment_list)
     }
 
     /// Return the fragment list used by the file given a parameter
     #[inline]
-    pub fn file_fragment_list(&self, name: &SharedStr) -> Result<&Vec<FragmentItem>, Error> {
-        let name = &self.file(name)?.fragment_list;
+    pub fn file_fragment_list(&self, name: impl FileKey) -> Result<&Vec<FragmentItem>, Error> {
+        let name = self.file_fragment_list_name(name)?;
         self.fragment_list(name)
     }
 
     /// Return all the fragment exceptions for the given file
     #[inline]
-    pub fn file_fragment_exceptions(&self, name: &SharedStr) -> Result<&Vec<SharedStr>, Error> {
+    pub fn file_fragment_exceptions(&self, name: impl FileKey) -> Result<&Vec<SharedStr>, Error> {
         Ok(&self.file(name)?.fragment_list_exceptions)
     }
 
@@ -305,22 +757,34 @@ impl Config {
     pub fn build_rust_fmt(&self) -> Option<rust_format::RustFmt> {
         self.general.build_rust_fmt()
     }
+
+    /// Return a [RustFmt](rust_format::RustFmt) instance that formats the raw token string directly
+    /// (replacing any markers) if `skip_pretty_please` is set. In that case, PrettyPlease is not
+    /// used and the [final format](Config::build_rust_fmt) is skipped by the generator
+    #[inline]
+    pub fn build_rust_fmt_only(&self) -> Option<rust_format::RustFmt> {
+        self.general.build_rust_fmt_only()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
+    use std::env;
     use std::path::PathBuf;
     use std::str::FromStr;
 
     use flexstr::{shared_str, SharedStr};
+    use indexmap::IndexMap;
     use pretty_assertions::assert_eq;
 
-    use crate::config::{Config, File, FragmentItem, FragmentLists, General, RustFmt};
-    use crate::var::{CodeValue, VarItem, VarValue};
+    use crate::config::{Config, File, FragmentItem, FragmentLists, General, LineEnding, RustFmt};
+    use crate::var::{CodeValue, TokenItem, TokenValue, VarItem, VarValue};
+    use crate::Error;
 
     const CONFIG: &str = r#"
         [general]
+        flexgen_version = ">=0.4"
         base_path = "src/"
         
         [general.rust_fmt]
@@ -347,7 +811,7 @@ mod tests {
     "#;
 
     fn general() -> General {
-        let mut vars = HashMap::new();
+        let mut vars = IndexMap::new();
 
         let product = VarValue::String(shared_str!("FlexStr"));
         vars.insert(shared_str!("product"), VarItem::Single(product.clone()));
@@ -368,13 +832,17 @@ mod tests {
 
         let rust_fmt = RustFmt {
             omit_final_format: false,
+            skip_pretty_please: false,
             path: Some("rustfmt".into()),
             options: Default::default(),
         };
 
         General {
+            flexgen_version: Some(shared_str!(">=0.4")),
             base_path: PathBuf::from("src/"),
             rust_fmt,
+            line_ending: None,
+            api_summary: false,
             vars,
         }
     }
@@ -382,7 +850,7 @@ mod tests {
     fn fragment_lists() -> FragmentLists {
         use FragmentItem::*;
 
-        let mut lists = HashMap::new();
+        let mut lists = IndexMap::new();
         lists.insert(
             shared_str!("impl"),
             vec![
@@ -397,11 +865,14 @@ mod tests {
                 Fragment(shared_str!("from_ref")),
             ],
         );
-        FragmentLists(lists)
+        FragmentLists {
+            lists,
+            vars: HashMap::new(),
+        }
     }
 
     fn files() -> HashMap<SharedStr, File> {
-        let mut str_vars = HashMap::new();
+        let mut str_vars = IndexMap::new();
         str_vars.insert(
             shared_str!("str_type"),
             VarItem::Single(VarValue::String(shared_str!("str"))),
@@ -411,6 +882,11 @@ mod tests {
             path: PathBuf::from("strings/generated/std_str.rs"),
             fragment_list: shared_str!("impl"),
             fragment_list_exceptions: vec![shared_str!("impl_core_ref")],
+            transitive_exceptions: false,
+            sections: None,
+            layout: None,
+            line_ending: None,
+            api_summary: None,
             vars: str_vars,
         };
 
@@ -430,4 +906,196 @@ mod tests {
 
         assert_eq!(expected, actual);
     }
+
+    // IndexMap equality ignores order, so verify it separately
+    #[test]
+    fn preserves_order() {
+        let config = Config::from_toml_reader(CONFIG.as_bytes()).unwrap();
+
+        let vars: Vec<_> = config.general.vars.keys().map(|k| &**k).collect();
+        assert_eq!(vec!["product", "generate", "count", "suffix", "list"], vars);
+
+        let lists: Vec<_> = config
+            .fragment_list_names()
+            .into_iter()
+            .map(|k| &**k)
+            .collect();
+        assert_eq!(vec!["impl", "impl_struct"], lists);
+    }
+
+    #[test]
+    fn line_ending() {
+        let source = "fn main() {\r\n}\n".to_string();
+
+        assert_eq!("fn main() {\n}\n", LineEnding::Lf.apply(source.clone()));
+        assert_eq!("fn main() {\r\n}\r\n", LineEnding::Crlf.apply(source));
+    }
+
+    #[test]
+    fn file_fragments() {
+        let mut config = Config::from_toml_reader(CONFIG.as_bytes()).unwrap();
+        config.fragment_lists = config.fragment_lists.build();
+
+        let actual = config.file_fragments(shared_str!("str")).unwrap();
+        let expected = vec![shared_str!("empty"), shared_str!("from_ref")];
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn flexgen_version() {
+        let mut config = Config::from_toml_reader(CONFIG.as_bytes()).unwrap();
+
+        config.general.flexgen_version = Some(shared_str!(">=0.5, <0.7"));
+        assert!(config.check_version("0.6.1").is_ok());
+        assert!(matches!(
+            config.check_version("0.4.5"),
+            Err(Error::IncompatibleVersion(ref req, ref version))
+                if &**req == ">=0.5, <0.7" && version == "0.4.5"
+        ));
+
+        config.general.flexgen_version = Some(shared_str!("invalid"));
+        assert!(matches!(
+            config.check_version("0.4.5"),
+            Err(Error::DeserializeError(_))
+        ));
+    }
+
+    const SCOPED_CONFIG: &str = r#"
+        [general.vars]
+        product = "FlexStr"
+
+        [fragment_lists]
+        impl = [ "impl_struct", "impl_core_ref" ]
+
+        [fragment_lists.impl_struct]
+        fragments = [ "empty", "from_ref" ]
+
+        [fragment_lists.impl_struct.vars]
+        product = "ListStr"
+        str_type = "list_str"
+
+        [files.str]
+        path = "std_str.rs"
+        fragment_list = "impl"
+        fragment_list_exceptions = [ "impl_core_ref" ]
+
+        [files.str.vars]
+        str_type = "str"
+    "#;
+
+    #[test]
+    fn scoped_vars() {
+        let mut config = Config::from_toml_reader(SCOPED_CONFIG.as_bytes()).unwrap();
+        config.fragment_lists = config.fragment_lists.build();
+        let name = shared_str!("str");
+
+        let scopes = config.file_fragment_scopes(&name).unwrap();
+        let expected = vec![
+            (shared_str!("empty"), vec![shared_str!("impl_struct")]),
+            (shared_str!("from_ref"), vec![shared_str!("impl_struct")]),
+        ];
+        assert_eq!(expected, scopes);
+
+        // List vars override general vars, but file vars override list vars
+        let vars = config.scoped_vars(&name, &scopes[0].1).unwrap();
+        let str_value = |s| TokenItem::Single(TokenValue::String(SharedStr::from_static(s)));
+        assert_eq!(
+            Some(&str_value("ListStr")),
+            vars.get(&shared_str!("product"))
+        );
+        assert_eq!(Some(&str_value("str")), vars.get(&shared_str!("str_type")));
+    }
+
+    const NESTED_CONFIG: &str = r#"
+        [fragment_lists]
+        all = [ "first", "outer", "shared", "other" ]
+        outer = [ "inner", "outer_only" ]
+        inner = [ "shared", "inner_only" ]
+        other = [ "inner" ]
+
+        [files.str]
+        path = "str.rs"
+        fragment_list = "all"
+        fragment_list_exceptions = [ "inner" ]
+        transitive_exceptions = false
+        vars = {}
+    "#;
+
+    fn nested_file_fragments(transitive: bool) -> Vec<SharedStr> {
+        let mut config = Config::from_toml_reader(NESTED_CONFIG.as_bytes()).unwrap();
+        config.fragment_lists = config.fragment_lists.build();
+        config
+            .files
+            .get_mut(&shared_str!("str"))
+            .unwrap()
+            .transitive_exceptions = transitive;
+
+        config.file_fragments(shared_str!("str")).unwrap()
+    }
+
+    // A list exception skips the list wherever it is referenced, but fragments it contains can
+    // still be included via other paths
+    #[test]
+    fn nested_list_exception() {
+        let expected = vec![
+            shared_str!("first"),
+            shared_str!("outer_only"),
+            shared_str!("shared"),
+        ];
+        assert_eq!(expected, nested_file_fragments(false));
+    }
+
+    // A transitive list exception excludes everything reachable from that list
+    #[test]
+    fn transitive_list_exception() {
+        let expected = vec![shared_str!("first"), shared_str!("outer_only")];
+        assert_eq!(expected, nested_file_fragments(true));
+    }
+
+    // A list that includes itself (here via another list) is an error rather than a stack overflow
+    #[test]
+    fn fragment_list_cycle() {
+        let mut config = Config::from_toml_reader(NESTED_CONFIG.as_bytes()).unwrap();
+        config
+            .fragment_lists
+            .lists
+            .get_mut(&shared_str!("outer"))
+            .unwrap()
+            .push(FragmentItem::FragmentListRef(shared_str!("all")));
+        config.fragment_lists = config.fragment_lists.build();
+
+        let err = config.file_fragments(shared_str!("str")).unwrap_err();
+        let expected = vec![shared_str!("all"), shared_str!("outer"), shared_str!("all")];
+        assert!(matches!(err, Error::FragmentListCycle(ref cycle) if *cycle == expected));
+    }
+
+    // Skipping PrettyPlease doesn't change the final format RustFmt (the generator skips it instead)
+    #[test]
+    fn skip_pretty_please() {
+        let mut config = Config::from_toml_reader(CONFIG.as_bytes()).unwrap();
+        assert!(config.build_rust_fmt().is_some());
+        assert!(config.build_rust_fmt_only().is_none());
+
+        config.general.rust_fmt.skip_pretty_please = true;
+        assert!(config.build_rust_fmt().is_some());
+        assert!(config.build_rust_fmt_only().is_some());
+    }
+
+    #[test]
+    fn env_base_path() {
+        let mut config = Config::from_toml_reader(CONFIG.as_bytes()).unwrap();
+        config.general.base_path = PathBuf::from("$FLEXGEN_TEST_OUT_DIR/generated");
+
+        assert!(matches!(
+            config.file_path(shared_str!("str")),
+            Err(Error::MissingEnvVar(ref var)) if &**var == "FLEXGEN_TEST_OUT_DIR"
+        ));
+
+        env::set_var("FLEXGEN_TEST_OUT_DIR", "out");
+        let expected: PathBuf = ["out", "generated", "strings", "generated", "std_str.rs"]
+            .iter()
+            .collect();
+        assert_eq!(expected, config.file_path(shared_str!("str")).unwrap());
+    }
 }
@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::{Config, DEFAULT_FILENAME};
+use crate::report::ErrorReport;
+use crate::timing::TimingReport;
+use crate::{CodeFragments, CodeGenerator, Error};
+
+// *** Config Discovery ***
+
+#[inline]
+fn skip_dir(path: &Path) -> bool {
+    match path.file_name().and_then(|name| name.to_str()) {
+        Some(name) => name == "target" || name.starts_with('.'),
+        None => false,
+    }
+}
+
+fn find_configs_in(dir: &Path, configs: &mut Vec<PathBuf>) -> Result<(), Error> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+
+        // Unlike `Path::is_dir`, this doesn't follow symlinks (which could form a cycle)
+        if entry.file_type()?.is_dir() {
+            if !skip_dir(&path) {
+                find_configs_in(&path, configs)?;
+            }
+        } else if path.file_name().and_then(|name| name.to_str()) == Some(DEFAULT_FILENAME) {
+            configs.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// Recursively find every `flexgen.toml` file under the given directory (sorted by path). Hidden
+/// and `target` directories are skipped, and symlinked directories are not followed
+pub fn find_configs(root: impl AsRef<Path>) -> Result<Vec<PathBuf>, Error> {
+    let mut configs = Vec::new();
+    find_configs_in(root.as_ref(), &mut configs)?;
+    configs.sort();
+    Ok(configs)
+}
+
+fn generate_config(path: &Path, code: &CodeFragments) -> Result<TimingReport, Error> {
+    let config = Config::from_toml_file(path)?;
+    // Panic safety: a file that was found always has a parent directory
+    let config = config.relative_to(path.parent().unwrap());
+
+    CodeGenerator::new(code.clone(), config)?.generate_files()
+}
+
+/// Find every `flexgen.toml` file under the given directory and generate its files using the given
+/// code fragments. Each config's base path is resolved relative to the directory containing it.
+/// Every config is generated even if some fail, and any errors are returned together (each
+/// wrapped with the path of its config file). On success, it returns the timing report of each
+/// config file
+pub fn generate_all(
+    root: impl AsRef<Path>,
+    code: &CodeFragments,
+) -> Result<HashMap<PathBuf, TimingReport>, Error> {
+    let configs = find_configs(root)?;
+    let mut reports = HashMap::with_capacity(configs.len());
+    let mut errors = Vec::new();
+
+    for path in configs {
+        match generate_config(&path, code) {
+            Ok(report) => {
+                reports.insert(path, report);
+            }
+            Err(err) => errors.push(Error::ConfigError(path, Box::new(err))),
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(reports)
+    } else {
+        Err(Error::ExecutionErrors(ErrorReport::new(errors)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use pretty_assertions::assert_eq;
+    use proc_macro2::TokenStream;
+    use quote::quote;
+
+    use crate::discover::{find_configs, generate_all};
+    use crate::var::TokenVars;
+    use crate::{register_fragments, CodeFragment, Error};
+
+    struct Hello;
+
+    impl CodeFragment for Hello {
+        fn generate(&self, _vars: &TokenVars) -> Result<TokenStream, Error> {
+            Ok(quote! { fn hello() {} })
+        }
+    }
+
+    const CONFIG: &str = r#"
+        [general]
+        base_path = "src"
+
+        [general.rust_fmt]
+        omit_final_format = true
+
+        [fragment_lists]
+        all = [ "hello" ]
+
+        [files.hello]
+        path = "hello.rs"
+        fragment_list = "all"
+        layout = [ "sections" ]
+        vars = {}
+    "#;
+
+    #[test]
+    fn discover_and_generate() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+
+        for dir in ["a", "b/c", "target/d", ".hidden"] {
+            fs::create_dir_all(root.join(dir).join("src")).unwrap();
+            fs::write(root.join(dir).join("flexgen.toml"), CONFIG).unwrap();
+        }
+
+        let expected: Vec<PathBuf> =
+            vec![root.join("a/flexgen.toml"), root.join("b/c/flexgen.toml")];
+        assert_eq!(expected, find_configs(root).unwrap());
+
+        let reports = generate_all(root, &register_fragments!(Hello)).unwrap();
+        assert_eq!(2, reports.len());
+        for dir in ["a", "b/c"] {
+            let source = fs::read_to_string(root.join(dir).join("src/hello.rs")).unwrap();
+            assert_eq!("fn hello() {}\n", source);
+        }
+
+        // A config that fails doesn't stop the others
+        fs::write(root.join("b/c/flexgen.toml"), "invalid").unwrap();
+        let result = generate_all(root, &register_fragments!(Hello));
+
+        match result {
+            Err(Error::ExecutionErrors(errors)) => match errors.errors() {
+                [err @ Error::ConfigError(path, _)] => {
+                    assert_eq!(&root.join("b/c/flexgen.toml"), path);
+                    // The cause is only available via `source`
+                    let expected =
+                        format!("An error occurred with config file '{}'", path.display());
+                    assert_eq!(expected, err.to_string());
+                }
+                _ => panic!("Expected a single config error"),
+            },
+            _ => panic!("Expected execution errors"),
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_cycle() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+
+        fs::create_dir_all(root.join("a")).unwrap();
+        fs::write(root.join("a/flexgen.toml"), CONFIG).unwrap();
+        std::os::unix::fs::symlink(root, root.join("a/root")).unwrap();
+
+        let result = find_configs(root);
+        assert_eq!(vec![root.join("a/flexgen.toml")], result.unwrap());
+    }
+}
@@ -0,0 +1,122 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::parse::{Parse, ParseStream};
+use syn::Attribute;
+
+pub use quote_doctest::{DocTestBuilder, DocTestOptions, FenceAttr, GenMain};
+
+use crate::Error;
+
+// *** Doc Tests ***
+
+/// Create a doctest from the given tokens (typically built with `quote` using imported vars). It
+/// is identical to `quote_doctest::doc_test!`, except it returns a flexgen [Error]
+#[inline]
+pub fn doc_test(tokens: TokenStream) -> Result<TokenStream, Error> {
+    doc_test_with(tokens, DocTestOptions::default())
+}
+
+/// Create a doctest from the given tokens using the given options. See [doc_test]
+#[inline]
+pub fn doc_test_with(
+    tokens: TokenStream,
+    options: impl Into<DocTestOptions>,
+) -> Result<TokenStream, Error> {
+    let options: DocTestOptions = options.into();
+    Ok(quote_doctest::doc_test!(tokens, options)?)
+}
+
+// An item split into its outer attributes and everything else
+struct AttrItem {
+    attrs: Vec<Attribute>,
+    rest: TokenStream,
+}
+
+impl Parse for AttrItem {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        Ok(Self {
+            attrs: input.call(Attribute::parse_outer)?,
+            rest: input.parse()?,
+        })
+    }
+}
+
+/// Create a doctest from the given test tokens and attach it to the given item. The doctest is
+/// placed after the item's doc comments (if any) and before its other attributes
+pub fn attach_doc_test(test: TokenStream, item: TokenStream) -> Result<TokenStream, Error> {
+    let doc_test = doc_test(test)?;
+    let AttrItem { attrs, rest } = syn::parse2(item)?;
+    let (docs, attrs): (Vec<_>, Vec<_>) = attrs
+        .into_iter()
+        .partition(|attr| attr.path.is_ident("doc"));
+
+    Ok(quote! {
+        #(#docs)*
+        #doc_test
+        #(#attrs)*
+        #rest
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use quote::quote;
+    use quote_doctest::FormatDocTest;
+
+    use crate::doctest::{attach_doc_test, doc_test_with, DocTestOptions, FenceAttr, GenMain};
+
+    #[test]
+    fn with_options() {
+        let test = quote! { let x: u32 = "1".parse()?; };
+
+        // Both the builder and the options it builds are accepted
+        let builder = DocTestOptions::builder()
+            .gen_main(GenMain::Fallible)
+            .fence_attrs([FenceAttr::NoRun]);
+        let expected = quote! {
+            /// ```no_run
+            /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+            /// let x: u32 = "1".parse()?;
+            /// # Ok(())
+            /// # }
+            /// ```
+        }
+        .format_tokens()
+        .unwrap();
+
+        let actual = doc_test_with(test.clone(), builder.clone()).unwrap();
+        assert_eq!(expected, actual.format_tokens().unwrap());
+        let actual = doc_test_with(test, builder.build()).unwrap();
+        assert_eq!(expected, actual.format_tokens().unwrap());
+    }
+
+    #[test]
+    fn attach() {
+        let test = quote! { assert_eq!(one(), 1); };
+        let item = quote! {
+            /// Returns one
+            #[inline]
+            fn one() -> u32 {
+                1
+            }
+        };
+
+        let expected = quote! {
+            /// Returns one
+            /// ```
+            /// assert_eq!(one(), 1);
+            /// ```
+            #[inline]
+            fn one() -> u32 {
+                1
+            }
+        };
+
+        let actual = attach_doc_test(test, item).unwrap();
+        assert_eq!(
+            expected.format_tokens().unwrap(),
+            actual.format_tokens().unwrap()
+        );
+    }
+}
@@ -0,0 +1,47 @@
+use std::collections::HashMap;
+
+use flexstr::SharedStr;
+use proc_macro2::TokenStream;
+
+use crate::Error;
+
+// *** Hooks ***
+
+/// A hook that transforms the assembled tokens of a file before they are formatted
+pub type TokenHook = Box<dyn Fn(TokenStream) -> Result<TokenStream, Error> + Send + Sync>;
+
+/// A hook that transforms the source of a file after it has been formatted
+pub type StringHook = Box<dyn Fn(String) -> Result<String, Error> + Send + Sync>;
+
+// The hooks registered for each file (run in registration order)
+#[derive(Default)]
+pub(crate) struct Hooks {
+    pub tokens: HashMap<SharedStr, Vec<TokenHook>>,
+    pub strings: HashMap<SharedStr, Vec<StringHook>>,
+}
+
+impl Hooks {
+    pub fn run_token_hooks(
+        &self,
+        name: &SharedStr,
+        mut tokens: TokenStream,
+    ) -> Result<TokenStream, Error> {
+        if let Some(hooks) = self.tokens.get(name) {
+            for hook in hooks {
+                tokens = hook(tokens)?;
+            }
+        }
+
+        Ok(tokens)
+    }
+
+    pub fn run_string_hooks(&self, name: &SharedStr, mut source: String) -> Result<String, Error> {
+        if let Some(hooks) = self.strings.get(name) {
+            for hook in hooks {
+                source = hook(source)?;
+            }
+        }
+
+        Ok(source)
+    }
+}
@@ -0,0 +1,302 @@
+use std::collections::HashMap;
+
+use flexstr::SharedStr;
+use heck::ToUpperCamelCase;
+use proc_macro2::{Ident, TokenStream};
+use quote::quote;
+
+use crate::config::Config;
+use crate::Error;
+
+// *** Keys ***
+
+/// The name of a file in the [Config]. Implemented for string types (for dynamic lookups) and by
+/// enums defined with [file_keys](crate::file_keys) (for lookups checked at compile time)
+pub trait FileKey {
+    /// The name of the file as it appears in the config
+    fn file_name(&self) -> &str;
+}
+
+/// The name of a fragment list in the [Config]. Implemented for string types (for dynamic lookups)
+/// and by enums defined with [fragment_keys](crate::fragment_keys) (for lookups checked at compile
+/// time)
+pub trait FragmentKey {
+    /// The name of the fragment list as it appears in the config
+    fn fragment_name(&self) -> &str;
+}
+
+macro_rules! impl_str_keys {
+    ($($t:ty),+) => {
+        $(
+            impl FileKey for $t {
+                #[inline]
+                fn file_name(&self) -> &str {
+                    self
+                }
+            }
+
+            impl FragmentKey for $t {
+                #[inline]
+                fn fragment_name(&self) -> &str {
+                    self
+                }
+            }
+        )+
+    };
+}
+
+impl_str_keys!(str, String, SharedStr);
+
+impl<T: FileKey + ?Sized> FileKey for &T {
+    #[inline]
+    fn file_name(&self) -> &str {
+        (**self).file_name()
+    }
+}
+
+impl<T: FragmentKey + ?Sized> FragmentKey for &T {
+    #[inline]
+    fn fragment_name(&self) -> &str {
+        (**self).fragment_name()
+    }
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __key_enum {
+    ($key:ident, $method:ident, $(#[$attr:meta])* $vis:vis enum $name:ident {
+        $($variant:ident => $value:literal),+ $(,)?
+    }) => {
+        $(#[$attr])*
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+        $vis enum $name {
+            $($variant),+
+        }
+
+        impl $name {
+            /// All the keys of this enum
+            pub const ALL: &'static [$name] = &[$($name::$variant),+];
+        }
+
+        impl $crate::key::$key for $name {
+            #[inline]
+            fn $method(&self) -> &str {
+                match self {
+                    $($name::$variant => $value),+
+                }
+            }
+        }
+    };
+}
+
+/// Define an enum whose variants are the names of files in the config (as `Variant => "name"`),
+/// so that lookups such as [file_path](crate::config::Config::file_path) are checked at compile time
+#[macro_export]
+macro_rules! file_keys {
+    ($($tokens:tt)+) => { $crate::__key_enum!(FileKey, file_name, $($tokens)+); };
+}
+
+/// Define an enum whose variants are the names of fragment lists in the config (as
+/// `Variant => "name"`), so that lookups such as
+/// [fragment_list](crate::config::Config::fragment_list) are checked at compile time
+#[macro_export]
+macro_rules! fragment_keys {
+    ($($tokens:tt)+) => { $crate::__key_enum!(FragmentKey, fragment_name, $($tokens)+); };
+}
+
+// Parses the identifier, which fails for keywords (including `Self`) and names that don't start
+// with a letter or underscore
+fn key_ident(name: &SharedStr, ident: String) -> Result<Ident, Error> {
+    syn::parse_str(&ident).map_err(|_| Error::InvalidKeyName(name.clone(), ident))
+}
+
+fn key_enum<'a>(
+    mac: &str,
+    name: &str,
+    keys: impl IntoIterator<Item = &'a SharedStr>,
+) -> Result<TokenStream, Error> {
+    let mac = Ident::new(mac, proc_macro2::Span::call_site());
+    let name = key_ident(&name.into(), name.to_string())?;
+
+    let mut seen: HashMap<String, &SharedStr> = HashMap::new();
+    let mut variants = Vec::new();
+    let mut values = Vec::new();
+
+    for key in keys {
+        let variant = key.to_upper_camel_case();
+        if let Some(other) = seen.get(&variant) {
+            return Err(Error::DuplicateKeyName(
+                (*other).clone(),
+                key.clone(),
+                variant,
+            ));
+        }
+
+        variants.push(key_ident(key, variant.clone())?);
+        values.push(key.as_str());
+        seen.insert(variant, key);
+    }
+
+    Ok(quote! {
+        ::flexgen::#mac! {
+            pub enum #name {
+                #(#variants => #values),*
+            }
+        }
+    })
+}
+
+/// Generate the tokens for the key enums (named as given) covering every file and fragment list in
+/// the config. Typically used from a build script so the enums never drift from the config. An
+/// error is returned if a name doesn't convert into a valid variant, or if two names convert into
+/// the same one (such as `foo_bar` and `foo-bar`)
+pub fn key_enums(config: &Config, files: &str, fragment_lists: &str) -> Result<TokenStream, Error> {
+    // Files are kept in a hash map, so sort them for stable output
+    let mut file_names = config.file_names();
+    file_names.sort();
+
+    let files = key_enum("file_keys", files, file_names)?;
+    let fragment_lists = key_enum(
+        "fragment_keys",
+        fragment_lists,
+        config.fragment_list_names(),
+    )?;
+
+    Ok(quote! {
+        #files
+        #fragment_lists
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use quote::quote;
+
+    use crate::config::Config;
+    use crate::key::{key_enums, FileKey, FragmentKey};
+    use crate::Error;
+
+    const CONFIG: &str = r#"
+        [fragment_lists]
+        base = ["trait"]
+        impl_struct = ["base", "impl"]
+
+        [files.str]
+        path = "str.rs"
+        fragment_list = "impl_struct"
+
+        [files.str.vars]
+
+        [files.bool_str]
+        path = "bool_str.rs"
+        fragment_list = "base"
+
+        [files.bool_str.vars]
+    "#;
+
+    crate::file_keys! {
+        enum Files {
+            Str => "str",
+            BoolStr => "bool_str",
+        }
+    }
+
+    crate::fragment_keys! {
+        enum Lists {
+            Base => "base",
+            ImplStruct => "impl_struct",
+        }
+    }
+
+    #[test]
+    fn typed_lookups() {
+        let config = Config::from_toml_reader(CONFIG.as_bytes()).unwrap();
+
+        assert_eq!(
+            config.file_path(Files::Str).unwrap(),
+            config.file_path("str").unwrap()
+        );
+        assert_eq!(
+            config.fragment_list(Lists::ImplStruct).unwrap(),
+            config.fragment_list("impl_struct").unwrap()
+        );
+        assert_eq!(2, Files::ALL.len());
+        assert_eq!(&[Lists::Base, Lists::ImplStruct], Lists::ALL);
+        assert_eq!("bool_str", Files::BoolStr.file_name());
+        assert_eq!("base", Lists::Base.fragment_name());
+    }
+
+    #[test]
+    fn generate() {
+        let config = Config::from_toml_reader(CONFIG.as_bytes()).unwrap();
+
+        let expected = quote! {
+            ::flexgen::file_keys! {
+                pub enum Files {
+                    BoolStr => "bool_str",
+                    Str => "str"
+                }
+            }
+            ::flexgen::fragment_keys! {
+                pub enum Lists {
+                    Base => "base",
+                    ImplStruct => "impl_struct"
+                }
+            }
+        };
+
+        assert_eq!(
+            expected.to_string(),
+            key_enums(&config, "Files", "Lists").unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn invalid_names() {
+        for (name, ident) in [("2d", "2d"), ("self", "Self"), ("_", "")] {
+            let toml = format!(
+                r#"
+                [fragment_lists]
+                "{name}" = []
+
+                [files]
+                "#
+            );
+            let config = Config::from_toml_reader(toml.as_bytes()).unwrap();
+
+            match key_enums(&config, "Files", "Lists") {
+                Err(Error::InvalidKeyName(n, i)) => {
+                    assert_eq!((name, ident), (n.as_str(), i.as_str()))
+                }
+                result => panic!("unexpected result for '{name}': {result:?}"),
+            }
+        }
+
+        let config = Config::from_toml_reader(CONFIG.as_bytes()).unwrap();
+        assert!(matches!(
+            key_enums(&config, "2Files", "Lists"),
+            Err(Error::InvalidKeyName(..))
+        ));
+    }
+
+    #[test]
+    fn duplicate_names() {
+        const CONFIG: &str = r#"
+            [fragment_lists]
+            foo_bar = []
+            foo-bar = []
+
+            [files]
+        "#;
+        let config = Config::from_toml_reader(CONFIG.as_bytes()).unwrap();
+
+        match key_enums(&config, "Files", "Lists") {
+            Err(err @ Error::DuplicateKeyName(..)) => assert_eq!(
+                "The names 'foo_bar' and 'foo-bar' both convert into the key 'FooBar'",
+                err.to_string()
+            ),
+            result => panic!("unexpected result: {result:?}"),
+        }
+    }
+}
@@ -2,14 +2,39 @@
 
 #![warn(missing_docs)]
 
+/// Async code fragment related items
+#[cfg(feature = "async")]
+pub mod async_gen;
+/// Generated file comparison related items
+pub mod compare;
 /// Configuration related items
 pub mod config;
+/// Monorepo config discovery related items
+pub mod discover;
+/// Doctest generation related items
+#[cfg(feature = "doctest")]
+pub mod doctest;
+/// Token and string hook related items
+pub mod hook;
+/// Typed file and fragment list name related items
+pub mod key;
+/// Resolved configuration listing related items
+pub mod list;
+/// Aggregate error report related items
+pub mod report;
+/// Generated file destination related items
+pub mod sink;
+/// Generated API summary related items
+pub mod summary;
+/// Generation timing related items
+pub mod timing;
 /// Configuration variable related items
 pub mod var;
 
-use std::collections::HashMap;
-use std::io::Write;
-use std::{fs, io};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use std::{fmt, io, mem};
 
 use flexstr::SharedStr;
 use heck::ToSnakeCase;
@@ -17,15 +42,25 @@ use proc_macro2::TokenStream;
 use quote::quote;
 use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
 use rust_format::{Formatter, PostProcess, PrettyPlease};
-use use_builder::{UseBuilder, UseItems};
-
-use crate::config::{Config, FragmentItem};
+use use_builder::{StdExtCrateUse, UseBuilder, UseItems};
+
+use crate::compare::FileStatus;
+use crate::config::{Config, LayoutPart, Section};
+use crate::hook::Hooks;
+use crate::key::FileKey;
+use crate::list::Listing;
+use crate::report::ErrorReport;
+use crate::sink::{FileSink, FsSink, MemorySink};
+use crate::summary::ApiSummary;
+use crate::timing::{FileTiming, FragmentTiming, TimingReport};
 use crate::var::TokenVars;
 
+const MARKERS: [&str; 2] = ["_blank_!", "_comment_!"];
+
 #[doc(hidden)]
 #[inline]
 pub fn make_key(s: &'static str) -> SharedStr {
-    SharedStr::from_ref(&s.to_snake_case())
+    SharedStr::from_ref(s.to_snake_case())
 }
 
 /// Register code fragments in preparation for code generation
@@ -48,6 +83,21 @@ macro_rules! register_fragments {
     };
 }
 
+/// Include a file generated into `OUT_DIR` by a build script. The name is the path of the file
+/// relative to `OUT_DIR` without the `.rs` extension, which matches a config that uses
+/// `base_path = "$OUT_DIR"` and `path = "<name>.rs"`
+///
+/// ```ignore
+/// // Includes "$OUT_DIR/hello.rs"
+/// flexgen::include_generated!("hello");
+/// ```
+#[macro_export]
+macro_rules! include_generated {
+    ($name:literal) => {
+        include!(concat!(env!("OUT_DIR"), "/", $name, ".rs"))
+    };
+}
+
 // *** Error ***
 
 /// This error will be returned if any issues arise during the code generation process
@@ -77,9 +127,14 @@ pub enum Error {
     #[error("The configuration fragment list item '{0}' doesn't exist")]
     FragmentListNotFound(SharedStr),
 
-    /// A nested list of execution errors occurred while trying to generate source code
-    #[error("Errors occurred during execution: {0:?}")]
-    ExecutionErrors(Vec<Error>),
+    /// A fragment list includes itself, either directly or via other lists. The lists forming the
+    /// cycle are given in order, starting and ending with the same list
+    #[error("These fragment lists form a cycle: {0:?}")]
+    FragmentListCycle(Vec<SharedStr>),
+
+    /// Multiple errors occurred while trying to generate source code
+    #[error("{0}")]
+    ExecutionErrors(report::ErrorReport),
 
     /// The item imported was of the wrong type (either single when a list was needed or vice versa)
     #[error("The specified item was a 'list' instead of a 'single' item (or vice versa)")]
@@ -112,165 +167,504 @@ pub enum Error {
     /// An error occurred while parsing use sections
     #[error(transparent)]
     UseBuilderError(#[from] use_builder::Error),
+
+    /// An error occurred while generating a file. It contains the file name and the output path
+    /// (if it could be resolved)
+    #[error("An error occurred while generating file '{0}'{path}", path = display_path(.1.as_deref()))]
+    FileError(SharedStr, Option<PathBuf>, #[source] Box<Error>),
+
+    /// An error was returned by a code fragment. It contains the fragment name and the phase it
+    /// failed in
+    #[error("Code fragment '{0}' failed in '{1}'")]
+    FragmentError(SharedStr, FragmentPhase, #[source] Box<Error>),
+
+    /// The same part was specified more than once in the layout of the given file
+    #[error("The layout part '{0:?}' is specified more than once by file '{1}'")]
+    DuplicateLayoutPart(config::LayoutPart, SharedStr),
+
+    /// A blank or comment marker was still present after formatting (the post processor was unable
+    /// to match it)
+    #[error("A leftover marker was found in file '{0}' on line {1}")]
+    LeftoverMarker(SharedStr, usize),
+
+    /// The environment variable referenced by the base path was not set
+    #[error("The environment variable '{0}' referenced by the base path is not set")]
+    MissingEnvVar(SharedStr),
+
+    /// An error occurred while generating the files of the given config file
+    #[error("An error occurred with config file '{path}'", path = .0.display())]
+    ConfigError(PathBuf, #[source] Box<Error>),
+
+    /// The running version of flexgen doesn't satisfy the `flexgen_version` requirement in the
+    /// [Config](config::Config)
+    #[error("The config requires flexgen version '{0}', but this is version {1}")]
+    IncompatibleVersion(SharedStr, String),
+
+    /// The name of a file or fragment list doesn't convert into a valid key enum variant (the
+    /// converted identifier is given)
+    #[error("The name '{0}' can't be used as a key, as '{1}' is not a valid identifier")]
+    InvalidKeyName(SharedStr, String),
+
+    /// The names of two files or fragment lists convert into the same key enum variant
+    #[error("The names '{0}' and '{1}' both convert into the key '{2}'")]
+    DuplicateKeyName(SharedStr, SharedStr, String),
+}
+
+// The path (if any) as a suffix of an error message
+fn display_path(path: Option<&Path>) -> String {
+    match path {
+        Some(path) => format!(" ({})", path.display()),
+        None => String::new(),
+    }
+}
+
+impl Error {
+    // Errors that already have file context are not wrapped a second time
+    fn with_file_context(self, name: &SharedStr, config: &Config) -> Self {
+        match self {
+            err @ Error::FileError(..) => err,
+            err => Error::FileError(name.clone(), config.file_path(name).ok(), Box::new(err)),
+        }
+    }
+
+    // Errors that already have fragment context are not wrapped a second time
+    fn with_fragment_context(self, name: &SharedStr, phase: FragmentPhase) -> Self {
+        match self {
+            err @ Error::FragmentError(..) => err,
+            err => Error::FragmentError(name.clone(), phase, Box::new(err)),
+        }
+    }
+}
+
+// *** Fragment Phase ***
+
+/// The code fragment function that was running when a fragment failed
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FragmentPhase {
+    /// [uses](CodeFragment::uses) (including parsing the returned `use` items)
+    Uses,
+    /// [generate_top](CodeFragment::generate_top)
+    Top,
+    /// [generate_types](CodeFragment::generate_types)
+    Types,
+    /// [generate_impls](CodeFragment::generate_impls)
+    Impls,
+    /// [generate](CodeFragment::generate)
+    Body,
+    /// [generate_tests](CodeFragment::generate_tests)
+    Tests,
+}
+
+impl From<Section> for FragmentPhase {
+    #[inline]
+    fn from(section: Section) -> Self {
+        match section {
+            Section::Types => FragmentPhase::Types,
+            Section::Impls => FragmentPhase::Impls,
+            Section::Body => FragmentPhase::Body,
+            Section::Tests => FragmentPhase::Tests,
+        }
+    }
+}
+
+impl fmt::Display for FragmentPhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FragmentPhase::Uses => "uses",
+            FragmentPhase::Top => "generate_top",
+            FragmentPhase::Types => "generate_types",
+            FragmentPhase::Impls => "generate_impls",
+            FragmentPhase::Body => "generate",
+            FragmentPhase::Tests => "generate_tests",
+        })
+    }
 }
 
 // *** Execute ***
 
+// Any marker still present after formatting would be silently written into the generated file.
+// Comments and string/char literals (including doc attributes) are skipped over, as markers inside
+// them are not actual markers
+fn check_leftover_markers(name: &SharedStr, source: &str) -> Result<(), Error> {
+    let mut line = 1;
+    let mut idx = 0;
+
+    while let Some(ch) = source[idx..].chars().next() {
+        let rest = &source[idx..];
+        let after_ident = source[..idx].ends_with(|ch: char| ch.is_alphanumeric() || ch == '_');
+
+        let len = match ch {
+            '/' if rest.starts_with("//") => rest.find('\n').unwrap_or(rest.len()),
+            '/' if rest.starts_with("/*") => block_comment_len(rest),
+            '"' => quoted_len(rest, '"'),
+            '\'' => char_literal_len(rest).unwrap_or(1),
+            'b' | 'r' if !after_ident => raw_str_len(rest).unwrap_or(1),
+            '_' if !after_ident && MARKERS.iter().any(|&marker| rest.starts_with(marker)) => {
+                return Err(Error::LeftoverMarker(name.clone(), line));
+            }
+            _ => ch.len_utf8(),
+        };
+
+        line += rest[..len].matches('\n').count();
+        idx += len;
+    }
+
+    Ok(())
+}
+
+// The length of the (possibly nested) block comment at the start of the string
+fn block_comment_len(s: &str) -> usize {
+    let mut depth = 0;
+    let mut idx = 0;
+
+    while let Some(ch) = s[idx..].chars().next() {
+        if s[idx..].starts_with("/*") {
+            depth += 1;
+            idx += 2;
+        } else if s[idx..].starts_with("*/") {
+            depth -= 1;
+            idx += 2;
+
+            if depth == 0 {
+                return idx;
+            }
+        } else {
+            idx += ch.len_utf8();
+        }
+    }
+
+    s.len()
+}
+
+// The length of the string or char literal (with escapes) at the start of the string
+fn quoted_len(s: &str, quote: char) -> usize {
+    let mut chars = s.char_indices().skip(1);
+
+    while let Some((idx, ch)) = chars.next() {
+        match ch {
+            '\\' => {
+                chars.next();
+            }
+            _ if ch == quote => return idx + 1,
+            _ => {}
+        }
+    }
+
+    s.len()
+}
+
+// The length of the char literal at the start of the string (or `None` if it is a lifetime)
+fn char_literal_len(s: &str) -> Option<usize> {
+    let mut chars = s.char_indices().skip(1);
+
+    match (chars.next()?, chars.next()?) {
+        ((_, '\\'), _) => Some(quoted_len(s, '\'')),
+        (_, (idx, '\'')) => Some(idx + 1),
+        _ => None,
+    }
+}
+
+// The length of the raw (byte) string literal at the start of the string (if there is one)
+fn raw_str_len(s: &str) -> Option<usize> {
+    let body = s.strip_prefix('b').unwrap_or(s).strip_prefix('r')?;
+    let hashes = body.len() - body.trim_start_matches('#').len();
+    let body = body[hashes..].strip_prefix('"')?;
+
+    let end = body.find(&format!("\"{}", "#".repeat(hashes)))?;
+    Some(s.len() - body.len() + end + 1 + hashes)
+}
+
+// The results of all fragments for a single file, grouped by section
+struct FileParts {
+    sections: Vec<(Section, Vec<TokenStream>)>,
+    top_results: Vec<TokenStream>,
+    uses: Vec<UseItems>,
+    timings: Vec<FragmentTiming>,
+}
+
+impl FileParts {
+    fn new(sections: &[Section], fragments: usize) -> Self {
+        Self {
+            sections: sections
+                .iter()
+                .map(|&section| (section, Vec::with_capacity(fragments)))
+                .collect(),
+            top_results: Vec::with_capacity(3),
+            // Random choice based on a typical file
+            uses: Vec::with_capacity(10),
+            timings: Vec::with_capacity(fragments),
+        }
+    }
+}
+
 struct FileGenerator<'exec> {
     name: &'exec SharedStr,
     vars: TokenVars,
     fragments: &'exec CodeFragments,
     config: &'exec Config,
+    hooks: &'exec Hooks,
+    #[cfg(feature = "async")]
+    resolved: HashMap<SharedStr, async_gen::ResolvedFragment>,
 }
 
 impl<'exec> FileGenerator<'exec> {
-    fn new(
-        name: &'exec SharedStr,
-        fragments: &'exec CodeFragments,
-        config: &'exec Config,
-    ) -> Result<Self, Error> {
+    fn new(name: &'exec SharedStr, gen: &'exec CodeGenerator) -> Result<Self, Error> {
         // Get merged vars
-        let vars = config.vars(name)?;
+        let vars = gen.config.vars(name)?;
 
         Ok(Self {
             name,
             vars,
-            fragments,
-            config,
+            fragments: &gen.code,
+            config: &gen.config,
+            hooks: &gen.hooks,
+            #[cfg(feature = "async")]
+            resolved: HashMap::new(),
         })
     }
 
-    fn assemble_source(
+    fn assemble_tokens(
         &self,
-        results: Vec<TokenStream>,
-        top_results: Vec<TokenStream>,
-        uses: Vec<UseItems>,
-    ) -> Result<String, Error> {
-        // Would be nice to make this a constant, but _comment_! marker needs a literal
-        let comment = quote! {
-            _comment_!("+-------------------------------------------------------------------------------------------------+");
-            _comment_!("| WARNING: This file has been auto-generated using FlexGen (https://github.com/nu11ptr/flexgen).  |");
-            _comment_!("| Any manual modifications to this file will be overwritten the next time this file is generated. |");
-            _comment_!("+-------------------------------------------------------------------------------------------------+");
-        };
+        parts: FileParts,
+        uses: StdExtCrateUse,
+    ) -> Result<TokenStream, Error> {
+        let (std_uses, ext_uses, crate_uses) = uses;
+        let mut top_results = Some(parts.top_results);
+        let mut sections = Some(parts.sections);
+
+        let layout = self.config.file_layout(self.name)?;
+        let mut tokens = Vec::with_capacity(layout.len());
+
+        for part in layout {
+            match part {
+                LayoutPart::Banner => {
+                    // Would be nice to make this a constant, but _comment_! marker needs a literal
+                    tokens.push(quote! {
+                        _comment_!("+-------------------------------------------------------------------------------------------------+");
+                        _comment_!("| WARNING: This file has been auto-generated using FlexGen (https://github.com/nu11ptr/flexgen).  |");
+                        _comment_!("| Any manual modifications to this file will be overwritten the next time this file is generated. |");
+                        _comment_!("+-------------------------------------------------------------------------------------------------+");
+                        _blank_!();
+                    });
+                }
+                LayoutPart::Top => {
+                    // Each part can only be used once (validated when the config was loaded)
+                    let top_results = top_results.take().unwrap_or_default();
+                    tokens.push(quote! { #( #top_results )* });
+                }
+                LayoutPart::Uses => {
+                    tokens.push(quote! {
+                        #( #std_uses )*
+                        _blank_!();
+                        #( #ext_uses )*
+                        _blank_!();
+                        #( #crate_uses )*
+                        _blank_!();
+                    });
+                }
+                LayoutPart::Sections => {
+                    // Each section is separated by a blank line as is each result within a section
+                    let sections = sections.take().unwrap_or_default();
+                    let mut results = Vec::with_capacity(sections.len() * 4);
+
+                    for (_, section) in sections {
+                        for section_tokens in section {
+                            if !results.is_empty() {
+                                results.push(quote! { _blank_!(); });
+                            }
+                            results.push(section_tokens);
+                        }
+                    }
 
-        let builder = UseBuilder::from_uses(uses);
-        let (std_uses, ext_uses, crate_uses) = builder.into_items_sections()?;
+                    tokens.push(quote! { #( #results )* });
+                }
+            }
+        }
 
-        let tokens = quote! {
-            #comment
-            _blank_!();
+        Ok(quote! { #( #tokens )* })
+    }
 
-            #( #top_results )*
+    fn format_source(&self, tokens: TokenStream) -> Result<String, Error> {
+        let source = match self.config.build_rust_fmt_only() {
+            // RustFmt is the single source of truth for style, so PrettyPlease is skipped entirely
+            Some(rust_fmt) => rust_fmt.format_tokens(tokens)?,
+            None => {
+                let config = rust_format::Config::new_str().post_proc(PostProcess::ReplaceMarkers);
+                let formatter = PrettyPlease::from_config(config);
+                let source = formatter.format_tokens(tokens)?;
+
+                // Either return after PrettyPlease format or do one last final RustFmt run
+                match self.config.build_rust_fmt() {
+                    Some(rust_fmt) => rust_fmt.format_str(source)?,
+                    None => source,
+                }
+            }
+        };
 
-            #( #std_uses )*
-            _blank_!();
-            #( #ext_uses )*
-            _blank_!();
-            #( #crate_uses )*
-            _blank_!();
+        check_leftover_markers(self.name, &source)?;
+        Ok(source)
+    }
 
-            #( #results )*
-        };
+    fn fragment(&self, name: &SharedStr) -> &dyn CodeFragment {
+        // Async fragments were already awaited and their results stored
+        #[cfg(feature = "async")]
+        if let Some(fragment) = self.resolved.get(name) {
+            return fragment;
+        }
 
-        let config = rust_format::Config::new_str().post_proc(PostProcess::ReplaceMarkers);
-        let formatter = PrettyPlease::from_config(config);
-        let source = formatter.format_tokens(tokens)?;
+        // Panic safety: This was pre-validated
+        self.fragments[name]
+    }
 
-        // Either return after PrettyPlease format or do one last final RustFmt run
-        Ok(match self.config.build_rust_fmt() {
-            Some(rust_fmt) => rust_fmt.format_str(source)?,
-            None => source,
-        })
+    // Fragments reached via fragment lists with vars get their own merged vars
+    fn scoped_vars(&self, scope: &[SharedStr]) -> Result<Option<TokenVars>, Error> {
+        if scope.is_empty() {
+            Ok(None)
+        } else {
+            self.config.scoped_vars(self.name, scope).map(Some)
+        }
     }
 
-    fn build_source(
+    fn build_fragment(
         &self,
-        fragments: &[FragmentItem],
-        exceptions: &[SharedStr],
-        results: &mut Vec<TokenStream>,
-        top_results: &mut Vec<TokenStream>,
-        use_trees: &mut Vec<UseItems>,
+        name: &SharedStr,
+        scope: &[SharedStr],
+        parts: &mut FileParts,
     ) -> Result<(), Error> {
-        for (idx, fragment) in fragments.iter().enumerate() {
-            match fragment {
-                FragmentItem::FragmentListRef(name) => {
-                    if exceptions.contains(name) {
-                        continue;
-                    }
+        let fragment = self.fragment(name);
+        let scoped_vars = self.scoped_vars(scope)?;
+        let vars = scoped_vars.as_ref().unwrap_or(&self.vars);
+
+        for (section, results) in &mut parts.sections {
+            let tokens = section
+                .generate(fragment, vars)
+                .map_err(|err| err.with_fragment_context(name, (*section).into()))?;
+            if !tokens.is_empty() {
+                results.push(tokens);
+            }
+        }
 
-                    let fragments = self.config.fragment_list(name)?;
-                    return self.build_source(
-                        fragments,
-                        exceptions,
-                        results,
-                        top_results,
-                        use_trees,
-                    );
+        let top_tokens = fragment
+            .generate_top(vars)
+            .map_err(|err| err.with_fragment_context(name, FragmentPhase::Top))?;
+        if !top_tokens.is_empty() {
+            parts.top_results.push(top_tokens);
+        }
+
+        // Store the use tree, if we had one
+        let use_tokens = fragment
+            .uses(vars)
+            .and_then(|tokens| {
+                if tokens.is_empty() {
+                    Ok(None)
+                } else {
+                    Ok(Some(syn::parse2(tokens)?))
                 }
-                FragmentItem::Fragment(name) => {
-                    if exceptions.contains(name) {
-                        continue;
-                    }
+            })
+            .map_err(|err| err.with_fragment_context(name, FragmentPhase::Uses))?;
+        if let Some(use_items) = use_tokens {
+            parts.uses.push(use_items)
+        }
 
-                    // Panic safety: This was pre-validated
-                    let fragment = self.fragments[name];
-                    let tokens = fragment.generate(&self.vars)?;
-                    if !tokens.is_empty() {
-                        results.push(tokens);
-                    }
+        Ok(())
+    }
 
-                    let top_tokens = fragment.generate_top(&self.vars)?;
-                    if !top_tokens.is_empty() {
-                        top_results.push(top_tokens);
-                    }
+    fn collect_fragments(&self) -> Result<FileParts, Error> {
+        // Fragment list references are expanded in place and exceptions are already removed
+        let fragments = self.config.file_fragment_scopes(self.name)?;
+        let mut parts = FileParts::new(self.config.file_sections(self.name)?, fragments.len());
 
-                    // Store the use tree, if we had one
-                    let use_tokens = fragment.uses(&self.vars)?;
-                    if !use_tokens.is_empty() {
-                        use_trees.push(syn::parse2(use_tokens)?)
-                    }
+        for (name, scope) in &fragments {
+            let start = Instant::now();
+            self.build_fragment(name, scope, &mut parts)
+                .map_err(|err| err.with_file_context(self.name, self.config))?;
 
-                    // Push a blank line on all but the last fragment in the list
-                    if idx < fragments.len() - 1 {
-                        results.push(quote! { _blank_!(); })
-                    }
-                }
-            }
+            parts.timings.push(FragmentTiming {
+                name: name.clone(),
+                generate: start.elapsed(),
+            });
         }
 
-        Ok(())
+        Ok(parts)
+    }
+
+    #[inline]
+    fn build_uses(uses: Vec<UseItems>) -> Result<StdExtCrateUse, Error> {
+        let builder = UseBuilder::from_uses(uses);
+        Ok(builder.into_items_sections()?)
+    }
+
+    fn generate_uses(&self) -> Result<(SharedStr, StdExtCrateUse), Error> {
+        let parts = self.collect_fragments()?;
+        Ok((self.name.clone(), Self::build_uses(parts.uses)?))
+    }
+
+    fn build_tokens(&self) -> Result<(TokenStream, Vec<FragmentTiming>), Error> {
+        let mut parts = self.collect_fragments()?;
+        let timings = mem::take(&mut parts.timings);
+        let uses = Self::build_uses(mem::take(&mut parts.uses))?;
+        let tokens = self.assemble_tokens(parts, uses)?;
+
+        Ok((self.hooks.run_token_hooks(self.name, tokens)?, timings))
+    }
+
+    fn build_source(&self, tokens: TokenStream) -> Result<String, Error> {
+        let source = self.format_source(tokens)?;
+        let source = self.hooks.run_string_hooks(self.name, source)?;
+
+        Ok(match self.config.file_line_ending(self.name)? {
+            Some(line_ending) => line_ending.apply(source),
+            None => source,
+        })
+    }
+
+    fn generate_tokens(&self) -> Result<(SharedStr, TokenStream), Error> {
+        let (tokens, _) = self.build_tokens()?;
+        Ok((self.name.clone(), tokens))
     }
 
     fn generate_string(&self) -> Result<(SharedStr, String), Error> {
-        // TODO: Combine into one call?
-        let fragments = self.config.file_fragment_list(self.name)?;
-        let exceptions = self.config.file_fragment_exceptions(self.name)?;
+        let (tokens, _) = self.build_tokens()?;
+        Ok((self.name.clone(), self.build_source(tokens)?))
+    }
 
-        // TODO: What capacity? (we could have nested lists, etc.)
-        let mut results = Vec::with_capacity(self.fragments.len() * 2);
-        let mut top_results = Vec::with_capacity(3);
-        // Random choice based on a typical file
-        let mut uses = Vec::with_capacity(10);
+    fn generate_file(&self, sink: &dyn FileSink) -> Result<(SharedStr, FileTiming), Error> {
+        let start = Instant::now();
+        let (tokens, fragments) = self.build_tokens()?;
+        let generate = start.elapsed();
 
-        self.build_source(
-            fragments,
-            exceptions,
-            &mut results,
-            &mut top_results,
-            &mut uses,
-        )?;
-        let source = self.assemble_source(results, top_results, uses)?;
+        let start = Instant::now();
+        let source = self.build_source(tokens)?;
+        let format = start.elapsed();
+
+        let start = Instant::now();
+        sink.write(&self.config.file_path(self.name)?, &source)?;
+        if let Some(path) = self.config.file_api_summary_path(self.name)? {
+            let summary = ApiSummary::from_source(self.name.clone(), &source)?;
+            sink.write(&path, &summary.to_string())?;
+        }
+        let write = start.elapsed();
 
-        Ok((self.name.clone(), source))
+        let timing = FileTiming {
+            name: self.name.clone(),
+            fragments,
+            generate,
+            format,
+            write,
+        };
+        Ok((self.name.clone(), timing))
     }
 
-    fn generate_file(&self) -> Result<(), Error> {
-        let (_, source) = self.generate_string()?;
+    fn generate_summary(&self) -> Result<(SharedStr, ApiSummary), Error> {
+        let (name, source) = self.generate_string()?;
+        let summary = ApiSummary::from_source(name.clone(), &source)?;
+        Ok((name, summary))
+    }
 
-        let mut file = fs::File::create(self.config.file_path(self.name)?)?;
-        file.write_all(source.as_bytes())?;
-        Ok(())
+    fn compare_file(&self) -> Result<(SharedStr, FileStatus), Error> {
+        let (name, source) = self.generate_string()?;
+        let status = FileStatus::new(&self.config.file_path(self.name)?, &source)?;
+        Ok((name, status))
     }
 }
 
@@ -278,6 +672,7 @@ impl<'exec> FileGenerator<'exec> {
 pub struct CodeGenerator {
     code: CodeFragments,
     config: Config,
+    hooks: Hooks,
 }
 
 impl CodeGenerator {
@@ -285,8 +680,57 @@ impl CodeGenerator {
     /// an [Error] if there are any issues
     #[inline]
     pub fn new(code: CodeFragments, mut config: Config) -> Result<Self, Error> {
-        config.build_and_validate(&code)?;
-        Ok(Self { code, config })
+        config.build_and_validate(|name| code.contains_key(name))?;
+        Ok(Self {
+            code,
+            config,
+            hooks: Hooks::default(),
+        })
+    }
+
+    /// Register a hook for the given file that transforms its assembled tokens before they are
+    /// formatted. Multiple hooks for the same file are run in the order they were added
+    pub fn add_token_hook<F>(&mut self, name: impl FileKey, hook: F) -> Result<(), Error>
+    where
+        F: Fn(TokenStream) -> Result<TokenStream, Error> + Send + Sync + 'static,
+    {
+        let name = self.hook_file(name)?;
+        self.hooks
+            .tokens
+            .entry(name)
+            .or_default()
+            .push(Box::new(hook));
+        Ok(())
+    }
+
+    /// Register a hook for the given file that transforms its source after it has been formatted.
+    /// Multiple hooks for the same file are run in the order they were added
+    pub fn add_string_hook<F>(&mut self, name: impl FileKey, hook: F) -> Result<(), Error>
+    where
+        F: Fn(String) -> Result<String, Error> + Send + Sync + 'static,
+    {
+        let name = self.hook_file(name)?;
+        self.hooks
+            .strings
+            .entry(name)
+            .or_default()
+            .push(Box::new(hook));
+        Ok(())
+    }
+
+    // Ensure hooks are only added for files that exist
+    fn hook_file(&self, name: impl FileKey) -> Result<SharedStr, Error> {
+        let name = name.file_name();
+        self.config.file_path(name)?;
+        Ok(name.into())
+    }
+
+    /// Return the fully resolved configuration: all registered fragments, all fragment lists, and
+    /// each file with the effective sequence of fragments it will generate. The result implements
+    /// `Display` for easy printing
+    #[inline]
+    pub fn list(&self) -> Result<Listing, Error> {
+        Listing::new(self.code.keys(), &self.config)
     }
 
     fn parse_results<T>(results: Vec<Result<T, Error>>) -> Result<Vec<T>, Error> {
@@ -303,42 +747,113 @@ impl CodeGenerator {
         if errors.is_empty() {
             Ok(source)
         } else {
-            Err(Error::ExecutionErrors(errors))
+            Err(Error::ExecutionErrors(ErrorReport::new(errors)))
         }
     }
 
-    fn generate(&self, to_file: bool) -> Result<HashMap<SharedStr, String>, Error> {
-        let names = self.config.file_names();
+    // Runs the given generation function for a single file and adds file context to any error
+    fn generate_file_with<T, F>(&self, name: &SharedStr, f: F) -> Result<T, Error>
+    where
+        F: FnOnce(&FileGenerator) -> Result<T, Error>,
+    {
+        FileGenerator::new(name, self)
+            .and_then(|file_gen| f(&file_gen))
+            .map_err(|err| err.with_file_context(name, &self.config))
+    }
 
-        Ok(if to_file {
-            let results: Vec<Result<_, _>> = names
-                .par_iter()
-                .map(|&name| FileGenerator::new(name, &self.code, &self.config)?.generate_file())
-                .collect();
+    fn generate_parallel<T, F>(&self, f: F) -> Result<HashMap<SharedStr, T>, Error>
+    where
+        T: Send,
+        F: Fn(&FileGenerator) -> Result<(SharedStr, T), Error> + Sync,
+    {
+        let results: Vec<Result<_, _>> = self
+            .config
+            .file_names()
+            .par_iter()
+            .map(|&name| self.generate_file_with(name, &f))
+            .collect();
+        let results: HashMap<_, _> = Self::parse_results(results)?.into_iter().collect();
+        Ok(results)
+    }
 
-            Self::parse_results(results)?;
-            HashMap::new()
-        } else {
-            let results: Vec<Result<_, _>> = names
-                .par_iter()
-                .map(|&name| FileGenerator::new(name, &self.code, &self.config)?.generate_string())
-                .collect();
-            let results: HashMap<_, _> = Self::parse_results(results)?.into_iter().collect();
-            results
-        })
+    // Some of our results (TokenStream, syn items) are not Send, so those are done sequentially
+    fn generate_sequential<T, F>(&self, f: F) -> Result<HashMap<SharedStr, T>, Error>
+    where
+        F: Fn(&FileGenerator) -> Result<(SharedStr, T), Error>,
+    {
+        let results: Vec<Result<_, _>> = self
+            .config
+            .file_names()
+            .into_iter()
+            .map(|name| self.generate_file_with(name, &f))
+            .collect();
+        let results: HashMap<_, _> = Self::parse_results(results)?.into_iter().collect();
+        Ok(results)
+    }
+
+    /// Generate the files listed in the [Config], but return them as a map of the assembled (but not
+    /// yet formatted) token streams instead of actually writing them to the filesystem. The token
+    /// streams still contain any `_blank_!` and `_comment_!` markers
+    #[inline]
+    pub fn generate_token_streams(&self) -> Result<HashMap<SharedStr, TokenStream>, Error> {
+        self.generate_sequential(|file_gen| file_gen.generate_tokens())
+    }
+
+    /// Return the merged and deduplicated `use` items for each file listed in the [Config] without
+    /// generating the files. Each entry is partitioned into std, external, and crate level uses in
+    /// the same way they would be emitted into the file
+    #[inline]
+    pub fn generate_use_items(&self) -> Result<HashMap<SharedStr, StdExtCrateUse>, Error> {
+        self.generate_sequential(|file_gen| file_gen.generate_uses())
     }
 
     /// Generate the files listed in the [Config], but return them as a map of strings instead of
     /// actually writing them to he filesystem
     #[inline]
     pub fn generate_strings(&self) -> Result<HashMap<SharedStr, String>, Error> {
-        self.generate(false)
+        self.generate_parallel(|file_gen| file_gen.generate_string())
+    }
+
+    /// Generate the files listed in the [Config] and return an [ApiSummary] of each one (public
+    /// types, functions, and trait impls) without writing anything. Files with `api_summary` set
+    /// in the config have their summary written alongside them by [generate_files](Self::generate_files)
+    #[inline]
+    pub fn api_summaries(&self) -> Result<HashMap<SharedStr, ApiSummary>, Error> {
+        self.generate_parallel(|file_gen| file_gen.generate_summary())
+    }
+
+    /// Generate the files listed in the [Config] and compare each one against the file currently
+    /// on disk, without writing anything. The result is a map of file name to [FileStatus]
+    #[inline]
+    pub fn compare(&self) -> Result<HashMap<SharedStr, FileStatus>, Error> {
+        self.generate_parallel(|file_gen| file_gen.compare_file())
+    }
+
+    /// Generate the files listed in the [Config] into the given [FileSink]. It returns a
+    /// [TimingReport] with the wall time spent generating, formatting, and writing each file (and
+    /// generating each fragment)
+    pub fn generate_files_to(&self, sink: &dyn FileSink) -> Result<TimingReport, Error> {
+        let start = Instant::now();
+        let files = self.generate_parallel(|file_gen| file_gen.generate_file(sink))?;
+        Ok(TimingReport::new(
+            files.into_values().collect(),
+            start.elapsed(),
+        ))
+    }
+
+    /// Generate the files listed in the [Config] into memory instead of the filesystem. It returns
+    /// the tree of files that would have been written, keyed by their fully resolved paths
+    pub fn generate_in_memory(&self) -> Result<BTreeMap<PathBuf, String>, Error> {
+        let sink = MemorySink::new();
+        self.generate_files_to(&sink)?;
+        Ok(sink.into_files())
     }
 
-    /// Generate the files listed in the [Config]
+    /// Generate the files listed in the [Config]. It returns a [TimingReport] with the wall time
+    /// spent generating, formatting, and writing each file (and generating each fragment)
     #[inline]
-    pub fn generate_files(&self) -> Result<(), Error> {
-        self.generate(true).map(|_| ())
+    pub fn generate_files(&self) -> Result<TimingReport, Error> {
+        self.generate_files_to(&FsSink)
     }
 }
 
@@ -370,4 +885,514 @@ pub trait CodeFragment {
     fn generate(&self, vars: &TokenVars) -> Result<TokenStream, Error> {
         Ok(quote! {})
     }
+
+    /// Generate the type definitions section of the source file. Each snippet will be collected in
+    /// order from all fragments and emitted where the file's section layout places it
+    #[inline]
+    fn generate_types(&self, vars: &TokenVars) -> Result<TokenStream, Error> {
+        Ok(quote! {})
+    }
+
+    /// Generate the impl blocks section of the source file. Each snippet will be collected in
+    /// order from all fragments and emitted where the file's section layout places it
+    #[inline]
+    fn generate_impls(&self, vars: &TokenVars) -> Result<TokenStream, Error> {
+        Ok(quote! {})
+    }
+
+    /// Generate the tests section of the source file. Each snippet will be collected in order from
+    /// all fragments and emitted where the file's section layout places it
+    #[inline]
+    fn generate_tests(&self, vars: &TokenVars) -> Result<TokenStream, Error> {
+        Ok(quote! {})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use flexstr::shared_str;
+    use pretty_assertions::assert_eq;
+    use proc_macro2::TokenStream;
+    use quote::quote;
+
+    use crate::compare::FileStatus;
+    use crate::config::{Config, LayoutPart};
+    use crate::var::TokenVars;
+    use crate::{
+        check_leftover_markers, import_vars, CodeFragment, CodeGenerator, Error, FragmentPhase,
+    };
+
+    macro_rules! fragments {
+        ($($fragment:ident => $func:ident),+) => {
+            $(
+                struct $fragment;
+
+                impl CodeFragment for $fragment {
+                    fn generate(&self, _vars: &TokenVars) -> Result<TokenStream, Error> {
+                        Ok(quote! { fn $func() {} })
+                    }
+                }
+            )+
+        };
+    }
+
+    fragments!(First => first, One => one, Two => two, Middle => middle, Three => three, Last => last);
+
+    macro_rules! typed_fragments {
+        ($($fragment:ident => $ty:ident),+) => {
+            $(
+                struct $fragment;
+
+                impl CodeFragment for $fragment {
+                    fn generate_types(&self, _vars: &TokenVars) -> Result<TokenStream, Error> {
+                        Ok(quote! { struct $ty; })
+                    }
+
+                    fn generate_impls(&self, _vars: &TokenVars) -> Result<TokenStream, Error> {
+                        Ok(quote! { impl $ty {} })
+                    }
+
+                    fn generate_tests(&self, _vars: &TokenVars) -> Result<TokenStream, Error> {
+                        Ok(quote! { fn $fragment() {} })
+                    }
+                }
+            )+
+        };
+    }
+
+    typed_fragments!(TypeA => A, TypeB => B);
+
+    struct Failing;
+
+    impl CodeFragment for Failing {
+        fn generate(&self, vars: &TokenVars) -> Result<TokenStream, Error> {
+            import_vars! { vars => missing };
+            Ok(quote! { const MISSING: u32 = #missing; })
+        }
+    }
+
+    const HEADER: &str = r#"// +-------------------------------------------------------------------------------------------------+
+// | WARNING: This file has been auto-generated using FlexGen (https://github.com/nu11ptr/flexgen).  |
+// | Any manual modifications to this file will be overwritten the next time this file is generated. |
+// +-------------------------------------------------------------------------------------------------+
+
+
+
+
+"#;
+
+    fn generate(config: &str) -> String {
+        let fragments = register_fragments!(First, One, Two, Middle, Three, Last);
+        let config = Config::from_toml_reader(config.as_bytes()).unwrap();
+        let gen = CodeGenerator::new(fragments, config).unwrap();
+
+        let mut files = gen.generate_strings().unwrap();
+        files.remove(&shared_str!("test")).unwrap()
+    }
+
+    #[test]
+    fn mixed_fragment_lists() {
+        let config = r#"
+            [general.rust_fmt]
+            omit_final_format = true
+
+            [fragment_lists]
+            all = [ "first", "inner1", "middle", "inner2", "last" ]
+            inner1 = [ "one", "two" ]
+            inner2 = [ "three" ]
+
+            [files.test]
+            path = "test.rs"
+            fragment_list = "all"
+            vars = {}
+        "#;
+
+        let expected = format!(
+            "{HEADER}fn first() {{}}\n\nfn one() {{}}\n\nfn two() {{}}\n\nfn middle() {{}}\n\n\
+            fn three() {{}}\n\nfn last() {{}}\n"
+        );
+        assert_eq!(expected, generate(config));
+    }
+
+    #[test]
+    fn mixed_fragment_lists_exceptions() {
+        let config = r#"
+            [general.rust_fmt]
+            omit_final_format = true
+
+            [fragment_lists]
+            all = [ "first", "inner1", "middle", "inner2", "last" ]
+            inner1 = [ "one", "two" ]
+            inner2 = [ "three" ]
+
+            [files.test]
+            path = "test.rs"
+            fragment_list = "all"
+            fragment_list_exceptions = [ "two", "inner2" ]
+            vars = {}
+        "#;
+
+        let expected = format!(
+            "{HEADER}fn first() {{}}\n\nfn one() {{}}\n\nfn middle() {{}}\n\nfn last() {{}}\n"
+        );
+        assert_eq!(expected, generate(config));
+    }
+
+    #[test]
+    fn section_layout() {
+        let config = r#"
+            [general.rust_fmt]
+            omit_final_format = true
+
+            [fragment_lists]
+            all = [ "type_a", "type_b" ]
+
+            [files.test]
+            path = "test.rs"
+            fragment_list = "all"
+            sections = [ "types", "impls" ]
+            vars = {}
+        "#;
+
+        let fragments = register_fragments!(TypeA, TypeB);
+        let config = Config::from_toml_reader(config.as_bytes()).unwrap();
+        let gen = CodeGenerator::new(fragments, config).unwrap();
+        let actual = gen
+            .generate_strings()
+            .unwrap()
+            .remove(&shared_str!("test"))
+            .unwrap();
+
+        let expected = format!("{HEADER}struct A;\n\nstruct B;\n\nimpl A {{}}\n\nimpl B {{}}\n");
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn file_layout() {
+        let config = r#"
+            [general.rust_fmt]
+            omit_final_format = true
+
+            [fragment_lists]
+            all = [ "first", "last" ]
+
+            [files.test]
+            path = "test.rs"
+            fragment_list = "all"
+            layout = [ "sections" ]
+            vars = {}
+        "#;
+
+        assert_eq!("fn first() {}\n\nfn last() {}\n", generate(config));
+    }
+
+    #[test]
+    fn rust_fmt_only() {
+        let config = r#"
+            [general.rust_fmt]
+            skip_pretty_please = true
+
+            [fragment_lists]
+            all = [ "first", "last" ]
+
+            [files.test]
+            path = "test.rs"
+            fragment_list = "all"
+            layout = [ "sections" ]
+            vars = {}
+        "#;
+
+        assert_eq!("fn first() {}\n\nfn last() {}\n", generate(config));
+    }
+
+    #[test]
+    fn hooks() {
+        let config = r#"
+            [general.rust_fmt]
+            omit_final_format = true
+
+            [fragment_lists]
+            all = [ "first" ]
+
+            [files.test]
+            path = "test.rs"
+            fragment_list = "all"
+            layout = [ "sections" ]
+            vars = {}
+        "#;
+
+        let fragments = register_fragments!(First);
+        let config = Config::from_toml_reader(config.as_bytes()).unwrap();
+        let mut gen = CodeGenerator::new(fragments, config).unwrap();
+
+        gen.add_token_hook("test", |tokens| Ok(quote! { #[rustfmt::skip] #tokens }))
+            .unwrap();
+        gen.add_string_hook("test", |source| Ok(format!("// Hooked\n{source}")))
+            .unwrap();
+        assert!(matches!(
+            gen.add_token_hook("missing", Ok),
+            Err(Error::FileNotFound(_))
+        ));
+
+        let actual = gen
+            .generate_strings()
+            .unwrap()
+            .remove(&shared_str!("test"))
+            .unwrap();
+        assert_eq!("// Hooked\n#[rustfmt::skip]\nfn first() {}\n", actual);
+    }
+
+    #[test]
+    fn file_layout_duplicate() {
+        let config = r#"
+            [fragment_lists]
+            all = [ "first" ]
+
+            [files.test]
+            path = "test.rs"
+            fragment_list = "all"
+            layout = [ "sections", "banner", "sections" ]
+            vars = {}
+        "#;
+
+        let fragments = register_fragments!(First);
+        let config = Config::from_toml_reader(config.as_bytes()).unwrap();
+
+        assert!(matches!(
+            CodeGenerator::new(fragments, config),
+            Err(Error::DuplicateLayoutPart(LayoutPart::Sections, _))
+        ));
+    }
+
+    #[test]
+    fn file_error_context() {
+        let config = r#"
+            [fragment_lists]
+            all = [ "first", "failing" ]
+
+            [files.test]
+            path = "test.rs"
+            fragment_list = "all"
+            vars = {}
+        "#;
+
+        let fragments = register_fragments!(First, Failing);
+        let config = Config::from_toml_reader(config.as_bytes()).unwrap();
+        let gen = CodeGenerator::new(fragments, config).unwrap();
+
+        match gen.generate_strings() {
+            Err(Error::ExecutionErrors(errors)) => match errors.errors() {
+                [Error::FileError(name, path, err)] => {
+                    assert_eq!("test", &**name);
+                    assert_eq!(Some(Path::new("test.rs")), path.as_deref());
+
+                    match &**err {
+                        Error::FragmentError(fragment, phase, err) => {
+                            assert_eq!("failing", &**fragment);
+                            assert_eq!(FragmentPhase::Body, *phase);
+                            assert!(
+                                matches!(**err, Error::MissingVar(ref var) if &**var == "missing")
+                            );
+                        }
+                        _ => panic!("Expected a fragment error"),
+                    }
+                }
+                _ => panic!("Expected a single file error"),
+            },
+            _ => panic!("Expected execution errors"),
+        }
+    }
+
+    // The cause is only available via `source`, and the path is omitted when it can't be resolved
+    #[test]
+    fn file_error_unresolved_path() {
+        let config = r#"
+            [general]
+            base_path = "$FLEXGEN_TEST_UNSET_VAR/src"
+
+            [fragment_lists]
+            all = [ "failing" ]
+
+            [files.test]
+            path = "test.rs"
+            fragment_list = "all"
+            vars = {}
+        "#;
+
+        let fragments = register_fragments!(Failing);
+        let config = Config::from_toml_reader(config.as_bytes()).unwrap();
+        let gen = CodeGenerator::new(fragments, config).unwrap();
+
+        match gen.generate_strings() {
+            Err(Error::ExecutionErrors(errors)) => match errors.errors() {
+                [err @ Error::FileError(_, None, _)] => {
+                    assert_eq!(
+                        "An error occurred while generating file 'test'",
+                        err.to_string()
+                    );
+                    assert_eq!(
+                        "Code fragment 'failing' failed in 'generate'",
+                        std::error::Error::source(err).unwrap().to_string()
+                    );
+                }
+                _ => panic!("Expected a single file error without a path"),
+            },
+            _ => panic!("Expected execution errors"),
+        }
+    }
+
+    #[test]
+    fn compare() {
+        let dir = tempfile::tempdir().unwrap();
+        let base_path = dir.path().join("generated");
+        std::fs::create_dir_all(&base_path).unwrap();
+
+        let config = r#"
+            [general]
+            base_path = "generated"
+
+            [fragment_lists]
+            all = [ "first" ]
+
+            [files.unchanged]
+            path = "unchanged.rs"
+            fragment_list = "all"
+            vars = {}
+
+            [files.modified]
+            path = "modified.rs"
+            fragment_list = "all"
+            vars = {}
+
+            [files.added]
+            path = "added.rs"
+            fragment_list = "all"
+            vars = {}
+
+            [files.missing]
+            path = "missing/missing.rs"
+            fragment_list = "all"
+            vars = {}
+        "#;
+
+        let fragments = register_fragments!(First);
+        let config = Config::from_toml_reader(config.as_bytes())
+            .unwrap()
+            .relative_to(dir.path());
+        let gen = CodeGenerator::new(fragments, config).unwrap();
+
+        let source = gen
+            .generate_strings()
+            .unwrap()
+            .remove(&shared_str!("unchanged"))
+            .unwrap();
+        std::fs::write(base_path.join("unchanged.rs"), &source).unwrap();
+        std::fs::write(base_path.join("modified.rs"), "fn old() {}\n").unwrap();
+
+        let mut statuses = gen.compare().unwrap();
+
+        assert_eq!(
+            FileStatus::Unchanged,
+            statuses.remove(&shared_str!("unchanged")).unwrap()
+        );
+        assert_eq!(
+            FileStatus::Added,
+            statuses.remove(&shared_str!("added")).unwrap()
+        );
+        assert_eq!(
+            FileStatus::Missing,
+            statuses.remove(&shared_str!("missing")).unwrap()
+        );
+        match statuses.remove(&shared_str!("modified")).unwrap() {
+            FileStatus::Modified { diff } => {
+                assert!(diff.contains("-fn old() {}\n"));
+                assert!(diff.contains("+fn first() {}\n"));
+            }
+            status => panic!("Expected a modified file, got: {status:?}"),
+        }
+    }
+
+    #[test]
+    fn generate_in_memory() {
+        let config = r#"
+            [general]
+            base_path = "src"
+
+            [general.rust_fmt]
+            omit_final_format = true
+
+            [fragment_lists]
+            all = [ "first" ]
+
+            [files.first]
+            path = "first.rs"
+            fragment_list = "all"
+            layout = [ "sections" ]
+            vars = {}
+
+            [files.nested]
+            path = "nested/first.rs"
+            fragment_list = "all"
+            layout = [ "sections" ]
+            vars = {}
+        "#;
+
+        let fragments = register_fragments!(First);
+        let config = Config::from_toml_reader(config.as_bytes()).unwrap();
+        let gen = CodeGenerator::new(fragments, config).unwrap();
+
+        let files: Vec<_> = gen.generate_in_memory().unwrap().into_iter().collect();
+        let expected = vec![
+            (
+                ["src", "first.rs"].iter().collect::<PathBuf>(),
+                "fn first() {}\n".to_string(),
+            ),
+            (
+                ["src", "nested", "first.rs"].iter().collect::<PathBuf>(),
+                "fn first() {}\n".to_string(),
+            ),
+        ];
+        assert_eq!(expected, files);
+    }
+
+    #[test]
+    fn no_leftover_markers() {
+        let source = "// _blank_!();\nfn main() {\n    // _comment_!(\"test\");\n}\n";
+        assert!(check_leftover_markers(&shared_str!("test"), source).is_ok());
+    }
+
+    #[test]
+    fn leftover_markers() {
+        let source = "fn main() {\n    println!(\"test\");\n    _blank_!();\n}\n";
+
+        match check_leftover_markers(&shared_str!("test"), source) {
+            Err(Error::LeftoverMarker(name, 3)) if &*name == "test" => {}
+            _ => panic!("Expected a leftover marker on line 3"),
+        }
+    }
+
+    #[test]
+    fn no_leftover_markers_in_literals() {
+        let source = r####"
+            #[doc = "Uses _blank_!() and _comment_!(\"test\")"]
+            fn main<'a>(s: &'a str) {
+                let _ = ('"', '\'', b'_', "_blank_!()", br#"_comment_!("test")"#);
+                /* _blank_!(); /* nested */ _blank_!(); */
+            }
+        "####;
+        assert!(check_leftover_markers(&shared_str!("test"), source).is_ok());
+    }
+
+    // A `//` inside a string doesn't start a comment, so the marker after it is still found
+    #[test]
+    fn leftover_marker_after_url() {
+        let source = "fn main() {\n    let url = \"http://example.com\"; _blank_!();\n}\n";
+
+        match check_leftover_markers(&shared_str!("test"), source) {
+            Err(Error::LeftoverMarker(name, 2)) if &*name == "test" => {}
+            _ => panic!("Expected a leftover marker on line 2"),
+        }
+    }
 }
@@ -0,0 +1,266 @@
+use std::fmt;
+use std::path::PathBuf;
+
+use flexstr::SharedStr;
+
+use crate::config::{Config, FragmentItem};
+use crate::Error;
+
+// *** Listing ***
+
+/// A single file entry in a [Listing]
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileListing {
+    /// The name of the file section in the config
+    pub name: SharedStr,
+    /// The full path of the file to be generated. If the base path starts with a `$VAR` component
+    /// whose environment variable isn't set, the path is given unresolved (still containing `$VAR`)
+    pub path: PathBuf,
+    /// The fragment list used by this file
+    pub fragment_list: SharedStr,
+    /// The effective sequence of fragments after expanding lists and applying exceptions
+    pub fragments: Vec<SharedStr>,
+}
+
+/// The fully resolved configuration: all registered fragments, all fragment lists (after they have
+/// been built), and each file with the sequence of fragments that will actually be generated
+#[derive(Clone, Debug, PartialEq)]
+pub struct Listing {
+    /// The names of all registered code fragments (sorted)
+    pub fragments: Vec<SharedStr>,
+    /// Each fragment list (in configuration order) along with its items
+    pub fragment_lists: Vec<(SharedStr, Vec<FragmentItem>)>,
+    /// Each file (sorted by name)
+    pub files: Vec<FileListing>,
+}
+
+impl Listing {
+    pub(crate) fn new<'a>(
+        code: impl Iterator<Item = &'a SharedStr>,
+        config: &Config,
+    ) -> Result<Self, Error> {
+        let mut fragments: Vec<_> = code.cloned().collect();
+        fragments.sort();
+
+        let fragment_lists = config
+            .fragment_list_names()
+            .into_iter()
+            .map(|name| Ok((name.clone(), config.fragment_list(name)?.clone())))
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        let mut file_names = config.file_names();
+        file_names.sort();
+        let files = file_names
+            .into_iter()
+            .map(|name| {
+                Ok(FileListing {
+                    name: name.clone(),
+                    path: match config.file_path(name) {
+                        Err(Error::MissingEnvVar(_)) => config.unresolved_file_path(name)?,
+                        path => path?,
+                    },
+                    fragment_list: config.file_fragment_list_name(name)?.clone(),
+                    fragments: config.file_fragments(name)?,
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        Ok(Self {
+            fragments,
+            fragment_lists,
+            files,
+        })
+    }
+
+    /// Render the relationships between files, fragment lists (including list to list references),
+    /// and code fragments as a [DOT](https://graphviz.org/doc/info/lang.html) graph for use with
+    /// Graphviz
+    pub fn to_dot(&self) -> String {
+        // Files and fragment lists can share names, so each node type gets its own prefix
+        let mut buffer = String::with_capacity(1024);
+        buffer.push_str("digraph flexgen {\n");
+        buffer.push_str("    rankdir=LR;\n");
+
+        for file in &self.files {
+            let name = escape_dot(&file.name);
+            buffer.push_str(&format!(
+                "    \"file:{name}\" [label=\"{name}\\n{}\", shape=note];\n",
+                escape_dot(&file.path.display().to_string())
+            ));
+            buffer.push_str(&format!(
+                "    \"file:{name}\" -> \"list:{}\";\n",
+                escape_dot(&file.fragment_list)
+            ));
+        }
+
+        for (name, items) in &self.fragment_lists {
+            let name = escape_dot(name);
+            buffer.push_str(&format!(
+                "    \"list:{name}\" [label=\"{name}\", shape=folder];\n"
+            ));
+
+            for item in items {
+                let target = match item {
+                    FragmentItem::Fragment(name) => format!("fragment:{}", escape_dot(name)),
+                    FragmentItem::FragmentListRef(name) => format!("list:{}", escape_dot(name)),
+                };
+                buffer.push_str(&format!("    \"list:{name}\" -> \"{target}\";\n"));
+            }
+        }
+
+        for fragment in &self.fragments {
+            let fragment = escape_dot(fragment);
+            buffer.push_str(&format!(
+                "    \"fragment:{fragment}\" [label=\"{fragment}\", shape=box];\n"
+            ));
+        }
+
+        buffer.push_str("}\n");
+        buffer
+    }
+}
+
+// Escape a value for use inside a quoted DOT identifier
+fn escape_dot(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+// Fragment list references are shown in brackets to distinguish them from code fragments
+impl fmt::Display for Listing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Fragments:")?;
+        for fragment in &self.fragments {
+            writeln!(f, "    {fragment}")?;
+        }
+
+        writeln!(f)?;
+        writeln!(f, "Fragment lists:")?;
+        for (name, items) in &self.fragment_lists {
+            write!(f, "    {name}: ")?;
+
+            for (idx, item) in items.iter().enumerate() {
+                if idx > 0 {
+                    f.write_str(", ")?;
+                }
+
+                match item {
+                    FragmentItem::Fragment(name) => f.write_str(name)?,
+                    FragmentItem::FragmentListRef(name) => write!(f, "[{name}]")?,
+                }
+            }
+            writeln!(f)?;
+        }
+
+        writeln!(f)?;
+        writeln!(f, "Files:")?;
+        for file in &self.files {
+            write!(
+                f,
+                "    {} ({}) [{}]: ",
+                file.name,
+                file.path.display(),
+                file.fragment_list
+            )?;
+            writeln!(f, "{}", file.fragments.join(", "))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use flexstr::shared_str;
+    use pretty_assertions::assert_eq;
+
+    use crate::config::{Config, FragmentItem};
+    use crate::list::{FileListing, Listing};
+
+    #[test]
+    fn to_dot() {
+        let listing = Listing {
+            fragments: vec![shared_str!("empty")],
+            fragment_lists: vec![
+                (
+                    shared_str!("impl"),
+                    vec![FragmentItem::FragmentListRef(shared_str!("str"))],
+                ),
+                (
+                    shared_str!("str"),
+                    vec![FragmentItem::Fragment(shared_str!("empty"))],
+                ),
+            ],
+            files: vec![FileListing {
+                name: shared_str!("str"),
+                path: PathBuf::from("str.rs"),
+                fragment_list: shared_str!("impl"),
+                fragments: vec![shared_str!("empty")],
+            }],
+        };
+
+        let expected = r#"digraph flexgen {
+    rankdir=LR;
+    "file:str" [label="str\nstr.rs", shape=note];
+    "file:str" -> "list:impl";
+    "list:impl" [label="impl", shape=folder];
+    "list:impl" -> "list:str";
+    "list:str" [label="str", shape=folder];
+    "list:str" -> "fragment:empty";
+    "fragment:empty" [label="empty", shape=box];
+}
+"#;
+
+        assert_eq!(expected, listing.to_dot());
+    }
+
+    #[test]
+    fn to_dot_escaped() {
+        let listing = Listing {
+            fragments: vec![],
+            fragment_lists: vec![(shared_str!("my \"list\""), vec![])],
+            files: vec![FileListing {
+                name: shared_str!("str"),
+                path: PathBuf::from(r"C:\generated\str.rs"),
+                fragment_list: shared_str!("my \"list\""),
+                fragments: vec![],
+            }],
+        };
+
+        let expected = r#"digraph flexgen {
+    rankdir=LR;
+    "file:str" [label="str\nC:\\generated\\str.rs", shape=note];
+    "file:str" -> "list:my \"list\"";
+    "list:my \"list\"" [label="my \"list\"", shape=folder];
+}
+"#;
+
+        assert_eq!(expected, listing.to_dot());
+    }
+
+    #[test]
+    fn unresolved_env_path() {
+        const CONFIG: &str = r#"
+            [general]
+            base_path = "$FLEXGEN_TEST_LIST_UNSET_VAR/src"
+
+            [fragment_lists]
+            base = ["empty"]
+
+            [files.str]
+            path = "str.rs"
+            fragment_list = "base"
+
+            [files.str.vars]
+        "#;
+        let config = Config::from_toml_reader(CONFIG.as_bytes()).unwrap();
+        let fragments = [shared_str!("empty")];
+        let listing = Listing::new(fragments.iter(), &config).unwrap();
+
+        let expected: PathBuf = ["$FLEXGEN_TEST_LIST_UNSET_VAR", "src", "str.rs"]
+            .iter()
+            .collect();
+        assert_eq!(expected, listing.files[0].path);
+    }
+}
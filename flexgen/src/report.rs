@@ -0,0 +1,202 @@
+use std::error::Error as StdError;
+use std::fmt;
+use std::path::Path;
+
+use flexstr::SharedStr;
+
+use crate::Error;
+
+// *** Error Report ***
+
+/// A collection of errors that occurred while generating multiple files (or configs). Each error
+/// is kept intact (including its source chain). The report implements `Display` which renders a
+/// readable summary with the errors grouped by file
+#[derive(Debug, Default)]
+pub struct ErrorReport {
+    errors: Vec<Error>,
+}
+
+impl ErrorReport {
+    pub(crate) fn new(errors: Vec<Error>) -> Self {
+        Self { errors }
+    }
+
+    /// Returns all the errors in the report
+    #[inline]
+    pub fn errors(&self) -> &[Error] {
+        &self.errors
+    }
+
+    /// Returns the number of errors in the report
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.errors.len()
+    }
+
+    /// Returns true if the report contains no errors
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    /// Returns an iterator over the errors in the report
+    #[inline]
+    pub fn iter(&self) -> std::slice::Iter<'_, Error> {
+        self.errors.iter()
+    }
+
+    /// Group the errors by the name of the file they occurred in (in the order each file was first
+    /// seen). Errors that aren't specific to a file are grouped under `None`
+    pub fn by_file(&self) -> Vec<(Option<&SharedStr>, Vec<&Error>)> {
+        let mut groups: Vec<(Option<&SharedStr>, Vec<&Error>)> = Vec::new();
+
+        for err in &self.errors {
+            let name = match err {
+                Error::FileError(name, ..) => Some(name),
+                _ => None,
+            };
+
+            match groups.iter_mut().find(|(group, _)| *group == name) {
+                Some((_, errors)) => errors.push(err),
+                None => groups.push((name, vec![err])),
+            }
+        }
+
+        groups
+    }
+}
+
+impl IntoIterator for ErrorReport {
+    type Item = Error;
+    type IntoIter = std::vec::IntoIter<Error>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.errors.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a ErrorReport {
+    type Item = &'a Error;
+    type IntoIter = std::slice::Iter<'a, Error>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.errors.iter()
+    }
+}
+
+fn write_error(f: &mut fmt::Formatter<'_>, err: &Error) -> fmt::Result {
+    // The file is already in the group header, so only the fragment and underlying error are shown
+    let err = match err {
+        Error::FileError(_, _, source) => &**source,
+        err => err,
+    };
+
+    let source: &(dyn StdError + 'static) = match err {
+        Error::FragmentError(fragment, phase, source) => {
+            writeln!(f, "  - in fragment '{fragment}' ({phase}): {source}")?;
+            &**source
+        }
+        err => {
+            writeln!(f, "  - {err}")?;
+            err
+        }
+    };
+
+    let mut cause = source.source();
+    while let Some(err) = cause {
+        writeln!(f, "      caused by: {err}")?;
+        cause = err.source();
+    }
+
+    Ok(())
+}
+
+fn file_path<'a>(errors: &[&'a Error]) -> Option<&'a Path> {
+    errors.iter().find_map(|err| match err {
+        Error::FileError(_, path, ..) => path.as_deref(),
+        _ => None,
+    })
+}
+
+impl fmt::Display for ErrorReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let plural = if self.errors.len() == 1 { "" } else { "s" };
+        writeln!(
+            f,
+            "{} error{plural} occurred during execution",
+            self.errors.len()
+        )?;
+
+        for (name, errors) in self.by_file() {
+            writeln!(f)?;
+
+            match (name, file_path(&errors)) {
+                (Some(name), Some(path)) => writeln!(f, "File '{name}' ({}):", path.display())?,
+                (Some(name), None) => writeln!(f, "File '{name}':")?,
+                (None, _) => writeln!(f, "General:")?,
+            }
+
+            for err in errors {
+                write_error(f, err)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::path::PathBuf;
+
+    use flexstr::shared_str;
+    use pretty_assertions::assert_eq;
+
+    use crate::report::ErrorReport;
+    use crate::{Error, FragmentPhase};
+
+    #[test]
+    fn display() {
+        let file_error =
+            |name, err| Error::FileError(name, Some(PathBuf::from("src/str.rs")), Box::new(err));
+
+        let report = ErrorReport::new(vec![
+            file_error(
+                shared_str!("str"),
+                Error::FragmentError(
+                    shared_str!("empty"),
+                    FragmentPhase::Body,
+                    Box::new(Error::MissingVar(shared_str!("str_type"))),
+                ),
+            ),
+            Error::WrongItem,
+            file_error(
+                shared_str!("str"),
+                Error::IOError(io::Error::new(io::ErrorKind::WriteZero, "disk full")),
+            ),
+        ]);
+
+        let groups: Vec<_> = report
+            .by_file()
+            .into_iter()
+            .map(|(name, errors)| (name.cloned(), errors.len()))
+            .collect();
+        assert_eq!(vec![(Some(shared_str!("str")), 2), (None, 1)], groups);
+
+        let expected = "\
+3 errors occurred during execution
+
+File 'str' (src/str.rs):
+  - in fragment 'empty' (generate): The specified variable 'str_type' was missing.
+  - disk full
+
+General:
+  - The specified item was a 'list' instead of a 'single' item (or vice versa)
+";
+        assert_eq!(expected, report.to_string());
+        assert_eq!(3, report.into_iter().count());
+    }
+}
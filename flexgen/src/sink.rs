@@ -0,0 +1,61 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::Error;
+
+// *** File Sink ***
+
+/// A destination for generated files. Files are generated in parallel, so `write` may be called
+/// concurrently from multiple threads
+pub trait FileSink: Sync {
+    /// Write the source of a single generated file to the given (fully resolved) path
+    fn write(&self, path: &Path, source: &str) -> Result<(), Error>;
+}
+
+/// A [FileSink] that writes files to the filesystem (this is what
+/// [generate_files](crate::CodeGenerator::generate_files) uses)
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FsSink;
+
+impl FileSink for FsSink {
+    fn write(&self, path: &Path, source: &str) -> Result<(), Error> {
+        let mut file = fs::File::create(path)?;
+        file.write_all(source.as_bytes())?;
+        Ok(())
+    }
+}
+
+/// A [FileSink] that keeps files in memory instead of writing them, which is useful for testing
+/// generators without touching the disk
+#[derive(Debug, Default)]
+pub struct MemorySink {
+    files: Mutex<BTreeMap<PathBuf, String>>,
+}
+
+impl MemorySink {
+    /// Create a new empty `MemorySink`
+    #[inline]
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Consume the sink and return the tree of files written to it, keyed by full path
+    #[inline]
+    pub fn into_files(self) -> BTreeMap<PathBuf, String> {
+        // Panic safety: a poisoned lock means a write panicked, so the tree is suspect anyway
+        self.files.into_inner().unwrap()
+    }
+}
+
+impl FileSink for MemorySink {
+    fn write(&self, path: &Path, source: &str) -> Result<(), Error> {
+        self.files
+            .lock()
+            .unwrap()
+            .insert(path.to_path_buf(), source.to_string());
+        Ok(())
+    }
+}
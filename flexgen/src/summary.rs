@@ -0,0 +1,203 @@
+use std::fmt;
+
+use flexstr::SharedStr;
+use proc_macro2::TokenStream;
+use quote::quote;
+use rust_format::{Formatter, PrettyPlease};
+use syn::{Item, Visibility};
+
+use crate::Error;
+
+// *** API Summary ***
+
+/// An at-a-glance index of the API of a single generated file: its public types and functions
+/// (including those in public inline modules) and all of its trait impls. It implements `Display`
+/// which renders it as markdown
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ApiSummary {
+    /// The name of the file section in the config
+    pub name: SharedStr,
+    /// Public structs, enums, unions, type aliases, and traits (such as `struct Foo`)
+    pub types: Vec<String>,
+    /// Public function signatures (such as `fn foo(x: u32) -> u32`)
+    pub functions: Vec<String>,
+    /// Trait impl headers (such as `impl Display for Foo`)
+    pub trait_impls: Vec<String>,
+}
+
+impl ApiSummary {
+    /// Build the summary of the given (final) generated source
+    pub fn from_source(name: SharedStr, source: &str) -> Result<Self, Error> {
+        let file = syn::parse_file(source)?;
+        let mut summary = Self {
+            name,
+            ..Default::default()
+        };
+        summary.add_items(&file.items, "")?;
+        Ok(summary)
+    }
+
+    fn add_items(&mut self, items: &[Item], prefix: &str) -> Result<(), Error> {
+        for item in items {
+            match item {
+                Item::Struct(s) if is_pub(&s.vis) => self.add_type("struct", prefix, &s.ident),
+                Item::Enum(e) if is_pub(&e.vis) => self.add_type("enum", prefix, &e.ident),
+                Item::Union(u) if is_pub(&u.vis) => self.add_type("union", prefix, &u.ident),
+                Item::Type(t) if is_pub(&t.vis) => self.add_type("type", prefix, &t.ident),
+                Item::Trait(t) if is_pub(&t.vis) => self.add_type("trait", prefix, &t.ident),
+                Item::Fn(f) if is_pub(&f.vis) => {
+                    let sig = &f.sig;
+                    let sig = header(quote! { #sig {} })?;
+                    // Qualifiers (const, async, etc.) come first, so the path goes after `fn`
+                    let sig = sig.replacen("fn ", &format!("fn {prefix}"), 1);
+                    self.functions.push(sig);
+                }
+                Item::Impl(i) if i.trait_.is_some() => {
+                    // Strip the body so only the header (with generics and where clause) remains
+                    let mut i = i.clone();
+                    i.attrs.clear();
+                    i.items.clear();
+                    self.trait_impls.push(header(quote! { #i })?);
+                }
+                Item::Mod(m) if is_pub(&m.vis) => {
+                    if let Some((_, items)) = &m.content {
+                        self.add_items(items, &format!("{prefix}{}::", m.ident))?;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    #[inline]
+    fn add_type(&mut self, kind: &str, prefix: &str, ident: &syn::Ident) {
+        self.types.push(format!("{kind} {prefix}{ident}"));
+    }
+
+    /// Returns true if the file has no public types or functions and no trait impls
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.types.is_empty() && self.functions.is_empty() && self.trait_impls.is_empty()
+    }
+}
+
+#[inline]
+fn is_pub(vis: &Visibility) -> bool {
+    matches!(vis, Visibility::Public(_))
+}
+
+// Format an item with an empty body and return its header on a single line
+fn header(tokens: TokenStream) -> Result<String, Error> {
+    let source = PrettyPlease::default().format_tokens(tokens)?;
+    let source = source.trim_end().trim_end_matches("{}").trim_end();
+    // Where clauses are split over multiple lines with a trailing comma
+    let header = source.split_whitespace().collect::<Vec<_>>().join(" ");
+    Ok(header.trim_end_matches(',').to_string())
+}
+
+fn write_section(f: &mut fmt::Formatter<'_>, title: &str, entries: &[String]) -> fmt::Result {
+    if !entries.is_empty() {
+        writeln!(f, "\n## {title}\n")?;
+        for entry in entries {
+            writeln!(f, "- `{entry}`")?;
+        }
+    }
+
+    Ok(())
+}
+
+impl fmt::Display for ApiSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "# API summary: {}", self.name)?;
+
+        if self.is_empty() {
+            return writeln!(f, "\nNo public items");
+        }
+
+        write_section(f, "Types", &self.types)?;
+        write_section(f, "Functions", &self.functions)?;
+        write_section(f, "Trait Impls", &self.trait_impls)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use flexstr::shared_str;
+    use pretty_assertions::assert_eq;
+
+    use crate::summary::ApiSummary;
+
+    const SOURCE: &str = r#"
+        use std::fmt;
+
+        pub struct Point<T> {
+            x: T,
+        }
+
+        struct Hidden;
+
+        impl<T: fmt::Debug> fmt::Debug for Point<T> where T: Clone {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                Ok(())
+            }
+        }
+
+        impl Hidden {
+            pub fn new() -> Self {
+                Hidden
+            }
+        }
+
+        pub fn origin(x: u32, y: u32) -> Point<u32> {
+            todo!()
+        }
+
+        fn private() {}
+
+        pub mod shapes {
+            pub enum Shape {
+                Circle,
+            }
+
+            pub fn area(shape: &Shape) -> f64 {
+                0.0
+            }
+        }
+    "#;
+
+    #[test]
+    fn summary() {
+        let summary = ApiSummary::from_source(shared_str!("point"), SOURCE).unwrap();
+
+        let expected = "\
+# API summary: point
+
+## Types
+
+- `struct Point`
+- `enum shapes::Shape`
+
+## Functions
+
+- `fn origin(x: u32, y: u32) -> Point<u32>`
+- `fn shapes::area(shape: &Shape) -> f64`
+
+## Trait Impls
+
+- `impl<T: fmt::Debug> fmt::Debug for Point<T> where T: Clone`
+";
+        assert_eq!(expected, summary.to_string());
+    }
+
+    #[test]
+    fn empty() {
+        let summary = ApiSummary::from_source(shared_str!("empty"), "fn main() {}").unwrap();
+        assert!(summary.is_empty());
+        assert_eq!(
+            "# API summary: empty\n\nNo public items\n",
+            summary.to_string()
+        );
+    }
+}
@@ -0,0 +1,157 @@
+use std::fmt;
+use std::time::Duration;
+
+use flexstr::SharedStr;
+
+// *** Timing Report ***
+
+/// The wall time spent generating a single code fragment for a file
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FragmentTiming {
+    /// The name of the code fragment
+    pub name: SharedStr,
+    /// The time spent in all of the fragment's generation functions
+    pub generate: Duration,
+}
+
+/// The wall time spent in each phase of generating a single file
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileTiming {
+    /// The name of the file section in the config
+    pub name: SharedStr,
+    /// Each fragment of the file in generation order
+    pub fragments: Vec<FragmentTiming>,
+    /// The time spent generating and assembling tokens (includes all fragments)
+    pub generate: Duration,
+    /// The time spent formatting (PrettyPlease, post processing, and rustfmt if enabled)
+    pub format: Duration,
+    /// The time spent writing the file
+    pub write: Duration,
+}
+
+impl FileTiming {
+    /// The total time spent on this file
+    #[inline]
+    pub fn total(&self) -> Duration {
+        self.generate + self.format + self.write
+    }
+}
+
+/// The timings for each file generated by a call to `generate_files`. Files are generated in
+/// parallel, so the overall wall time is usually less than the sum of the file totals. The report
+/// implements `Display` which prints it as a table
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TimingReport {
+    /// Each file (sorted by name)
+    pub files: Vec<FileTiming>,
+    /// The overall wall time of the run
+    pub total: Duration,
+}
+
+impl TimingReport {
+    pub(crate) fn new(mut files: Vec<FileTiming>, total: Duration) -> Self {
+        files.sort_by(|file1, file2| file1.name.cmp(&file2.name));
+        Self { files, total }
+    }
+}
+
+#[inline]
+fn millis(duration: Duration) -> String {
+    format!("{:.3}", duration.as_secs_f64() * 1000.0)
+}
+
+impl fmt::Display for TimingReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Name column is sized to fit the longest file or (indented) fragment name
+        let width = self
+            .files
+            .iter()
+            .flat_map(|file| {
+                std::iter::once(file.name.len())
+                    .chain(file.fragments.iter().map(|frag| frag.name.len() + 2))
+            })
+            .chain(std::iter::once("Total".len()))
+            .max()
+            .unwrap_or_default();
+
+        writeln!(
+            f,
+            "{:<width$}  {:>10}  {:>10}  {:>10}  {:>10}",
+            "File", "Gen (ms)", "Fmt (ms)", "Write (ms)", "Total (ms)"
+        )?;
+
+        for file in &self.files {
+            writeln!(
+                f,
+                "{:<width$}  {:>10}  {:>10}  {:>10}  {:>10}",
+                &*file.name,
+                millis(file.generate),
+                millis(file.format),
+                millis(file.write),
+                millis(file.total())
+            )?;
+
+            for fragment in &file.fragments {
+                writeln!(
+                    f,
+                    "  {:<frag_width$}  {:>10}",
+                    &*fragment.name,
+                    millis(fragment.generate),
+                    frag_width = width - 2
+                )?;
+            }
+        }
+
+        writeln!(
+            f,
+            "{:<width$}  {:>10}  {:>10}  {:>10}  {:>10}",
+            "Total",
+            "",
+            "",
+            "",
+            millis(self.total)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use flexstr::shared_str;
+    use pretty_assertions::assert_eq;
+
+    use crate::timing::{FileTiming, FragmentTiming, TimingReport};
+
+    #[test]
+    fn display() {
+        let file = |name, fragment| FileTiming {
+            name,
+            fragments: vec![FragmentTiming {
+                name: fragment,
+                generate: Duration::from_micros(500),
+            }],
+            generate: Duration::from_millis(1),
+            format: Duration::from_millis(20),
+            write: Duration::from_micros(250),
+        };
+
+        let report = TimingReport::new(
+            vec![
+                file(shared_str!("str"), shared_str!("from_ref")),
+                file(shared_str!("bool"), shared_str!("empty")),
+            ],
+            Duration::from_millis(25),
+        );
+
+        let expected = "\
+File          Gen (ms)    Fmt (ms)  Write (ms)  Total (ms)
+bool             1.000      20.000       0.250      21.250
+  empty          0.500
+str              1.000      20.000       0.250      21.250
+  from_ref       0.500
+Total                                               25.000
+";
+        assert_eq!(expected, report.to_string());
+    }
+}
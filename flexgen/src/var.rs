@@ -3,6 +3,7 @@ use std::fmt;
 use std::str::FromStr;
 
 use flexstr::{shared_str, SharedStr, ToSharedStr};
+use indexmap::IndexMap;
 use proc_macro2::TokenStream;
 use quote::ToTokens;
 
@@ -12,8 +13,8 @@ const IDENT: &str = "$ident$";
 const INT_LIT: &str = "$int_lit$";
 const TYPE: &str = "$type$";
 
-/// A hashmap of variables for interpolation into [CodeFragments]
-pub(crate) type Vars = HashMap<SharedStr, VarItem>;
+/// A map of variables for interpolation into [CodeFragments] (in the order they were specified)
+pub(crate) type Vars = IndexMap<SharedStr, VarItem>;
 
 /// Represents a map of variables ready for interpolation
 pub type TokenVars = HashMap<SharedStr, TokenItem>;
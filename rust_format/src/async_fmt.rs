@@ -0,0 +1,229 @@
+use std::ffi::OsStr;
+use std::path::Path;
+use std::process::{Output, Stdio};
+use std::time::Duration;
+
+use tokio::io::AsyncWriteExt;
+use tokio::process::{Child, Command};
+
+use crate::error::Context;
+use crate::marker::Markers;
+use crate::{backup_path, CommandFormatter, Error, FormatOutput, Operation, RustFmt};
+
+// *** Async Formatting ***
+
+// Send the source (if any) to the stdin of the process and wait for it to exit. If it doesn't exit
+// before the timeout (if any), it is killed
+async fn wait_output(
+    mut proc: Child,
+    source: Option<&str>,
+    timeout: Option<Duration>,
+) -> Result<Output, Error> {
+    let output = async {
+        if let Some(source) = source {
+            // Safety: Can't panic - we captured stdin when spawning
+            let mut stdin = proc.stdin.take().unwrap();
+            stdin.write_all(source.as_bytes()).await?;
+            // Close stdin
+            drop(stdin);
+        }
+        proc.wait_with_output().await
+    };
+
+    match timeout {
+        // The process is spawned with `kill_on_drop`, so dropping the timed out future kills it
+        Some(timeout) => match tokio::time::timeout(timeout, output).await {
+            Ok(output) => output.context(Operation::Wait),
+            Err(_) => Err(Error::timeout(timeout)),
+        },
+        None => output.await.context(Operation::Wait),
+    }
+}
+
+async fn run_command<I, S>(
+    mut command: Command,
+    args: I,
+    source: &str,
+    timeout: Option<Duration>,
+) -> Result<FormatOutput, Error>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    // Launch the formatter
+    let proc = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .args(args)
+        .kill_on_drop(true)
+        .spawn()
+        .context(Operation::Spawn)?;
+
+    // Parse the results and return stdout/stderr
+    let output = wait_output(proc, Some(source), timeout).await?;
+    let stderr = String::from_utf8(output.stderr).context(Operation::Wait)?;
+
+    if output.status.success() {
+        let code = String::from_utf8(output.stdout).context(Operation::Wait)?;
+        Ok(FormatOutput::new(code, &stderr))
+    } else {
+        Err(Error::from_process(output.status, stderr, Some(source)))
+    }
+}
+
+impl RustFmt {
+    /// The same as [format_str](crate::Formatter::format_str), but `rustfmt` is run without
+    /// blocking the async runtime
+    #[cfg_attr(docsrs, doc(cfg(feature = "tokio")))]
+    pub async fn format_str_async(&self, source: impl AsRef<str>) -> Result<String, Error> {
+        let args = self.build_args(None as Option<&Path>);
+        let output =
+            run_command(self.command().into(), args, source.as_ref(), self.timeout).await?;
+        Ok(output.post_process(self.post_proc, &self.markers)?.code)
+    }
+
+    /// The same as [format_file](crate::Formatter::format_file), but `rustfmt` (and any file I/O)
+    /// is run without blocking the async runtime
+    #[cfg_attr(docsrs, doc(cfg(feature = "tokio")))]
+    pub async fn format_file_async(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        // Just use regular string method if doing post processing so we don't write to file twice
+        if self.post_proc.is_enabled() {
+            let path = path.as_ref();
+            let source = tokio::fs::read_to_string(path)
+                .await
+                .path_context(Operation::ReadFile, path)?;
+            let result = self.format_str_async(source).await?;
+            self.backup_async(path).await?;
+            tokio::fs::write(path, result)
+                .await
+                .path_context(Operation::WriteFile, path)
+        } else {
+            self.backup_async(path.as_ref()).await?;
+            let path = self.file_path(path.as_ref())?;
+            let args = self.build_args(Some(&path));
+
+            // Launch rustfmt and parse the results
+            let proc = Command::from(self.command())
+                .stderr(Stdio::piped())
+                .args(args)
+                .kill_on_drop(true)
+                .spawn()
+                .context(Operation::Spawn)?;
+            let output = wait_output(proc, None, self.timeout).await?;
+            let stderr = String::from_utf8(output.stderr).context(Operation::Wait)?;
+
+            if output.status.success() {
+                Ok(())
+            } else {
+                Err(Error::from_process(output.status, stderr, None))
+            }
+        }
+    }
+
+    // Copy the file to its backup (if backups are enabled) before it is overwritten
+    async fn backup_async(&self, path: &Path) -> Result<(), Error> {
+        match &self.backup {
+            Some(suffix) => {
+                let backup = backup_path(path, suffix);
+                tokio::fs::copy(path, &backup)
+                    .await
+                    .map(|_| ())
+                    .path_context(Operation::WriteFile, &backup)
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+impl CommandFormatter {
+    /// The same as [format_str](crate::Formatter::format_str), but the command is run without
+    /// blocking the async runtime
+    #[cfg_attr(docsrs, doc(cfg(feature = "tokio")))]
+    pub async fn format_str_async(&self, source: impl AsRef<str>) -> Result<String, Error> {
+        let output = run_command(
+            Command::new(&self.program),
+            &self.args,
+            source.as_ref(),
+            self.timeout,
+        )
+        .await?;
+        Ok(output.post_process(self.post_proc, &Markers::NONE)?.code)
+    }
+
+    /// The same as [format_file](crate::Formatter::format_file), but the command (and any file
+    /// I/O) is run without blocking the async runtime
+    #[cfg_attr(docsrs, doc(cfg(feature = "tokio")))]
+    pub async fn format_file_async(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let path = path.as_ref();
+        let source = tokio::fs::read_to_string(path)
+            .await
+            .path_context(Operation::ReadFile, path)?;
+        let result = self.format_str_async(source).await?;
+        tokio::fs::write(path, result)
+            .await
+            .path_context(Operation::WriteFile, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::time::Duration;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::{CommandFormatter, Config, Error, ErrorKind, RustFmt, RUST_FMT};
+
+    const SOURCE: &str = r#"fn main() { println!("Hello World!"); }"#;
+    const EXPECTED: &str = r#"fn main() {
+    println!("Hello World!");
+}
+"#;
+
+    #[tokio::test]
+    async fn rustfmt_str_async() {
+        // Other tests change the environment variable, so the path is given explicitly
+        let rustfmt = RustFmt::from_config(Config::new_str().rust_fmt_path(RUST_FMT));
+
+        let actual = rustfmt.format_str_async(SOURCE).await.unwrap();
+        assert_eq!(EXPECTED, actual);
+
+        match rustfmt
+            .format_str_async("use")
+            .await
+            .map_err(Error::into_kind)
+        {
+            Err(ErrorKind::BadSourceCode(_)) => {}
+            _ => panic!("Expected bad source code"),
+        }
+    }
+
+    #[tokio::test]
+    async fn command_timeout_async() {
+        let sleep = CommandFormatter::new("sleep")
+            .arg("5")
+            .timeout(Duration::from_millis(100));
+
+        match sleep
+            .format_str_async(SOURCE)
+            .await
+            .map_err(Error::into_kind)
+        {
+            Err(ErrorKind::Timeout(_)) => {}
+            _ => panic!("Expected a timeout"),
+        }
+    }
+
+    #[tokio::test]
+    async fn command_file_async() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(SOURCE.as_bytes()).unwrap();
+
+        let rustfmt = CommandFormatter::new(RUST_FMT).args(["--edition", "2021"]);
+        rustfmt.format_file_async(file.path()).await.unwrap();
+
+        let actual = std::fs::read_to_string(file.path()).unwrap();
+        assert_eq!(EXPECTED, actual);
+    }
+}
@@ -0,0 +1,216 @@
+#![cfg(feature = "pretty_please")]
+
+use std::ffi::OsStr;
+use std::hash::Hash;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::{Check, Config, Error, FormatOutput, Formatter, PrettyPlease, RustFmt};
+
+// `rustfmt` if installed, else `prettyplease`
+const DEFAULT_PREFERENCE: [FormatterKind; 2] =
+    [FormatterKind::RustFmt, FormatterKind::PrettyPlease];
+
+// Run the same expression on whichever formatter was picked
+macro_rules! delegate {
+    ($self:ident, $fmt:ident => $expr:expr) => {
+        match &$self.inner {
+            Inner::RustFmt($fmt) => $expr,
+            Inner::PrettyPlease($fmt) => $expr,
+        }
+    };
+}
+
+// *** Auto ***
+
+/// A formatter that [Auto] can pick
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FormatterKind {
+    /// [RustFmt] (only picked if `rustfmt` is available)
+    RustFmt,
+    /// [PrettyPlease] (always available)
+    PrettyPlease,
+}
+
+#[derive(Clone, Debug)]
+enum Inner {
+    RustFmt(RustFmt),
+    PrettyPlease(PrettyPlease),
+}
+
+/// This formatter picks the best formatter available on this machine when it is created and then
+/// delegates to it. By default, [RustFmt] is used if `rustfmt` can be run, otherwise
+/// [PrettyPlease] is used. The order can be changed with
+/// [from_config_preferring](Auto::from_config_preferring)
+///
+/// ```
+/// use rust_format::{Auto, Formatter};
+///
+/// let source = r#"fn main() { println!("Hello World!"); }"#;
+///
+/// let actual = Auto::new().format_str(source).unwrap();
+/// let expected = r#"fn main() {
+///     println!("Hello World!");
+/// }
+/// "#;
+///
+/// assert_eq!(expected, actual);
+/// ```
+#[cfg_attr(docsrs, doc(cfg(feature = "pretty_please")))]
+#[derive(Clone, Debug)]
+pub struct Auto {
+    inner: Inner,
+}
+
+impl Auto {
+    /// Creates a new instance of `Auto` using a default configuration
+    #[inline]
+    pub fn new() -> Self {
+        Self::from_config(Config::<&OsStr, &OsStr, &OsStr>::new())
+    }
+
+    /// Creates a new instance of `Auto` from the given configuration, preferring `rustfmt`
+    #[inline]
+    pub fn from_config<K, P, V>(config: Config<K, P, V>) -> Self
+    where
+        K: Default + Eq + Hash + AsRef<OsStr>,
+        P: Default + Into<PathBuf>,
+        V: Default + AsRef<OsStr>,
+    {
+        Self::from_config_preferring(config, &DEFAULT_PREFERENCE)
+    }
+
+    /// Creates a new instance of `Auto` from the given configuration. The first available
+    /// formatter in `preference` is picked (or [PrettyPlease] if none of them are available)
+    pub fn from_config_preferring<K, P, V>(
+        config: Config<K, P, V>,
+        preference: &[FormatterKind],
+    ) -> Self
+    where
+        K: Default + Eq + Hash + AsRef<OsStr>,
+        P: Default + Into<PathBuf>,
+        V: Default + AsRef<OsStr>,
+    {
+        let pretty_please = PrettyPlease {
+            post_proc: config.post_proc,
+            config: config.pretty_please,
+            markers: config.markers.clone(),
+            backup: config.backup.clone(),
+        };
+        let rust_fmt = RustFmt::from_config(config);
+
+        for kind in preference {
+            match kind {
+                FormatterKind::RustFmt if rust_fmt.is_available() => {
+                    return Self {
+                        inner: Inner::RustFmt(rust_fmt),
+                    }
+                }
+                FormatterKind::RustFmt => {}
+                FormatterKind::PrettyPlease => break,
+            }
+        }
+
+        Self {
+            inner: Inner::PrettyPlease(pretty_please),
+        }
+    }
+
+    /// Returns which formatter was picked
+    #[inline]
+    pub fn kind(&self) -> FormatterKind {
+        match self.inner {
+            Inner::RustFmt(_) => FormatterKind::RustFmt,
+            Inner::PrettyPlease(_) => FormatterKind::PrettyPlease,
+        }
+    }
+}
+
+impl Default for Auto {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Formatter for Auto {
+    #[inline]
+    fn format_str(&self, source: impl AsRef<str>) -> Result<String, Error> {
+        delegate!(self, fmt => fmt.format_str(source))
+    }
+
+    #[inline]
+    fn format_str_output(&self, source: impl AsRef<str>) -> Result<FormatOutput, Error> {
+        delegate!(self, fmt => fmt.format_str_output(source))
+    }
+
+    #[inline]
+    fn format_file(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        delegate!(self, fmt => fmt.format_file(path))
+    }
+
+    #[inline]
+    fn format_to_writer(
+        &self,
+        source: impl AsRef<str>,
+        writer: &mut impl Write,
+    ) -> Result<(), Error> {
+        delegate!(self, fmt => fmt.format_to_writer(source, writer))
+    }
+
+    #[inline]
+    fn format_tokens(&self, tokens: proc_macro2::TokenStream) -> Result<String, Error> {
+        delegate!(self, fmt => fmt.format_tokens(tokens))
+    }
+
+    #[inline]
+    fn format_tokens_ref(&self, tokens: &proc_macro2::TokenStream) -> Result<String, Error> {
+        delegate!(self, fmt => fmt.format_tokens_ref(tokens))
+    }
+
+    #[inline]
+    fn format_syn_file(&self, file: &syn::File) -> Result<String, Error> {
+        delegate!(self, fmt => fmt.format_syn_file(file))
+    }
+
+    #[inline]
+    fn check_str(&self, source: impl AsRef<str>) -> Result<Check, Error> {
+        delegate!(self, fmt => fmt.check_str(source))
+    }
+
+    #[inline]
+    fn check_file(&self, path: impl AsRef<Path>) -> Result<Check, Error> {
+        delegate!(self, fmt => fmt.check_file(path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::{Auto, Config, Formatter, FormatterKind, RUST_FMT};
+
+    const SOURCE: &str = "fn main(){}";
+    const EXPECTED: &str = "fn main() {}\n";
+
+    #[test]
+    fn auto() {
+        let config = || Config::new_str().rust_fmt_path(RUST_FMT);
+
+        let auto = Auto::from_config(config());
+        assert_eq!(FormatterKind::RustFmt, auto.kind());
+        assert_eq!(EXPECTED, auto.format_str(SOURCE).unwrap());
+
+        let preference = [FormatterKind::PrettyPlease, FormatterKind::RustFmt];
+        let auto = Auto::from_config_preferring(config(), &preference);
+        assert_eq!(FormatterKind::PrettyPlease, auto.kind());
+        assert_eq!(EXPECTED, auto.format_str(SOURCE).unwrap());
+
+        // `rustfmt` isn't available, and nothing else was listed
+        let config =
+            Config::new_str().rust_fmt_path("this_is_never_going_to_be_a_valid_executable");
+        let auto = Auto::from_config_preferring(config, &[FormatterKind::RustFmt]);
+        assert_eq!(FormatterKind::PrettyPlease, auto.kind());
+        assert_eq!(EXPECTED, auto.format_str(SOURCE).unwrap());
+    }
+}
@@ -0,0 +1,56 @@
+use std::borrow::Cow;
+
+use crate::scanner::Scanner;
+
+// Collapse each run of blank lines to at most `max` lines. Blank lines inside literals and block
+// comments are part of the value or comment, so they are always kept
+pub(crate) fn collapse_blank_lines(s: &str, max: usize) -> Cow<'_, str> {
+    let mut result = String::with_capacity(s.len());
+    let mut scanner = Scanner::new();
+    let mut changed = false;
+    let mut run = 0;
+
+    for line in s.split_inclusive('\n') {
+        let in_code = scanner.in_code();
+        scanner.scan_line(line);
+
+        if in_code && line.trim().is_empty() {
+            run += 1;
+            if run > max {
+                changed = true;
+                continue;
+            }
+        } else {
+            run = 0;
+        }
+        result.push_str(line);
+    }
+
+    if changed {
+        Cow::Owned(result)
+    } else {
+        Cow::Borrowed(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::blank_lines::collapse_blank_lines;
+
+    #[test]
+    fn collapse() {
+        let source = "fn a() {}\n\n\n\nfn b() {\n\n\n    let s = \"\n\n\n\";\n}\n";
+
+        let expected = "fn a() {}\n\nfn b() {\n\n    let s = \"\n\n\n\";\n}\n";
+        assert_eq!(expected, collapse_blank_lines(source, 1));
+
+        let expected = "fn a() {}\nfn b() {\n    let s = \"\n\n\n\";\n}\n";
+        assert_eq!(expected, collapse_blank_lines(source, 0));
+
+        assert!(matches!(collapse_blank_lines(source, 3), Cow::Borrowed(_)));
+    }
+}
@@ -0,0 +1,206 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::{fs, io};
+
+use crate::error::Context;
+use crate::{Error, Formatter, Operation};
+
+// *** Cached ***
+
+/// This formatter memoizes the results of [format_str](Formatter::format_str) from the inner
+/// formatter. Results are keyed by the source code along with a fingerprint of the inner
+/// formatter's configuration (taken from its `Debug` output), so a change in configuration never
+/// returns a stale result. Errors are never cached.
+///
+/// Optionally, results can be persisted to a cache directory so they survive between runs. Entries
+/// are named after a hash of the key, so the directory should be cleared if the version of the
+/// underlying formatter (such as `rustfmt`) changes.
+///
+/// ```
+/// use rust_format::{Cached, Formatter, RustFmt};
+///
+/// let fmt = Cached::new(RustFmt::default());
+///
+/// let first = fmt.format_str("fn main(){}").unwrap();
+/// // Returned from the cache without running `rustfmt`
+/// let second = fmt.format_str("fn main(){}").unwrap();
+///
+/// assert_eq!(first, second);
+/// ```
+#[derive(Debug)]
+pub struct Cached<F> {
+    fmt: F,
+    fingerprint: String,
+    dir: Option<PathBuf>,
+    memo: Mutex<HashMap<String, String>>,
+}
+
+impl<F: Formatter + Debug> Cached<F> {
+    /// Creates a new instance of `Cached` wrapping the given formatter (results are only cached in
+    /// memory)
+    #[inline]
+    pub fn new(fmt: F) -> Self {
+        Self {
+            fingerprint: format!("{fmt:?}"),
+            fmt,
+            dir: None,
+            memo: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Set a directory to persist results to (it is created if it doesn't exist)
+    #[inline]
+    pub fn dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.dir = Some(dir.into());
+        self
+    }
+
+    /// Returns the wrapped formatter
+    #[inline]
+    pub fn inner(&self) -> &F {
+        &self.fmt
+    }
+
+    /// Remove all results cached in memory (any persisted results are left untouched)
+    #[inline]
+    pub fn clear(&self) {
+        self.memo().clear();
+    }
+
+    #[inline]
+    fn memo(&self) -> std::sync::MutexGuard<'_, HashMap<String, String>> {
+        // A panic while holding the lock can't leave the map in an inconsistent state
+        self.memo
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn path(&self, source: &str) -> Option<PathBuf> {
+        self.dir.as_ref().map(|dir| {
+            let mut hasher = DefaultHasher::new();
+            self.fingerprint.hash(&mut hasher);
+            source.hash(&mut hasher);
+            dir.join(format!("{:016x}.rs", hasher.finish()))
+        })
+    }
+
+    fn persist(&self, path: &PathBuf, result: &str) -> Result<(), Error> {
+        let write = || {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+
+            // Write to a temp file first so a concurrent reader never sees a partial entry
+            let tmp_path = path.with_extension(format!("{}.tmp", std::process::id()));
+            fs::write(&tmp_path, result)?;
+            fs::rename(tmp_path, path)
+        };
+        write().path_context(Operation::WriteFile, path)
+    }
+}
+
+impl<F: Formatter + Debug> Formatter for Cached<F> {
+    fn format_str(&self, source: impl AsRef<str>) -> Result<String, Error> {
+        let source = source.as_ref();
+
+        if let Some(result) = self.memo().get(source) {
+            return Ok(result.clone());
+        }
+
+        let path = self.path(source);
+        let result = match &path {
+            Some(path) => match fs::read_to_string(path) {
+                Ok(result) => Some(result),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+                Err(err) => return Err(Error::from(err).with_path(Operation::ReadFile, path)),
+            },
+            None => None,
+        };
+
+        let result = match result {
+            Some(result) => result,
+            None => {
+                let result = self.fmt.format_str(source)?;
+                if let Some(path) = &path {
+                    self.persist(path, &result)?;
+                }
+                result
+            }
+        };
+
+        self.memo().insert(source.to_string(), result.clone());
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::{Cached, Error, Formatter};
+
+    // Counts how many times it was asked to format
+    #[derive(Debug, Default)]
+    struct Counter {
+        calls: Cell<usize>,
+        suffix: &'static str,
+    }
+
+    impl Formatter for Counter {
+        fn format_str(&self, source: impl AsRef<str>) -> Result<String, Error> {
+            self.calls.set(self.calls.get() + 1);
+            match source.as_ref() {
+                "bad" => Err(Error::bad_source_code("bad")),
+                source => Ok(format!("{source}{}", self.suffix)),
+            }
+        }
+    }
+
+    #[test]
+    fn memoize() {
+        let fmt = Cached::new(Counter::default());
+
+        assert_eq!("a", fmt.format_str("a").unwrap());
+        assert_eq!("a", fmt.format_str("a").unwrap());
+        assert_eq!("b", fmt.format_str("b").unwrap());
+        assert_eq!(2, fmt.inner().calls.get());
+
+        // Errors are not cached
+        assert!(fmt.format_str("bad").is_err());
+        assert!(fmt.format_str("bad").is_err());
+        assert_eq!(4, fmt.inner().calls.get());
+
+        fmt.clear();
+        fmt.format_str("a").unwrap();
+        assert_eq!(5, fmt.inner().calls.get());
+    }
+
+    #[test]
+    fn persist() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path().join("cache");
+
+        let fmt = Cached::new(Counter::default()).dir(&dir);
+        assert_eq!("a", fmt.format_str("a").unwrap());
+
+        // A new instance picks up the persisted result
+        let fmt = Cached::new(Counter::default()).dir(&dir);
+        assert_eq!("a", fmt.format_str("a").unwrap());
+        assert_eq!(0, fmt.inner().calls.get());
+
+        // ...unless the configuration differs
+        let counter = Counter {
+            suffix: "!",
+            ..Default::default()
+        };
+        let fmt = Cached::new(counter).dir(&dir);
+        assert_eq!("a!", fmt.format_str("a").unwrap());
+        assert_eq!(1, fmt.inner().calls.get());
+    }
+}
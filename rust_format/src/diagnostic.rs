@@ -0,0 +1,232 @@
+use std::fmt;
+use std::process::ExitStatus;
+
+// The most source code (in bytes) kept in a `SourceCodeError`
+#[cfg(not(target_arch = "wasm32"))]
+const MAX_SOURCE_LEN: usize = 64 * 1024;
+
+// *** Diagnostic ***
+
+/// A single error reported by the formatter. The location and snippet are only present when the
+/// formatter reported them (such as for syntax errors found by `rustfmt`)
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// The error message
+    pub message: String,
+    /// The line (starting at 1) of the source code the error occurred on
+    pub line: Option<usize>,
+    /// The column (starting at 1) of the source code the error occurred on
+    pub column: Option<usize>,
+    /// The annotated source code lines that the formatter printed with the error
+    pub snippet: String,
+}
+
+impl Diagnostic {
+    /// Creates a new diagnostic with just a message (no location or snippet)
+    #[inline]
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Parse the stderr output of `rustfmt` into a list of diagnostics. Lines that aren't part of
+    /// an `error:` block each become a diagnostic with just a message
+    pub fn parse(stderr: &str) -> Vec<Diagnostic> {
+        let mut diagnostics: Vec<Diagnostic> = Vec::new();
+        let mut in_block = false;
+
+        for line in stderr.lines() {
+            let line = line.trim_end();
+
+            if let Some(message) = error_message(line) {
+                diagnostics.push(Diagnostic::new(message));
+                in_block = true;
+            } else if line.is_empty() {
+                in_block = false;
+            } else if in_block {
+                // Panic safety: We are only in a block after pushing a diagnostic
+                let diagnostic = diagnostics.last_mut().unwrap();
+
+                match location(line) {
+                    Some((line, column)) if diagnostic.line.is_none() => {
+                        diagnostic.line = Some(line);
+                        diagnostic.column = Some(column);
+                    }
+                    _ => {
+                        if !diagnostic.snippet.is_empty() {
+                            diagnostic.snippet.push('\n');
+                        }
+                        diagnostic.snippet.push_str(line);
+                    }
+                }
+            } else {
+                diagnostics.push(Diagnostic::new(line.trim_start()));
+            }
+        }
+
+        diagnostics
+    }
+}
+
+// Matches both `error: msg` and `error[code]: msg`
+fn error_message(line: &str) -> Option<&str> {
+    let rest = line.strip_prefix("error")?;
+    let rest = match rest.strip_prefix('[') {
+        Some(rest) => &rest[rest.find(']')? + 1..],
+        None => rest,
+    };
+    Some(rest.strip_prefix(": ")?.trim())
+}
+
+// Matches ` --> <stdin>:line:column` (the file name may itself contain colons)
+fn location(line: &str) -> Option<(usize, usize)> {
+    let location = line.trim_start().strip_prefix("--> ")?;
+    let mut parts = location.rsplitn(3, ':');
+    let column = parts.next()?.parse().ok()?;
+    let line = parts.next()?.parse().ok()?;
+    Some((line, column))
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)?;
+
+        if let (Some(line), Some(column)) = (self.line, self.column) {
+            write!(f, " (line {line}, column {column})")?;
+        }
+        if !self.snippet.is_empty() {
+            write!(f, "\n{}", self.snippet)?;
+        }
+        Ok(())
+    }
+}
+
+// *** Source Code Error ***
+
+/// The details of source code that could not be formatted
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SourceCodeError {
+    /// Each error reported by the formatter
+    pub diagnostics: Vec<Diagnostic>,
+    /// The exit status of the formatter (only present if it was run as a separate process)
+    pub status: Option<ExitStatus>,
+    /// Everything the formatter printed to stderr (empty if it wasn't run as a separate process)
+    pub stderr: String,
+    /// The source code given to the formatter, truncated to the first 64 KiB (only present if it
+    /// was formatted from a string by a separate process)
+    pub source: Option<String>,
+}
+
+impl SourceCodeError {
+    /// Creates a new error with just diagnostics (no process details or source code)
+    #[inline]
+    pub fn new(diagnostics: Vec<Diagnostic>) -> Self {
+        Self {
+            diagnostics,
+            ..Default::default()
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn from_process(status: ExitStatus, stderr: String, source: Option<&str>) -> Self {
+        Self {
+            diagnostics: Diagnostic::parse(&stderr),
+            status: Some(status),
+            stderr,
+            source: source.map(|source| truncate(source, MAX_SOURCE_LEN).to_string()),
+        }
+    }
+}
+
+// Truncate to at most `max_len` bytes without splitting a char
+#[cfg(not(target_arch = "wasm32"))]
+fn truncate(s: &str, max_len: usize) -> &str {
+    let mut len = s.len().min(max_len);
+    while !s.is_char_boundary(len) {
+        len -= 1;
+    }
+    &s[..len]
+}
+
+impl fmt::Display for SourceCodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (idx, diagnostic) in self.diagnostics.iter().enumerate() {
+            if idx > 0 {
+                f.write_str("\n")?;
+            }
+            <Diagnostic as fmt::Display>::fmt(diagnostic, f)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for SourceCodeError {}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::diagnostic::truncate;
+    use crate::Diagnostic;
+
+    const STDERR: &str = r#"error: expected expression, found `;`
+ --> <stdin>:1:21
+  |
+1 | fn main() { let x = ; }
+  |                     ^ expected expression
+
+error[E0000]: mismatched closing delimiter: `}`
+ --> <stdin>:3:8
+  |
+3 |     foo(
+  |        ^ unclosed delimiter
+
+invalid key=val pair: `hard_tabs=maybe`
+"#;
+
+    #[test]
+    fn parse() {
+        let expected = vec![
+            Diagnostic {
+                message: "expected expression, found `;`".to_string(),
+                line: Some(1),
+                column: Some(21),
+                snippet: "  |\n1 | fn main() { let x = ; }\n  |                     ^ expected expression"
+                    .to_string(),
+            },
+            Diagnostic {
+                message: "mismatched closing delimiter: `}`".to_string(),
+                line: Some(3),
+                column: Some(8),
+                snippet: "  |\n3 |     foo(\n  |        ^ unclosed delimiter".to_string(),
+            },
+            Diagnostic::new("invalid key=val pair: `hard_tabs=maybe`"),
+        ];
+
+        assert_eq!(expected, Diagnostic::parse(STDERR));
+    }
+
+    #[test]
+    fn truncate_on_char_boundary() {
+        assert_eq!("ab", truncate("abc", 2));
+        assert_eq!("abc", truncate("abc", 10));
+        // 'é' is 2 bytes
+        assert_eq!("a", truncate("aé", 2));
+    }
+
+    #[test]
+    fn display() {
+        let diagnostics = Diagnostic::parse(STDERR);
+
+        assert_eq!(
+            "expected expression, found `;` (line 1, column 21)\n  |\n1 | fn main() { let x = ; }\n  |                     ^ expected expression",
+            diagnostics[0].to_string()
+        );
+        assert_eq!(
+            "invalid key=val pair: `hard_tabs=maybe`",
+            diagnostics[2].to_string()
+        );
+    }
+}
@@ -0,0 +1,239 @@
+use crate::source_map::matches;
+
+const DEFAULT_CONTEXT: usize = 3;
+const NO_NEWLINE: &str = "\\ No newline at end of file\n";
+
+// *** Diff Options ***
+
+/// Options for [unified_diff]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct DiffOptions {
+    context: usize,
+    original_label: String,
+    formatted_label: String,
+}
+
+impl DiffOptions {
+    /// Creates new diff options (3 lines of context, labeled `original` and `formatted`)
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the number of unchanged lines shown around each change
+    #[inline]
+    pub fn context(mut self, context: usize) -> Self {
+        self.context = context;
+        self
+    }
+
+    /// Set the labels shown in the `---` and `+++` header lines (typically file paths)
+    #[inline]
+    pub fn labels(mut self, original: impl Into<String>, formatted: impl Into<String>) -> Self {
+        self.original_label = original.into();
+        self.formatted_label = formatted.into();
+        self
+    }
+}
+
+impl Default for DiffOptions {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            context: DEFAULT_CONTEXT,
+            original_label: "original".to_string(),
+            formatted_label: "formatted".to_string(),
+        }
+    }
+}
+
+// *** Unified Diff ***
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Equal,
+    Delete,
+    Insert,
+}
+
+// A single line edit along with the position in each side before it is applied
+#[derive(Clone, Copy)]
+struct Edit {
+    op: Op,
+    original: usize,
+    formatted: usize,
+}
+
+// Push deletes and then inserts until both positions reach the given lines
+fn push_until(edits: &mut Vec<Edit>, a: &mut usize, b: &mut usize, x: usize, y: usize) {
+    while *a < x {
+        edits.push(Edit {
+            op: Op::Delete,
+            original: *a,
+            formatted: *b,
+        });
+        *a += 1;
+    }
+    while *b < y {
+        edits.push(Edit {
+            op: Op::Insert,
+            original: *a,
+            formatted: *b,
+        });
+        *b += 1;
+    }
+}
+
+// Turn the matching lines into a list of edits (deletes before inserts within each change)
+fn edits(original: &[&str], formatted: &[&str]) -> Vec<Edit> {
+    let mut edits = Vec::with_capacity(original.len().max(formatted.len()));
+    let (mut a, mut b) = (0, 0);
+
+    for (x, y) in matches(original, formatted, |a, b| a == b) {
+        push_until(&mut edits, &mut a, &mut b, x, y);
+        edits.push(Edit {
+            op: Op::Equal,
+            original: a,
+            formatted: b,
+        });
+        a += 1;
+        b += 1;
+    }
+    push_until(&mut edits, &mut a, &mut b, original.len(), formatted.len());
+
+    edits
+}
+
+// The `start,len` part of a hunk header (the start is 1-based, or the line before an empty range)
+fn range(start: usize, len: usize) -> String {
+    match len {
+        0 => format!("{start},0"),
+        1 => format!("{}", start + 1),
+        len => format!("{},{len}", start + 1),
+    }
+}
+
+fn push_line(result: &mut String, prefix: char, line: &str) {
+    result.push(prefix);
+    result.push_str(line);
+    if !line.ends_with('\n') {
+        result.push('\n');
+        result.push_str(NO_NEWLINE);
+    }
+}
+
+/// Returns a unified diff (as used by `diff -u` and `git diff`) of the changes formatting made to
+/// the original source code, or an empty string if nothing changed
+///
+/// ```
+/// use rust_format::{unified_diff, DiffOptions};
+///
+/// let original = "fn main(){}\n";
+/// let formatted = "fn main() {}\n";
+///
+/// let diff = unified_diff(original, formatted, &DiffOptions::new());
+/// let expected = "--- original\n+++ formatted\n@@ -1 +1 @@\n-fn main(){}\n+fn main() {}\n";
+/// assert_eq!(expected, diff);
+/// ```
+pub fn unified_diff(original: &str, formatted: &str, options: &DiffOptions) -> String {
+    let a: Vec<_> = original.split_inclusive('\n').collect();
+    let b: Vec<_> = formatted.split_inclusive('\n').collect();
+    let edits = edits(&a, &b);
+
+    let changes: Vec<_> = (0..edits.len())
+        .filter(|&idx| edits[idx].op != Op::Equal)
+        .collect();
+    if changes.is_empty() {
+        return String::new();
+    }
+
+    let mut result = format!(
+        "--- {}\n+++ {}\n",
+        options.original_label, options.formatted_label
+    );
+
+    // Changes closer together than twice the context share a hunk
+    let mut idx = 0;
+    while idx < changes.len() {
+        let mut last = idx;
+        while last + 1 < changes.len()
+            && changes[last + 1] - changes[last] <= 2 * options.context + 1
+        {
+            last += 1;
+        }
+
+        let start = changes[idx].saturating_sub(options.context);
+        let end = (changes[last] + options.context + 1).min(edits.len());
+        let hunk = &edits[start..end];
+
+        let original_len = hunk.iter().filter(|edit| edit.op != Op::Insert).count();
+        let formatted_len = hunk.iter().filter(|edit| edit.op != Op::Delete).count();
+        result.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            range(hunk[0].original, original_len),
+            range(hunk[0].formatted, formatted_len)
+        ));
+
+        for edit in hunk {
+            match edit.op {
+                Op::Equal => push_line(&mut result, ' ', a[edit.original]),
+                Op::Delete => push_line(&mut result, '-', a[edit.original]),
+                Op::Insert => push_line(&mut result, '+', b[edit.formatted]),
+            }
+        }
+
+        idx = last + 1;
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::{unified_diff, DiffOptions};
+
+    #[test]
+    fn no_changes() {
+        let source = "fn main() {}\n";
+        assert_eq!("", unified_diff(source, source, &DiffOptions::new()));
+        assert_eq!("", unified_diff("", "", &DiffOptions::new()));
+    }
+
+    #[test]
+    fn hunks() {
+        let original = "use std::io;\nfn a(){}\n\n\n\n\n\n\n\nfn b() {}\nfn c(){}";
+        let formatted = "use std::io;\nfn a() {}\n\n\n\n\n\n\n\nfn b() {}\nfn c() {}\n";
+
+        let options = DiffOptions::new()
+            .context(1)
+            .labels("a/main.rs", "b/main.rs");
+        let expected = concat!(
+            "--- a/main.rs\n",
+            "+++ b/main.rs\n",
+            "@@ -1,3 +1,3 @@\n",
+            " use std::io;\n",
+            "-fn a(){}\n",
+            "+fn a() {}\n",
+            " \n",
+            "@@ -10,2 +10,2 @@\n",
+            " fn b() {}\n",
+            "-fn c(){}\n",
+            "\\ No newline at end of file\n",
+            "+fn c() {}\n",
+        );
+        assert_eq!(expected, unified_diff(original, formatted, &options));
+
+        // With enough context, both changes share a hunk
+        let diff = unified_diff(original, formatted, &DiffOptions::new().context(4));
+        assert_eq!(1, diff.matches("@@ -").count());
+    }
+
+    #[test]
+    fn insert_only() {
+        let expected = "--- original\n+++ formatted\n@@ -0,0 +1 @@\n+fn main() {}\n";
+        let actual = unified_diff("", "fn main() {}\n", &DiffOptions::new());
+        assert_eq!(expected, actual);
+    }
+}
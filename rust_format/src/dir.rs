@@ -0,0 +1,193 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::Context;
+use crate::{Error, Operation};
+
+/// The path of each file formatted by [format_files](crate::Formatter::format_files) or
+/// [format_dir](crate::Formatter::format_dir) along with its result
+pub type FileResults = Vec<(PathBuf, Result<(), Error>)>;
+
+// *** Format Dir Options ***
+
+/// Options for [format_dir](crate::Formatter::format_dir)
+///
+/// Exclude patterns are globs matched against paths relative to the directory being formatted
+/// (using `/` as the separator). `*` matches any part of a name, `?` matches any single char, and
+/// `**` matches any number of directories. A pattern without a `/` is matched against the name of
+/// each file and directory at any depth (like `.gitignore`). An excluded directory is not walked.
+///
+/// ```
+/// use rust_format::FormatDirOptions;
+///
+/// let options = FormatDirOptions::new().exclude("target").exclude("src/generated/**/*.rs");
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct FormatDirOptions {
+    exclude: Vec<String>,
+}
+
+impl FormatDirOptions {
+    /// Creates a new set of options that formats every `.rs` file
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a glob pattern for files and directories that should not be formatted
+    #[inline]
+    pub fn exclude(mut self, pattern: impl Into<String>) -> Self {
+        self.exclude.push(pattern.into());
+        self
+    }
+
+    fn is_excluded(&self, rel_path: &str) -> bool {
+        self.exclude.iter().any(|pattern| {
+            if pattern.contains('/') {
+                glob_match(pattern, rel_path)
+            } else {
+                // Panic safety: `rsplit` always returns at least one item
+                let name = rel_path.rsplit('/').next().unwrap();
+                match_name(&chars(pattern), &chars(name))
+            }
+        })
+    }
+}
+
+// *** Walking ***
+
+// Returns all the `.rs` files in the directory tree (sorted so results are deterministic)
+pub(crate) fn rust_files(root: &Path, options: &FormatDirOptions) -> Result<Vec<PathBuf>, Error> {
+    let mut files = Vec::new();
+    walk(root, "", options, &mut files)?;
+    Ok(files)
+}
+
+fn walk(
+    dir: &Path,
+    rel_dir: &str,
+    options: &FormatDirOptions,
+    files: &mut Vec<PathBuf>,
+) -> Result<(), Error> {
+    let mut entries = fs::read_dir(dir)
+        .and_then(|entries| entries.collect::<Result<Vec<_>, _>>())
+        .path_context(Operation::ReadDir, dir)?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        let rel_path = if rel_dir.is_empty() {
+            name.to_string()
+        } else {
+            format!("{rel_dir}/{name}")
+        };
+
+        if options.is_excluded(&rel_path) {
+            continue;
+        }
+
+        let path = entry.path();
+        let file_type = entry.file_type().path_context(Operation::ReadDir, &path)?;
+        if file_type.is_dir() {
+            walk(&path, &rel_path, options, files)?;
+        } else if path.extension().is_some_and(|ext| ext == "rs") {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+// *** Glob Matching ***
+
+#[inline]
+fn chars(s: &str) -> Vec<char> {
+    s.chars().collect()
+}
+
+fn glob_match(pattern: &str, path: &str) -> bool {
+    let pattern: Vec<_> = pattern.split('/').map(chars).collect();
+    let path: Vec<_> = path.split('/').map(chars).collect();
+    match_segments(&pattern, &path)
+}
+
+fn match_segments(pattern: &[Vec<char>], path: &[Vec<char>]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        // Any number of directories (including none)
+        Some((segment, rest)) if segment.as_slice() == ['*', '*'] => {
+            (0..=path.len()).any(|idx| match_segments(rest, &path[idx..]))
+        }
+        Some((segment, rest)) => match path.split_first() {
+            Some((name, path)) => match_name(segment, name) && match_segments(rest, path),
+            None => false,
+        },
+    }
+}
+
+fn match_name(pattern: &[char], name: &[char]) -> bool {
+    match (pattern.split_first(), name.split_first()) {
+        (None, None) => true,
+        // Either match nothing or consume one more char
+        (Some(('*', rest)), _) => {
+            match_name(rest, name) || (!name.is_empty() && match_name(pattern, &name[1..]))
+        }
+        (Some(('?', rest)), Some((_, name))) => match_name(rest, name),
+        (Some((p, rest)), Some((n, name))) if p == n => match_name(rest, name),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::dir::{glob_match, rust_files};
+    use crate::FormatDirOptions;
+
+    #[test]
+    fn globs() {
+        assert!(glob_match("src/*.rs", "src/lib.rs"));
+        assert!(!glob_match("src/*.rs", "src/a/lib.rs"));
+        assert!(glob_match("src/**/*.rs", "src/lib.rs"));
+        assert!(glob_match("src/**/*.rs", "src/a/b/lib.rs"));
+        assert!(glob_match("src/?.rs", "src/a.rs"));
+        assert!(!glob_match("src/?.rs", "src/ab.rs"));
+        assert!(glob_match("**", "a/b"));
+    }
+
+    #[test]
+    fn walk() {
+        let dir = tempfile::tempdir().unwrap();
+        for path in [
+            "b.rs",
+            "a.rs",
+            "a.txt",
+            "gen/x.gen.rs",
+            "gen/y.rs",
+            "target/z.rs",
+        ] {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+
+        let options = FormatDirOptions::new()
+            .exclude("target")
+            .exclude("*.gen.rs");
+        let actual: Vec<_> = rust_files(dir.path(), &options)
+            .unwrap()
+            .into_iter()
+            .map(|path| path.strip_prefix(dir.path()).unwrap().to_path_buf())
+            .collect();
+
+        let expected: Vec<_> = ["a.rs", "b.rs", "gen/y.rs"]
+            .iter()
+            .map(|path| path.split('/').collect::<std::path::PathBuf>())
+            .collect();
+        assert_eq!(expected, actual);
+    }
+}
@@ -0,0 +1,221 @@
+use std::borrow::Cow;
+
+use crate::marker::Markers;
+use crate::replace::replace_markers_counting;
+use crate::wrap::{is_fence, DocLine};
+use crate::{Error, PostProcess, PostProcessStats};
+
+// Code block attributes that rustdoc still treats as Rust code
+const RUST_ATTRS: [&str; 6] = [
+    "rust",
+    "ignore",
+    "no_run",
+    "should_panic",
+    "compile_fail",
+    "test_harness",
+];
+
+// Returns true if rustdoc treats the code block opened by this fence as Rust code (an empty info
+// string or only Rust attributes)
+fn is_rust_fence(text: &str) -> bool {
+    text.trim_start()
+        .trim_start_matches(['`', '~'])
+        .split([',', ' ', '\t'])
+        .filter(|attr| !attr.is_empty())
+        .all(|attr| RUST_ATTRS.contains(&attr) || attr.starts_with("edition"))
+}
+
+// Returns the lines of the code block that starts after the opening fence (at `start`) along with
+// the index of the closing fence, or `None` if the block isn't closed
+fn code_block<'a>(
+    lines: &[&'a str],
+    start: usize,
+    open: DocLine,
+) -> Option<(Vec<DocLine<'a>>, usize)> {
+    let mut code = Vec::new();
+
+    for (idx, line) in lines.iter().enumerate().skip(start) {
+        match DocLine::parse(line) {
+            Some(line) if line.indent == open.indent && line.prefix == open.prefix => {
+                if is_fence(line.text) {
+                    return Some((code, idx));
+                }
+                code.push(line);
+            }
+            // The doc comment ended first
+            _ => return None,
+        }
+    }
+
+    None
+}
+
+// Replace the markers in the code and add the doc comment prefix back to each line. Returns `None`
+// if there were no markers
+fn replace_code(
+    code: &[DocLine],
+    open: DocLine,
+    post_proc: PostProcess,
+    markers: &Markers,
+    stats: &mut PostProcessStats,
+) -> Result<Option<String>, Error> {
+    let mut source = String::new();
+    for line in code {
+        source.push_str(line.text);
+        source.push('\n');
+    }
+
+    match replace_markers_counting(&source, post_proc, markers, stats)? {
+        Cow::Borrowed(_) => Ok(None),
+        Cow::Owned(replaced) => {
+            let mut result = String::with_capacity(replaced.len() + code.len() * 8);
+            for line in replaced.lines() {
+                result.push_str(open.indent);
+                result.push_str(open.prefix);
+                if !line.is_empty() {
+                    result.push(' ');
+                    result.push_str(line);
+                }
+                result.push_str(open.ending);
+            }
+            Ok(Some(result))
+        }
+    }
+}
+
+pub(crate) fn replace_doc_markers<'a>(
+    s: &'a str,
+    post_proc: PostProcess,
+    markers: &Markers,
+    stats: &mut PostProcessStats,
+) -> Result<Cow<'a, str>, Error> {
+    // Only markers are replaced in doc comment code (doc blocks in it are left as is)
+    let post_proc = PostProcess::from_flags(post_proc.flags & PostProcess::ReplaceMarkers.flags);
+
+    let lines: Vec<_> = s.split_inclusive('\n').collect();
+    let mut result = String::with_capacity(s.len());
+    let mut changed = false;
+    let mut idx = 0;
+
+    while idx < lines.len() {
+        let line = lines[idx];
+        result.push_str(line);
+        idx += 1;
+
+        let open = match DocLine::parse(line) {
+            Some(open) if is_fence(open.text) && is_rust_fence(open.text) => open,
+            _ => continue,
+        };
+
+        if let Some((code, close)) = code_block(&lines, idx, open) {
+            match replace_code(&code, open, post_proc, markers, stats)? {
+                Some(replaced) => {
+                    result.push_str(&replaced);
+                    changed = true;
+                }
+                None => lines[idx..close]
+                    .iter()
+                    .for_each(|line| result.push_str(line)),
+            }
+            result.push_str(lines[close]);
+            idx = close + 1;
+        }
+    }
+
+    if changed {
+        Ok(Cow::Owned(result))
+    } else {
+        Ok(Cow::Borrowed(s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::doc_markers::{is_rust_fence, replace_doc_markers};
+    use crate::marker::Markers;
+    use crate::{PostProcess, PostProcessStats};
+
+    #[test]
+    fn rust_fences() {
+        assert!(is_rust_fence("```"));
+        assert!(is_rust_fence("```rust"));
+        assert!(is_rust_fence("~~~no_run,edition2021"));
+        assert!(!is_rust_fence("```text"));
+        assert!(!is_rust_fence("```toml"));
+    }
+
+    #[test]
+    fn doc_markers() {
+        let source = r#"/// Use `_blank_!();` to insert a blank line
+///
+/// ```
+/// _comment_!("Calling fibonacci with 10 returns 55");
+/// assert_eq!(fibonacci(10), 55);
+/// _blank_!();
+/// ```
+///
+/// ```text
+/// _blank_!();
+/// ```
+fn fibonacci(n: u64) -> u64 {
+    n
+}
+"#;
+        let expected = r#"/// Use `_blank_!();` to insert a blank line
+///
+/// ```
+/// // Calling fibonacci with 10 returns 55
+/// assert_eq!(fibonacci(10), 55);
+///
+/// ```
+///
+/// ```text
+/// _blank_!();
+/// ```
+fn fibonacci(n: u64) -> u64 {
+    n
+}
+"#;
+
+        let post_proc = PostProcess::ReplaceMarkers.union(PostProcess::DOC_MARKERS);
+        let mut stats = PostProcessStats::default();
+        let actual = replace_doc_markers(source, post_proc, &Markers::NONE, &mut stats).unwrap();
+        assert_eq!(expected, actual);
+        assert_eq!((1, 1), (stats.blanks, stats.comments));
+
+        // Nothing is left to replace
+        assert!(matches!(
+            replace_doc_markers(expected, post_proc, &Markers::NONE, &mut stats).unwrap(),
+            Cow::Borrowed(_)
+        ));
+    }
+
+    #[test]
+    fn doc_block_markers() {
+        let source = r#"#[doc = " ```"]
+#[doc = " _comment_!(\"Comment\");"]
+#[doc = " let x = 1;"]
+#[doc = " ```"]
+fn main() {}
+"#;
+        let expected = r#"/// ```
+/// // Comment
+/// let x = 1;
+/// ```
+fn main() {}
+"#;
+
+        let post_proc = PostProcess::ReplaceMarkersAndDocBlocks;
+        let actual = crate::post_process(post_proc, &Markers::NONE, source.to_string()).unwrap();
+        // Markers leak into the docs unless opted in
+        assert!(actual.contains("_comment_!"));
+
+        let post_proc = post_proc.union(PostProcess::DOC_MARKERS);
+        let actual = crate::post_process(post_proc, &Markers::NONE, source.to_string()).unwrap();
+        assert_eq!(expected, actual);
+    }
+}
@@ -0,0 +1,220 @@
+use std::ffi::{OsStr, OsString};
+use std::hash::Hash;
+use std::path::PathBuf;
+use std::process::Command;
+use std::time::Duration;
+
+use crate::marker::Markers;
+use crate::{run_command, Config, Error, FormatOutput, Formatter, PostProcess};
+
+const DPRINT: &str = "dprint";
+const DPRINT_KEY: &str = "DPRINT";
+// dprint picks the plugin (and any file specific config) based on the file name
+const FILE_NAME: &str = "file.rs";
+
+// *** Dprint CLI ***
+
+/// This formatter is a wrapper around the [dprint](https://dprint.dev) CLI. It runs the installed
+/// `dprint` executable, which in turn uses whichever Rust plugin (such as `dprint-plugin-rustfmt`)
+/// is set up in the dprint configuration file. This gives repos standardized on dprint the same
+/// formatting as `dprint fmt`. No dprint plugin is linked into this crate, so `dprint` must be
+/// installed and configured on the machine
+///
+/// The `dprint` executable is found via the `DPRINT` environment variable (or the `PATH`) unless
+/// [dprint_path](DprintCli::dprint_path) is set
+///
+/// ```no_run
+/// use rust_format::{DprintCli, Formatter};
+///
+/// let dprint = DprintCli::new().config_path("dprint.json");
+/// let actual = dprint.format_str("fn main(){}").unwrap();
+///
+/// assert_eq!("fn main() {}\n", actual);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct DprintCli {
+    dprint_path: Option<PathBuf>,
+    config_path: Option<PathBuf>,
+    file_name: Option<PathBuf>,
+    post_proc: PostProcess,
+    markers: Markers,
+    timeout: Option<Duration>,
+}
+
+impl DprintCli {
+    /// Creates a new instance of `DprintCli` that uses the dprint configuration file found by dprint
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a new instance of `DprintCli` from the given configuration. Only its post
+    /// processing (including any [custom markers](crate::MarkerHandler)) and timeout are used, as
+    /// formatting itself is set up in the dprint configuration file
+    #[inline]
+    pub fn from_config<K, P, V>(config: Config<K, P, V>) -> Self
+    where
+        K: Default + Eq + Hash + AsRef<OsStr>,
+        P: Default + Into<PathBuf>,
+        V: Default + AsRef<OsStr>,
+    {
+        Self {
+            post_proc: config.post_proc,
+            markers: config.markers,
+            timeout: config.timeout,
+            ..Self::default()
+        }
+    }
+
+    /// Set the path to the `dprint` executable
+    #[inline]
+    pub fn dprint_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.dprint_path = Some(path.into());
+        self
+    }
+
+    /// Set the path to the dprint configuration file. By default, dprint searches the current
+    /// directory and its ancestors
+    #[inline]
+    pub fn config_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config_path = Some(path.into());
+        self
+    }
+
+    /// Set the file name dprint is told the source code came from (`file.rs` by default). This
+    /// matters if the dprint configuration has file specific includes, excludes or overrides
+    #[inline]
+    pub fn file_name(mut self, file_name: impl Into<PathBuf>) -> Self {
+        self.file_name = Some(file_name.into());
+        self
+    }
+
+    /// Set the post processing option after formatting
+    #[inline]
+    pub fn post_proc(mut self, post_proc: PostProcess) -> Self {
+        self.post_proc = post_proc;
+        self
+    }
+
+    /// Set the maximum time `dprint` may run before it is killed and
+    /// [ErrorKind::Timeout](crate::ErrorKind::Timeout) is returned. By default, there is no timeout
+    #[inline]
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    fn program(&self) -> PathBuf {
+        match &self.dprint_path {
+            Some(path) => path.clone(),
+            None => std::env::var_os(DPRINT_KEY)
+                .unwrap_or_else(|| DPRINT.into())
+                .into(),
+        }
+    }
+
+    fn build_args(&self) -> Vec<OsString> {
+        let mut args = Vec::with_capacity(5);
+        args.push("fmt".into());
+
+        if let Some(config_path) = &self.config_path {
+            args.push("--config".into());
+            args.push(config_path.into());
+        }
+
+        args.push("--stdin".into());
+        match &self.file_name {
+            Some(file_name) => args.push(file_name.into()),
+            None => args.push(FILE_NAME.into()),
+        }
+        args
+    }
+}
+
+impl Formatter for DprintCli {
+    #[inline]
+    fn format_str(&self, source: impl AsRef<str>) -> Result<String, Error> {
+        Ok(self.format_str_output(source)?.code)
+    }
+
+    fn format_str_output(&self, source: impl AsRef<str>) -> Result<FormatOutput, Error> {
+        let output = run_command(
+            Command::new(self.program()),
+            self.build_args(),
+            source.as_ref(),
+            self.timeout,
+        )?;
+        output.post_process(self.post_proc, &self.markers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsString;
+    #[cfg(feature = "post_process")]
+    use std::time::Duration;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::dprint_cli::DPRINT_KEY;
+    #[cfg(feature = "post_process")]
+    use crate::{Config, MarkerFn, PostProcess};
+    use crate::{DprintCli, Error, ErrorKind, Formatter};
+
+    fn args(args: &[&str]) -> Vec<OsString> {
+        args.iter().map(OsString::from).collect()
+    }
+
+    #[test]
+    fn build_args() {
+        assert_eq!(
+            args(&["fmt", "--stdin", "file.rs"]),
+            DprintCli::new().build_args()
+        );
+
+        let dprint = DprintCli::new()
+            .config_path("dprint.json")
+            .file_name("src/lib.rs");
+        assert_eq!(
+            args(&["fmt", "--config", "dprint.json", "--stdin", "src/lib.rs"]),
+            dprint.build_args()
+        );
+    }
+
+    #[cfg(feature = "post_process")]
+    #[test]
+    fn from_config() {
+        let todo = MarkerFn::new("todo", |_, _| Ok(String::new()));
+        let config = Config::new_str()
+            .post_proc(PostProcess::ReplaceMarkers)
+            .timeout(Duration::from_secs(5))
+            .marker_handler(todo);
+
+        let dprint = DprintCli::from_config(config);
+        assert_eq!(PostProcess::ReplaceMarkers, dprint.post_proc);
+        assert_eq!(Some(Duration::from_secs(5)), dprint.timeout);
+        assert!(dprint.markers.find(b"todo_!()").is_some());
+    }
+
+    #[test]
+    #[ignore = "requires dprint to be installed and configured with a Rust plugin"]
+    fn format() {
+        let actual = DprintCli::new().format_str("fn main(){}").unwrap();
+        assert_eq!("fn main() {}\n", actual);
+    }
+
+    #[test]
+    fn not_installed() {
+        temp_env::with_var(
+            DPRINT_KEY,
+            Some("this_is_never_going_to_be_a_valid_executable"),
+            || match DprintCli::new()
+                .format_str("fn main() {}")
+                .map_err(Error::into_kind)
+            {
+                Err(ErrorKind::IOError(_)) => {}
+                _ => panic!("Expected an I/O error"),
+            },
+        );
+    }
+}
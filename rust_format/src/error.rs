@@ -0,0 +1,280 @@
+use std::path::{Path, PathBuf};
+#[cfg(not(target_arch = "wasm32"))]
+use std::process::ExitStatus;
+use std::time::Duration;
+use std::{fmt, io, string};
+
+use crate::{Diagnostic, SourceCodeError};
+
+// *** Error Kind ***
+
+/// The kind of error that occurred
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// An I/O related error occurred
+    IOError(io::Error),
+    /// The response of formatting was not valid UTF8
+    UTFConversionError(string::FromUtf8Error),
+    /// The source code has bad syntax and could not be formatted. It contains each error reported
+    /// by the formatter along with details useful for debugging
+    BadSourceCode(SourceCodeError),
+    /// The formatter did not complete within the configured timeout (and was killed)
+    Timeout(Duration),
+    /// The version reported by the formatter could not be parsed. It contains the full output
+    UnknownVersion(String),
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorKind::IOError(err) => <io::Error as fmt::Display>::fmt(err, f),
+            ErrorKind::UTFConversionError(err) => {
+                <string::FromUtf8Error as fmt::Display>::fmt(err, f)
+            }
+            ErrorKind::BadSourceCode(err) => {
+                f.write_str("An error occurred while formatting the source code: ")?;
+                <SourceCodeError as fmt::Display>::fmt(err, f)
+            }
+            ErrorKind::Timeout(timeout) => {
+                write!(f, "The formatter did not complete within {timeout:?}")
+            }
+            ErrorKind::UnknownVersion(version) => {
+                write!(f, "The formatter reported an unknown version: {version}")
+            }
+        }
+    }
+}
+
+// *** Operation ***
+
+/// The operation that was being performed when an error occurred
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Operation {
+    /// Launching the formatter process (such as when it isn't installed)
+    Spawn,
+    /// Sending the source code to the formatter process and waiting for its output
+    Wait,
+    /// Reading a source file
+    ReadFile,
+    /// Writing a formatted file
+    WriteFile,
+    /// Reading the contents of a directory
+    ReadDir,
+    /// Parsing the source code (in process formatters only)
+    Parse,
+    /// Post processing the formatted source code
+    PostProcess,
+}
+
+impl Operation {
+    fn as_str(self) -> &'static str {
+        match self {
+            Operation::Spawn => "launch the formatter",
+            Operation::Wait => "wait for the formatter",
+            Operation::ReadFile => "read file",
+            Operation::WriteFile => "write file",
+            Operation::ReadDir => "read directory",
+            Operation::Parse => "parse the source code",
+            Operation::PostProcess => "post process the source code",
+        }
+    }
+}
+
+impl fmt::Display for Operation {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+// *** Error ***
+
+/// This error is returned when errors are triggered during the formatting process. Along with the
+/// [kind](Error::kind) of error, it records which [operation](Error::operation) failed and the
+/// [path](Error::path) involved (when known)
+#[derive(Debug)]
+pub struct Error {
+    kind: ErrorKind,
+    operation: Option<Operation>,
+    path: Option<PathBuf>,
+}
+
+impl Error {
+    /// Creates a new error of the given kind without any context
+    #[inline]
+    pub fn new(kind: ErrorKind) -> Self {
+        Self {
+            kind,
+            operation: None,
+            path: None,
+        }
+    }
+
+    /// Returns the kind of error
+    #[inline]
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+
+    /// Consumes the error and returns its kind
+    #[inline]
+    pub fn into_kind(self) -> ErrorKind {
+        self.kind
+    }
+
+    /// Returns the operation that failed, if known
+    #[inline]
+    pub fn operation(&self) -> Option<Operation> {
+        self.operation
+    }
+
+    /// Returns the path of the file or directory involved, if any
+    #[inline]
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    // Record the operation (unless a more specific one was already recorded)
+    #[inline]
+    pub(crate) fn with_operation(mut self, operation: Operation) -> Self {
+        self.operation.get_or_insert(operation);
+        self
+    }
+
+    // Record the operation and path (unless more specific ones were already recorded)
+    #[inline]
+    pub(crate) fn with_path(self, operation: Operation, path: &Path) -> Self {
+        let mut err = self.with_operation(operation);
+        err.path.get_or_insert_with(|| path.to_path_buf());
+        err
+    }
+
+    // A bad source code error with a single diagnostic that has no location
+    #[cfg_attr(not(feature = "post_process"), allow(dead_code))]
+    #[inline]
+    pub(crate) fn bad_source_code(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::BadSourceCode(SourceCodeError::new(vec![
+            Diagnostic::new(message),
+        ])))
+    }
+
+    // A bad source code error from a formatter process that failed
+    #[cfg(not(target_arch = "wasm32"))]
+    #[inline]
+    pub(crate) fn from_process(status: ExitStatus, stderr: String, source: Option<&str>) -> Self {
+        Self::new(ErrorKind::BadSourceCode(SourceCodeError::from_process(
+            status, stderr, source,
+        )))
+    }
+
+    // The formatter did not complete within the timeout
+    #[cfg(not(target_arch = "wasm32"))]
+    #[inline]
+    pub(crate) fn timeout(timeout: Duration) -> Self {
+        Self::new(ErrorKind::Timeout(timeout)).with_operation(Operation::Wait)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.operation, &self.path) {
+            (Some(operation), Some(path)) => {
+                write!(f, "Failed to {operation} '{}': ", path.display())?
+            }
+            (Some(operation), None) => write!(f, "Failed to {operation}: ")?,
+            (None, _) => {}
+        }
+        <ErrorKind as fmt::Display>::fmt(&self.kind, f)
+    }
+}
+
+// The message already includes the underlying error, so there is no `source` (otherwise it would
+// be shown twice when the chain is walked). It is still available via `kind`
+impl std::error::Error for Error {}
+
+impl From<ErrorKind> for Error {
+    #[inline]
+    fn from(kind: ErrorKind) -> Self {
+        Self::new(kind)
+    }
+}
+
+impl From<io::Error> for Error {
+    #[inline]
+    fn from(err: io::Error) -> Self {
+        Self::new(ErrorKind::IOError(err))
+    }
+}
+
+impl From<string::FromUtf8Error> for Error {
+    #[inline]
+    fn from(err: string::FromUtf8Error) -> Self {
+        Self::new(ErrorKind::UTFConversionError(err))
+    }
+}
+
+#[cfg(feature = "syn")]
+impl From<syn::Error> for Error {
+    #[inline]
+    fn from(err: syn::Error) -> Self {
+        Error::bad_source_code(err.to_string()).with_operation(Operation::Parse)
+    }
+}
+
+// *** Context ***
+
+// Attach the failed operation (and path) to any error that converts into `Error`
+pub(crate) trait Context<T> {
+    fn context(self, operation: Operation) -> Result<T, Error>;
+
+    fn path_context(self, operation: Operation, path: &Path) -> Result<T, Error>;
+}
+
+impl<T, E: Into<Error>> Context<T> for Result<T, E> {
+    #[inline]
+    fn context(self, operation: Operation) -> Result<T, Error> {
+        self.map_err(|err| err.into().with_operation(operation))
+    }
+
+    #[inline]
+    fn path_context(self, operation: Operation, path: &Path) -> Result<T, Error> {
+        self.map_err(|err| err.into().with_path(operation, path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error as _;
+    use std::io;
+    use std::path::Path;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::error::Context;
+    use crate::{Error, ErrorKind, Operation};
+
+    #[test]
+    fn context() {
+        let err = Error::from(io::Error::new(io::ErrorKind::NotFound, "not found"));
+        assert_eq!("not found", err.to_string());
+        assert_eq!(None, err.operation());
+
+        let path = Path::new("src/main.rs");
+        let result: Result<(), _> = Err(err);
+        let err = result
+            .path_context(Operation::ReadFile, path)
+            .context(Operation::Spawn)
+            .unwrap_err();
+
+        // The innermost operation wins
+        assert_eq!(Some(Operation::ReadFile), err.operation());
+        assert_eq!(Some(path), err.path());
+        assert_eq!(
+            "Failed to read file 'src/main.rs': not found",
+            err.to_string()
+        );
+        assert!(err.source().is_none());
+        assert!(matches!(err.into_kind(), ErrorKind::IOError(_)));
+    }
+}
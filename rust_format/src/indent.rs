@@ -0,0 +1,182 @@
+#![cfg(feature = "pretty_please")]
+
+// The indent `prettyplease` always uses
+pub(crate) const PP_INDENT: usize = 4;
+
+#[inline]
+fn is_ident(ch: char) -> bool {
+    ch.is_alphanumeric() || ch == '_'
+}
+
+// Skips a string literal starting after the opening quote and returns the index after the closing
+// quote (and any hashes for raw strings). Each line that starts inside the literal is marked
+fn skip_literal(
+    chars: &[char],
+    start: usize,
+    raw_hashes: Option<usize>,
+    in_literal: &mut Vec<bool>,
+) -> usize {
+    let raw = raw_hashes.is_some();
+    let hashes = raw_hashes.unwrap_or(0);
+    let mut idx = start;
+
+    while idx < chars.len() {
+        match chars[idx] {
+            '\\' if !raw => {
+                if chars.get(idx + 1) == Some(&'\n') {
+                    in_literal.push(true);
+                }
+                idx += 2;
+            }
+            '\n' => {
+                in_literal.push(true);
+                idx += 1;
+            }
+            '"' if chars[idx + 1..]
+                .iter()
+                .take(hashes)
+                .filter(|&&ch| ch == '#')
+                .count()
+                == hashes =>
+            {
+                return idx + 1 + hashes;
+            }
+            _ => idx += 1,
+        }
+    }
+
+    idx
+}
+
+// Returns whether each line starts inside a string literal (and must be left as is)
+fn literal_lines(s: &str) -> Vec<bool> {
+    let chars: Vec<_> = s.chars().collect();
+    let mut in_literal = vec![false];
+    let mut idx = 0;
+
+    while idx < chars.len() {
+        let prev_ident = idx > 0 && is_ident(chars[idx - 1]);
+
+        match chars[idx] {
+            '\n' => {
+                in_literal.push(false);
+                idx += 1;
+            }
+            // Line comment (the line ending is left for the next iteration)
+            '/' if chars.get(idx + 1) == Some(&'/') => {
+                while idx < chars.len() && chars[idx] != '\n' {
+                    idx += 1;
+                }
+            }
+            // Regular or byte string
+            '"' => idx = skip_literal(&chars, idx + 1, None, &mut in_literal),
+            // Possible raw string (or raw byte string)
+            'r' if !prev_ident
+                || (idx >= 1
+                    && chars[idx - 1] == 'b'
+                    && (idx < 2 || !is_ident(chars[idx - 2]))) =>
+            {
+                let mut end = idx + 1;
+                while chars.get(end) == Some(&'#') {
+                    end += 1;
+                }
+
+                if chars.get(end) == Some(&'"') {
+                    idx = skip_literal(&chars, end + 1, Some(end - idx - 1), &mut in_literal);
+                } else {
+                    idx += 1;
+                }
+            }
+            // Char literal or lifetime (only needed so a quote char isn't taken as a string)
+            '\'' => match chars.get(idx + 1) {
+                Some('\\') => {
+                    idx += 3;
+                    while idx < chars.len() && chars[idx] != '\'' {
+                        idx += 1;
+                    }
+                    idx += 1;
+                }
+                Some(_) if chars.get(idx + 2) == Some(&'\'') => idx += 3,
+                _ => idx += 1,
+            },
+            _ => idx += 1,
+        }
+    }
+
+    in_literal
+}
+
+// Replace each level of `prettyplease` indent with the given indent. Lines that start inside a
+// string literal are left as is
+pub(crate) fn reindent(source: &str, indent: &str) -> String {
+    let in_literal = literal_lines(source);
+    let mut result = String::with_capacity(source.len());
+
+    for (idx, line) in source.split_inclusive('\n').enumerate() {
+        if in_literal.get(idx) == Some(&true) {
+            result.push_str(line);
+            continue;
+        }
+
+        let code = line.trim_start_matches(' ');
+        let spaces = line.len() - code.len();
+
+        for _ in 0..spaces / PP_INDENT {
+            result.push_str(indent);
+        }
+        for _ in 0..spaces % PP_INDENT {
+            result.push(' ');
+        }
+        result.push_str(code);
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::indent::reindent;
+
+    #[test]
+    fn reindent_code() {
+        let source = r##"fn main() {
+    let s = "a
+    b";
+    let r = r#"c
+        "d"
+    "#;
+    let c = '"';
+    let b = br"e
+    f";
+    if true {
+        // "
+        println!("{}", 'a');
+    }
+}
+"##;
+
+        let expected = r##"fn main() {
+  let s = "a
+    b";
+  let r = r#"c
+        "d"
+    "#;
+  let c = '"';
+  let b = br"e
+    f";
+  if true {
+    // "
+    println!("{}", 'a');
+  }
+}
+"##;
+
+        assert_eq!(expected, reindent(source, "  "));
+        assert_eq!(
+            "fn main() {\n\tlet x = 1;\n}\n",
+            reindent("fn main() {\n    let x = 1;\n}\n", "\t")
+        );
+    }
+}
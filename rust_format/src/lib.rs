@@ -3,8 +3,9 @@
 
 //! A Rust source code formatting crate with a unified interface for string, file, and
 //! [TokenStream](proc_macro2::TokenStream) input. It currently supports
-//! [rustfmt](https://crates.io/crates/rustfmt-nightly) and
-//! [prettyplease](https://crates.io/crates/prettyplease).
+//! [rustfmt](https://crates.io/crates/rustfmt-nightly),
+//! [prettyplease](https://crates.io/crates/prettyplease), and any external command that formats
+//! from stdin to stdout.
 //!
 //! ```
 //! use rust_format::{Formatter, RustFmt};
@@ -20,17 +21,61 @@
 //! assert_eq!(expected, actual);
 //! ```
 
+#[cfg(feature = "tokio")]
+#[cfg(not(target_arch = "wasm32"))]
+mod async_fmt;
+#[cfg(not(target_arch = "wasm32"))]
+mod auto;
+#[cfg(feature = "post_process")]
+mod blank_lines;
+#[cfg(feature = "cache")]
+mod cache;
+mod diagnostic;
+#[cfg(feature = "diff")]
+mod diff;
+mod dir;
+#[cfg(feature = "post_process")]
+mod doc_markers;
+#[cfg(feature = "dprint_cli")]
+#[cfg(not(target_arch = "wasm32"))]
+mod dprint_cli;
+mod error;
+mod indent;
+#[cfg_attr(not(feature = "post_process"), allow(dead_code))]
+mod marker;
+mod prologue;
 #[cfg(feature = "post_process")]
 mod replace;
+#[cfg(any(feature = "post_process", not(target_arch = "wasm32")))]
+mod scanner;
+mod shared;
+
+mod source_map;
+#[cfg(not(target_arch = "wasm32"))]
+mod stream;
+mod version;
+#[cfg(feature = "post_process")]
+mod wrap;
 
 #[cfg(not(feature = "post_process"))]
 mod replace {
     use std::borrow::Cow;
 
-    use crate::Error;
+    use crate::marker::Markers;
+    use crate::{Error, PostProcess, PostProcessStats};
+
+    #[inline]
+    pub(crate) fn replace_markers_counting<'a>(
+        s: &'a str,
+        _post_proc: PostProcess,
+        _markers: &Markers,
+        _stats: &mut PostProcessStats,
+    ) -> Result<Cow<'a, str>, Error> {
+        Ok(Cow::Borrowed(s))
+    }
 
     #[inline]
-    pub(crate) fn replace_markers(s: &str, _replace_doc_blocks: bool) -> Result<Cow<str>, Error> {
+    pub(crate) fn replace_doc_comments(s: &str) -> Result<Cow<'_, str>, Error> {
         Ok(Cow::Borrowed(s))
     }
 }
@@ -50,18 +95,78 @@ mod test_readme {
     external_doc_test!(include_str!("../README.md"));
 }
 
+#[cfg(feature = "pretty_please")]
+#[cfg(not(target_arch = "wasm32"))]
+#[cfg_attr(docsrs, doc(cfg(feature = "pretty_please")))]
+pub use auto::{Auto, FormatterKind};
+#[cfg(feature = "cache")]
+#[cfg_attr(docsrs, doc(cfg(feature = "cache")))]
+pub use cache::Cached;
+pub use diagnostic::{Diagnostic, SourceCodeError};
+#[cfg(feature = "diff")]
+#[cfg_attr(docsrs, doc(cfg(feature = "diff")))]
+pub use diff::{unified_diff, DiffOptions};
+pub use dir::{FileResults, FormatDirOptions};
+#[cfg(feature = "dprint_cli")]
+#[cfg(not(target_arch = "wasm32"))]
+#[cfg_attr(docsrs, doc(cfg(feature = "dprint_cli")))]
+pub use dprint_cli::DprintCli;
+pub use error::{Error, ErrorKind, Operation};
+#[cfg(feature = "post_process")]
+#[cfg_attr(docsrs, doc(cfg(feature = "post_process")))]
+pub use marker::{MarkerFn, MarkerHandler};
+pub use shared::SharedFormatter;
+pub use source_map::SourceMap;
+pub use version::{Channel, RustFmtVersion};
+
 use std::borrow::Cow;
 use std::collections::HashMap;
 use std::default::Default;
 use std::ffi::{OsStr, OsString};
 use std::hash::Hash;
 use std::io::{Read, Write};
+use std::ops::{AddAssign, BitOr, BitOrAssign};
 use std::path::{Path, PathBuf};
-use std::process::{Command, Stdio};
-use std::{env, fmt, fs, io, string};
+use std::time::Duration;
+use std::{fs, io};
 
+use error::Context;
+use marker::Markers;
+#[cfg(feature = "pretty_please")]
+use quote::ToTokens;
+
+// There are no processes (or threads) on wasm, so only in-process formatting is supported there
+#[cfg(not(target_arch = "wasm32"))]
+use std::num::NonZeroUsize;
+#[cfg(not(target_arch = "wasm32"))]
+use std::process::{Child, Command, Output, Stdio};
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+#[cfg(not(target_arch = "wasm32"))]
+use std::{env, panic, thread};
+
+#[cfg(not(target_arch = "wasm32"))]
+use stream::ChunkedPostProcessor;
+
+#[cfg(not(target_arch = "wasm32"))]
 const RUST_FMT: &str = "rustfmt";
+#[cfg(not(target_arch = "wasm32"))]
 const RUST_FMT_KEY: &str = "RUSTFMT";
+#[cfg(not(target_arch = "wasm32"))]
+const RUST_FMT_FLAGS_KEY: &str = "RUSTFMT_FLAGS";
+#[cfg(not(target_arch = "wasm32"))]
+const RUSTUP: &str = "rustup";
+// How often a formatter with a timeout is checked for completion
+#[cfg(not(target_arch = "wasm32"))]
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+// Files at least this large are streamed through `rustfmt` when post processing
+#[cfg(not(target_arch = "wasm32"))]
+const STREAM_THRESHOLD: u64 = 1024 * 1024;
+// The approximate size of each chunk that is post processed while streaming
+#[cfg(not(target_arch = "wasm32"))]
+const STREAM_CHUNK_SIZE: usize = 64 * 1024;
+// Appended to the file name of a backup unless another suffix is configured
+const BACKUP_SUFFIX: &str = ".bak";
 
 // *** Marker macros ***
 
@@ -72,7 +177,8 @@ const RUST_FMT_KEY: &str = "RUSTFMT";
 /// It is important to understand this is NOT actually a macro that is executed. In fact, it is just
 /// here for documentation purposes. Instead, this works as a raw set of tokens in the source code
 /// that we match against verbatim. This means it cannot be renamed on import for example, and it MUST be
-/// invoked as `_blank_!(`, then an optional Rust integer literal, and then `);`.
+/// invoked as `_blank_!(`, then an optional Rust integer literal, and then `)` (optionally followed
+/// by `;` or `,`) at the end of the line.
 ///
 /// Actually executing this macro has no effect and it is not meant to even be imported.
 #[cfg(feature = "post_process")]
@@ -84,14 +190,15 @@ macro_rules! _blank_ {
 }
 
 /// A "marker" macro used to mark locations in the source code where comments should be inserted.
-/// If no parameter is given, a single blank comment is assumed, otherwise the string literal
-/// specified is broken into lines and those comments will be inserted individually.
+/// If no parameter is given, a single blank comment is assumed, otherwise each string literal
+/// specified (separated by commas) is broken into lines and those comments will be inserted
+/// individually.
 ///
 /// It is important to understand this is NOT actually a macro that is executed. In fact, it is just
 /// here for documentation purposes. Instead, this works as a raw set of tokens in the source code
 /// that we match against verbatim. This means it cannot be renamed on import for example, and it MUST be
-/// invoked as `_comment_!(`, then an optional Rust `str` literal (regular or raw, not byte string),
-/// and then `);`.
+/// invoked as `_comment_!(`, then optional comma separated Rust `str` literals (regular or raw, not
+/// byte string), and then `)` (optionally followed by `;` or `,`) at the end of the line.
 ///
 /// Actually executing this macro has no effect and it is not meant to even be imported.
 #[cfg(feature = "post_process")]
@@ -99,57 +206,69 @@ macro_rules! _blank_ {
 #[macro_export]
 macro_rules! _comment_ {
     () => {};
-    ($lit:literal) => {};
-}
-
-// *** Error ***
-
-/// This error is returned when errors are triggered during the formatting process
-#[derive(Debug)]
-pub enum Error {
-    /// An I/O related error occurred
-    IOError(io::Error),
-    /// The response of formatting was not valid UTF8
-    UTFConversionError(string::FromUtf8Error),
-    /// The source code has bad syntax and could not be formatted
-    BadSourceCode(String),
-}
-
-impl fmt::Display for Error {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Error::IOError(err) => <io::Error as fmt::Display>::fmt(err, f),
-            Error::UTFConversionError(err) => <string::FromUtf8Error as fmt::Display>::fmt(err, f),
-            Error::BadSourceCode(cause) => {
-                f.write_str("An error occurred while formatting the source code: ")?;
-                f.write_str(cause)
-            }
-        }
-    }
+    ($($lit:literal),+ $(,)?) => {};
 }
 
-impl std::error::Error for Error {}
-
-impl From<io::Error> for Error {
-    #[inline]
-    fn from(err: io::Error) -> Self {
-        Error::IOError(err)
-    }
+/// A "marker" macro used to mark locations in the source code where doc comments should be inserted.
+/// If no parameter is given, a single blank doc comment is assumed, otherwise each string literal
+/// specified (separated by commas) is broken into lines and those doc comments (`///`) will be
+/// inserted individually.
+/// These are replaced whenever [PostProcess::DOCS] is set (independently of doc blocks).
+///
+/// It is important to understand this is NOT actually a macro that is executed. In fact, it is just
+/// here for documentation purposes. Instead, this works as a raw set of tokens in the source code
+/// that we match against verbatim. This means it cannot be renamed on import for example, and it MUST be
+/// invoked as `_doc_!(`, then optional comma separated Rust `str` literals (regular or raw, not
+/// byte string), and then `)` (optionally followed by `;` or `,`) at the end of the line.
+///
+/// Actually executing this macro has no effect and it is not meant to even be imported.
+#[cfg(feature = "post_process")]
+#[cfg_attr(docsrs, doc(cfg(feature = "post_process")))]
+#[macro_export]
+macro_rules! _doc_ {
+    () => {};
+    ($($lit:literal),+ $(,)?) => {};
 }
 
-impl From<string::FromUtf8Error> for Error {
-    #[inline]
-    fn from(err: string::FromUtf8Error) -> Self {
-        Error::UTFConversionError(err)
-    }
+/// A "marker" macro used to mark locations in the source code where inner doc comments (such as
+/// module level documentation) should be inserted. If no parameter is given, a single blank inner
+/// doc comment is assumed, otherwise each string literal specified (separated by commas) is broken
+/// into lines and those inner doc comments (`//!`) will be inserted individually.
+///
+/// It is important to understand this is NOT actually a macro that is executed. In fact, it is just
+/// here for documentation purposes. Instead, this works as a raw set of tokens in the source code
+/// that we match against verbatim. This means it cannot be renamed on import for example, and it MUST be
+/// invoked as `_inner_doc_!(`, then optional comma separated Rust `str` literals (regular or raw,
+/// not byte string), and then `)` (optionally followed by `;` or `,`) at the end of the line.
+///
+/// Actually executing this macro has no effect and it is not meant to even be imported.
+#[cfg(feature = "post_process")]
+#[cfg_attr(docsrs, doc(cfg(feature = "post_process")))]
+#[macro_export]
+macro_rules! _inner_doc_ {
+    () => {};
+    ($($lit:literal),+ $(,)?) => {};
 }
 
-#[cfg(feature = "syn")]
-impl From<syn::Error> for Error {
-    #[inline]
-    fn from(err: syn::Error) -> Self {
-        Error::BadSourceCode(err.to_string())
-    }
+/// A "marker" macro used to mark locations in the source code where text should be inserted
+/// verbatim. The string literal specified is broken into lines and those lines are inserted as is
+/// (without a comment prefix or any indentation). This allows inserting things that can't be
+/// expressed as tokens, such as shebang lines or pre-formatted blocks. The text may not contain
+/// anything that post processing would replace (such as another marker), as post processing the
+/// output again must not change it.
+///
+/// It is important to understand this is NOT actually a macro that is executed. In fact, it is just
+/// here for documentation purposes. Instead, this works as a raw set of tokens in the source code
+/// that we match against verbatim. This means it cannot be renamed on import for example, and it MUST be
+/// invoked as `_raw_!(`, then a Rust `str` literal (regular or raw, not byte string), and then `)`
+/// (optionally followed by `;` or `,`) at the end of the line.
+///
+/// Actually executing this macro has no effect and it is not meant to even be imported.
+#[cfg(feature = "post_process")]
+#[cfg_attr(docsrs, doc(cfg(feature = "post_process")))]
+#[macro_export]
+macro_rules! _raw_ {
+    ($lit:literal) => {};
 }
 
 // *** Edition ***
@@ -163,15 +282,23 @@ pub enum Edition {
     Rust2018,
     /// Rust 2021 edition
     Rust2021,
+    /// Rust 2024 edition
+    Rust2024,
+    /// Any other edition, passed through to `rustfmt` verbatim (allows using editions newer than
+    /// this crate knows about)
+    Custom(&'static str),
 }
 
 impl Edition {
+    #[cfg(not(target_arch = "wasm32"))]
     #[inline]
     fn as_os_str(self) -> &'static OsStr {
         match self {
             Edition::Rust2015 => "2015",
             Edition::Rust2018 => "2018",
             Edition::Rust2021 => "2021",
+            Edition::Rust2024 => "2024",
+            Edition::Custom(edition) => edition,
         }
         .as_ref()
     }
@@ -186,29 +313,249 @@ impl Default for Edition {
 
 // *** Post Processing ***
 
-/// Post format processing options - optionally replace comment/blank markers and doc blocks
-#[derive(Clone, Copy, Debug)]
-pub enum PostProcess {
+/// Post format processing options - optionally replace comment/blank markers and doc blocks (or
+/// doc comments). Each kind of processing is an independent flag and flags are combined with `|`
+///
+/// ```
+/// use rust_format::PostProcess;
+///
+/// let post_proc = PostProcess::BLANKS | PostProcess::COMMENTS;
+///
+/// assert!(post_proc.contains(PostProcess::BLANKS));
+/// assert!(!post_proc.contains(PostProcess::DOC_BLOCKS));
+/// assert!(PostProcess::ReplaceMarkers.contains(post_proc));
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct PostProcess {
+    flags: u8,
+    // Zero if doc comments aren't wrapped
+    doc_width: usize,
+    max_blank_lines: Option<usize>,
+}
+
+#[allow(non_upper_case_globals)]
+impl PostProcess {
     /// No post processing after formatting (default)
-    None,
+    pub const None: PostProcess = PostProcess::from_flags(0);
+
+    /// Replace [`_blank_!`], [`_comment_!`], [`_doc_!`], [`_inner_doc_!`], and [`_raw_!`] markers
+    #[cfg(feature = "post_process")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "post_process")))]
+    pub const ReplaceMarkers: PostProcess = PostProcess::BLANKS
+        .union(PostProcess::COMMENTS)
+        .union(PostProcess::DOCS)
+        .union(PostProcess::RAW);
 
-    /// Replace [`_blank_!`] and [`_comment_!`] markers
+    /// Replace [`_blank_!`], [`_comment_!`], [`_doc_!`], [`_inner_doc_!`], and [`_raw_!`] markers
+    /// and  `#[doc = ""]` (with `///`)
     #[cfg(feature = "post_process")]
     #[cfg_attr(docsrs, doc(cfg(feature = "post_process")))]
-    ReplaceMarkers,
+    pub const ReplaceMarkersAndDocBlocks: PostProcess =
+        PostProcess::ReplaceMarkers.union(PostProcess::DOC_BLOCKS);
 
-    /// Replace [`_blank_!`] and [`_comment_!`] markers and  `#[doc = ""]` (with `///`)
+    /// The same as [ReplaceMarkers](PostProcess::ReplaceMarkers), except nothing is replaced
+    /// inside items annotated with `#[rustfmt::skip]`
     #[cfg(feature = "post_process")]
     #[cfg_attr(docsrs, doc(cfg(feature = "post_process")))]
-    ReplaceMarkersAndDocBlocks,
+    pub const ReplaceMarkersRespectingSkip: PostProcess =
+        PostProcess::ReplaceMarkers.union(PostProcess::RESPECT_SKIP);
+
+    /// The same as [ReplaceMarkersAndDocBlocks](PostProcess::ReplaceMarkersAndDocBlocks), except
+    /// nothing is replaced inside items annotated with `#[rustfmt::skip]`
+    #[cfg(feature = "post_process")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "post_process")))]
+    pub const ReplaceMarkersAndDocBlocksRespectingSkip: PostProcess =
+        PostProcess::ReplaceMarkersAndDocBlocks.union(PostProcess::RESPECT_SKIP);
+
+    /// Replace `///` and `//!` doc comments with `#[doc = ""]` and `#![doc = ""]` (the reverse of
+    /// replacing doc blocks - markers are not replaced)
+    #[cfg(feature = "post_process")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "post_process")))]
+    pub const ReplaceDocComments: PostProcess = PostProcess::DOC_COMMENTS;
 }
 
 impl PostProcess {
-    /// Returns true if blank and comment markers should be replaced in the formatted source or
-    /// false if they should not be
+    /// Replace [`_blank_!`] markers
+    #[cfg(feature = "post_process")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "post_process")))]
+    pub const BLANKS: PostProcess = PostProcess::from_flags(1);
+
+    /// Replace [`_comment_!`] markers
+    #[cfg(feature = "post_process")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "post_process")))]
+    pub const COMMENTS: PostProcess = PostProcess::from_flags(1 << 1);
+
+    /// Replace [`_doc_!`] and [`_inner_doc_!`] markers
+    #[cfg(feature = "post_process")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "post_process")))]
+    pub const DOCS: PostProcess = PostProcess::from_flags(1 << 2);
+
+    /// Replace [`_raw_!`] markers
+    #[cfg(feature = "post_process")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "post_process")))]
+    pub const RAW: PostProcess = PostProcess::from_flags(1 << 3);
+
+    /// Replace `#[doc = ""]` with `///`
+    #[cfg(feature = "post_process")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "post_process")))]
+    pub const DOC_BLOCKS: PostProcess = PostProcess::from_flags(1 << 4);
+
+    /// Replace `///` and `//!` doc comments with `#[doc = ""]` and `#![doc = ""]` (done after any
+    /// other replacements)
+    #[cfg(feature = "post_process")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "post_process")))]
+    pub const DOC_COMMENTS: PostProcess = PostProcess::from_flags(1 << 5);
+
+    /// Don't replace markers or doc blocks inside items annotated with `#[rustfmt::skip]`
+    #[cfg(feature = "post_process")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "post_process")))]
+    pub const RESPECT_SKIP: PostProcess = PostProcess::from_flags(1 << 6);
+
+    /// Also replace the enabled markers inside the Rust code blocks of `///` and `//!` doc
+    /// comments (such as doctests generated before post processing), including doc blocks
+    /// replaced with `///`. Markers mentioned anywhere else in doc comments are left as is
+    #[cfg(feature = "post_process")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "post_process")))]
+    pub const DOC_MARKERS: PostProcess = PostProcess::from_flags(1 << 7);
+
+    #[inline]
+    const fn from_flags(flags: u8) -> Self {
+        Self {
+            flags,
+            doc_width: 0,
+            max_blank_lines: None,
+        }
+    }
+
+    /// Returns the flags set in either this or the other options. If both wrap doc comments or cap
+    /// blank lines, the width or cap of the other options is used
+    #[inline]
+    pub const fn union(self, other: PostProcess) -> PostProcess {
+        PostProcess {
+            flags: self.flags | other.flags,
+            doc_width: if other.doc_width != 0 {
+                other.doc_width
+            } else {
+                self.doc_width
+            },
+            max_blank_lines: match other.max_blank_lines {
+                Some(max) => Some(max),
+                None => self.max_blank_lines,
+            },
+        }
+    }
+
+    /// Returns true if every flag set in the other options is also set in these options
+    #[inline]
+    pub const fn contains(self, other: PostProcess) -> bool {
+        self.flags & other.flags == other.flags
+    }
+
+    /// Returns true if any flag set in the other options is also set in these options
+    #[inline]
+    pub const fn intersects(self, other: PostProcess) -> bool {
+        self.flags & other.flags != 0
+    }
+
+    /// Returns true if no flags are set, doc comments aren't wrapped, and blank lines aren't capped
+    /// (no post processing is done)
+    #[inline]
+    pub const fn is_empty(self) -> bool {
+        self.flags == 0 && self.doc_width == 0 && self.max_blank_lines.is_none()
+    }
+
+    /// Re-wrap `///` and `//!` doc comment paragraphs with lines longer than the given width
+    /// (including indent). Code fences, lists, headings, tables, and other block level markdown are
+    /// preserved, and words (such as long links) are never split. This is done after any marker
+    /// and doc block replacements
+    ///
+    /// ```
+    /// use rust_format::PostProcess;
+    ///
+    /// let post_proc = PostProcess::ReplaceMarkers.wrap_docs(100);
+    /// assert_eq!(Some(100), post_proc.doc_width());
+    /// ```
+    #[cfg(feature = "post_process")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "post_process")))]
+    #[inline]
+    pub const fn wrap_docs(mut self, width: usize) -> Self {
+        self.doc_width = width;
+        self
+    }
+
+    /// Returns the width doc comments are wrapped to, if they are wrapped
+    #[inline]
+    pub fn doc_width(self) -> Option<usize> {
+        match self.doc_width {
+            0 => None,
+            width => Some(width),
+        }
+    }
+
+    /// Collapse each run of consecutive blank lines (including those inserted by [`_blank_!`]) to
+    /// at most the given number of lines. Blank lines inside string literals and block comments are
+    /// left as is. This is done after any marker and doc block replacements
+    ///
+    /// ```
+    /// use rust_format::{Config, Formatter, PostProcess, RustFmt};
+    ///
+    /// let post_proc = PostProcess::ReplaceMarkers.max_consecutive_blank_lines(1);
+    /// let config = Config::new_str().post_proc(post_proc);
+    /// let source = "fn main() {\n    _blank_!(3);\n    let x = 1;\n}\n";
+    ///
+    /// let actual = RustFmt::from_config(config).format_str(source).unwrap();
+    /// assert_eq!("fn main() {\n\n    let x = 1;\n}\n", actual);
+    /// ```
+    #[cfg(feature = "post_process")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "post_process")))]
+    #[inline]
+    pub const fn max_consecutive_blank_lines(mut self, max: usize) -> Self {
+        self.max_blank_lines = Some(max);
+        self
+    }
+
+    /// Returns the maximum number of consecutive blank lines, if runs of blank lines are collapsed
+    #[inline]
+    pub fn max_blank_lines(self) -> Option<usize> {
+        self.max_blank_lines
+    }
+
+    /// Returns true if blank, comment, doc, inner doc, or raw markers should be replaced in the
+    /// formatted source or false if they should not be
+    #[cfg(feature = "post_process")]
     #[inline]
     pub fn replace_markers(self) -> bool {
-        !matches!(self, PostProcess::None)
+        self.intersects(PostProcess::ReplaceMarkers)
+    }
+
+    /// Returns true if blank, comment, doc, inner doc, or raw markers should be replaced in the
+    /// formatted source or false if they should not be
+    #[cfg(not(feature = "post_process"))]
+    #[inline]
+    pub fn replace_markers(self) -> bool {
+        false
+    }
+
+    /// Returns true if doc comments should be replaced with doc blocks in the formatted source or
+    /// false if they should not be
+    #[cfg(feature = "post_process")]
+    #[inline]
+    pub fn replace_doc_comments(self) -> bool {
+        self.contains(PostProcess::DOC_COMMENTS)
+    }
+
+    /// Returns true if doc comments should be replaced with doc blocks in the formatted source or
+    /// false if they should not be
+    #[cfg(not(feature = "post_process"))]
+    #[inline]
+    pub fn replace_doc_comments(self) -> bool {
+        false
+    }
+
+    // Returns true if any post processing is done on the formatted source
+    #[inline]
+    pub(crate) fn is_enabled(self) -> bool {
+        !self.is_empty()
     }
 
     /// Returns true if doc blocks should be replaced in the formatted source or false if they
@@ -216,7 +563,7 @@ impl PostProcess {
     #[cfg(feature = "post_process")]
     #[inline]
     pub fn replace_doc_blocks(self) -> bool {
-        matches!(self, PostProcess::ReplaceMarkersAndDocBlocks)
+        self.contains(PostProcess::DOC_BLOCKS)
     }
 
     /// Returns true if doc blocks should be replaced in the formatted source or false if they
@@ -226,12 +573,53 @@ impl PostProcess {
     pub fn replace_doc_blocks(self) -> bool {
         false
     }
+
+    /// Returns true if nothing should be replaced inside items annotated with `#[rustfmt::skip]`
+    /// or false if they should be processed like everything else
+    #[cfg(feature = "post_process")]
+    #[inline]
+    pub fn respect_skip(self) -> bool {
+        self.contains(PostProcess::RESPECT_SKIP)
+    }
+
+    /// Returns true if nothing should be replaced inside items annotated with `#[rustfmt::skip]`
+    /// or false if they should be processed like everything else
+    #[cfg(not(feature = "post_process"))]
+    #[inline]
+    pub fn respect_skip(self) -> bool {
+        false
+    }
+
+    /// Returns true if markers should also be replaced inside the code blocks of doc comments or
+    /// false if they should not be
+    #[cfg(feature = "post_process")]
+    #[inline]
+    pub fn replace_doc_markers(self) -> bool {
+        self.contains(PostProcess::DOC_MARKERS) && self.replace_markers()
+    }
+
+    /// Returns true if markers should also be replaced inside the code blocks of doc comments or
+    /// false if they should not be
+    #[cfg(not(feature = "post_process"))]
+    #[inline]
+    pub fn replace_doc_markers(self) -> bool {
+        false
+    }
 }
 
-impl Default for PostProcess {
+impl BitOr for PostProcess {
+    type Output = PostProcess;
+
     #[inline]
-    fn default() -> Self {
-        PostProcess::None
+    fn bitor(self, rhs: PostProcess) -> Self::Output {
+        self.union(rhs)
+    }
+}
+
+impl BitOrAssign for PostProcess {
+    #[inline]
+    fn bitor_assign(&mut self, rhs: PostProcess) {
+        *self = self.union(rhs);
     }
 }
 
@@ -249,10 +637,20 @@ where
     rust_fmt: Option<P>,
     edition: Edition,
     post_proc: PostProcess,
+    timeout: Option<Duration>,
+    config_path: Option<PathBuf>,
+    toolchain: Option<OsString>,
+    unstable_features: bool,
+    #[cfg(feature = "pretty_please")]
+    pretty_please: PrettyPleaseConfig,
     options: HashMap<K, V>,
+    args: Vec<OsString>,
+    env: ProcessEnv,
+    markers: Markers,
+    backup: Option<OsString>,
 }
 
-impl<'a, 'b> Config<&'a str, &'b str, &'a str> {
+impl<'a> Config<&'a str, &str, &'a str> {
     /// Creates a new blank configuration with `&str` for all type params
     /// (if you wish to use different types, use [new](Config::new) instead)
     #[inline]
@@ -287,7 +685,17 @@ where
             rust_fmt: None,
             edition: Edition::Rust2021,
             post_proc: PostProcess::None,
+            timeout: None,
+            config_path: None,
+            toolchain: None,
+            unstable_features: false,
+            #[cfg(feature = "pretty_please")]
+            pretty_please: PrettyPleaseConfig::default(),
             options,
+            args: Vec::new(),
+            env: ProcessEnv::default(),
+            markers: Markers::default(),
+            backup: None,
         }
     }
 
@@ -313,6 +721,48 @@ where
         self
     }
 
+    /// Set the maximum time `rustfmt` may run before it is killed and [ErrorKind::Timeout] is
+    /// returned (`RustFmt` only, ignored by `PrettyPlease`). By default, there is no timeout
+    #[inline]
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Set the path of a `rustfmt.toml` file (or a directory containing one) whose settings should
+    /// be used when formatting (`RustFmt` only, ignored by `PrettyPlease`). Any key/value pair
+    /// [options](Config::option) take precedence over the settings in the file
+    #[inline]
+    pub fn config_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config_path = Some(path.into());
+        self
+    }
+
+    /// Set the toolchain (such as `nightly`) to run `rustfmt` with via `rustup run` (`RustFmt` only,
+    /// ignored by `PrettyPlease`). By default, `rustfmt` is run directly
+    #[inline]
+    pub fn toolchain(mut self, toolchain: impl AsRef<OsStr>) -> Self {
+        self.toolchain = Some(toolchain.as_ref().to_os_string());
+        self
+    }
+
+    /// Set whether unstable (nightly only) options are enabled via `--unstable-features`
+    /// (`RustFmt` only, ignored by `PrettyPlease`). This requires a nightly `rustfmt`
+    #[inline]
+    pub fn unstable_features(mut self, enabled: bool) -> Self {
+        self.unstable_features = enabled;
+        self
+    }
+
+    /// Set the `PrettyPlease` specific options (`PrettyPlease` only, ignored by `RustFmt`)
+    #[cfg(feature = "pretty_please")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "pretty_please")))]
+    #[inline]
+    pub fn pretty_please(mut self, config: PrettyPleaseConfig) -> Self {
+        self.pretty_please = config;
+        self
+    }
+
     /// Set a key/value pair option (`RustFmt` only, ignored by `PrettyPlease`).
     /// See [here](https://rust-lang.github.io/rustfmt/) for a list of possible options
     #[inline]
@@ -320,50 +770,635 @@ where
         self.options.insert(key, value);
         self
     }
-}
 
-// *** Misc. format related functions ***
+    /// Add an argument to pass to `rustfmt` as is (`RustFmt` only, ignored by `PrettyPlease`).
+    /// These are passed after any arguments from the `RUSTFMT_FLAGS` environment variable
+    #[inline]
+    pub fn arg(mut self, arg: impl AsRef<OsStr>) -> Self {
+        self.args.push(arg.as_ref().to_os_string());
+        self
+    }
 
-#[inline]
-fn post_process(post_proc: PostProcess, source: String) -> Result<String, Error> {
-    if post_proc.replace_markers() {
-        match replace::replace_markers(&source, post_proc.replace_doc_blocks())? {
-            // No change
-            Cow::Borrowed(_) => Ok(source),
-            // Changed
-            Cow::Owned(source) => Ok(source),
-        }
-    } else {
-        Ok(source)
+    /// Add multiple arguments to pass to `rustfmt` as is (`RustFmt` only, ignored by
+    /// `PrettyPlease`)
+    #[inline]
+    pub fn args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        self.args
+            .extend(args.into_iter().map(|arg| arg.as_ref().to_os_string()));
+        self
     }
-}
 
-#[inline]
-fn file_to_string(path: impl AsRef<Path>) -> Result<String, Error> {
-    // Read our file into a string
-    let mut file = fs::File::open(path.as_ref())?;
-    let len = file.metadata()?.len();
-    let mut source = String::with_capacity(len as usize);
+    /// Set the working directory `rustfmt` is run in (`RustFmt` only, ignored by `PrettyPlease`).
+    /// `rustfmt` searches for a `rustfmt.toml` starting from this directory when formatting
+    /// strings. By default, it is run in the current working directory
+    #[inline]
+    pub fn current_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.env.current_dir = Some(dir.into());
+        self
+    }
 
-    file.read_to_string(&mut source)?;
-    Ok(source)
-}
+    /// Set an environment variable for `rustfmt` (`RustFmt` only, ignored by `PrettyPlease`).
+    /// Overrides of `RUSTFMT` and `RUSTFMT_FLAGS` are also used when building the formatter
+    #[inline]
+    pub fn env(mut self, key: impl AsRef<OsStr>, value: impl AsRef<OsStr>) -> Self {
+        self.env.set(key.as_ref(), Some(value.as_ref()));
+        self
+    }
 
-#[inline]
-fn string_to_file(path: impl AsRef<Path>, source: &str) -> Result<(), Error> {
-    let mut file = fs::File::create(path)?;
-    file.write_all(source.as_bytes())?;
-    Ok(())
-}
+    /// Remove an environment variable for `rustfmt`, such as `RUSTFMT` (`RustFmt` only, ignored
+    /// by `PrettyPlease`)
+    #[inline]
+    pub fn env_remove(mut self, key: impl AsRef<OsStr>) -> Self {
+        self.env.set(key.as_ref(), None);
+        self
+    }
 
-// *** Formatter ***
+    /// Run `rustfmt` without inheriting any environment variables, so only those set via
+    /// [env](Config::env) are present (`RustFmt` only, ignored by `PrettyPlease`). This clears any
+    /// variables set before it is called
+    #[inline]
+    pub fn env_clear(mut self) -> Self {
+        self.env.vars.clear();
+        self.env.clear = true;
+        self
+    }
 
-/// A unified interface to all formatters. It allows for formatting from string, file, or
-/// [TokenStream](proc_macro2::TokenStream)
-pub trait Formatter {
-    /// Format the given string and return the results in another `String`. An error is returned
-    /// if any issues occur during formatting
-    fn format_str(&self, source: impl AsRef<str>) -> Result<String, Error>;
+    /// Register a handler for a custom marker that is replaced along with the built-in markers
+    /// (used by both `RustFmt` and `PrettyPlease`). Handlers named after a built-in marker are
+    /// ignored
+    #[cfg(feature = "post_process")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "post_process")))]
+    #[inline]
+    pub fn marker_handler(mut self, handler: impl MarkerHandler + 'static) -> Self {
+        self.markers.push(handler);
+        self
+    }
+
+    /// Set whether [format_file](Formatter::format_file) copies the original file to
+    /// `<file>.bak` before overwriting it (used by both `RustFmt` and `PrettyPlease`). By default,
+    /// no backup is made
+    #[inline]
+    pub fn backup(mut self, enabled: bool) -> Self {
+        self.backup = enabled.then(|| BACKUP_SUFFIX.into());
+        self
+    }
+
+    /// Set the suffix appended to the file name of backups and enable them (used by both
+    /// `RustFmt` and `PrettyPlease`). See [backup](Config::backup)
+    #[inline]
+    pub fn backup_suffix(mut self, suffix: impl AsRef<OsStr>) -> Self {
+        self.backup = Some(suffix.as_ref().to_os_string());
+        self
+    }
+}
+
+// *** Process Environment ***
+
+// The working directory and environment variable overrides for a formatter process (a value of
+// `None` removes the variable)
+#[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+#[derive(Clone, Debug, Default)]
+struct ProcessEnv {
+    current_dir: Option<PathBuf>,
+    vars: Vec<(OsString, Option<OsString>)>,
+    clear: bool,
+}
+
+impl ProcessEnv {
+    fn set(&mut self, key: &OsStr, value: Option<&OsStr>) {
+        let value = value.map(OsStr::to_os_string);
+        match self.vars.iter_mut().find(|(k, _)| k == key) {
+            Some((_, v)) => *v = value,
+            None => self.vars.push((key.to_os_string(), value)),
+        }
+    }
+
+    // Returns the value of the variable as the process will see it
+    #[cfg(not(target_arch = "wasm32"))]
+    fn var_os(&self, key: &str) -> Option<OsString> {
+        match self.vars.iter().find(|(k, _)| k == key) {
+            Some((_, value)) => value.clone(),
+            None if self.clear => None,
+            None => env::var_os(key),
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn apply(&self, command: &mut Command) {
+        if let Some(dir) = &self.current_dir {
+            command.current_dir(dir);
+        }
+        if self.clear {
+            command.env_clear();
+        }
+        for (key, value) in &self.vars {
+            match value {
+                Some(value) => command.env(key, value),
+                None => command.env_remove(key),
+            };
+        }
+    }
+}
+
+// *** Misc. format related functions ***
+
+/// Post process already formatted source code exactly as the formatters do, without running a
+/// formatter. The source is returned as is (borrowed) if nothing was replaced
+///
+/// ```
+/// use std::borrow::Cow;
+///
+/// use rust_format::{post_process_str, PostProcess};
+///
+/// let source = "fn main() {\n    _comment_!(\"Comment\");\n}\n";
+/// let actual = post_process_str(source, PostProcess::ReplaceMarkers).unwrap();
+/// assert_eq!("fn main() {\n    // Comment\n}\n", actual);
+///
+/// let source = "fn main() {}\n";
+/// let actual = post_process_str(source, PostProcess::ReplaceMarkers).unwrap();
+/// assert!(matches!(actual, Cow::Borrowed(_)));
+/// ```
+#[cfg(feature = "post_process")]
+#[cfg_attr(docsrs, doc(cfg(feature = "post_process")))]
+pub fn post_process_str(source: &str, post_proc: PostProcess) -> Result<Cow<'_, str>, Error> {
+    if !post_proc.is_enabled() {
+        return Ok(Cow::Borrowed(source));
+    }
+
+    let result = post_process(post_proc, &Markers::NONE, source.to_string())?;
+    if result == source {
+        Ok(Cow::Borrowed(source))
+    } else {
+        Ok(Cow::Owned(result))
+    }
+}
+
+/// Post process already formatted source code exactly as the formatters do, returning the results
+/// along with counts of what was replaced. This allows generators to verify in tests that each
+/// marker they emitted was actually replaced
+///
+/// ```
+/// use rust_format::{post_process_with_stats, PostProcess, PostProcessStats};
+///
+/// let source = "fn main() {\n    _comment_!(\"Comment\");\n    _blank_!(2);\n}\n";
+///
+/// let (actual, stats) = post_process_with_stats(PostProcess::ReplaceMarkers, source).unwrap();
+/// assert_eq!("fn main() {\n    // Comment\n\n\n}\n", actual);
+///
+/// let expected = PostProcessStats {
+///     blanks: 1,
+///     comments: 1,
+///     ..Default::default()
+/// };
+/// assert_eq!(expected, stats);
+/// ```
+#[cfg(feature = "post_process")]
+#[cfg_attr(docsrs, doc(cfg(feature = "post_process")))]
+pub fn post_process_with_stats(
+    post_proc: PostProcess,
+    source: impl Into<String>,
+) -> Result<(String, PostProcessStats), Error> {
+    let mut stats = PostProcessStats::default();
+    let source = post_process_counting(post_proc, &Markers::NONE, source.into(), &mut stats)?;
+    Ok((source, stats))
+}
+
+#[inline]
+fn post_process(
+    post_proc: PostProcess,
+    markers: &Markers,
+    source: String,
+) -> Result<String, Error> {
+    post_process_counting(post_proc, markers, source, &mut PostProcessStats::default())
+}
+
+fn post_process_counting(
+    post_proc: PostProcess,
+    markers: &Markers,
+    source: String,
+    stats: &mut PostProcessStats,
+) -> Result<String, Error> {
+    if !post_proc.is_enabled() {
+        return Ok(source);
+    }
+
+    // The prologue (shebang and/or frontmatter) isn't Rust code, so it is never post processed
+    match prologue::split_prologue(&source) {
+        ("", _) => post_process_code_counting(post_proc, markers, source, stats),
+        (prologue, code) => {
+            let code = post_process_code_counting(post_proc, markers, code.to_string(), stats)?;
+            Ok(format!("{prologue}{code}"))
+        }
+    }
+    .context(Operation::PostProcess)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[inline]
+fn post_process_code(
+    post_proc: PostProcess,
+    markers: &Markers,
+    source: String,
+) -> Result<String, Error> {
+    post_process_code_counting(post_proc, markers, source, &mut PostProcessStats::default())
+}
+
+fn post_process_code_counting(
+    post_proc: PostProcess,
+    markers: &Markers,
+    source: String,
+    stats: &mut PostProcessStats,
+) -> Result<String, Error> {
+    let source = if post_proc.replace_markers() || post_proc.replace_doc_blocks() {
+        match replace::replace_markers_counting(&source, post_proc, markers, stats)? {
+            // No change
+            Cow::Borrowed(_) => source,
+            // Changed
+            Cow::Owned(source) => source,
+        }
+    } else {
+        source
+    };
+
+    #[cfg(feature = "post_process")]
+    let source = if post_proc.replace_doc_markers() {
+        match doc_markers::replace_doc_markers(&source, post_proc, markers, stats)? {
+            Cow::Borrowed(_) => source,
+            Cow::Owned(source) => source,
+        }
+    } else {
+        source
+    };
+
+    #[cfg(feature = "post_process")]
+    let source = match post_proc.doc_width() {
+        Some(width) => match wrap::wrap_doc_comments(&source, width) {
+            Cow::Borrowed(_) => source,
+            Cow::Owned(source) => source,
+        },
+        None => source,
+    };
+
+    #[cfg(feature = "post_process")]
+    let source = match post_proc.max_blank_lines() {
+        Some(max) => match blank_lines::collapse_blank_lines(&source, max) {
+            Cow::Borrowed(_) => source,
+            Cow::Owned(source) => source,
+        },
+        None => source,
+    };
+
+    if post_proc.replace_doc_comments() {
+        match replace::replace_doc_comments(&source)? {
+            Cow::Borrowed(_) => Ok(source),
+            Cow::Owned(source) => Ok(source),
+        }
+    } else {
+        Ok(source)
+    }
+}
+
+#[inline]
+fn file_to_string(path: impl AsRef<Path>) -> Result<String, Error> {
+    // Read our file into a string
+    let path = path.as_ref();
+    let read = || {
+        let mut file = fs::File::open(path)?;
+        let len = file.metadata()?.len();
+        let mut source = String::with_capacity(len as usize);
+
+        file.read_to_string(&mut source)?;
+        Ok::<_, io::Error>(source)
+    };
+    read().path_context(Operation::ReadFile, path)
+}
+
+// The path of the backup of a file (the suffix is appended to the file name)
+fn backup_path(path: &Path, suffix: &OsStr) -> PathBuf {
+    let mut backup = path.as_os_str().to_os_string();
+    backup.push(suffix);
+    backup.into()
+}
+
+// Copy the file to its backup (if backups are enabled) before it is overwritten
+fn backup_file(path: &Path, suffix: Option<&OsStr>) -> Result<(), Error> {
+    match suffix {
+        Some(suffix) => {
+            let backup = backup_path(path, suffix);
+            fs::copy(path, &backup)
+                .map(|_| ())
+                .path_context(Operation::WriteFile, &backup)
+        }
+        None => Ok(()),
+    }
+}
+
+#[inline]
+fn string_to_file(path: impl AsRef<Path>, source: &str) -> Result<(), Error> {
+    let path = path.as_ref();
+    fs::File::create(path)
+        .and_then(|mut file| file.write_all(source.as_bytes()))
+        .path_context(Operation::WriteFile, path)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+// Read everything from the pipe (if it was captured)
+fn read_pipe(pipe: Option<impl Read>) -> io::Result<Vec<u8>> {
+    let mut buffer = Vec::new();
+    if let Some(mut pipe) = pipe {
+        pipe.read_to_end(&mut buffer)?;
+    }
+    Ok(buffer)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[inline]
+fn join<T>(handle: thread::ScopedJoinHandle<'_, T>) -> T {
+    handle
+        .join()
+        .unwrap_or_else(|err| panic::resume_unwind(err))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+// Send the source (if any) to the stdin of the process and wait for it to exit. If it doesn't exit
+// before the timeout (if any), it is killed
+fn wait_output(
+    proc: Child,
+    source: Option<&str>,
+    timeout: Option<Duration>,
+) -> Result<Output, Error> {
+    wait_output_inner(proc, source, timeout).context(Operation::Wait)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn wait_output_inner(
+    mut proc: Child,
+    source: Option<&str>,
+    timeout: Option<Duration>,
+) -> Result<Output, Error> {
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => {
+            if let Some(source) = source {
+                // Safety: Can't panic - we captured stdin when spawning
+                let mut stdin = proc.stdin.take().unwrap();
+                stdin.write_all(source.as_bytes())?;
+                // Close stdin
+                drop(stdin);
+            }
+            return Ok(proc.wait_with_output()?);
+        }
+    };
+
+    // The pipes are serviced on their own threads so a hung process can't block us
+    let stdin = proc.stdin.take();
+    let stdout = proc.stdout.take();
+    let stderr = proc.stderr.take();
+    let deadline = Instant::now() + timeout;
+
+    thread::scope(|scope| {
+        let writer = scope.spawn(move || match (stdin, source) {
+            (Some(mut stdin), Some(source)) => stdin.write_all(source.as_bytes()),
+            _ => Ok(()),
+        });
+        let stdout = scope.spawn(move || read_pipe(stdout));
+        let stderr = scope.spawn(move || read_pipe(stderr));
+
+        let status = loop {
+            if let Some(status) = proc.try_wait()? {
+                break status;
+            } else if Instant::now() >= deadline {
+                // Killing the process closes its pipes, which unblocks our threads
+                proc.kill()?;
+                proc.wait()?;
+                return Err(Error::timeout(timeout));
+            }
+            thread::sleep(POLL_INTERVAL);
+        };
+
+        join(writer)?;
+        Ok(Output {
+            status,
+            stdout: join(stdout)?,
+            stderr: join(stderr)?,
+        })
+    })
+}
+
+// Run the command, sending the source to its stdin and returning its stdout and any warnings (or
+// stderr on failure)
+#[cfg(not(target_arch = "wasm32"))]
+fn run_command<I, S>(
+    mut command: Command,
+    args: I,
+    source: &str,
+    timeout: Option<Duration>,
+) -> Result<FormatOutput, Error>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    // Launch the formatter
+    let proc = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .args(args)
+        .spawn()
+        .context(Operation::Spawn)?;
+
+    // Parse the results and return stdout/stderr
+    let output = wait_output(proc, Some(source), timeout)?;
+    let stderr = String::from_utf8(output.stderr).context(Operation::Wait)?;
+
+    if output.status.success() {
+        let code = String::from_utf8(output.stdout).context(Operation::Wait)?;
+        Ok(FormatOutput::new(code, &stderr))
+    } else {
+        Err(Error::from_process(output.status, stderr, Some(source)))
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+// The same as `run_command`, but the tokens are written straight to stdin. The tokens are only
+// converted into a `String` if the command fails (for the error)
+#[cfg(feature = "token_stream")]
+fn run_command_tokens<I, S>(
+    mut command: Command,
+    args: I,
+    tokens: &proc_macro2::TokenStream,
+) -> Result<FormatOutput, Error>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    // Launch the formatter
+    let mut proc = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .args(args)
+        .spawn()
+        .context(Operation::Spawn)?;
+
+    // Safety: Can't panic - we captured stdin when spawning
+    let mut stdin = io::BufWriter::new(proc.stdin.take().unwrap());
+    write!(stdin, "{tokens}").context(Operation::Wait)?;
+    // Flush and close stdin
+    drop(
+        stdin
+            .into_inner()
+            .map_err(io::IntoInnerError::into_error)
+            .context(Operation::Wait)?,
+    );
+    let output = proc.wait_with_output().context(Operation::Wait)?;
+
+    // Parse the results and return stdout/stderr
+    let stderr = String::from_utf8(output.stderr).context(Operation::Wait)?;
+
+    if output.status.success() {
+        let code = String::from_utf8(output.stdout).context(Operation::Wait)?;
+        Ok(FormatOutput::new(code, &stderr))
+    } else {
+        let source = tokens.to_string();
+        Err(Error::from_process(output.status, stderr, Some(&source)))
+    }
+}
+
+// *** Format Output ***
+
+/// The formatted source code along with any warnings the formatter printed while still succeeding
+/// (such as `rustfmt` warning about unknown or unstable configuration options)
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FormatOutput {
+    /// The formatted source code
+    pub code: String,
+    /// Each non-blank line the formatter printed to stderr
+    pub warnings: Vec<String>,
+}
+
+// Only process based formatters capture stderr and post process their output separately
+#[cfg(not(target_arch = "wasm32"))]
+impl FormatOutput {
+    fn new(code: String, stderr: &str) -> Self {
+        let warnings = stderr
+            .lines()
+            .map(str::trim_end)
+            .filter(|line| !line.is_empty())
+            .map(ToString::to_string)
+            .collect();
+        Self { code, warnings }
+    }
+
+    #[inline]
+    fn post_process(self, post_proc: PostProcess, markers: &Markers) -> Result<Self, Error> {
+        Ok(Self {
+            code: post_process(post_proc, markers, self.code)?,
+            warnings: self.warnings,
+        })
+    }
+}
+
+// *** Check ***
+
+/// The result of checking whether source code is already formatted
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Check {
+    /// The source code is already formatted
+    Formatted,
+    /// Formatting would change the source code. A diff of the changes is included when the
+    /// formatter provides one (`RustFmt` does) or, for other formatters, when the `diff` feature is
+    /// enabled (a unified diff)
+    Unformatted(Option<String>),
+}
+
+impl Check {
+    /// Returns true if the source code is already formatted or false if it is not
+    #[inline]
+    pub fn is_formatted(&self) -> bool {
+        matches!(self, Check::Formatted)
+    }
+}
+
+// Check by formatting the source and comparing the result to the original
+fn check_by_formatting(fmt: &(impl Formatter + ?Sized), source: &str) -> Result<Check, Error> {
+    let result = fmt.format_str(source)?;
+    if result == source {
+        Ok(Check::Formatted)
+    } else {
+        #[cfg(feature = "diff")]
+        let diff = Some(unified_diff(source, &result, &DiffOptions::new()));
+        #[cfg(not(feature = "diff"))]
+        let diff = None;
+        Ok(Check::Unformatted(diff))
+    }
+}
+
+// *** Format Result ***
+
+/// The formatted source code along with whether formatting changed it
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FormatResult {
+    /// The formatted source code
+    pub code: String,
+    /// True if the formatted source code differs from the original or false if it was already
+    /// formatted
+    pub changed: bool,
+}
+
+// *** Post Process Stats ***
+
+/// Counts of what post processing replaced (see [post_process_with_stats])
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct PostProcessStats {
+    /// The number of `_blank_!` markers replaced with blank lines
+    pub blanks: usize,
+    /// The number of `_comment_!` markers replaced with comments
+    pub comments: usize,
+    /// The number of `_doc_!` and `_inner_doc_!` markers replaced with doc comments
+    pub docs: usize,
+    /// The number of `_raw_!` markers replaced with their text
+    pub raw: usize,
+    /// The number of `#[doc = ...]` blocks converted into doc comments
+    pub doc_blocks: usize,
+    /// The number of custom markers replaced (see `MarkerHandler`)
+    pub custom: usize,
+}
+
+impl AddAssign for PostProcessStats {
+    #[inline]
+    fn add_assign(&mut self, rhs: Self) {
+        self.blanks += rhs.blanks;
+        self.comments += rhs.comments;
+        self.docs += rhs.docs;
+        self.raw += rhs.raw;
+        self.doc_blocks += rhs.doc_blocks;
+        self.custom += rhs.custom;
+    }
+}
+
+// *** Formatter ***
+
+/// A unified interface to all formatters. It allows for formatting from string, file, or
+/// [TokenStream](proc_macro2::TokenStream)
+pub trait Formatter {
+    /// Format the given string and return the results in another `String`. An error is returned
+    /// if any issues occur during formatting
+    fn format_str(&self, source: impl AsRef<str>) -> Result<String, Error>;
+
+    /// Format the given string and return the results along with any warnings the formatter
+    /// emitted. Formatters that never emit warnings (the default) always return an empty list
+    #[inline]
+    fn format_str_output(&self, source: impl AsRef<str>) -> Result<FormatOutput, Error> {
+        Ok(FormatOutput {
+            code: self.format_str(source)?,
+            warnings: Vec::new(),
+        })
+    }
 
     /// Format the given file specified hte path and overwrite the file with the results. An error
     /// is returned if any issues occur during formatting
@@ -373,19 +1408,206 @@ pub trait Formatter {
         string_to_file(path, &result)
     }
 
+    /// Format the source file specified by `src` and write the results to the file specified by
+    /// `dst` (which is created or truncated). The source file is never modified. An error is
+    /// returned if any issues occur during formatting
+    fn format_file_to(&self, src: impl AsRef<Path>, dst: impl AsRef<Path>) -> Result<(), Error> {
+        let source = file_to_string(src)?;
+        let result = self.format_str(source)?;
+        string_to_file(dst, &result)
+    }
+
+    /// Format the given source code and write the results to the writer. An error is returned if
+    /// any issues occur during formatting or writing
+    fn format_to_writer(
+        &self,
+        source: impl AsRef<str>,
+        writer: &mut impl Write,
+    ) -> Result<(), Error> {
+        let result = self.format_str(source)?;
+        Ok(writer.write_all(result.as_bytes())?)
+    }
+
     /// Format the given [TokenStream](proc_macro2::TokenStream) and return the results in a `String`.
     /// An error is returned if any issues occur during formatting
     #[cfg(feature = "token_stream")]
     #[cfg_attr(docsrs, doc(cfg(feature = "token_stream")))]
     #[inline]
     fn format_tokens(&self, tokens: proc_macro2::TokenStream) -> Result<String, Error> {
+        self.format_tokens_ref(&tokens)
+    }
+
+    /// The same as [format_tokens](Formatter::format_tokens), but the tokens are borrowed.
+    /// Formatters that run a separate process (such as `RustFmt`) write the tokens straight to it
+    /// instead of converting them into a `String` first
+    #[cfg(feature = "token_stream")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "token_stream")))]
+    #[inline]
+    fn format_tokens_ref(&self, tokens: &proc_macro2::TokenStream) -> Result<String, Error> {
         self.format_str(tokens.to_string())
     }
+
+    /// Format the given [syn::File] and return the results in a `String`. Formatters that work on
+    /// a syntax tree (such as `PrettyPlease`) use it as is, while others render it to a
+    /// [TokenStream](proc_macro2::TokenStream) first. An error is returned if any issues occur
+    /// during formatting
+    #[cfg(feature = "pretty_please")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "pretty_please")))]
+    #[inline]
+    fn format_syn_file(&self, file: &syn::File) -> Result<String, Error> {
+        self.format_tokens(file.to_token_stream())
+    }
+
+    /// Check whether the given source code is already formatted without modifying anything. An
+    /// error is returned if any issues occur during formatting
+    fn check_str(&self, source: impl AsRef<str>) -> Result<Check, Error> {
+        check_by_formatting(self, source.as_ref())
+    }
+
+    /// Check whether the given file specified by the path is already formatted without modifying
+    /// it. An error is returned if any issues occur during formatting
+    fn check_file(&self, path: impl AsRef<Path>) -> Result<Check, Error> {
+        let source = file_to_string(path)?;
+        self.check_str(source)
+    }
+
+    /// Format the given string and return the results along with whether formatting changed
+    /// anything. An error is returned if any issues occur during formatting
+    fn format_str_with_report(&self, source: impl AsRef<str>) -> Result<FormatResult, Error> {
+        let source = source.as_ref();
+        let code = self.format_str(source)?;
+        let changed = code != source;
+        Ok(FormatResult { code, changed })
+    }
+
+    /// Format the given string and return the results along with a [SourceMap] from each line of
+    /// the source code to the lines of the results it became. An error is returned if any issues
+    /// occur during formatting
+    fn format_str_with_source_map(
+        &self,
+        source: impl AsRef<str>,
+    ) -> Result<(String, SourceMap), Error> {
+        let source = source.as_ref();
+        let code = self.format_str(source)?;
+        let source_map = SourceMap::new(source, &code);
+        Ok((code, source_map))
+    }
+
+    /// Format the given file specified by the path and return whether formatting changed
+    /// anything. The file is only written to if it changed. An error is returned if any issues
+    /// occur during formatting
+    fn format_file_with_report(&self, path: impl AsRef<Path>) -> Result<bool, Error> {
+        let source = file_to_string(path.as_ref())?;
+        let result = self.format_str_with_report(source)?;
+        if result.changed {
+            string_to_file(path, &result.code)?;
+        }
+        Ok(result.changed)
+    }
+
+    /// Format each of the given strings and return the results in the same order. A failure to
+    /// format one string does not stop the others from being formatted
+    fn format_strs(&self, sources: impl IntoIterator<Item = String>) -> Vec<Result<String, Error>> {
+        sources
+            .into_iter()
+            .map(|source| self.format_str(source))
+            .collect()
+    }
+
+    /// Format each of the given files in place and return the path and result of each file in the
+    /// same order. A failure to format one file does not stop the others from being formatted, so
+    /// every problem file can be reported at once
+    fn format_files(&self, paths: &[impl AsRef<Path>]) -> FileResults {
+        paths
+            .iter()
+            .map(|path| (path.as_ref().to_path_buf(), self.format_file(path)))
+            .collect()
+    }
+
+    /// The same as [format_strs](Formatter::format_strs), but the strings are formatted in
+    /// parallel (one thread per available CPU)
+    fn format_strs_par(
+        &self,
+        sources: impl IntoIterator<Item = String>,
+    ) -> Vec<Result<String, Error>>
+    where
+        Self: Sync,
+    {
+        let sources: Vec<_> = sources.into_iter().collect();
+        par_map(&sources, |source| self.format_str(source))
+    }
+
+    /// The same as [format_files](Formatter::format_files), but the files are formatted in
+    /// parallel (one thread per available CPU)
+    fn format_files_par<P>(&self, paths: &[P]) -> FileResults
+    where
+        Self: Sync,
+        P: AsRef<Path> + Sync,
+    {
+        par_map(paths, |path| {
+            (path.as_ref().to_path_buf(), self.format_file(path))
+        })
+    }
+
+    /// Format every `.rs` file in the directory tree in place (except those excluded by the
+    /// options) and return the path and result of each file in a stable order. A failure to format
+    /// one file does not stop the others from being formatted, however, an error is returned if
+    /// the directory tree could not be read
+    fn format_dir(
+        &self,
+        path: impl AsRef<Path>,
+        options: &FormatDirOptions,
+    ) -> Result<FileResults, Error> {
+        let paths = dir::rust_files(path.as_ref(), options)?;
+        Ok(self.format_files(&paths))
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+// Map the items in parallel, splitting them into one chunk per available CPU and keeping the order
+fn par_map<T, R, F>(items: &[T], f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let threads = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+    let chunk_size = items.len().div_ceil(threads).max(1);
+
+    thread::scope(|scope| {
+        let handles: Vec<_> = items
+            .chunks(chunk_size)
+            .map(|chunk| scope.spawn(|| chunk.iter().map(&f).collect::<Vec<_>>()))
+            .collect();
+
+        handles
+            .into_iter()
+            .flat_map(|handle| {
+                handle
+                    .join()
+                    .unwrap_or_else(|err| panic::resume_unwind(err))
+            })
+            .collect()
+    })
+}
+
+// Threads can't be spawned on wasm, so the items are mapped one at a time
+#[cfg(target_arch = "wasm32")]
+#[inline]
+fn par_map<T, R, F>(items: &[T], f: F) -> Vec<R>
+where
+    F: Fn(&T) -> R,
+{
+    items.iter().map(f).collect()
 }
 
 // *** Rust Fmt ***
 
-/// This formatter uses `rustfmt` for formatting source code
+/// This formatter uses `rustfmt` for formatting source code. It is always run as a separate
+/// process, as linking `rustfmt` in-process requires the nightly-only `rustc_private` crates
+///
+/// Any whitespace separated flags in the `RUSTFMT_FLAGS` environment variable are passed to
+/// `rustfmt` as well (followed by any [args](Config::args) from the configuration)
 ///
 /// An example using a custom configuration:
 /// ```
@@ -408,14 +1630,25 @@ pub trait Formatter {
 ///
 /// assert_eq!(expected, actual);
 /// ```
-#[derive(Clone)]
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Clone, Debug)]
 pub struct RustFmt {
     rust_fmt: PathBuf,
     edition: Edition,
     post_proc: PostProcess,
+    timeout: Option<Duration>,
+    config_path: Option<PathBuf>,
     config_str: Option<OsString>,
+    toolchain: Option<OsString>,
+    unstable_features: bool,
+    args: Vec<OsString>,
+    // Boxed since it is rarely used (and would otherwise make this much larger)
+    env: Box<ProcessEnv>,
+    markers: Markers,
+    backup: Option<OsString>,
 }
 
+#[cfg(not(target_arch = "wasm32"))]
 impl RustFmt {
     /// Creates a new instance of `RustFmt` using a default configuration
     #[inline]
@@ -446,21 +1679,71 @@ impl RustFmt {
         // else use the default
         let rust_fmt = match config.rust_fmt {
             Some(path) => path.into(),
-            None => env::var_os(RUST_FMT_KEY)
+            None => config
+                .env
+                .var_os(RUST_FMT_KEY)
                 .unwrap_or_else(|| RUST_FMT.parse().unwrap())
                 .into(),
         };
 
+        // Whitespace separated flags from the environment var go before those from the config
+        let flags = config.env.var_os(RUST_FMT_FLAGS_KEY);
+        let mut args = match flags.as_ref().and_then(|flags| flags.to_str()) {
+            Some(flags) => Self::split_flags(flags),
+            None => Vec::new(),
+        };
+        args.extend(config.args);
+
         let edition = config.edition;
         let config_str = Self::build_config_str(config.options);
         Self {
             rust_fmt,
             edition,
             post_proc: config.post_proc,
+            timeout: config.timeout,
+            config_path: config.config_path,
             config_str,
+            toolchain: config.toolchain,
+            unstable_features: config.unstable_features,
+            args,
+            env: Box::new(config.env),
+            markers: config.markers,
+            backup: config.backup,
+        }
+    }
+
+    // The program to launch (`rustup` if running via a toolchain)
+    #[inline]
+    fn program(&self) -> &Path {
+        match &self.toolchain {
+            Some(_) => RUSTUP.as_ref(),
+            None => &self.rust_fmt,
+        }
+    }
+
+    // A command to launch the program in the configured working directory and environment
+    fn command(&self) -> Command {
+        let mut command = Command::new(self.program());
+        self.env.apply(&mut command);
+        command
+    }
+
+    // File paths are relative to our working directory, not the one `rustfmt` is run in
+    fn file_path<'a>(&self, path: &'a Path) -> Result<Cow<'a, Path>, Error> {
+        match &self.env.current_dir {
+            Some(_) if path.is_relative() => {
+                let dir = env::current_dir().path_context(Operation::ReadFile, path)?;
+                Ok(Cow::Owned(dir.join(path)))
+            }
+            _ => Ok(Cow::Borrowed(path)),
         }
     }
 
+    #[inline]
+    fn split_flags(flags: &str) -> Vec<OsString> {
+        flags.split_whitespace().map(OsString::from).collect()
+    }
+
     fn build_config_str<K, V>(cfg_options: HashMap<K, V>) -> Option<OsString>
     where
         K: Default + AsRef<OsStr>,
@@ -469,7 +1752,10 @@ impl RustFmt {
         if !cfg_options.is_empty() {
             // Random # that should hold a few options
             let mut options = OsString::with_capacity(512);
-            let iter = cfg_options.iter();
+            // Sorted so the same options always produce the same arguments
+            let mut cfg_options: Vec<_> = cfg_options.iter().collect();
+            cfg_options.sort_by(|(k1, _), (k2, _)| k1.as_ref().cmp(k2.as_ref()));
+            let iter = cfg_options.into_iter();
 
             for (idx, (k, v)) in iter.enumerate() {
                 // Build a comma separated list but only between items (no trailing comma)
@@ -487,99 +1773,646 @@ impl RustFmt {
         }
     }
 
-    fn build_args<'a, P>(&'a self, path: Option<&'a P>) -> Vec<&'a OsStr>
-    where
-        P: AsRef<Path> + ?Sized,
-    {
-        let mut args = match path {
-            Some(path) => {
-                let mut args = Vec::with_capacity(5);
-                args.push(path.as_ref().as_ref());
-                args
-            }
-            None => Vec::with_capacity(4),
-        };
-
-        args.push("--edition".as_ref());
-        args.push(self.edition.as_os_str());
+    // The arguments needed before any `rustfmt` arguments (`run <toolchain> rustfmt` if running via
+    // a toolchain)
+    fn toolchain_args(&self, capacity: usize) -> Vec<&OsStr> {
+        let mut args = Vec::with_capacity(capacity);
 
-        if let Some(config_str) = &self.config_str {
-            args.push("--config".as_ref());
-            args.push(config_str);
+        if let Some(toolchain) = &self.toolchain {
+            args.push("run".as_ref());
+            args.push(toolchain.as_os_str());
+            args.push(self.rust_fmt.as_os_str());
         }
-
         args
     }
-}
 
-impl Default for RustFmt {
+    /// Runs `rustfmt --version` and returns the parsed version. This can be used to check
+    /// requirements (such as a nightly `rustfmt`) before formatting
+    pub fn version(&self) -> Result<RustFmtVersion, Error> {
+        let mut args = self.toolchain_args(4);
+        args.push("--version".as_ref());
+
+        let proc = self
+            .command()
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .args(args)
+            .spawn()
+            .context(Operation::Spawn)?;
+        let output = wait_output(proc, None, self.timeout)?;
+        let stderr = String::from_utf8(output.stderr).context(Operation::Wait)?;
+
+        if output.status.success() {
+            let version = String::from_utf8(output.stdout).context(Operation::Wait)?;
+            RustFmtVersion::parse(&version).ok_or(ErrorKind::UnknownVersion(version).into())
+        } else {
+            Err(Error::from_process(output.status, stderr, None))
+        }
+    }
+
+    /// Returns true if `rustfmt` can be run (and reports a valid version)
     #[inline]
-    fn default() -> Self {
-        Self::new()
+    pub fn is_available(&self) -> bool {
+        self.version().is_ok()
     }
-}
 
-impl Formatter for RustFmt {
-    fn format_str(&self, source: impl AsRef<str>) -> Result<String, Error> {
+    /// Format the given file specified by the path in place, streaming it through `rustfmt`
+    /// instead of reading it into memory first. Any post processing is done a chunk at a time, so
+    /// memory use stays bounded regardless of the size of the file. The results are written to a
+    /// temporary file that replaces the original only if formatting succeeds. The timeout (if any)
+    /// is not enforced. [format_file](Formatter::format_file) does this automatically for large
+    /// files when post processing and there is no timeout
+    pub fn format_file_streamed(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let path = path.as_ref();
+        backup_file(path, self.backup.as_deref())?;
+        let input = fs::File::open(path).path_context(Operation::ReadFile, path)?;
+        let permissions = input
+            .metadata()
+            .path_context(Operation::ReadFile, path)?
+            .permissions();
+
+        let tmp_path = path.with_extension(format!("{}.tmp", std::process::id()));
+        let result = fs::File::create(&tmp_path)
+            .and_then(|output| {
+                output.set_permissions(permissions)?;
+                Ok(output)
+            })
+            .path_context(Operation::WriteFile, &tmp_path)
+            .and_then(|output| self.stream(input, output))
+            .and_then(|()| fs::rename(&tmp_path, path).path_context(Operation::WriteFile, path));
+
+        if result.is_err() {
+            // The original error is more useful than any error removing the temporary file
+            let _ = fs::remove_file(&tmp_path);
+        }
+        result
+    }
+
+    // Pipe the input file through `rustfmt`, post processing its output into the output file
+    fn stream(&self, input: fs::File, output: fs::File) -> Result<(), Error> {
         let args = self.build_args(None as Option<&Path>);
+        let mut proc = self
+            .command()
+            .stdin(input)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .args(args)
+            .spawn()
+            .context(Operation::Spawn)?;
+
+        // Safety: Can't panic - we captured all the pipes when spawning
+        let stdout = io::BufReader::new(proc.stdout.take().unwrap());
+        let stderr = proc.stderr.take();
+        let output = io::BufWriter::new(output);
+        let mut processor =
+            ChunkedPostProcessor::new(output, self.post_proc, &self.markers, STREAM_CHUNK_SIZE);
+
+        // Stderr is serviced on its own thread while stdout is post processed
+        let stderr = thread::scope(|scope| {
+            let stderr = scope.spawn(move || read_pipe(stderr));
+
+            if let Err(err) = processor.write_lines(stdout) {
+                // Killing the process closes its pipes, which unblocks our thread
+                proc.kill().context(Operation::Wait)?;
+                proc.wait().context(Operation::Wait)?;
+                return Err(err);
+            }
+            join(stderr).context(Operation::Wait)
+        })?;
+
+        let status = proc.wait().context(Operation::Wait)?;
+        if status.success() {
+            processor.finish()?;
+            Ok(())
+        } else {
+            let stderr = String::from_utf8(stderr).context(Operation::Wait)?;
+            Err(Error::from_process(status, stderr, None))
+        }
+    }
+
+    fn build_args<'a, P>(&'a self, path: Option<&'a P>) -> Vec<&'a OsStr>
+    where
+        P: AsRef<Path> + ?Sized,
+    {
+        let mut args = self.toolchain_args(11);
+
+        if let Some(path) = path {
+            args.push(path.as_ref().as_os_str());
+        }
+
+        args.push("--edition".as_ref());
+        args.push(self.edition.as_os_str());
+
+        if self.unstable_features {
+            args.push("--unstable-features".as_ref());
+        }
+
+        if let Some(config_path) = &self.config_path {
+            args.push("--config-path".as_ref());
+            args.push(config_path.as_os_str());
+        }
+
+        if let Some(config_str) = &self.config_str {
+            args.push("--config".as_ref());
+            args.push(config_str);
+        }
+
+        args.extend(self.args.iter().map(OsString::as_os_str));
+        args
+    }
+
+    // Run `rustfmt --check` on either the file or the source (via stdin)
+    fn check(&self, path: Option<&Path>, source: Option<&str>) -> Result<Check, Error> {
+        let mut args = self.build_args(path);
+        args.extend(["--check", "--color", "never"].map(OsStr::new));
 
         // Launch rustfmt
-        let mut proc = Command::new(&self.rust_fmt)
-            .stdin(Stdio::piped())
+        let mut command = self.command();
+        if source.is_some() {
+            command.stdin(Stdio::piped());
+        }
+        let proc = command
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
             .args(args)
-            .spawn()?;
-
-        // Get stdin and send our source code to it to be formatted
-        // Safety: Can't panic - we captured stdin above
-        let mut stdin = proc.stdin.take().unwrap();
-        stdin.write_all(source.as_ref().as_bytes())?;
-        // Close stdin
-        drop(stdin);
+            .spawn()
+            .context(Operation::Spawn)?;
 
-        // Parse the results and return stdout/stderr
-        let output = proc.wait_with_output()?;
-        let stderr = String::from_utf8(output.stderr)?;
+        // Any diff is printed to stdout (the exit code is only non-zero for files)
+        let output = wait_output(proc, source, self.timeout)?;
+        let diff = String::from_utf8(output.stdout).context(Operation::Wait)?;
 
-        if output.status.success() {
-            let stdout = String::from_utf8(output.stdout)?;
-            post_process(self.post_proc, stdout)
+        if !diff.is_empty() {
+            Ok(Check::Unformatted(Some(diff)))
+        } else if output.status.success() {
+            Ok(Check::Formatted)
         } else {
-            Err(Error::BadSourceCode(stderr))
+            let stderr = String::from_utf8(output.stderr).context(Operation::Wait)?;
+            Err(Error::from_process(output.status, stderr, source))
         }
     }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Default for RustFmt {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Formatter for RustFmt {
+    #[inline]
+    fn format_str(&self, source: impl AsRef<str>) -> Result<String, Error> {
+        Ok(self.format_str_output(source)?.code)
+    }
+
+    fn format_str_output(&self, source: impl AsRef<str>) -> Result<FormatOutput, Error> {
+        let args = self.build_args(None as Option<&Path>);
+        let output = run_command(self.command(), args, source.as_ref(), self.timeout)?;
+        output.post_process(self.post_proc, &self.markers)
+    }
+
+    #[cfg(feature = "token_stream")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "token_stream")))]
+    fn format_tokens_ref(&self, tokens: &proc_macro2::TokenStream) -> Result<String, Error> {
+        // Timeouts need stdin written from another thread, which tokens can't be sent to
+        if self.timeout.is_some() {
+            return self.format_str(tokens.to_string());
+        }
+
+        let args = self.build_args(None as Option<&Path>);
+        let output = run_command_tokens(self.command(), args, tokens)?;
+        Ok(output.post_process(self.post_proc, &self.markers)?.code)
+    }
 
     fn format_file(&self, path: impl AsRef<Path>) -> Result<(), Error> {
         // Just use regular string method if doing post processing so we don't write to file twice
-        if self.post_proc.replace_markers() {
-            let source = file_to_string(path.as_ref())?;
+        if self.post_proc.is_enabled() {
+            // Large files are streamed instead (timeouts are only enforced on the string path)
+            let len = fs::metadata(path.as_ref()).map_or(0, |metadata| metadata.len());
+            if len >= STREAM_THRESHOLD && self.timeout.is_none() {
+                return self.format_file_streamed(path);
+            }
+
+            let path = path.as_ref();
+            let source = file_to_string(path)?;
             let result = self.format_str(source)?;
+            backup_file(path, self.backup.as_deref())?;
             string_to_file(path, &result)
         } else {
-            let args = self.build_args(Some(path.as_ref()));
+            backup_file(path.as_ref(), self.backup.as_deref())?;
+            let path = self.file_path(path.as_ref())?;
+            let args = self.build_args(Some(&path));
 
             // Launch rustfmt
-            let proc = Command::new(&self.rust_fmt)
+            let proc = self
+                .command()
                 .stderr(Stdio::piped())
                 .args(args)
-                .spawn()?;
+                .spawn()
+                .context(Operation::Spawn)?;
 
             // Parse the results and return stdout/stderr
-            let output = proc.wait_with_output()?;
-            let stderr = String::from_utf8(output.stderr)?;
+            let output = wait_output(proc, None, self.timeout)?;
+            let stderr = String::from_utf8(output.stderr).context(Operation::Wait)?;
 
             if output.status.success() {
                 Ok(())
             } else {
-                Err(Error::BadSourceCode(stderr))
+                Err(Error::from_process(output.status, stderr, None))
             }
         }
     }
+
+    fn format_to_writer(
+        &self,
+        source: impl AsRef<str>,
+        writer: &mut impl Write,
+    ) -> Result<(), Error> {
+        // Post processing needs the whole output and timeouts are enforced by `wait_output`, so
+        // the output is only streamed without either
+        if self.post_proc.is_enabled() || self.timeout.is_some() {
+            let result = self.format_str(source)?;
+            return Ok(writer.write_all(result.as_bytes())?);
+        }
+
+        // Launch rustfmt
+        let args = self.build_args(None as Option<&Path>);
+        let mut proc = self
+            .command()
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .args(args)
+            .spawn()
+            .context(Operation::Spawn)?;
+
+        // Safety: Can't panic - we captured all the pipes when spawning
+        let mut stdin = proc.stdin.take().unwrap();
+        let mut stdout = proc.stdout.take().unwrap();
+        let stderr = proc.stderr.take();
+        let source = source.as_ref();
+
+        // Copy stdout straight into the writer while stdin and stderr are serviced on their own
+        // threads
+        let stderr = thread::scope(|scope| {
+            let input = scope.spawn(move || stdin.write_all(source.as_bytes()));
+            let stderr = scope.spawn(move || read_pipe(stderr));
+
+            if let Err(err) = io::copy(&mut stdout, writer) {
+                // Killing the process closes its pipes, which unblocks our threads
+                proc.kill()?;
+                proc.wait()?;
+                return Err(err);
+            }
+            join(input)?;
+            join(stderr)
+        })
+        .context(Operation::Wait)?;
+
+        let status = proc.wait().context(Operation::Wait)?;
+        if status.success() {
+            Ok(())
+        } else {
+            let stderr = String::from_utf8(stderr).context(Operation::Wait)?;
+            Err(Error::from_process(status, stderr, Some(source)))
+        }
+    }
+
+    fn check_str(&self, source: impl AsRef<str>) -> Result<Check, Error> {
+        // `rustfmt` can't check the post processed output, so compare it ourselves
+        if self.post_proc.is_enabled() {
+            check_by_formatting(self, source.as_ref())
+        } else {
+            self.check(None, Some(source.as_ref()))
+        }
+    }
+
+    fn check_file(&self, path: impl AsRef<Path>) -> Result<Check, Error> {
+        if self.post_proc.is_enabled() {
+            check_by_formatting(self, &file_to_string(path)?)
+        } else {
+            let path = self.file_path(path.as_ref())?;
+            self.check(Some(&path), None)
+        }
+    }
+}
+
+// *** Command Formatter ***
+
+/// This formatter runs an arbitrary external command for formatting source code (such as a fork
+/// of `rustfmt` or an in-house formatter). The command must read the source code from stdin, write
+/// the formatted source code to stdout, and exit with a non-zero status on failure (stderr is
+/// then returned in the error)
+///
+/// ```
+/// use rust_format::{CommandFormatter, Formatter};
+///
+/// let source = r#"fn main() { println!("Hello World!"); }"#;
+///
+/// let rustfmt = CommandFormatter::new("rustfmt").args(["--edition", "2021"]);
+///
+/// let actual = rustfmt.format_str(source).unwrap();
+/// let expected = r#"fn main() {
+///     println!("Hello World!");
+/// }
+/// "#;
+///
+/// assert_eq!(expected, actual);
+/// ```
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Clone, Debug)]
+pub struct CommandFormatter {
+    program: PathBuf,
+    args: Vec<OsString>,
+    post_proc: PostProcess,
+    timeout: Option<Duration>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl CommandFormatter {
+    /// Creates a new instance of `CommandFormatter` that runs the given program with no arguments
+    #[inline]
+    pub fn new(program: impl Into<PathBuf>) -> Self {
+        Self {
+            program: program.into(),
+            args: Vec::new(),
+            post_proc: PostProcess::None,
+            timeout: None,
+        }
+    }
+
+    /// Add an argument to pass to the program
+    #[inline]
+    pub fn arg(mut self, arg: impl AsRef<OsStr>) -> Self {
+        self.args.push(arg.as_ref().to_os_string());
+        self
+    }
+
+    /// Add multiple arguments to pass to the program
+    #[inline]
+    pub fn args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        self.args
+            .extend(args.into_iter().map(|arg| arg.as_ref().to_os_string()));
+        self
+    }
+
+    /// Set the post processing option after formatting
+    #[inline]
+    pub fn post_proc(mut self, post_proc: PostProcess) -> Self {
+        self.post_proc = post_proc;
+        self
+    }
+
+    /// Set the maximum time the program may run before it is killed and [ErrorKind::Timeout] is
+    /// returned. By default, there is no timeout
+    #[inline]
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Formatter for CommandFormatter {
+    #[inline]
+    fn format_str(&self, source: impl AsRef<str>) -> Result<String, Error> {
+        Ok(self.format_str_output(source)?.code)
+    }
+
+    fn format_str_output(&self, source: impl AsRef<str>) -> Result<FormatOutput, Error> {
+        let output = run_command(
+            Command::new(&self.program),
+            &self.args,
+            source.as_ref(),
+            self.timeout,
+        )?;
+        output.post_process(self.post_proc, &Markers::NONE)
+    }
+
+    #[cfg(feature = "token_stream")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "token_stream")))]
+    fn format_tokens_ref(&self, tokens: &proc_macro2::TokenStream) -> Result<String, Error> {
+        // Timeouts need stdin written from another thread, which tokens can't be sent to
+        if self.timeout.is_some() {
+            return self.format_str(tokens.to_string());
+        }
+
+        let output = run_command_tokens(Command::new(&self.program), &self.args, tokens)?;
+        Ok(output.post_process(self.post_proc, &Markers::NONE)?.code)
+    }
+}
+
+// *** Fallback ***
+
+/// Which formatter of a [Fallback] produced the output
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    /// The primary formatter
+    Primary,
+    /// The secondary formatter (the primary could not be run)
+    Secondary,
+}
+
+// Use the primary result unless the primary formatter couldn't be run
+#[inline]
+fn fallback<T>(
+    primary: Result<T, Error>,
+    secondary: impl FnOnce() -> Result<T, Error>,
+) -> Result<(T, Backend), Error> {
+    match primary {
+        Err(err) if err.operation() == Some(Operation::Spawn) => {
+            Ok((secondary()?, Backend::Secondary))
+        }
+        result => Ok((result?, Backend::Primary)),
+    }
+}
+
+/// This formatter tries the primary formatter first, but falls back to the secondary formatter if
+/// the primary could not be launched ([Operation::Spawn], such as when `rustfmt` is not installed).
+/// Any other error from the primary formatter (such as bad source code) is returned as is
+///
+/// ```
+/// use rust_format::{Backend, CommandFormatter, Fallback, RustFmt};
+///
+/// let source = r#"fn main() { println!("Hello World!"); }"#;
+///
+/// let primary = CommandFormatter::new("this_is_never_going_to_be_a_valid_executable");
+/// let fmt = Fallback::new(primary, RustFmt::default());
+///
+/// let (actual, backend) = fmt.format_str_backend(source).unwrap();
+/// let expected = r#"fn main() {
+///     println!("Hello World!");
+/// }
+/// "#;
+///
+/// assert_eq!(expected, actual);
+/// assert_eq!(Backend::Secondary, backend);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Fallback<A, B> {
+    primary: A,
+    secondary: B,
+}
+
+impl<A: Formatter, B: Formatter> Fallback<A, B> {
+    /// Creates a new instance of `Fallback` from the primary and secondary formatters
+    #[inline]
+    pub fn new(primary: A, secondary: B) -> Self {
+        Self { primary, secondary }
+    }
+
+    /// Returns the primary formatter
+    #[inline]
+    pub fn primary(&self) -> &A {
+        &self.primary
+    }
+
+    /// Returns the secondary formatter
+    #[inline]
+    pub fn secondary(&self) -> &B {
+        &self.secondary
+    }
+
+    /// The same as [format_str](Formatter::format_str), but also returns which formatter produced
+    /// the output
+    pub fn format_str_backend(&self, source: impl AsRef<str>) -> Result<(String, Backend), Error> {
+        let source = source.as_ref();
+        fallback(self.primary.format_str(source), || {
+            self.secondary.format_str(source)
+        })
+    }
+
+    /// The same as [format_file](Formatter::format_file), but also returns which formatter
+    /// formatted the file
+    pub fn format_file_backend(&self, path: impl AsRef<Path>) -> Result<Backend, Error> {
+        let path = path.as_ref();
+        let (_, backend) = fallback(self.primary.format_file(path), || {
+            self.secondary.format_file(path)
+        })?;
+        Ok(backend)
+    }
+}
+
+impl<A: Formatter, B: Formatter> Formatter for Fallback<A, B> {
+    #[inline]
+    fn format_str(&self, source: impl AsRef<str>) -> Result<String, Error> {
+        Ok(self.format_str_backend(source)?.0)
+    }
+
+    fn format_str_output(&self, source: impl AsRef<str>) -> Result<FormatOutput, Error> {
+        let source = source.as_ref();
+        let (output, _) = fallback(self.primary.format_str_output(source), || {
+            self.secondary.format_str_output(source)
+        })?;
+        Ok(output)
+    }
+
+    #[inline]
+    fn format_file(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        self.format_file_backend(path)?;
+        Ok(())
+    }
+
+    #[cfg(feature = "token_stream")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "token_stream")))]
+    fn format_tokens(&self, tokens: proc_macro2::TokenStream) -> Result<String, Error> {
+        let (result, _) = fallback(self.primary.format_tokens_ref(&tokens), || {
+            self.secondary.format_tokens(tokens)
+        })?;
+        Ok(result)
+    }
+
+    #[cfg(feature = "token_stream")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "token_stream")))]
+    fn format_tokens_ref(&self, tokens: &proc_macro2::TokenStream) -> Result<String, Error> {
+        let (result, _) = fallback(self.primary.format_tokens_ref(tokens), || {
+            self.secondary.format_tokens_ref(tokens)
+        })?;
+        Ok(result)
+    }
+
+    #[cfg(feature = "pretty_please")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "pretty_please")))]
+    fn format_syn_file(&self, file: &syn::File) -> Result<String, Error> {
+        let (result, _) = fallback(self.primary.format_syn_file(file), || {
+            self.secondary.format_syn_file(file)
+        })?;
+        Ok(result)
+    }
 }
 
 // *** Pretty Please ***
 
+/// The `PrettyPlease` specific options, set via [Config::pretty_please]
+///
+/// ```
+/// use rust_format::{Config, Formatter, PrettyPlease, PrettyPleaseConfig};
+///
+/// let config = Config::new_str().pretty_please(PrettyPleaseConfig::new().indent(2));
+/// let actual = PrettyPlease::from_config(config).format_str("fn main() { let x = 1; }").unwrap();
+///
+/// assert_eq!("fn main() {\n  let x = 1;\n}\n", actual);
+/// ```
+#[cfg(feature = "pretty_please")]
+#[cfg_attr(docsrs, doc(cfg(feature = "pretty_please")))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PrettyPleaseConfig {
+    indent: usize,
+    hard_tabs: bool,
+}
+
+#[cfg(feature = "pretty_please")]
+impl Default for PrettyPleaseConfig {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            indent: indent::PP_INDENT,
+            hard_tabs: false,
+        }
+    }
+}
+
+#[cfg(feature = "pretty_please")]
+impl PrettyPleaseConfig {
+    /// Creates a new configuration using the `prettyplease` defaults (an indent of 4 spaces)
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the number of spaces used for each level of indent (like the `tab_spaces` option of
+    /// `rustfmt`). Lines inside multi-line string literals are never changed
+    #[inline]
+    pub fn indent(mut self, indent: usize) -> Self {
+        self.indent = indent;
+        self
+    }
+
+    /// Set whether a tab is used for each level of indent instead of spaces (like the `hard_tabs`
+    /// option of `rustfmt`)
+    #[inline]
+    pub fn hard_tabs(mut self, hard_tabs: bool) -> Self {
+        self.hard_tabs = hard_tabs;
+        self
+    }
+
+    // The text used for each level of indent
+    fn indent_str(&self) -> String {
+        if self.hard_tabs {
+            "\t".to_string()
+        } else {
+            " ".repeat(self.indent)
+        }
+    }
+}
+
 /// This formatter uses [prettyplease](https://crates.io/crates/prettyplease) for formatting source code
 ///
 /// From string:
@@ -614,9 +2447,12 @@ impl Formatter for RustFmt {
 /// ```
 #[cfg(feature = "pretty_please")]
 #[cfg_attr(docsrs, doc(cfg(feature = "pretty_please")))]
-#[derive(Clone, Default)]
+#[derive(Clone, Debug, Default)]
 pub struct PrettyPlease {
     post_proc: PostProcess,
+    config: PrettyPleaseConfig,
+    markers: Markers,
+    backup: Option<OsString>,
 }
 
 #[cfg(feature = "pretty_please")]
@@ -648,13 +2484,83 @@ impl PrettyPlease {
 
         Self {
             post_proc: config.post_proc,
+            config: config.pretty_please,
+            markers: config.markers,
+            backup: config.backup,
         }
     }
 
     #[inline]
     fn format(&self, f: &syn::File) -> Result<String, Error> {
-        let result = prettyplease::unparse(f);
-        post_process(self.post_proc, result)
+        let mut result = prettyplease::unparse(f);
+        if self.config != PrettyPleaseConfig::default() {
+            result = indent::reindent(&result, &self.config.indent_str());
+        }
+        post_process(self.post_proc, &self.markers, result)
+    }
+
+    /// Format a single expression (such as a `match`) that isn't part of a full file
+    ///
+    /// ```
+    /// use rust_format::PrettyPlease;
+    ///
+    /// let actual = PrettyPlease::default().format_expr("match x { 1 => a, _ => b }").unwrap();
+    /// let expected = r#"match x {
+    ///     1 => a,
+    ///     _ => b,
+    /// }
+    /// "#;
+    ///
+    /// assert_eq!(expected, actual);
+    /// ```
+    pub fn format_expr(&self, source: impl AsRef<str>) -> Result<String, Error> {
+        let expr = syn::parse_str::<syn::Expr>(source.as_ref())?;
+        self.format_fn_body(vec![syn::Stmt::Expr(expr)])
+    }
+
+    /// Format a single item (such as an `impl` block) that isn't part of a full file
+    pub fn format_item(&self, source: impl AsRef<str>) -> Result<String, Error> {
+        let item = syn::parse_str::<syn::Item>(source.as_ref())?;
+        self.format(&file(vec![item]))
+    }
+
+    /// Format a list of statements (such as the body of a function) that isn't part of a full file
+    pub fn format_stmts(&self, source: impl AsRef<str>) -> Result<String, Error> {
+        use syn::parse::Parser;
+
+        let stmts = syn::Block::parse_within.parse_str(source.as_ref())?;
+        self.format_fn_body(stmts)
+    }
+
+    // Format the statements as the body of a synthetic function and then remove the wrapper
+    fn format_fn_body(&self, stmts: Vec<syn::Stmt>) -> Result<String, Error> {
+        let mut func = syn::parse_str::<syn::ItemFn>("fn __snippet__() {}")?;
+        func.block.stmts = stmts;
+        let source = self.format(&file(vec![syn::Item::Fn(func)]))?;
+
+        let lines: Vec<_> = source.lines().collect();
+        let mut body = String::with_capacity(source.len());
+        let indent = self.config.indent_str();
+
+        // Skip the signature and closing brace lines (an empty body is formatted on one line)
+        if let [_, lines @ .., _] = lines.as_slice() {
+            for line in lines {
+                body.push_str(line.strip_prefix(indent.as_str()).unwrap_or(line));
+                body.push('\n');
+            }
+        }
+
+        Ok(body)
+    }
+}
+
+#[cfg(feature = "pretty_please")]
+#[inline]
+fn file(items: Vec<syn::Item>) -> syn::File {
+    syn::File {
+        shebang: None,
+        attrs: Vec::new(),
+        items,
     }
 }
 
@@ -662,8 +2568,14 @@ impl PrettyPlease {
 impl Formatter for PrettyPlease {
     #[inline]
     fn format_str(&self, source: impl AsRef<str>) -> Result<String, Error> {
-        let f = syn::parse_file(source.as_ref())?;
-        self.format(&f)
+        // `syn` can't parse frontmatter, so the prologue is split off and added back as is
+        match prologue::split_prologue(source.as_ref()) {
+            ("", source) => self.format(&syn::parse_file(source)?),
+            (prologue, source) => {
+                let result = self.format(&syn::parse_file(source)?)?;
+                Ok(format!("{prologue}{result}"))
+            }
+        }
     }
 
     #[inline]
@@ -673,21 +2585,52 @@ impl Formatter for PrettyPlease {
         let f = syn::parse2::<syn::File>(tokens)?;
         self.format(&f)
     }
+
+    #[inline]
+    #[cfg(feature = "token_stream")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "token_stream")))]
+    fn format_tokens_ref(&self, tokens: &proc_macro2::TokenStream) -> Result<String, Error> {
+        // Token streams are reference counted, so this is cheap
+        self.format_tokens(tokens.clone())
+    }
+
+    fn format_file(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let path = path.as_ref();
+        let source = file_to_string(path)?;
+        let result = self.format_str(source)?;
+        backup_file(path, self.backup.as_deref())?;
+        string_to_file(path, &result)
+    }
+
+    #[inline]
+    fn format_syn_file(&self, file: &syn::File) -> Result<String, Error> {
+        self.format(file)
+    }
 }
 
 // *** Tests ***
 
 #[cfg(test)]
 mod tests {
+    use std::env;
+    use std::ffi::{OsStr, OsString};
     use std::io::{Read, Seek, Write};
+    use std::path::Path;
+    use std::time::{Duration, Instant};
 
     use pretty_assertions::assert_eq;
 
+    #[cfg(feature = "post_process")]
+    use crate::marker::Markers;
     #[cfg(feature = "post_process")]
     use crate::PostProcess;
+    use crate::{
+        Backend, Channel, Check, CommandFormatter, Config, Edition, Error, ErrorKind, Fallback,
+        FormatDirOptions, FormatOutput, FormatResult, Formatter, Operation, RustFmt, RUST_FMT,
+        RUST_FMT_FLAGS_KEY, RUST_FMT_KEY,
+    };
     #[cfg(feature = "pretty_please")]
-    use crate::PrettyPlease;
-    use crate::{Config, Error, Formatter, RustFmt, RUST_FMT, RUST_FMT_KEY};
+    use crate::{PrettyPlease, PrettyPleaseConfig};
 
     const PLAIN_EXPECTED: &str = r#"#[doc = " This is main"]
 fn main() {
@@ -718,31 +2661,336 @@ fn main() {
     // This prints hello world
     println!("Hello World!");
 
-}
-"#;
+}
+"#;
+
+    #[test]
+    fn rustfmt_bad_env_path() {
+        temp_env::with_var(
+            RUST_FMT_KEY,
+            Some("this_is_never_going_to_be_a_valid_executable"),
+            || match RustFmt::new().format_str("bogus").map_err(Error::into_kind) {
+                Err(ErrorKind::IOError(_)) => {}
+                _ => panic!("'rustfmt' should have failed due to bad path"),
+            },
+        );
+    }
+
+    #[test]
+    fn rustfmt_bad_config_path() {
+        temp_env::with_var(RUST_FMT_KEY, Some(RUST_FMT), || {
+            let config =
+                Config::new_str().rust_fmt_path("this_is_never_going_to_be_a_valid_executable");
+            match RustFmt::from_config(config)
+                .format_str("bogus")
+                .map_err(Error::into_kind)
+            {
+                Err(ErrorKind::IOError(_)) => {}
+                _ => panic!("'rustfmt' should have failed due to bad path"),
+            }
+        });
+    }
+
+    #[test]
+    fn rustfmt_editions() {
+        // `async` is only a keyword starting with 2018, and `gen` is only reserved starting with 2024
+        let source = "fn gen() { let async = 1; }";
+
+        for (edition, success) in [
+            (Edition::Rust2015, true),
+            (Edition::Rust2021, false),
+            (Edition::Rust2024, false),
+            (Edition::Custom("2015"), true),
+        ] {
+            let config = Config::new_str().rust_fmt_path(RUST_FMT).edition(edition);
+            let result = RustFmt::from_config(config).format_str(source);
+            assert_eq!(success, result.is_ok(), "{edition:?}");
+        }
+
+        let config = Config::new_str()
+            .rust_fmt_path(RUST_FMT)
+            .edition(Edition::Custom("1999"));
+        match RustFmt::from_config(config)
+            .format_str(source)
+            .map_err(Error::into_kind)
+        {
+            Err(ErrorKind::BadSourceCode(_)) => {}
+            _ => panic!("Expected an invalid edition to be rejected"),
+        }
+    }
+
+    #[test]
+    fn rustfmt_config_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("rustfmt.toml");
+        std::fs::write(&path, "reorder_imports = false\n").unwrap();
+        let source = "use std::marker; use std::io;";
+
+        let config = Config::new_str().rust_fmt_path(RUST_FMT).config_path(&path);
+        let actual = RustFmt::from_config(config).format_str(source).unwrap();
+        assert_eq!("use std::marker;\nuse std::io;\n", actual);
+
+        // Key/value options override the file
+        let config = Config::new_str()
+            .rust_fmt_path(RUST_FMT)
+            .config_path(dir.path())
+            .option("reorder_imports", "true");
+        let actual = RustFmt::from_config(config).format_str(source).unwrap();
+        assert_eq!("use std::io;\nuse std::marker;\n", actual);
+    }
+
+    #[test]
+    fn rustfmt_current_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("rustfmt.toml"), "reorder_imports = false\n").unwrap();
+        let source = "use std::marker; use std::io;";
+
+        // `rustfmt.toml` is found relative to the working directory
+        let config = Config::new_str()
+            .rust_fmt_path(RUST_FMT)
+            .current_dir(dir.path());
+        let rustfmt = RustFmt::from_config(config);
+        let actual = rustfmt.format_str(source).unwrap();
+        assert_eq!("use std::marker;\nuse std::io;\n", actual);
+
+        // Relative file paths are still relative to our working directory
+        let path = Path::new("src/lib.rs");
+        assert_eq!(
+            env::current_dir().unwrap().join(path),
+            rustfmt.file_path(path).unwrap()
+        );
+    }
+
+    #[test]
+    fn rustfmt_env() {
+        let source = "fn main(){}";
+
+        temp_env::with_var(
+            RUST_FMT_KEY,
+            Some("this_is_never_going_to_be_a_valid_executable"),
+            || {
+                let config = Config::new_str().env_remove(RUST_FMT_KEY);
+                let actual = RustFmt::from_config(config).format_str(source).unwrap();
+                assert_eq!("fn main() {}\n", actual);
+            },
+        );
+
+        // Only the variables set are passed to `rustfmt`
+        let config = Config::new_str()
+            .rust_fmt_path(RUST_FMT)
+            .env_clear()
+            .env("PATH", env::var_os("PATH").unwrap())
+            .env(RUST_FMT_FLAGS_KEY, "--config reorder_imports=false");
+        let rustfmt = RustFmt::from_config(config);
+        assert_eq!(
+            ["--edition", "2021", "--config", "reorder_imports=false"]
+                .map(OsStr::new)
+                .as_slice(),
+            rustfmt.build_args(None as Option<&Path>)
+        );
+        let actual = rustfmt.format_str(source).unwrap();
+        assert_eq!("fn main() {}\n", actual);
+    }
+
+    #[test]
+    fn rustfmt_args() {
+        let source = "use std::marker; use std::io;";
+
+        let config = Config::new_str()
+            .rust_fmt_path(RUST_FMT)
+            .args(["--config", "reorder_imports=false"]);
+        let actual = RustFmt::from_config(config).format_str(source).unwrap();
+        assert_eq!("use std::marker;\nuse std::io;\n", actual);
+
+        let config = Config::new_str()
+            .rust_fmt_path(RUST_FMT)
+            .arg("--not-a-real-flag");
+        match RustFmt::from_config(config)
+            .format_str(source)
+            .map_err(Error::into_kind)
+        {
+            Err(ErrorKind::BadSourceCode(_)) => {}
+            _ => panic!("Expected an unknown flag to be rejected"),
+        }
+
+        // Setting `RUSTFMT_FLAGS` here would leak into other tests, so just check the splitting
+        let expected: Vec<OsString> = vec!["--config".into(), "reorder_imports=false".into()];
+        assert_eq!(
+            expected,
+            RustFmt::split_flags(" --config \t reorder_imports=false\n")
+        );
+    }
+
+    #[test]
+    fn rustfmt_toolchain() {
+        let source = "fn main(){}";
+
+        let config = Config::new_str()
+            .rust_fmt_path(RUST_FMT)
+            .toolchain("stable")
+            .unstable_features(true);
+        let rustfmt = RustFmt::from_config(config);
+        assert_eq!(
+            [
+                "run",
+                "stable",
+                RUST_FMT,
+                "--edition",
+                "2021",
+                "--unstable-features"
+            ]
+            .map(OsStr::new)
+            .as_slice(),
+            rustfmt.build_args(None as Option<&Path>)
+        );
+        // Unstable features are only accepted by nightly
+        match rustfmt.format_str(source).map_err(Error::into_kind) {
+            Err(ErrorKind::BadSourceCode(_)) => {}
+            _ => panic!("Expected stable to reject unstable features"),
+        }
+
+        let config = Config::new_str()
+            .rust_fmt_path(RUST_FMT)
+            .toolchain("stable");
+        let actual = RustFmt::from_config(config).format_str(source).unwrap();
+        assert_eq!("fn main() {}\n", actual);
+    }
+
+    #[test]
+    fn rustfmt_version() {
+        let rustfmt = RustFmt::from_config(Config::new_str().rust_fmt_path(RUST_FMT));
+        assert!(rustfmt.is_available());
+        assert!(rustfmt.version().unwrap().major >= 1);
+
+        let config = Config::new_str()
+            .rust_fmt_path(RUST_FMT)
+            .toolchain("stable");
+        assert_eq!(
+            Channel::Stable,
+            RustFmt::from_config(config).version().unwrap().channel
+        );
+
+        let config =
+            Config::new_str().rust_fmt_path("this_is_never_going_to_be_a_valid_executable");
+        let rustfmt = RustFmt::from_config(config);
+        assert!(!rustfmt.is_available());
+        match rustfmt.version().map_err(Error::into_kind) {
+            Err(ErrorKind::IOError(_)) => {}
+            _ => panic!("Expected an I/O error"),
+        }
+
+        // Runs, but isn't `rustfmt`
+        let config = Config::new_str().rust_fmt_path("cargo");
+        match RustFmt::from_config(config)
+            .version()
+            .map_err(Error::into_kind)
+        {
+            Err(ErrorKind::UnknownVersion(version)) => assert!(version.starts_with("cargo")),
+            _ => panic!("Expected an unknown version"),
+        }
+    }
 
     #[test]
-    fn rustfmt_bad_env_path() {
-        temp_env::with_var(
-            RUST_FMT_KEY,
-            Some("this_is_never_going_to_be_a_valid_executable"),
-            || match RustFmt::new().format_str("bogus") {
-                Err(Error::IOError(_)) => {}
-                _ => panic!("'rustfmt' should have failed due to bad path"),
-            },
+    fn fallback() {
+        let source = "fn main(){}";
+        let bad = || CommandFormatter::new("this_is_never_going_to_be_a_valid_executable");
+        let good = || CommandFormatter::new(RUST_FMT);
+
+        let fmt = Fallback::new(bad(), good());
+        let (actual, backend) = fmt.format_str_backend(source).unwrap();
+        assert_eq!(
+            ("fn main() {}\n", Backend::Secondary),
+            (actual.as_str(), backend)
+        );
+
+        let fmt = Fallback::new(good(), bad());
+        let (actual, backend) = fmt.format_str_backend(source).unwrap();
+        assert_eq!(
+            ("fn main() {}\n", Backend::Primary),
+            (actual.as_str(), backend)
+        );
+
+        // Only a formatter that can't be run falls back
+        let fmt = Fallback::new(good(), good());
+        match fmt.format_str_backend("use").map_err(Error::into_kind) {
+            Err(ErrorKind::BadSourceCode(_)) => {}
+            _ => panic!("Expected bad source code"),
+        }
+
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(source.as_bytes()).unwrap();
+        let fmt = Fallback::new(bad(), good());
+        assert_eq!(
+            Backend::Secondary,
+            fmt.format_file_backend(file.path()).unwrap()
         );
+        let actual = std::fs::read_to_string(file.path()).unwrap();
+        assert_eq!("fn main() {}\n", actual);
     }
 
     #[test]
-    fn rustfmt_bad_config_path() {
-        temp_env::with_var(RUST_FMT_KEY, Some(RUST_FMT), || {
-            let config =
-                Config::new_str().rust_fmt_path("this_is_never_going_to_be_a_valid_executable");
-            match RustFmt::from_config(config).format_str("bogus") {
-                Err(Error::IOError(_)) => {}
-                _ => panic!("'rustfmt' should have failed due to bad path"),
+    fn command_bad_path() {
+        match CommandFormatter::new("this_is_never_going_to_be_a_valid_executable").format_str("x")
+        {
+            Err(err) => {
+                assert_eq!(Some(Operation::Spawn), err.operation());
+                assert!(matches!(err.kind(), ErrorKind::IOError(_)));
             }
-        });
+            _ => panic!("Command should have failed due to bad path"),
+        }
+    }
+
+    #[test]
+    fn command_str() {
+        let source = r#"#[doc = " This is main"] fn main() { _comment_!("This prints hello world");
+            println!("Hello World!"); _blank_!(); }"#;
+        let rustfmt = CommandFormatter::new(RUST_FMT).args(["--edition", "2021"]);
+
+        assert_eq!(PLAIN_EXPECTED, rustfmt.format_str(source).unwrap());
+    }
+
+    #[test]
+    fn command_warnings() {
+        let warn =
+            CommandFormatter::new("sh").args(["-c", "cat; echo 'Warning: careful' >&2; echo >&2"]);
+
+        let actual = warn.format_str_output("fn main() {}").unwrap();
+        let expected = FormatOutput {
+            code: "fn main() {}".to_string(),
+            warnings: vec!["Warning: careful".to_string()],
+        };
+        assert_eq!(expected, actual);
+
+        // The plain string API still succeeds and just drops the warnings
+        assert_eq!("fn main() {}", warn.format_str("fn main() {}").unwrap());
+    }
+
+    #[test]
+    fn command_timeout() {
+        let sleep = CommandFormatter::new("sleep")
+            .arg("5")
+            .timeout(Duration::from_millis(100));
+
+        let start = Instant::now();
+        match sleep.format_str("bogus").map_err(Error::into_kind) {
+            Err(ErrorKind::Timeout(timeout)) => assert_eq!(Duration::from_millis(100), timeout),
+            _ => panic!("Expected a timeout"),
+        }
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn rustfmt_within_timeout() {
+        let config = Config::new_str()
+            .rust_fmt_path(RUST_FMT)
+            .timeout(Duration::from_secs(30));
+        let rustfmt = RustFmt::from_config(config);
+
+        let source = r#"#[doc = " This is main"] fn main() { _comment_!("This prints hello world");
+            println!("Hello World!"); _blank_!(); }"#;
+        assert_eq!(PLAIN_EXPECTED, rustfmt.format_str(source).unwrap());
+        format_file(rustfmt.clone(), PLAIN_EXPECTED);
+        bad_format_file(rustfmt);
     }
 
     fn format_file(fmt: impl Formatter, expected: &str) {
@@ -769,6 +3017,72 @@ fn main() {
         });
     }
 
+    #[cfg(feature = "post_process")]
+    #[test]
+    fn rustfmt_file_streamed() {
+        let source = r#"#[doc = " This is main"] fn main() { _comment_!("This prints hello world");
+            println!("Hello World!"); _blank_!(); }"#;
+        let config = Config::new_str()
+            .rust_fmt_path(RUST_FMT)
+            .post_proc(PostProcess::ReplaceMarkers);
+        let rustfmt = RustFmt::from_config(config);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("main.rs");
+        let file_count = || std::fs::read_dir(dir.path()).unwrap().count();
+
+        std::fs::write(&path, source).unwrap();
+        rustfmt.format_file_streamed(&path).unwrap();
+        assert_eq!(REPLACE_EXPECTED, std::fs::read_to_string(&path).unwrap());
+        // The temporary file was renamed over the original
+        assert_eq!(1, file_count());
+
+        // The original is left as is when formatting fails
+        std::fs::write(&path, "use").unwrap();
+        match rustfmt
+            .format_file_streamed(&path)
+            .map_err(Error::into_kind)
+        {
+            Err(ErrorKind::BadSourceCode(_)) => {}
+            _ => panic!("Expected bad source code"),
+        }
+        assert_eq!("use", std::fs::read_to_string(&path).unwrap());
+        assert_eq!(1, file_count());
+    }
+
+    fn backup_file(fmt: impl Formatter, expected: &str, backup_name: &str) {
+        let source = r#"#[doc = " This is main"] fn main() { _comment_!("This prints hello world");
+            println!("Hello World!"); _blank_!(); }"#;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("main.rs");
+
+        std::fs::write(&path, source).unwrap();
+        fmt.format_file(&path).unwrap();
+        assert_eq!(expected, std::fs::read_to_string(&path).unwrap());
+
+        let backup = dir.path().join(backup_name);
+        assert_eq!(source, std::fs::read_to_string(backup).unwrap());
+    }
+
+    #[test]
+    fn rustfmt_backup() {
+        let config = Config::new_str().rust_fmt_path(RUST_FMT).backup(true);
+        backup_file(RustFmt::from_config(config), PLAIN_EXPECTED, "main.rs.bak");
+
+        let config = Config::new_str()
+            .rust_fmt_path(RUST_FMT)
+            .backup_suffix(".orig");
+        backup_file(RustFmt::from_config(config), PLAIN_EXPECTED, "main.rs.orig");
+    }
+
+    #[cfg(feature = "pretty_please")]
+    #[test]
+    fn prettyplease_backup() {
+        let config = Config::new_str().backup(true);
+        let prettyplease = PrettyPlease::from_config(config);
+        backup_file(prettyplease, PLAIN_PP_EXPECTED, "main.rs.bak");
+    }
+
     // prettyplease replaces doc blocks by default
     #[cfg(feature = "pretty_please")]
     #[test]
@@ -794,6 +3108,29 @@ fn main() {
         format_file(PrettyPlease::from_config(config), REPLACE_BLOCKS_EXPECTED);
     }
 
+    // Post processing output that was already post processed (such as by a `prettyplease` pass
+    // followed by a `rustfmt` pass) changes nothing
+    #[cfg(feature = "post_process")]
+    #[cfg(feature = "pretty_please")]
+    #[test]
+    fn post_process_twice() {
+        let source = r#"#[doc = " This is main"] fn main() { _comment_!("This prints hello world");
+            println!("Hello World!"); _blank_!(); }"#;
+
+        for post_proc in [
+            PostProcess::ReplaceMarkersAndDocBlocks.wrap_docs(12),
+            PostProcess::ReplaceMarkers | PostProcess::ReplaceDocComments,
+            PostProcess::ReplaceMarkers.max_consecutive_blank_lines(0),
+        ] {
+            let config = Config::new_str().post_proc(post_proc);
+            let once = PrettyPlease::from_config(config)
+                .format_str(source)
+                .unwrap();
+            let twice = crate::post_process(post_proc, &Markers::NONE, once.clone()).unwrap();
+            assert_eq!(once, twice);
+        }
+    }
+
     #[cfg(feature = "post_process")]
     #[test]
     fn rustfmt_file_replace_markers_and_docs() {
@@ -811,18 +3148,441 @@ fn main() {
         format_file(PrettyPlease::from_config(config), REPLACE_BLOCKS_EXPECTED);
     }
 
+    #[cfg(feature = "post_process")]
+    #[cfg(feature = "pretty_please")]
+    #[test]
+    fn prettyplease_replace_doc_comments() {
+        // PrettyPlease emits doc comments, so convert them back for a token based round trip
+        let config = Config::new_str().post_proc(PostProcess::ReplaceDocComments);
+        let actual = PrettyPlease::from_config(config)
+            .format_str(r#"#[doc = " This is main"] fn main() { _blank_!(); }"#)
+            .unwrap();
+
+        assert_eq!(
+            "#[doc = \" This is main\"]\nfn main() {\n    _blank_!();\n}\n",
+            actual
+        );
+    }
+
+    fn format_file_to(fmt: impl Formatter, expected: &str) {
+        let source = r#"#[doc = " This is main"] fn main() { _comment_!("This prints hello world");
+            println!("Hello World!"); _blank_!(); }"#;
+        let mut src = tempfile::NamedTempFile::new().unwrap();
+        src.write_all(source.as_bytes()).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let dst = dir.path().join("out.rs");
+
+        fmt.format_file_to(src.path(), &dst).unwrap();
+
+        assert_eq!(expected, std::fs::read_to_string(&dst).unwrap());
+        // The source file is left untouched
+        assert_eq!(source, std::fs::read_to_string(src.path()).unwrap());
+    }
+
+    #[test]
+    fn rustfmt_file_to() {
+        temp_env::with_var(RUST_FMT_KEY, Some(RUST_FMT), || {
+            format_file_to(RustFmt::new(), PLAIN_EXPECTED);
+        });
+    }
+
+    #[cfg(feature = "pretty_please")]
+    #[test]
+    fn prettyplease_file_to() {
+        format_file_to(PrettyPlease::new(), PLAIN_PP_EXPECTED);
+    }
+
+    fn format_to_writer(fmt: impl Formatter) {
+        let mut output = Vec::new();
+        fmt.format_to_writer("fn main(){}", &mut output).unwrap();
+        assert_eq!("fn main() {}\n", String::from_utf8(output).unwrap());
+
+        let mut output = Vec::new();
+        match fmt
+            .format_to_writer("use", &mut output)
+            .map_err(Error::into_kind)
+        {
+            Err(ErrorKind::BadSourceCode(_)) => assert!(output.is_empty()),
+            _ => panic!("Expected bad source code"),
+        }
+    }
+
+    #[test]
+    fn rustfmt_to_writer() {
+        temp_env::with_var(RUST_FMT_KEY, Some(RUST_FMT), || {
+            format_to_writer(RustFmt::new());
+        });
+    }
+
+    #[cfg(feature = "pretty_please")]
+    #[test]
+    fn prettyplease_to_writer() {
+        format_to_writer(PrettyPlease::new());
+    }
+
+    #[cfg(feature = "pretty_please")]
+    #[test]
+    fn prettyplease_config() {
+        let source = "fn main() { if x { let s = \"a\n    b\"; } }";
+
+        let config = Config::new_str().pretty_please(PrettyPleaseConfig::new().indent(2));
+        let pp = PrettyPlease::from_config(config);
+        let expected = "fn main() {\n  if x {\n    let s = \"a\n    b\";\n  }\n}\n";
+        assert_eq!(expected, pp.format_str(source).unwrap());
+        // Snippets are unindented by the configured indent
+        assert_eq!("if x {\n  y;\n}\n", pp.format_stmts("if x { y; }").unwrap());
+
+        let config = Config::new_str().pretty_please(PrettyPleaseConfig::new().hard_tabs(true));
+        let pp = PrettyPlease::from_config(config);
+        let expected = "fn main() {\n\tif x {\n\t\tlet s = \"a\n    b\";\n\t}\n}\n";
+        assert_eq!(expected, pp.format_str(source).unwrap());
+    }
+
+    #[cfg(feature = "pretty_please")]
+    #[test]
+    fn prettyplease_snippets() {
+        let pp = PrettyPlease::new();
+
+        let actual = pp.format_expr("if x { a } else { b }").unwrap();
+        assert_eq!("if x { a } else { b }\n", actual);
+
+        let actual = pp
+            .format_item("impl Foo { fn foo(&self) -> u32 { 1 } }")
+            .unwrap();
+        assert_eq!(
+            "impl Foo {\n    fn foo(&self) -> u32 {\n        1\n    }\n}\n",
+            actual
+        );
+
+        let actual = pp
+            .format_stmts("let x = 1; if x > 0 { println!(\"{x}\"); } x")
+            .unwrap();
+        let expected = r#"let x = 1;
+if x > 0 {
+    println!("{x}");
+}
+x
+"#;
+        assert_eq!(expected, actual);
+        assert_eq!("", pp.format_stmts("").unwrap());
+
+        for result in [
+            pp.format_expr("let x = 1;"),
+            pp.format_item("x + 1"),
+            pp.format_stmts("let x = ;"),
+        ] {
+            match result.map_err(Error::into_kind) {
+                Err(ErrorKind::BadSourceCode(_)) => {}
+                _ => panic!("Expected bad source code"),
+            }
+        }
+    }
+
     fn bad_format_file(fmt: impl Formatter) {
         // Write source code to file
         let source = r#"use"#;
         let mut file = tempfile::NamedTempFile::new().unwrap();
         file.write_all(source.as_bytes()).unwrap();
 
-        match fmt.format_file(file.path()) {
-            Err(Error::BadSourceCode(_)) => {}
+        match fmt.format_file(file.path()).map_err(Error::into_kind) {
+            Err(ErrorKind::BadSourceCode(_)) => {}
             _ => panic!("Expected bad source code"),
         }
     }
 
+    #[cfg(feature = "post_process")]
+    #[test]
+    fn command_file_replace_markers_and_docs() {
+        let rustfmt = CommandFormatter::new(RUST_FMT)
+            .arg("--edition")
+            .arg("2021")
+            .post_proc(PostProcess::ReplaceMarkersAndDocBlocks);
+        format_file(rustfmt, REPLACE_BLOCKS_EXPECTED);
+    }
+
+    #[test]
+    fn command_bad_file() {
+        bad_format_file(CommandFormatter::new(RUST_FMT));
+    }
+
+    fn format_strs(fmt: impl Formatter + Sync, expected: &str) {
+        let sources = || {
+            vec![
+                r#"#[doc = " This is main"] fn main() { _comment_!("This prints hello world");
+                    println!("Hello World!"); _blank_!(); }"#
+                    .to_string(),
+                "use".to_string(),
+                "fn main() {}".to_string(),
+            ]
+        };
+
+        for results in [fmt.format_strs(sources()), fmt.format_strs_par(sources())] {
+            match results.as_slice() {
+                [Ok(first), Err(err), Ok(third)]
+                    if matches!(err.kind(), ErrorKind::BadSourceCode(_)) =>
+                {
+                    assert_eq!(expected, first);
+                    assert_eq!("fn main() {}\n", third);
+                }
+                _ => panic!("Expected results in order with the second one failing"),
+            }
+        }
+    }
+
+    #[test]
+    fn rustfmt_strs() {
+        temp_env::with_var(RUST_FMT_KEY, Some(RUST_FMT), || {
+            format_strs(RustFmt::new(), PLAIN_EXPECTED);
+        });
+    }
+
+    #[cfg(feature = "pretty_please")]
+    #[test]
+    fn prettyplease_strs() {
+        format_strs(PrettyPlease::new(), PLAIN_PP_EXPECTED);
+    }
+
+    #[test]
+    fn rustfmt_files() {
+        temp_env::with_var(RUST_FMT_KEY, Some(RUST_FMT), || {
+            let dir = tempfile::tempdir().unwrap();
+            let paths = ["a.rs", "b.rs", "c.rs"].map(|name| dir.path().join(name));
+            std::fs::write(&paths[0], "fn a(){}").unwrap();
+            std::fs::write(&paths[1], "use").unwrap();
+            std::fs::write(&paths[2], "fn c(){}").unwrap();
+
+            // A bad file doesn't stop the files after it from being formatted
+            let results = RustFmt::new().format_files(&paths);
+            match results.as_slice() {
+                [(a, Ok(())), (b, Err(err)), (c, Ok(()))]
+                    if matches!(err.kind(), ErrorKind::BadSourceCode(_)) =>
+                {
+                    assert_eq!(&paths[0], a);
+                    assert_eq!(&paths[1], b);
+                    assert_eq!(&paths[2], c);
+                }
+                _ => panic!("Expected only 'b.rs' to fail"),
+            }
+
+            let actual = std::fs::read_to_string(&paths[2]).unwrap();
+            assert_eq!("fn c() {}\n", actual);
+        });
+    }
+
+    #[test]
+    fn rustfmt_files_par() {
+        temp_env::with_var(RUST_FMT_KEY, Some(RUST_FMT), || {
+            let files: Vec<_> = (0..4)
+                .map(|_| {
+                    let mut file = tempfile::NamedTempFile::new().unwrap();
+                    file.write_all(b"fn main() { }").unwrap();
+                    file
+                })
+                .collect();
+            let paths: Vec<_> = files.iter().map(|file| file.path().to_path_buf()).collect();
+
+            let results = RustFmt::new().format_files_par(&paths);
+            assert_eq!(4, results.len());
+
+            for ((path, result), file) in results.into_iter().zip(files) {
+                assert_eq!(file.path(), path);
+                result.unwrap();
+                let actual = std::fs::read_to_string(file.path()).unwrap();
+                assert_eq!("fn main() {}\n", actual);
+            }
+        });
+    }
+
+    #[test]
+    fn rustfmt_dir() {
+        temp_env::with_var(RUST_FMT_KEY, Some(RUST_FMT), || {
+            let dir = tempfile::tempdir().unwrap();
+            let sub_dir = dir.path().join("sub");
+            std::fs::create_dir(&sub_dir).unwrap();
+            std::fs::write(dir.path().join("a.rs"), "fn a(){}").unwrap();
+            std::fs::write(dir.path().join("skip.rs"), "fn skip(){}").unwrap();
+            std::fs::write(sub_dir.join("b.rs"), "use").unwrap();
+
+            let options = FormatDirOptions::new().exclude("skip.rs");
+            let results = RustFmt::new().format_dir(dir.path(), &options).unwrap();
+
+            match results.as_slice() {
+                [(a, Ok(())), (b, Err(err))]
+                    if matches!(err.kind(), ErrorKind::BadSourceCode(_)) =>
+                {
+                    assert_eq!(&dir.path().join("a.rs"), a);
+                    assert_eq!(&sub_dir.join("b.rs"), b);
+                }
+                _ => panic!("Expected 'a.rs' to be formatted and 'b.rs' to fail"),
+            }
+
+            let actual = std::fs::read_to_string(dir.path().join("a.rs")).unwrap();
+            assert_eq!("fn a() {}\n", actual);
+            let actual = std::fs::read_to_string(dir.path().join("skip.rs")).unwrap();
+            assert_eq!("fn skip(){}", actual);
+        });
+    }
+
+    #[test]
+    fn rustfmt_diagnostics() {
+        temp_env::with_var(RUST_FMT_KEY, Some(RUST_FMT), || {
+            match RustFmt::new()
+                .format_str("fn main() { let x = ; }")
+                .map_err(Error::into_kind)
+            {
+                Err(ErrorKind::BadSourceCode(err)) => {
+                    assert!(!err.status.unwrap().success());
+                    assert!(err.stderr.contains("expected expression"));
+                    assert_eq!(Some("fn main() { let x = ; }"), err.source.as_deref());
+
+                    match err.diagnostics.as_slice() {
+                        [diagnostic] => {
+                            assert_eq!((Some(1), Some(21)), (diagnostic.line, diagnostic.column));
+                            assert!(diagnostic.snippet.contains("let x = ;"));
+                        }
+                        _ => panic!("Expected a single diagnostic"),
+                    }
+                }
+                _ => panic!("Expected bad source code"),
+            }
+        });
+    }
+
+    #[cfg(feature = "token_stream")]
+    #[test]
+    fn rustfmt_tokens_ref() {
+        temp_env::with_var(RUST_FMT_KEY, Some(RUST_FMT), || {
+            let tokens = quote::quote! { fn main() { println!("Hello World!"); } };
+            let expected = "fn main() {\n    println!(\"Hello World!\");\n}\n";
+
+            assert_eq!(expected, RustFmt::new().format_tokens_ref(&tokens).unwrap());
+            let command = CommandFormatter::new(RUST_FMT);
+            assert_eq!(expected, command.format_tokens_ref(&tokens).unwrap());
+            // Timeouts take the string path
+            let rustfmt = RustFmt::from_config(Config::new_str().timeout(Duration::from_secs(60)));
+            assert_eq!(expected, rustfmt.format_tokens(tokens).unwrap());
+
+            let tokens = quote::quote! { fn main() { let x = ; } };
+            match RustFmt::new()
+                .format_tokens_ref(&tokens)
+                .map_err(Error::into_kind)
+            {
+                Err(ErrorKind::BadSourceCode(err)) => {
+                    assert_eq!(Some(tokens.to_string()), err.source);
+                }
+                _ => panic!("Expected bad source code"),
+            }
+        });
+    }
+
+    #[cfg(feature = "pretty_please")]
+    #[test]
+    fn syn_file() {
+        let file: syn::File = syn::parse_quote! { fn main() { println!("Hello World!"); } };
+        let expected = "fn main() {\n    println!(\"Hello World!\");\n}\n";
+
+        let rustfmt = RustFmt::from_config(Config::new_str().rust_fmt_path(RUST_FMT));
+        assert_eq!(expected, rustfmt.format_syn_file(&file).unwrap());
+        assert_eq!(
+            expected,
+            PrettyPlease::default().format_syn_file(&file).unwrap()
+        );
+    }
+
+    // The diff of formatters that are checked by formatting and comparing
+    #[cfg(feature = "diff")]
+    const CHECK_DIFF: Option<&str> = Some(
+        "--- original\n+++ formatted\n@@ -1 +1 @@\n-fn main(){}\n\\ No newline at end of file\n+fn main() {}\n",
+    );
+    #[cfg(not(feature = "diff"))]
+    const CHECK_DIFF: Option<&str> = None;
+
+    fn check(fmt: impl Formatter, expected_diff: Option<&str>) {
+        let formatted = "fn main() {}\n";
+        let unformatted = "fn main(){}";
+
+        assert_eq!(Check::Formatted, fmt.check_str(formatted).unwrap());
+        let expected = Check::Unformatted(expected_diff.map(ToString::to_string));
+        assert_eq!(expected, fmt.check_str(unformatted).unwrap());
+
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(unformatted.as_bytes()).unwrap();
+        assert!(!fmt.check_file(file.path()).unwrap().is_formatted());
+        // Checking never modifies the file
+        let actual = std::fs::read_to_string(file.path()).unwrap();
+        assert_eq!(unformatted, actual);
+
+        match fmt.check_str("use").map_err(Error::into_kind) {
+            Err(ErrorKind::BadSourceCode(_)) => {}
+            _ => panic!("Expected bad source code"),
+        }
+    }
+
+    #[test]
+    fn rustfmt_check() {
+        temp_env::with_var(RUST_FMT_KEY, Some(RUST_FMT), || {
+            let diff = "Diff in <stdin>:1:\n-fn main(){}\n+fn main() {}\n+\n";
+            check(RustFmt::new(), Some(diff));
+        });
+    }
+
+    #[cfg(feature = "pretty_please")]
+    #[test]
+    fn prettyplease_check() {
+        check(PrettyPlease::new(), CHECK_DIFF);
+    }
+
+    #[test]
+    fn command_check() {
+        check(CommandFormatter::new(RUST_FMT), CHECK_DIFF);
+    }
+
+    fn report(fmt: impl Formatter) {
+        let formatted = "fn main() {}\n";
+
+        let expected = FormatResult {
+            code: formatted.to_string(),
+            changed: true,
+        };
+        assert_eq!(expected, fmt.format_str_with_report("fn main(){}").unwrap());
+        let expected = FormatResult {
+            code: formatted.to_string(),
+            changed: false,
+        };
+        assert_eq!(expected, fmt.format_str_with_report(formatted).unwrap());
+
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(b"fn main(){}").unwrap();
+        assert!(fmt.format_file_with_report(file.path()).unwrap());
+        assert!(!fmt.format_file_with_report(file.path()).unwrap());
+        let actual = std::fs::read_to_string(file.path()).unwrap();
+        assert_eq!(formatted, actual);
+    }
+
+    #[test]
+    fn rustfmt_source_map() {
+        temp_env::with_var(RUST_FMT_KEY, Some(RUST_FMT), || {
+            let source = "fn main() { let x = 1; }\n\nfn other() {}\n";
+            let (code, source_map) = RustFmt::new().format_str_with_source_map(source).unwrap();
+
+            assert_eq!("fn main() {\n    let x = 1;\n}\n\nfn other() {}\n", code);
+            assert_eq!(&[Some(0..3), None, Some(4..5)], source_map.lines());
+        });
+    }
+
+    #[test]
+    fn rustfmt_report() {
+        temp_env::with_var(RUST_FMT_KEY, Some(RUST_FMT), || {
+            report(RustFmt::new());
+        });
+    }
+
+    #[cfg(feature = "pretty_please")]
+    #[test]
+    fn prettyplease_report() {
+        report(PrettyPlease::new());
+    }
+
     #[test]
     fn rustfmt_bad_file() {
         temp_env::with_var(RUST_FMT_KEY, Some(RUST_FMT), || {
@@ -835,4 +3595,46 @@ fn main() {
     fn prettyplease_bad_file() {
         bad_format_file(PrettyPlease::new());
     }
+
+    #[cfg(feature = "post_process")]
+    fn format_script(fmt: impl Formatter) {
+        let source = r#"#!/usr/bin/env -S cargo +nightly -Zscript
+---
+[dependencies]
+foo = "1"
+---
+fn main(){ _blank_!(); }"#;
+        let expected = r#"#!/usr/bin/env -S cargo +nightly -Zscript
+---
+[dependencies]
+foo = "1"
+---
+fn main() {
+
+}
+"#;
+
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(source.as_bytes()).unwrap();
+        fmt.format_file(file.path()).unwrap();
+
+        let actual = std::fs::read_to_string(file.path()).unwrap();
+        assert_eq!(expected, actual);
+    }
+
+    #[cfg(feature = "post_process")]
+    #[test]
+    fn rustfmt_script() {
+        let config = Config::new_str()
+            .rust_fmt_path(RUST_FMT)
+            .post_proc(PostProcess::ReplaceMarkers);
+        format_script(RustFmt::from_config(config));
+    }
+
+    #[cfg(all(feature = "post_process", feature = "pretty_please"))]
+    #[test]
+    fn prettyplease_script() {
+        let config = Config::new_str().post_proc(PostProcess::ReplaceMarkers);
+        format_script(PrettyPlease::from_config(config));
+    }
 }
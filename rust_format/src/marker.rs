@@ -0,0 +1,133 @@
+use std::fmt;
+use std::sync::Arc;
+
+// The names of the built-in markers (these can't be handled by a `MarkerHandler`)
+#[cfg(feature = "post_process")]
+const BUILT_IN: [&str; 5] = ["blank", "comment", "doc", "inner_doc", "raw"];
+
+// *** Marker Handler ***
+
+/// A handler for a custom `_name_!(...)` marker. Custom markers are found by the same scanner as
+/// the built-in markers (so markers in strings and comments are ignored) whenever markers are
+/// replaced during post processing. Handlers are registered with
+/// [marker_handler](crate::Config::marker_handler)
+///
+/// ```
+/// use rust_format::{Config, Formatter, MarkerFn, PostProcess, RustFmt};
+///
+/// let todo = MarkerFn::new("todo", |arg, indent| {
+///     let text = arg.ok_or("A description is required")?;
+///     Ok(format!("{:indent$}// TODO: {text}", ""))
+/// });
+///
+/// let config = Config::new_str()
+///     .post_proc(PostProcess::ReplaceMarkers)
+///     .marker_handler(todo);
+/// let source = r#"fn main() { _todo_!("Write main"); }"#;
+///
+/// let actual = RustFmt::from_config(config).format_str(source).unwrap();
+/// assert_eq!("fn main() {\n    // TODO: Write main\n}\n", actual);
+/// ```
+pub trait MarkerHandler: Send + Sync {
+    /// The name of the marker without the surrounding underscores (`todo` handles `_todo_!()`)
+    fn name(&self) -> &str;
+
+    /// Returns the text that replaces the marker given the value of its string literal argument
+    /// (if any) and the indentation (in spaces) of the line it is on. Each line of the text is
+    /// inserted as is (so it should include any indentation) and an empty string removes the
+    /// marker. An error message fails post processing
+    fn replace(&self, arg: Option<&str>, indent: usize) -> Result<String, String>;
+}
+
+/// A [MarkerHandler] made from a name and a closure
+pub struct MarkerFn<F> {
+    name: String,
+    f: F,
+}
+
+impl<F> MarkerFn<F>
+where
+    F: Fn(Option<&str>, usize) -> Result<String, String> + Send + Sync,
+{
+    /// Creates a new handler for the named marker (without the surrounding underscores) that calls
+    /// the closure to replace it
+    #[inline]
+    pub fn new(name: impl Into<String>, f: F) -> Self {
+        Self {
+            name: name.into(),
+            f,
+        }
+    }
+}
+
+impl<F> MarkerHandler for MarkerFn<F>
+where
+    F: Fn(Option<&str>, usize) -> Result<String, String> + Send + Sync,
+{
+    #[inline]
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[inline]
+    fn replace(&self, arg: Option<&str>, indent: usize) -> Result<String, String> {
+        (self.f)(arg, indent)
+    }
+}
+
+impl<F> fmt::Debug for MarkerFn<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MarkerFn")
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
+}
+
+// *** Markers ***
+
+// The custom marker handlers registered with a formatter
+#[derive(Clone, Default)]
+pub(crate) struct Markers {
+    handlers: Vec<Arc<dyn MarkerHandler>>,
+}
+
+impl Markers {
+    // No custom markers
+    pub(crate) const NONE: Markers = Markers {
+        handlers: Vec::new(),
+    };
+
+    #[cfg(feature = "post_process")]
+    #[inline]
+    pub(crate) fn push(&mut self, handler: impl MarkerHandler + 'static) {
+        self.handlers.push(Arc::new(handler));
+    }
+
+    #[cfg(feature = "post_process")]
+    #[inline]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.handlers.is_empty()
+    }
+
+    // Returns the handler whose marker name starts the bytes (which must be followed by `_`)
+    #[cfg(feature = "post_process")]
+    pub(crate) fn find(&self, bytes: &[u8]) -> Option<&dyn MarkerHandler> {
+        self.handlers
+            .iter()
+            .map(Arc::as_ref)
+            .filter(|handler| !BUILT_IN.contains(&handler.name()))
+            .find(|handler| {
+                let name = handler.name().as_bytes();
+                !name.is_empty() && bytes.starts_with(name) && bytes.get(name.len()) == Some(&b'_')
+            })
+    }
+}
+
+// Only the names are shown (which is enough to tell formatters apart)
+impl fmt::Debug for Markers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.handlers.iter().map(|handler| handler.name()))
+            .finish()
+    }
+}
@@ -0,0 +1,96 @@
+// The minimum number of dashes that open a frontmatter block
+const MIN_DASHES: usize = 3;
+
+// Split off the first line (including the line ending)
+#[inline]
+fn split_line(s: &str) -> (&str, &str) {
+    match s.find('\n') {
+        Some(idx) => s.split_at(idx + 1),
+        None => (s, ""),
+    }
+}
+
+// A shebang is `#!` on the first line, as long as it isn't the start of an inner attribute
+fn shebang_len(s: &str) -> usize {
+    match s.strip_prefix("#!") {
+        Some(rest) if !rest.trim_start().starts_with('[') => split_line(s).0.len(),
+        _ => 0,
+    }
+}
+
+// A frontmatter block (as used by `cargo` scripts) starts with a line of 3 or more dashes
+// (optionally followed by an info string) and ends with a line of the same number of dashes
+fn frontmatter_len(s: &str) -> usize {
+    // Only blank lines may come before the opening dashes
+    let mut offset = 0;
+    let mut rest = s;
+    while !rest.is_empty() {
+        let (line, remaining) = split_line(rest);
+        if !line.trim().is_empty() {
+            break;
+        }
+        offset += line.len();
+        rest = remaining;
+    }
+
+    let (open, mut rest) = split_line(rest);
+    let dashes = open.chars().take_while(|&ch| ch == '-').count();
+    if dashes < MIN_DASHES {
+        return 0;
+    }
+    let fence = &open[..dashes];
+    let mut len = offset + open.len();
+
+    while !rest.is_empty() {
+        let (line, remaining) = split_line(rest);
+        len += line.len();
+        if line.trim_end() == fence {
+            return len;
+        }
+        rest = remaining;
+    }
+
+    // Unterminated - leave it for the formatter to report
+    0
+}
+
+// Splits the source into the prologue (a shebang line and/or frontmatter block) and the Rust code
+// that follows it. The prologue isn't Rust code, so it is kept as is when formatting
+pub(crate) fn split_prologue(s: &str) -> (&str, &str) {
+    let shebang = shebang_len(s);
+    let len = shebang + frontmatter_len(&s[shebang..]);
+    s.split_at(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::prologue::split_prologue;
+
+    #[test]
+    fn prologue() {
+        let shebang = "#!/usr/bin/env rust-script\n";
+        let frontmatter = "\n---cargo\n[dependencies]\nfoo = \"1\"\n---\n";
+        let code = "fn main() {}\n";
+
+        assert_eq!(("", code), split_prologue(code));
+        let source = format!("{shebang}{code}");
+        assert_eq!((shebang, code), split_prologue(&source));
+        let source = format!("{shebang}{frontmatter}{code}");
+        assert_eq!(
+            (&*format!("{shebang}{frontmatter}"), code),
+            split_prologue(&source)
+        );
+        let source = format!("{frontmatter}{code}");
+        assert_eq!((frontmatter, code), split_prologue(&source));
+
+        // Inner attributes, unterminated frontmatter and too few dashes aren't a prologue
+        let source = "#![allow(unused)]\nfn main() {}\n";
+        assert_eq!(("", source), split_prologue(source));
+        let source = "---\nfn main() {}\n";
+        assert_eq!(("", source), split_prologue(source));
+        let source = "--\n--\nfn main() {}\n";
+        assert_eq!(("", source), split_prologue(source));
+    }
+}
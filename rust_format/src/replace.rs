@@ -3,33 +3,64 @@
 use std::borrow::Cow;
 use std::{cmp, slice};
 
-use crate::Error;
+use syn::parse::Parser;
+use syn::punctuated::Punctuated;
+use syn::Token;
+
+use crate::marker::{MarkerHandler, Markers};
+use crate::{Error, PostProcess, PostProcessStats};
 
 const BLANK_START: &[&[u8]] = &[b"lank_", b"!", b"("];
-const BLANK_END: &[&[u8]] = &[b";"];
+const BLANK_END: &[&[u8]] = &[];
 const COMMENT_START: &[&[u8]] = &[b"omment_", b"!", b"("];
-const COMMENT_END: &[&[u8]] = &[b")", b";"];
-const COMMENT_END2: &[&[u8]] = &[b";"];
+const COMMENT_END: &[&[u8]] = &[b")"];
+const COMMENT_END2: &[&[u8]] = &[];
+const DOC_START: &[&[u8]] = &[b"oc_", b"!", b"("];
+const INNER_DOC_START: &[&[u8]] = &[b"nner_doc_", b"!", b"("];
+const RAW_START: &[&[u8]] = &[b"aw_", b"!", b"("];
+const RAW_END: &[&[u8]] = &[b")"];
 const DOC_BLOCK_START: &[&[u8]] = &[b"[", b"doc", b"="];
 const DOC_BLOCK_END: &[&[u8]] = &[b"]"];
+const RUSTFMT_SKIP: &[&[u8]] = &[b"[", b"rustfmt", b"::", b"skip", b"]"];
 
 const EMPTY_COMMENT: &str = "//";
 const COMMENT: &str = "// ";
 const DOC_COMMENT: &str = "///";
+const OUTER_DOC_BLOCK: &str = "#[doc = ";
+const INNER_DOC_BLOCK: &str = "#![doc = ";
+const DOC_COMMENT_SPACE: &str = "/// ";
+const INNER_DOC_COMMENT: &str = "//!";
+const INNER_DOC_COMMENT_SPACE: &str = "//! ";
 const LF_STR: &str = "\n";
 const CRLF_STR: &str = "\r\n";
 
+// The non-ASCII whitespace code points Rust recognizes (as UTF-8)
+const UNICODE_WHITESPACE: &[&[u8]] = &[
+    // U+0085 (next line)
+    &[0xC2, 0x85],
+    // U+200E (left-to-right mark)
+    &[0xE2, 0x80, 0x8E],
+    // U+200F (right-to-left mark)
+    &[0xE2, 0x80, 0x8F],
+    // U+2028 (line separator)
+    &[0xE2, 0x80, 0xA8],
+    // U+2029 (paragraph separator)
+    &[0xE2, 0x80, 0xA9],
+];
+
 const CR: u8 = b'\r';
 const LF: u8 = b'\n';
 
 const MIN_BUFF_SIZE: usize = 128;
+// Replacements rarely grow the source by much, so this is extra room for them
+const GROWTH_DIVISOR: usize = 8;
 
 // In order to replace the markers there were a few options:
 // 1. Create a full special purpose Rust lexer, replace the tokens we want as we go, write it back
 // 2. Find the markers via regular string search, copy everything up to that point, replace, repeat
 // 3. A hybrid of 1 and 2
 //
-// The problem with #1 is it is hugely overkill - we are only interested in 3 markers
+// The problem with #1 is it is hugely overkill - we are only interested in 6 markers
 // The problem with #2 is that it would find markers in strings and comments - likely not an issue, but it bothered me
 // (and also we generalize the marker replacement code also for doc blocks, which someone could have commented out)
 // #3 is what is below - it does basic lexing of Rust comments and strings for the purposes of skipping them only. It
@@ -38,6 +69,39 @@ const MIN_BUFF_SIZE: usize = 128;
 // I think this is a good compromise. Regardless, the user should be advised to not use `_comment_!(` or `_blank_!(`
 // anywhere in the source file other than where they want markers.
 
+// Returns the length of the whitespace char at the start of the bytes (if any)
+fn whitespace_len(bytes: &[u8]) -> Option<usize> {
+    match bytes.first() {
+        Some(&ch) if CopyingCursor::is_whitespace(ch) => Some(1),
+        Some(_) => UNICODE_WHITESPACE
+            .iter()
+            .find(|ws| bytes.starts_with(ws))
+            .map(|ws| ws.len()),
+        None => None,
+    }
+}
+
+// Returns the line ending used by most lines of the source (LF if there is a tie or no lines end)
+fn dominant_line_ending(source: &str) -> &'static str {
+    let bytes = source.as_bytes();
+    let (crlf, lf) = bytes.iter().enumerate().filter(|&(_, &ch)| ch == LF).fold(
+        (0, 0),
+        |(crlf, lf), (idx, _)| {
+            if idx > 0 && bytes[idx - 1] == CR {
+                (crlf + 1, lf)
+            } else {
+                (crlf, lf + 1)
+            }
+        },
+    );
+
+    if crlf > lf {
+        CRLF_STR
+    } else {
+        LF_STR
+    }
+}
+
 struct CopyingCursor<'a> {
     start_idx: usize,
     curr_idx: usize,
@@ -47,13 +111,16 @@ struct CopyingCursor<'a> {
     // any unicode, however, and copy it verbatim
     iter: slice::Iter<'a, u8>,
     source: &'a str,
-    buffer: String,
+    // Only whole `str` values are ever copied into this, so it is always valid UTF-8. It isn't
+    // allocated until something is replaced
+    buffer: Vec<u8>,
+    stats: PostProcessStats,
+    // Every inserted line ends with this (so mixed inputs don't get mixed insertions)
+    ending: &'static str,
 }
 
 impl<'a> CopyingCursor<'a> {
     fn new(source: &'a str) -> Option<Self> {
-        // Better to be too large than not large enough
-        let buffer = String::with_capacity(cmp::max(source.len() * 2, MIN_BUFF_SIZE));
         let mut iter = source.as_bytes().iter();
 
         iter.next().map(|&ch| Self {
@@ -62,7 +129,9 @@ impl<'a> CopyingCursor<'a> {
             curr: ch,
             iter,
             source,
-            buffer,
+            buffer: Vec::new(),
+            stats: PostProcessStats::default(),
+            ending: dominant_line_ending(source),
         })
     }
 
@@ -75,11 +144,25 @@ impl<'a> CopyingCursor<'a> {
         })
     }
 
+    // The current char is the last one. A failed match can't advance past it, so it must be treated
+    // as a regular char (re-examining it would never end)
+    #[inline]
+    fn at_eof(&self) -> bool {
+        self.iter.as_slice().is_empty()
+    }
+
     #[inline]
     fn copy_to_marker(&mut self, marker: usize, new_start_idx: usize) {
+        if self.buffer.capacity() == 0 {
+            let len = self.source.len();
+            self.buffer
+                .reserve(cmp::max(len + len / GROWTH_DIVISOR, MIN_BUFF_SIZE));
+        }
+
         if marker > self.start_idx {
             // Copy exclusive of marker position
-            self.buffer.push_str(&self.source[self.start_idx..marker]);
+            self.buffer
+                .extend_from_slice(&self.source.as_bytes()[self.start_idx..marker]);
         }
         self.start_idx = new_start_idx;
     }
@@ -90,8 +173,8 @@ impl<'a> CopyingCursor<'a> {
             // Last write to ensure everything is copied
             self.copy_to_marker(self.curr_idx + 1, self.curr_idx + 1);
 
-            self.buffer.shrink_to_fit();
-            Cow::Owned(self.buffer)
+            let buffer = String::from_utf8(self.buffer).expect("Only `str` values are copied");
+            Cow::Owned(buffer)
         // We have done nothing - just return original str
         } else {
             Cow::Borrowed(self.source)
@@ -222,6 +305,79 @@ impl<'a> CopyingCursor<'a> {
         true
     }
 
+    // Only needed so a quote char literal isn't mistaken for the start of a string
+    fn skip_char(&mut self) {
+        match self.iter.as_slice() {
+            // Escaped char literal
+            [b'\\', ..] => {
+                self.next();
+                while let Some(ch) = self.next() {
+                    if ch == b'\'' {
+                        break;
+                    }
+                }
+            }
+            // Single (ASCII) char literal
+            [_, b'\'', ..] => {
+                self.next();
+                self.next();
+            }
+            // Lifetime, label, or non-ASCII char literal (none of which can contain a quote)
+            _ => {}
+        }
+    }
+
+    // Returns false if this turned out to not be a comment (the current char is not consumed)
+    fn try_replace_doc_comment(&mut self, line_start: bool) -> bool {
+        let comment_start = self.curr_idx;
+
+        match self.next() {
+            // Line comment of some form
+            Some(b'/') => {
+                let prefix = match self.iter.as_slice() {
+                    // Regular comment (four or more slashes)
+                    [b'/', b'/', ..] => None,
+                    [b'/', ..] => Some(OUTER_DOC_BLOCK),
+                    [b'!', ..] => Some(INNER_DOC_BLOCK),
+                    _ => None,
+                };
+                let text_start = comment_start + 3;
+
+                // Find the end of the line (excluding the line ending)
+                let mut line_end = self.source.len();
+                while let Some(ch) = self.next() {
+                    if ch == LF {
+                        line_end = self.curr_idx;
+                        break;
+                    }
+                }
+                if self.source[..line_end].ends_with(CR as char) {
+                    line_end -= 1;
+                }
+
+                // Doc comments must start their line to be attached to anything
+                if let (Some(prefix), true) = (prefix, line_start) {
+                    self.copy_to_marker(comment_start, line_end);
+                    let text = format!("{:?}", &self.source[text_start..line_end]);
+                    self.buffer.extend_from_slice(prefix.as_bytes());
+                    self.buffer.extend_from_slice(text.as_bytes());
+                    self.buffer.push(b']');
+                }
+
+                true
+            }
+            // Block comment of some form (we don't care which)
+            Some(b'*') => {
+                self.skip_block_comment();
+                true
+            }
+            // EOF - nothing left to do
+            None => true,
+            // Not a comment - should be impossible in valid code
+            _ => false,
+        }
+    }
+
     #[inline]
     fn skip_blank_param(&mut self) -> Result<(), Error> {
         while let Some(ch) = self.next() {
@@ -231,12 +387,12 @@ impl<'a> CopyingCursor<'a> {
         }
 
         // EOF
-        Err(Error::BadSourceCode("Unexpected end of input".to_string()))
+        Err(Error::bad_source_code("Unexpected end of input"))
     }
 
     fn try_skip_string(&mut self) -> Result<Option<u8>, Error> {
         while let Some(ch) = self.next() {
-            if Self::is_whitespace(ch) {
+            if self.is_any_whitespace(ch) {
                 continue;
             }
 
@@ -251,7 +407,7 @@ impl<'a> CopyingCursor<'a> {
                     if self.try_skip_raw_string() {
                         Ok(None)
                     } else {
-                        Err(Error::BadSourceCode("Bad raw string".to_string()))
+                        Err(Error::bad_source_code("Bad raw string"))
                     }
                 }
                 // Something else
@@ -260,27 +416,40 @@ impl<'a> CopyingCursor<'a> {
         }
 
         // EOF
-        Err(Error::BadSourceCode("Unexpected end of input".to_string()))
+        Err(Error::bad_source_code("Unexpected end of input"))
     }
 
-    // TODO: Was planning to match values here (but we only recognize ASCII atm):
+    // The whitespace Rust recognizes (Pattern_White_Space) is matched here:
     // https://github.com/rust-lang/rust/blob/38e0ae590caab982a4305da58a0a62385c2dd880/compiler/rustc_lexer/src/lib.rs#L245
-    // We could switch back to UTF8 since we have been matching valid ASCII up to this point, but atm
-    // any unicode whitespace will make it not match (not sure any code formatter preserves non-ASCII whitespace?)
-    // For now, users should use NO whitespace and let the code formatters add any, if needed. I suspect
-    // they will not add any non-ASCII whitespace on their own at min, but likely just ' ', '\n', and '\r'
-    //
-    // Code points we don't handle that we should (for future ref):
-    // Code point 0x0085 == 0xC285
-    // Code point 0x200E == 0xE2808E
-    // Code point 0x200F == 0xE2808F
-    // Code point 0x2028 == 0xE280A8
-    // Code point 0x2029 == 0xE280A9
+    // This only matches the ASCII subset - see `is_any_whitespace` for the rest
     #[inline]
     fn is_whitespace(ch: u8) -> bool {
         matches!(ch, b' ' | b'\n' | b'\r' | b'\t' | b'\x0b' | b'\x0c')
     }
 
+    // If the current char starts a non-ASCII whitespace code point, the rest of it is consumed
+    fn try_skip_unicode_whitespace(&mut self) -> bool {
+        let rest = self.iter.as_slice();
+
+        for &ws in UNICODE_WHITESPACE {
+            // Panic safety: none of our whitespace sequences are blank
+            if ws[0] == self.curr && rest.starts_with(&ws[1..]) {
+                for _ in 1..ws.len() {
+                    self.next();
+                }
+                return true;
+            }
+        }
+
+        false
+    }
+
+    // The current char must be `ch`. If it is Unicode whitespace, the whole code point is consumed
+    #[inline]
+    fn is_any_whitespace(&mut self, ch: u8) -> bool {
+        Self::is_whitespace(ch) || self.try_skip_unicode_whitespace()
+    }
+
     fn try_ws_matches(&mut self, slices: &[&[u8]], allow_whitespace_first: bool) -> bool {
         let mut allow_whitespace = allow_whitespace_first;
 
@@ -299,7 +468,7 @@ impl<'a> CopyingCursor<'a> {
                     }
                     allow_whitespace = true;
                     continue 'top;
-                } else if allow_whitespace && Self::is_whitespace(ch) {
+                } else if allow_whitespace && self.is_any_whitespace(ch) {
                     // no op
                 } else {
                     return false;
@@ -333,80 +502,82 @@ impl<'a> CopyingCursor<'a> {
         true
     }
 
+    // Consume the line ending (CRLF or LF) after the current char, returning false if there isn't one
     #[inline]
-    fn detect_line_ending(&mut self) -> Option<&'static str> {
+    fn skip_line_ending(&mut self) -> bool {
         match self.next() {
-            Some(CR) => match self.next() {
-                Some(LF) => Some(CRLF_STR),
-                _ => None,
-            },
-            Some(LF) => Some(LF_STR),
-            _ => None,
+            Some(CR) => self.next() == Some(LF),
+            Some(LF) => true,
+            _ => false,
         }
     }
 
     #[inline]
-    fn push_spaces(spaces: usize, buffer: &mut String) {
-        for _ in 0..spaces {
-            buffer.push(' ');
-        }
+    fn push_spaces(spaces: usize, buffer: &mut Vec<u8>) {
+        buffer.resize(buffer.len() + spaces, b' ');
     }
 
     fn process_blanks(
         _spaces: usize,
-        buffer: &mut String,
+        buffer: &mut Vec<u8>,
         num: &str,
         ending: &str,
     ) -> Result<(), Error> {
         // Single blank line
         if num.is_empty() {
-            buffer.push_str(ending);
+            buffer.extend_from_slice(ending.as_bytes());
         // Multiple blank lines
         } else {
             let num: syn::LitInt = syn::parse_str(num)?;
             let blanks: u32 = num.base10_parse()?;
 
             for _ in 0..blanks {
-                buffer.push_str(ending);
+                buffer.extend_from_slice(ending.as_bytes());
             }
         }
 
         Ok(())
     }
 
-    fn process_comments(
+    // Each line is prefixed with `prefix`, except empty lines which are just `empty` (to avoid
+    // trailing whitespace)
+    fn process_prefixed_lines(
         spaces: usize,
-        buffer: &mut String,
+        buffer: &mut Vec<u8>,
         s: &str,
         ending: &str,
+        (empty, prefix): (&str, &str),
     ) -> Result<(), Error> {
         // Single blank comment
         if s.is_empty() {
             Self::push_spaces(spaces, buffer);
-            buffer.push_str(EMPTY_COMMENT);
-            buffer.push_str(ending);
-        // Multiple comments
+            buffer.extend_from_slice(empty.as_bytes());
+            buffer.extend_from_slice(ending.as_bytes());
+        // Multiple comments (each literal is broken into lines separately)
         } else {
-            let s: syn::LitStr = syn::parse_str(s)?;
-            let comment = s.value();
+            let lits = Punctuated::<syn::LitStr, Token![,]>::parse_terminated.parse_str(s)?;
 
-            // Blank comment after parsing
-            if comment.is_empty() {
-                Self::push_spaces(spaces, buffer);
-                buffer.push_str(EMPTY_COMMENT);
-                buffer.push_str(ending);
-            } else {
-                for line in comment.lines() {
+            for lit in lits {
+                let comment = lit.value();
+
+                // Blank comment after parsing
+                if comment.is_empty() {
                     Self::push_spaces(spaces, buffer);
+                    buffer.extend_from_slice(empty.as_bytes());
+                    buffer.extend_from_slice(ending.as_bytes());
+                } else {
+                    for line in comment.lines() {
+                        Self::push_spaces(spaces, buffer);
+
+                        if line.is_empty() {
+                            buffer.extend_from_slice(empty.as_bytes());
+                        } else {
+                            buffer.extend_from_slice(prefix.as_bytes());
+                            buffer.extend_from_slice(line.as_bytes());
+                        }
 
-                    if line.is_empty() {
-                        buffer.push_str(EMPTY_COMMENT);
-                    } else {
-                        buffer.push_str(COMMENT);
-                        buffer.push_str(line);
+                        buffer.extend_from_slice(ending.as_bytes());
                     }
-
-                    buffer.push_str(ending);
                 }
             }
         }
@@ -414,19 +585,76 @@ impl<'a> CopyingCursor<'a> {
         Ok(())
     }
 
+    #[inline]
+    fn process_comments(
+        spaces: usize,
+        buffer: &mut Vec<u8>,
+        s: &str,
+        ending: &str,
+    ) -> Result<(), Error> {
+        Self::process_prefixed_lines(spaces, buffer, s, ending, (EMPTY_COMMENT, COMMENT))
+    }
+
+    #[inline]
+    fn process_docs(
+        spaces: usize,
+        buffer: &mut Vec<u8>,
+        s: &str,
+        ending: &str,
+    ) -> Result<(), Error> {
+        Self::process_prefixed_lines(spaces, buffer, s, ending, (DOC_COMMENT, DOC_COMMENT_SPACE))
+    }
+
+    #[inline]
+    fn process_inner_docs(
+        spaces: usize,
+        buffer: &mut Vec<u8>,
+        s: &str,
+        ending: &str,
+    ) -> Result<(), Error> {
+        let markers = (INNER_DOC_COMMENT, INNER_DOC_COMMENT_SPACE);
+        Self::process_prefixed_lines(spaces, buffer, s, ending, markers)
+    }
+
+    // Unlike comments, the lines are inserted verbatim (no prefix and no indentation)
+    fn process_raw(
+        buffer: &mut Vec<u8>,
+        s: &str,
+        ending: &str,
+        post_proc: PostProcess,
+    ) -> Result<(), Error> {
+        let s: syn::LitStr = syn::parse_str(s)?;
+        let value = s.value();
+
+        // Raw text is copied as is, so any markers in it would only be replaced if the output were
+        // post processed again (post processing must be a no-op the second time)
+        if let Cow::Owned(_) = replace_markers(&value, post_proc)? {
+            return Err(Error::bad_source_code(format!(
+                "Raw marker text contains a marker that would be replaced: {value}"
+            )));
+        }
+
+        for line in value.lines() {
+            buffer.extend_from_slice(line.as_bytes());
+            buffer.extend_from_slice(ending.as_bytes());
+        }
+
+        Ok(())
+    }
+
     // This is slightly different than comment in that we don't prepend a space but need to translate
     // the doc block literally (#[doc = "test"] == ///test <-- no prepended space)
     fn process_doc_block(
         spaces: usize,
-        buffer: &mut String,
+        buffer: &mut Vec<u8>,
         s: &str,
         ending: &str,
     ) -> Result<(), Error> {
         // Single blank comment
         if s.is_empty() {
             Self::push_spaces(spaces, buffer);
-            buffer.push_str(DOC_COMMENT);
-            buffer.push_str(ending);
+            buffer.extend_from_slice(DOC_COMMENT.as_bytes());
+            buffer.extend_from_slice(ending.as_bytes());
         // Multiple comments
         } else {
             let s: syn::LitStr = syn::parse_str(s)?;
@@ -435,14 +663,14 @@ impl<'a> CopyingCursor<'a> {
             // Blank comment after parsing
             if comment.is_empty() {
                 Self::push_spaces(spaces, buffer);
-                buffer.push_str(DOC_COMMENT);
-                buffer.push_str(ending);
+                buffer.extend_from_slice(DOC_COMMENT.as_bytes());
+                buffer.extend_from_slice(ending.as_bytes());
             } else {
                 for line in comment.lines() {
                     Self::push_spaces(spaces, buffer);
-                    buffer.push_str(DOC_COMMENT);
-                    buffer.push_str(line);
-                    buffer.push_str(ending);
+                    buffer.extend_from_slice(DOC_COMMENT.as_bytes());
+                    buffer.extend_from_slice(line.as_bytes());
+                    buffer.extend_from_slice(ending.as_bytes());
                 }
             }
         }
@@ -468,106 +696,264 @@ impl<'a> CopyingCursor<'a> {
         }
     }
 
+    // Markers may be used as a statement (`;`), an element/match arm (`,`), or an expression
+    // (nothing) so consume the terminator, if any
+    fn skip_terminator(&mut self) {
+        let rest = self.iter.as_slice();
+        let mut idx = 0;
+
+        while let Some(len) = whitespace_len(&rest[idx..]) {
+            idx += len;
+        }
+
+        if let Some(b';' | b',') = rest.get(idx) {
+            for _ in 0..=idx {
+                self.next();
+            }
+        }
+    }
+
     fn try_replace<F>(
         &mut self,
         spaces: usize,
         chars_matched: usize,
         suffixes: &[&[u8]],
-        mark_start_ident: usize,
-        mark_start_value: usize,
+        (mark_start_ident, mark_start_value): (usize, usize),
+        is_marker: bool,
         f: F,
     ) -> Result<(), Error>
     where
-        F: FnOnce(usize, &mut String, &str, &str) -> Result<(), Error>,
+        F: FnOnce(usize, &mut Vec<u8>, &str, &str) -> Result<(), Error>,
     {
         // End of value (exclusive)
         let mark_end_value = self.curr_idx + (1 - chars_matched);
 
         if !self.try_ws_matches(suffixes, true) {
-            return Err(Error::BadSourceCode(
-                "Unable to match suffix on doc block or marker.".to_string(),
+            return Err(Error::bad_source_code(
+                "Unable to match suffix on doc block or marker.",
             ));
         }
+        if !is_marker {
+            if !self.skip_line_ending() {
+                return Err(Error::bad_source_code("Expected CR or LF"));
+            }
 
-        if let Some(ending) = self.detect_line_ending() {
             // Mark end of ident here (inclusive)
             let mark_end_ident = self.curr_idx + 1;
 
-            // Copy everything up until this marker
+            // Copy everything up until this doc block
             self.copy_to_marker(mark_start_ident, mark_end_ident);
 
             // Parse and output
-            f(
+            return f(
                 spaces,
                 &mut self.buffer,
                 &self.source[mark_start_value..mark_end_value],
-                ending,
-            )?;
-            Ok(())
+                self.ending,
+            );
+        }
+
+        self.skip_terminator();
+        let (line_indent, at_line_start) = self.line_info(mark_start_ident);
+
+        // Markers can share their line with code before and/or after them, and each of those
+        // is split onto its own line
+        let code_follows = self.skip_trailing_spaces();
+        if !code_follows && !self.skip_line_ending() {
+            return Err(Error::bad_source_code("Expected CR or LF"));
+        }
+        let ending = self.ending;
+
+        // Mark end of ident here (inclusive)
+        let mark_end_ident = self.curr_idx + 1;
+
+        // Copy everything up until this marker
+        self.copy_to_marker(mark_start_ident, mark_end_ident);
+
+        let spaces = if at_line_start {
+            spaces
         } else {
-            Err(Error::BadSourceCode("Expected CR or LF".to_string()))
+            // End the line of the preceding code and use its indentation
+            self.buffer.extend_from_slice(ending.as_bytes());
+            line_indent
+        };
+
+        // Parse and output (any code that follows gets the same indentation)
+        f(
+            spaces,
+            &mut self.buffer,
+            &self.source[mark_start_value..mark_end_value],
+            ending,
+        )?;
+
+        if code_follows {
+            Self::push_spaces(spaces, &mut self.buffer);
+        }
+        Ok(())
+    }
+
+    // Returns the indentation (in spaces) of the line containing `idx` and whether or not there
+    // is only whitespace before `idx` on that line
+    fn line_info(&self, idx: usize) -> (usize, bool) {
+        let line_start = self.source[..idx].rfind('\n').map_or(0, |idx| idx + 1);
+        let before = &self.source[line_start..idx];
+        let indent = before.len() - before.trim_start_matches(' ').len();
+
+        (indent, before.trim().is_empty())
+    }
+
+    // Consume any spaces or tabs after the current char and return true if more code follows
+    // on the same line (the current char is then the last one before that code)
+    fn skip_trailing_spaces(&mut self) -> bool {
+        let rest = self.iter.as_slice();
+        let spaces = rest
+            .iter()
+            .take_while(|&&ch| ch == b' ' || ch == b'\t')
+            .count();
+
+        match rest.get(spaces) {
+            Some(&CR) | Some(&LF) | None => false,
+            Some(_) => {
+                for _ in 0..spaces {
+                    self.next();
+                }
+                true
+            }
         }
     }
 
     fn try_replace_blank_marker(&mut self, spaces: usize) -> Result<bool, Error> {
-        // 6 or 7 sections to match: _blank_ ! ( [int] ) ; CRLF|LF
+        // 5 to 7 sections to match: _blank_ ! ( [int] ) [;|,] CRLF|LF
 
         match self.try_match_prefixes(spaces, 2, BLANK_START, false) {
-            Some((ident_start, value_start)) => {
+            Some(marks) => {
                 self.skip_blank_param()?;
 
                 self.try_replace(
                     spaces,
                     1,
                     BLANK_END,
-                    ident_start,
-                    value_start,
+                    marks,
+                    true,
                     CopyingCursor::process_blanks,
                 )?;
+                self.stats.blanks += 1;
                 Ok(true)
             }
             None => Ok(false),
         }
     }
 
+    #[inline]
     fn try_replace_comment_marker(&mut self, spaces: usize) -> Result<bool, Error> {
-        // 6 or 7 sections to match: _comment_ ! ( [string] ) ; CRLF|LF
+        // 5 to 7 sections to match: _comment_ ! ( [string] ) [;|,] CRLF|LF
+        let replaced =
+            self.try_replace_string_marker(spaces, COMMENT_START, CopyingCursor::process_comments)?;
+        self.stats.comments += usize::from(replaced);
+        Ok(replaced)
+    }
+
+    #[inline]
+    fn try_replace_doc_marker(&mut self, spaces: usize) -> Result<bool, Error> {
+        // 5 to 7 sections to match: _doc_ ! ( [string] ) [;|,] CRLF|LF
+        let replaced =
+            self.try_replace_string_marker(spaces, DOC_START, CopyingCursor::process_docs)?;
+        self.stats.docs += usize::from(replaced);
+        Ok(replaced)
+    }
+
+    #[inline]
+    fn try_replace_inner_doc_marker(&mut self, spaces: usize) -> Result<bool, Error> {
+        // 5 to 7 sections to match: _inner_doc_ ! ( [string] ) [;|,] CRLF|LF
+        let replaced = self.try_replace_string_marker(
+            spaces,
+            INNER_DOC_START,
+            CopyingCursor::process_inner_docs,
+        )?;
+        self.stats.docs += usize::from(replaced);
+        Ok(replaced)
+    }
 
-        match self.try_match_prefixes(spaces, 2, COMMENT_START, false) {
-            Some((ident_start, value_start)) => {
+    // Skip a comma followed by another string, returning false if there isn't one. A trailing
+    // comma is consumed, but the `)` after it is not
+    fn try_skip_next_string(&mut self) -> Result<bool, Error> {
+        let rest = self.iter.as_slice();
+        let mut idx = 0;
+        while let Some(len) = whitespace_len(&rest[idx..]) {
+            idx += len;
+        }
+        if rest.get(idx) != Some(&b',') {
+            return Ok(false);
+        }
+
+        let mut after = idx + 1;
+        while let Some(len) = whitespace_len(&rest[after..]) {
+            after += len;
+        }
+        let trailing = rest.get(after) == Some(&b')');
+
+        // Consume through the comma
+        for _ in 0..=idx {
+            self.next();
+        }
+        if trailing {
+            return Ok(false);
+        }
+
+        match self.try_skip_string()? {
+            None => Ok(true),
+            Some(ch) => Err(Error::bad_source_code(format!(
+                "Expected string, but got: {}",
+                ch as char
+            ))),
+        }
+    }
+
+    // Replace a marker with an optional string parameter (or several separated by commas)
+    fn try_replace_string_marker<F>(
+        &mut self,
+        spaces: usize,
+        prefixes: &[&[u8]],
+        f: F,
+    ) -> Result<bool, Error>
+    where
+        F: FnOnce(usize, &mut Vec<u8>, &str, &str) -> Result<(), Error>,
+    {
+        match self.try_match_prefixes(spaces, 2, prefixes, false) {
+            Some(marks) => {
                 // Make sure it is empty or a string
                 let (matched, suffix) = match self.try_skip_string()? {
-                    // String
-                    None => (0, COMMENT_END),
+                    // String(s)
+                    None => {
+                        while self.try_skip_next_string()? {}
+                        (0, COMMENT_END)
+                    }
                     // Empty
                     Some(b')') => (1, COMMENT_END2),
                     Some(ch) => {
-                        return Err(Error::BadSourceCode(format!(
+                        return Err(Error::bad_source_code(format!(
                             "Expected ')' or string, but got: {}",
                             ch as char
                         )))
                     }
                 };
 
-                self.try_replace(
-                    spaces,
-                    matched,
-                    suffix,
-                    ident_start,
-                    value_start,
-                    CopyingCursor::process_comments,
-                )?;
+                self.try_replace(spaces, matched, suffix, marks, true, f)?;
                 Ok(true)
             }
             None => Ok(false),
         }
     }
 
-    fn try_replace_doc_block(&mut self, spaces: usize) -> Result<bool, Error> {
-        // 7 sections to match: # [ doc = <string> ] CRLF|LF
+    fn try_replace_raw_marker(
+        &mut self,
+        spaces: usize,
+        post_proc: PostProcess,
+    ) -> Result<bool, Error> {
+        // 6 or 7 sections to match: _raw_ ! ( <string> ) [;|,] CRLF|LF
 
-        match self.try_match_prefixes(spaces, 1, DOC_BLOCK_START, true) {
-            Some((ident_start, value_start)) => {
+        match self.try_match_prefixes(spaces, 2, RAW_START, false) {
+            Some(marks) => {
                 // Make sure it is a string
                 match self.try_skip_string()? {
                     // String
@@ -575,14 +961,17 @@ impl<'a> CopyingCursor<'a> {
                         self.try_replace(
                             spaces,
                             0,
-                            DOC_BLOCK_END,
-                            ident_start,
-                            value_start,
-                            CopyingCursor::process_doc_block,
+                            RAW_END,
+                            marks,
+                            true,
+                            |_, buffer, s, ending| {
+                                CopyingCursor::process_raw(buffer, s, ending, post_proc)
+                            },
                         )?;
+                        self.stats.raw += 1;
                         Ok(true)
                     }
-                    Some(ch) => Err(Error::BadSourceCode(format!(
+                    Some(ch) => Err(Error::bad_source_code(format!(
                         "Expected string, but got: {}",
                         ch as char
                     ))),
@@ -591,96 +980,405 @@ impl<'a> CopyingCursor<'a> {
             None => Ok(false),
         }
     }
-}
-
-pub(crate) fn replace_markers(s: &str, replace_doc_blocks: bool) -> Result<Cow<str>, Error> {
-    match CopyingCursor::new(s) {
-        Some(mut cursor) => {
-            let mut indent = 0;
-
-            loop {
-                match cursor.curr {
-                    // Possible raw string
-                    b'r' => {
-                        indent = 0;
-                        if !cursor.try_skip_raw_string() {
-                            continue;
-                        }
-                    }
-                    // Regular string
-                    b'\"' => {
-                        indent = 0;
-                        cursor.skip_string()
-                    }
-                    // Possible comment
-                    b'/' => {
-                        indent = 0;
-                        if !cursor.try_skip_comment() {
-                            continue;
-                        }
-                    }
-                    // Possible special ident (_comment!_ or _blank!_)
-                    b'_' => {
-                        if cursor.next().is_none() {
-                            break;
-                        }
-
-                        match cursor.curr {
-                            // Possible blank marker
-                            b'b' => {
-                                if !cursor.try_replace_blank_marker(indent)? {
-                                    indent = 0;
-                                    continue;
-                                }
-                            }
-                            // Possible comment marker
-                            b'c' => {
-                                if !cursor.try_replace_comment_marker(indent)? {
-                                    indent = 0;
-                                    continue;
-                                }
-                            }
-                            // Nothing we are interested in
-                            _ => {
-                                indent = 0;
-                                continue;
-                            }
-                        }
-
-                        indent = 0;
-                    }
-                    // Possible doc block
-                    b'#' if replace_doc_blocks => {
-                        if !cursor.try_replace_doc_block(indent)? {
-                            indent = 0;
-                            continue;
-                        }
-
-                        indent = 0;
-                    }
-                    // Count spaces in front of our three special replacements
-                    b' ' => {
-                        indent += 1;
-                    }
-                    // Anything else
-                    _ => {
-                        indent = 0;
-                    }
-                }
 
-                if cursor.next().is_none() {
-                    break;
-                }
+    // The current char must be `_`. Returns the handler of the custom marker it starts, if any
+    // (nothing is consumed)
+    fn custom_marker<'m>(&self, markers: &'m Markers) -> Option<&'m dyn MarkerHandler> {
+        let rest = self.iter.as_slice();
+        let handler = markers.find(rest)?;
+
+        // _name_ ! (
+        let mut rest = &rest[handler.name().len() + 1..];
+        for ch in [b'!', b'('] {
+            while let Some(len) = whitespace_len(rest) {
+                rest = &rest[len..];
             }
 
-            Ok(cursor.into_buffer())
+            match rest.split_first() {
+                Some((&first, remainder)) if first == ch => rest = remainder,
+                _ => return None,
+            }
         }
-        // Empty file
-        None => Ok(Cow::Borrowed(s)),
+
+        Some(handler)
     }
-}
 
-// *** Tests ***
+    // Unlike comments, the text is inserted verbatim (the handler is given the indentation)
+    fn process_custom(
+        handler: &dyn MarkerHandler,
+        spaces: usize,
+        buffer: &mut Vec<u8>,
+        s: &str,
+        ending: &str,
+    ) -> Result<(), Error> {
+        let arg = if s.trim().is_empty() {
+            None
+        } else {
+            let s: syn::LitStr = syn::parse_str(s)?;
+            Some(s.value())
+        };
+
+        let text = handler.replace(arg.as_deref(), spaces).map_err(|err| {
+            let name = handler.name();
+            Error::bad_source_code(format!("Unable to replace marker '_{name}_!': {err}"))
+        })?;
+
+        for line in text.lines() {
+            buffer.extend_from_slice(line.as_bytes());
+            buffer.extend_from_slice(ending.as_bytes());
+        }
+
+        Ok(())
+    }
+
+    fn replace_custom_marker(
+        &mut self,
+        spaces: usize,
+        handler: &dyn MarkerHandler,
+    ) -> Result<(), Error> {
+        // 5 to 7 sections to match: _name_ ! ( [string] ) [;|,] CRLF|LF
+        let ident = format!("{}_", handler.name());
+        let prefixes: [&[u8]; 3] = [ident.as_bytes(), b"!", b"("];
+
+        match self.try_match_prefixes(spaces, 1, &prefixes, false) {
+            Some(marks) => {
+                // Make sure it is empty or a string
+                let (matched, suffix) = match self.try_skip_string()? {
+                    // String
+                    None => (0, COMMENT_END),
+                    // Empty
+                    Some(b')') => (1, COMMENT_END2),
+                    Some(ch) => {
+                        return Err(Error::bad_source_code(format!(
+                            "Expected ')' or string, but got: {}",
+                            ch as char
+                        )))
+                    }
+                };
+
+                self.try_replace(
+                    spaces,
+                    matched,
+                    suffix,
+                    marks,
+                    true,
+                    |spaces, buffer, s, ending| {
+                        CopyingCursor::process_custom(handler, spaces, buffer, s, ending)
+                    },
+                )?;
+                self.stats.custom += 1;
+                Ok(())
+            }
+            // Panic safety: the lookahead already matched the prefixes
+            None => unreachable!("Custom marker '{ident}' did not match"),
+        }
+    }
+
+    // The current char must be `#`. Returns true if it starts a `#[rustfmt::skip]` attribute
+    // (nothing is consumed)
+    fn at_rustfmt_skip(&self) -> bool {
+        let mut rest = self.iter.as_slice();
+
+        for &sl in RUSTFMT_SKIP {
+            while let Some(len) = whitespace_len(rest) {
+                rest = &rest[len..];
+            }
+
+            match rest.strip_prefix(sl) {
+                Some(remainder) => rest = remainder,
+                None => return false,
+            }
+        }
+
+        true
+    }
+
+    // The current char must be the `#` of a `#[rustfmt::skip]` attribute. The attribute and the
+    // item it annotates are skipped by tracking bracket depth until a `;` or the closing `}` at
+    // the top level. An unbalanced closing bracket (such as the end of the enclosing block) also
+    // ends the item
+    fn skip_rustfmt_skip_item(&mut self) {
+        // Skip the attribute itself
+        while let Some(ch) = self.next() {
+            if ch == b']' {
+                break;
+            }
+        }
+
+        let mut depth = 0usize;
+        if self.next().is_none() {
+            return;
+        }
+
+        loop {
+            let idx = self.curr_idx;
+
+            match self.curr {
+                // Possible raw string
+                b'r' => {
+                    // If it wasn't one, the char consumed must be evaluated (unless at EOF)
+                    let skipped = self.try_skip_raw_string();
+                    if !skipped && self.curr_idx != idx {
+                        continue;
+                    }
+                }
+                // Regular string
+                b'\"' => self.skip_string(),
+                // Possible char literal
+                b'\'' => self.skip_char(),
+                // Possible comment
+                b'/' => {
+                    let skipped = self.try_skip_comment();
+                    if !skipped && self.curr_idx != idx {
+                        continue;
+                    }
+                }
+                b'(' | b'[' | b'{' => depth += 1,
+                b')' | b']' | b'}' => match depth {
+                    0 => break,
+                    1 if self.curr == b'}' => break,
+                    _ => depth -= 1,
+                },
+                b';' if depth == 0 => break,
+                _ => {}
+            }
+
+            if self.next().is_none() {
+                break;
+            }
+        }
+    }
+
+    fn try_replace_doc_block(&mut self, spaces: usize) -> Result<bool, Error> {
+        // 7 sections to match: # [ doc = <string> ] CRLF|LF
+
+        match self.try_match_prefixes(spaces, 1, DOC_BLOCK_START, true) {
+            Some(marks) => {
+                // Make sure it is a string
+                match self.try_skip_string()? {
+                    // String
+                    None => {
+                        self.try_replace(
+                            spaces,
+                            0,
+                            DOC_BLOCK_END,
+                            marks,
+                            false,
+                            CopyingCursor::process_doc_block,
+                        )?;
+                        self.stats.doc_blocks += 1;
+                        Ok(true)
+                    }
+                    Some(ch) => Err(Error::bad_source_code(format!(
+                        "Expected string, but got: {}",
+                        ch as char
+                    ))),
+                }
+            }
+            None => Ok(false),
+        }
+    }
+}
+
+#[inline]
+pub(crate) fn replace_markers(s: &str, post_proc: PostProcess) -> Result<Cow<'_, str>, Error> {
+    replace_markers_counting(
+        s,
+        post_proc,
+        &Markers::NONE,
+        &mut PostProcessStats::default(),
+    )
+}
+
+// The same as `replace_markers`, but custom markers are also replaced and each marker and doc
+// block replaced is added to the stats
+pub(crate) fn replace_markers_counting<'a>(
+    s: &'a str,
+    post_proc: PostProcess,
+    markers: &Markers,
+    stats: &mut PostProcessStats,
+) -> Result<Cow<'a, str>, Error> {
+    let replace_doc_blocks = post_proc.contains(PostProcess::DOC_BLOCKS);
+    let respect_skip = post_proc.contains(PostProcess::RESPECT_SKIP);
+    let replace_custom = post_proc.replace_markers() && !markers.is_empty();
+
+    match CopyingCursor::new(s) {
+        Some(mut cursor) => {
+            let mut indent = 0;
+
+            loop {
+                match cursor.curr {
+                    // Possible raw string
+                    b'r' if !cursor.at_eof() => {
+                        indent = 0;
+                        if !cursor.try_skip_raw_string() {
+                            continue;
+                        }
+                    }
+                    // Regular string
+                    b'\"' => {
+                        indent = 0;
+                        cursor.skip_string()
+                    }
+                    // Possible comment
+                    b'/' if !cursor.at_eof() => {
+                        indent = 0;
+                        if !cursor.try_skip_comment() {
+                            continue;
+                        }
+                    }
+                    // Custom marker
+                    b'_' if replace_custom && cursor.custom_marker(markers).is_some() => {
+                        // Panic safety: we just found it
+                        let handler = cursor.custom_marker(markers).unwrap();
+                        cursor.replace_custom_marker(indent, handler)?;
+                        indent = 0;
+                    }
+                    // Possible special ident (_comment!_, _blank!_, _doc!_, _inner_doc!_, or _raw!_)
+                    b'_' => {
+                        if cursor.next().is_none() {
+                            break;
+                        }
+
+                        match cursor.curr {
+                            // Possible blank marker
+                            b'b' if post_proc.contains(PostProcess::BLANKS) => {
+                                if !cursor.try_replace_blank_marker(indent)? {
+                                    indent = 0;
+                                    continue;
+                                }
+                            }
+                            // Possible comment marker
+                            b'c' if post_proc.contains(PostProcess::COMMENTS) => {
+                                if !cursor.try_replace_comment_marker(indent)? {
+                                    indent = 0;
+                                    continue;
+                                }
+                            }
+                            // Possible doc marker
+                            b'd' if post_proc.contains(PostProcess::DOCS) => {
+                                if !cursor.try_replace_doc_marker(indent)? {
+                                    indent = 0;
+                                    continue;
+                                }
+                            }
+                            // Possible inner doc marker
+                            b'i' if post_proc.contains(PostProcess::DOCS) => {
+                                if !cursor.try_replace_inner_doc_marker(indent)? {
+                                    indent = 0;
+                                    continue;
+                                }
+                            }
+                            // Possible raw marker
+                            b'r' if post_proc.contains(PostProcess::RAW) => {
+                                if !cursor.try_replace_raw_marker(indent, post_proc)? {
+                                    indent = 0;
+                                    continue;
+                                }
+                            }
+                            // Nothing we are interested in
+                            _ => {
+                                indent = 0;
+                                continue;
+                            }
+                        }
+
+                        indent = 0;
+                    }
+                    // Item annotated with `#[rustfmt::skip]` (left as is)
+                    b'#' if respect_skip && cursor.at_rustfmt_skip() => {
+                        cursor.skip_rustfmt_skip_item();
+                        indent = 0;
+                    }
+                    // Possible doc block
+                    b'#' if replace_doc_blocks && !cursor.at_eof() => {
+                        if !cursor.try_replace_doc_block(indent)? {
+                            indent = 0;
+                            continue;
+                        }
+
+                        indent = 0;
+                    }
+                    // Count spaces in front of our special replacements
+                    b' ' => {
+                        indent += 1;
+                    }
+                    // Anything else
+                    _ => {
+                        indent = 0;
+                    }
+                }
+
+                if cursor.next().is_none() {
+                    break;
+                }
+            }
+
+            *stats += cursor.stats;
+            Ok(cursor.into_buffer())
+        }
+        // Empty file
+        None => Ok(Cow::Borrowed(s)),
+    }
+}
+
+pub(crate) fn replace_doc_comments(s: &str) -> Result<Cow<'_, str>, Error> {
+    match CopyingCursor::new(s) {
+        Some(mut cursor) => {
+            // Only whitespace has been seen on the current line so far
+            let mut line_start = true;
+
+            loop {
+                match cursor.curr {
+                    // Possible raw string
+                    b'r' if !cursor.at_eof() => {
+                        line_start = false;
+                        if !cursor.try_skip_raw_string() {
+                            continue;
+                        }
+                    }
+                    // Regular string
+                    b'\"' => {
+                        line_start = false;
+                        cursor.skip_string()
+                    }
+                    // Possible char literal
+                    b'\'' => {
+                        line_start = false;
+                        cursor.skip_char()
+                    }
+                    // Possible comment (line comments consume the line ending)
+                    b'/' => {
+                        if !cursor.try_replace_doc_comment(line_start) {
+                            line_start = false;
+                            continue;
+                        }
+                        line_start = cursor.curr == LF;
+                    }
+                    LF => {
+                        line_start = true;
+                    }
+                    // Anything else (whitespace doesn't end the start of the line)
+                    ch => {
+                        if !cursor.is_any_whitespace(ch) {
+                            line_start = false;
+                        }
+                    }
+                }
+
+                if cursor.next().is_none() {
+                    break;
+                }
+            }
+
+            Ok(cursor.into_buffer())
+        }
+        // Empty file
+        None => Ok(Cow::Borrowed(s)),
+    }
+}
+
+// *** Tests ***
 
 #[cfg(test)]
 mod tests {
@@ -688,14 +1386,15 @@ mod tests {
 
     use pretty_assertions::assert_eq;
 
-    use crate::replace::replace_markers;
-    use crate::Error;
+    use crate::marker::Markers;
+    use crate::replace::{replace_doc_comments, replace_markers, replace_markers_counting};
+    use crate::{Error, ErrorKind, MarkerFn, PostProcess, PostProcessStats};
 
     #[test]
     fn blank() {
         let source = "";
 
-        let actual = replace_markers(source, false).unwrap();
+        let actual = replace_markers(source, PostProcess::ReplaceMarkers).unwrap();
         let expected = source;
 
         assert_eq!(expected, actual);
@@ -716,13 +1415,27 @@ fn main() {
 _blank!_;
 "####;
 
-        let actual = replace_markers(source, false).unwrap();
+        let actual = replace_markers(source, PostProcess::ReplaceMarkers).unwrap();
         let expected = source;
 
         assert_eq!(expected, actual);
         assert!(matches!(actual, Cow::Borrowed(_)));
     }
 
+    // A partial raw string, comment, or doc block at the very end is left as is
+    #[test]
+    fn trailing_partial_match() {
+        for source in ["let x = bar", "let x = 1 /", "let x = r#"] {
+            let actual = replace_markers(source, PostProcess::ReplaceMarkers).unwrap();
+            assert!(matches!(actual, Cow::Borrowed(s) if s == source));
+        }
+
+        for source in ["let x = 1; #", "#[#", "_br"] {
+            let actual = replace_markers(source, PostProcess::ReplaceMarkersAndDocBlocks).unwrap();
+            assert!(matches!(actual, Cow::Borrowed(s) if s == source));
+        }
+    }
+
     #[test]
     fn replace_comments() {
         let source = r####"// _comment!_("comment");
@@ -746,7 +1459,7 @@ comments"# )
 _blank!_;
 "####;
 
-        let actual = replace_markers(source, false).unwrap();
+        let actual = replace_markers(source, PostProcess::ReplaceMarkers).unwrap();
         let expected = r####"// _comment!_("comment");
 
 /* /* nested comment */ */
@@ -771,6 +1484,37 @@ _blank!_;
         assert_eq!(expected, actual);
     }
 
+    #[test]
+    fn replace_multiple_literals() {
+        let source = r#"fn main() {
+    _comment_!("line one", "", r"line two
+line three");
+    _doc_!("doc one" , "doc two",);
+    _inner_doc_!("inner one", "inner two");
+    f(_comment_!("a", "b"), x);
+}
+"#;
+
+        let actual = replace_markers(source, PostProcess::ReplaceMarkers).unwrap();
+        let expected = r#"fn main() {
+    // line one
+    //
+    // line two
+    // line three
+    /// doc one
+    /// doc two
+    //! inner one
+    //! inner two
+    f(
+    // a
+    // b
+    x);
+}
+"#;
+
+        assert_eq!(expected, actual);
+    }
+
     #[test]
     fn replace_blanks() {
         let source = r####"// _blank!_(5);
@@ -793,7 +1537,7 @@ fn main() {
 _blank!_;
 "####;
 
-        let actual = replace_markers(source, false).unwrap();
+        let actual = replace_markers(source, PostProcess::ReplaceMarkers).unwrap();
         let expected = r####"// _blank!_(5);
 
 /* /* nested comment */ */
@@ -816,6 +1560,140 @@ _blank!_;
         assert_eq!(expected, actual);
     }
 
+    #[test]
+    fn replace_docs() {
+        let source = r####"// _doc!_("skip this");
+_doc_!("A point\n\nWith two coordinates");
+struct Point {
+    _doc_!("The x coordinate");
+    x: i32,
+    _doc_!();
+    _doc_!(r"");
+    _done: bool,
+}
+"####;
+
+        // Doc markers are replaced even when doc blocks are not
+        let actual = replace_markers(source, PostProcess::ReplaceMarkers).unwrap();
+        let expected = r####"// _doc!_("skip this");
+/// A point
+///
+/// With two coordinates
+struct Point {
+    /// The x coordinate
+    x: i32,
+    ///
+    ///
+    _done: bool,
+}
+"####;
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn replace_inner_docs() {
+        let source = r####"_inner_doc_!("Generated shapes\n\nDo not edit");
+_inner_doc_!();
+// _inner_doc!_("skip this");
+mod shapes {
+    _inner_doc_!(r"Shape types");
+    let _inner = 1;
+}
+"####;
+
+        let actual = replace_markers(source, PostProcess::ReplaceMarkers).unwrap();
+        let expected = r####"//! Generated shapes
+//!
+//! Do not edit
+//!
+// _inner_doc!_("skip this");
+mod shapes {
+    //! Shape types
+    let _inner = 1;
+}
+"####;
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn replace_raw() {
+        let source = r####"_raw_!("#!/usr/bin/env run-cargo-script");
+// _raw!_("skip this");
+_raw!_("skip this");
+fn main() {
+    let _result = "hello";
+    _raw_!(r#"    let x = 1;
+    let y = "two";"#);
+    _raw_!("");
+}
+
+  _raw_
+!(
+"#[cfg(hack)]"
+);
+"####;
+
+        let actual = replace_markers(source, PostProcess::ReplaceMarkers).unwrap();
+        let expected = r####"#!/usr/bin/env run-cargo-script
+// _raw!_("skip this");
+_raw!_("skip this");
+fn main() {
+    let _result = "hello";
+    let x = 1;
+    let y = "two";
+}
+
+#[cfg(hack)]
+"####;
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn replace_markers_idempotent() {
+        let source = r####"#![doc = " Crate docs"]
+_raw_!("#!/usr/bin/env run-cargo-script");
+#[doc = " This is main"]
+#[doc = "_blank_!();"]
+fn main() {
+    _comment_!("_comment_!(\"nested\")", "_blank_!();");
+    _doc_!("_raw_!(\"raw\")");
+    _inner_doc_!("#[doc = \"block\"]");
+    let _s = "_blank_!();";
+    _blank_!(2);
+    _raw_!(r#"    let x = "_blank_!();";"#);
+}
+"####;
+
+        for post_proc in [
+            PostProcess::ReplaceMarkers,
+            PostProcess::ReplaceMarkersAndDocBlocks,
+        ] {
+            let once = replace_markers(source, post_proc).unwrap();
+            let twice = replace_markers(&once, post_proc).unwrap();
+            assert!(matches!(twice, Cow::Borrowed(_)));
+            assert_eq!(once, twice);
+        }
+
+        // Raw text that would be replaced the next time isn't allowed
+        let source = "_raw_!(\"#[doc = \\\" test\\\"]\");\nfn main() {}\n";
+        assert!(replace_markers(source, PostProcess::ReplaceMarkers).is_ok());
+        match replace_markers(source, PostProcess::ReplaceMarkersAndDocBlocks)
+            .map_err(Error::into_kind)
+        {
+            Err(ErrorKind::BadSourceCode(_)) => {}
+            _ => panic!("Expected bad source code"),
+        }
+        match replace_markers("_raw_!(\"_blank_!();\");", PostProcess::ReplaceMarkers)
+            .map_err(Error::into_kind)
+        {
+            Err(ErrorKind::BadSourceCode(_)) => {}
+            _ => panic!("Expected bad source code"),
+        }
+    }
+
     #[test]
     fn replace_doc_blocks() {
         let source = r####"// _blank!_(5);
@@ -843,7 +1721,7 @@ fn test() {
 _blank!_;
 "####;
 
-        let actual = replace_markers(source, true).unwrap();
+        let actual = replace_markers(source, PostProcess::ReplaceMarkersAndDocBlocks).unwrap();
         let expected = r####"// _blank!_(5);
 
 /* not a nested comment */
@@ -869,44 +1747,417 @@ _blank!_;
         assert_eq!(expected, actual);
     }
 
+    #[test]
+    fn replace_doc_comments_with_blocks() {
+        let source = r####"//! A "module"
+//!
+// Regular comment
+//// Also a regular comment
+/* /// not a doc comment */
+
+/// This is a main function
+///with no space
+fn main() {
+    let quote = '"';
+    let s = "/// not a doc comment";
+    println!(r#"/// hello raw world!"#); /// not attached to anything
+    /// A doc comment
+    let x = 'a';
+}
+///"####;
+
+        let actual = replace_doc_comments(source).unwrap();
+        let expected = r####"#![doc = " A \"module\""]
+#![doc = ""]
+// Regular comment
+//// Also a regular comment
+/* /// not a doc comment */
+
+#[doc = " This is a main function"]
+#[doc = "with no space"]
+fn main() {
+    let quote = '"';
+    let s = "/// not a doc comment";
+    println!(r#"/// hello raw world!"#); /// not attached to anything
+    #[doc = " A doc comment"]
+    let x = 'a';
+}
+#[doc = ""]"####;
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn replace_doc_comments_crlf() {
+        let actual = replace_doc_comments("/// test\r\nfn main() {}\r\n").unwrap();
+        assert_eq!("#[doc = \" test\"]\r\nfn main() {}\r\n", actual);
+
+        let actual = replace_doc_comments("\u{2028}/// test\n").unwrap();
+        assert_eq!("\u{2028}#[doc = \" test\"]\n", actual);
+
+        for source in ["fn main() {}\n", "/", "let x = bar", "r#"] {
+            assert!(matches!(
+                replace_doc_comments(source).unwrap(),
+                Cow::Borrowed(_)
+            ));
+        }
+    }
+
+    #[test]
+    fn replace_unicode_whitespace() {
+        let source = "fn main() {\n    _comment_\u{2028}!\u{200e}(\u{85}\"test\"\u{2029});\n    \
+            _blank_!\u{200f}(\u{2028}1\u{2028})\u{85};\n    let s = \"\u{2028}\";\n}\n";
+
+        let actual = replace_markers(source, PostProcess::ReplaceMarkers).unwrap();
+        let expected = "fn main() {\n    // test\n\n    let s = \"\u{2028}\";\n}\n";
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn replace_expression_markers() {
+        let source = r####"fn main() {
+    let x = match y {
+        1 => {
+            a();
+            _blank_!()
+        }
+        _ => {
+            _comment_!("nothing to do")
+        }
+    };
+    let v = vec![
+        1,
+        _comment_!("the rest"),
+        _blank_!(2) ,
+        2,
+    ];
+}
+"####;
+
+        let actual = replace_markers(source, PostProcess::ReplaceMarkers).unwrap();
+        let expected = r####"fn main() {
+    let x = match y {
+        1 => {
+            a();
+
+        }
+        _ => {
+            // nothing to do
+        }
+    };
+    let v = vec![
+        1,
+        // the rest
+
+
+        2,
+    ];
+}
+"####;
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn replace_markers_sharing_lines() {
+        let source = r####"fn main() {
+    let x = 1; _blank_!();
+    let y = 2;  _comment_!("after y"); let z = 3;
+    _comment_!("before w"); let w = 4;
+    if x > 0 { _blank_!(2) }
+}
+"####;
+
+        let actual = replace_markers(source, PostProcess::ReplaceMarkers).unwrap();
+        let expected = r####"fn main() {
+    let x = 1;
+
+    let y = 2;
+    // after y
+    let z = 3;
+    // before w
+    let w = 4;
+    if x > 0 {
+
+
+    }
+}
+"####;
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn replace_markers_respecting_skip() {
+        let source = r#"#[rustfmt::skip]
+fn skipped() {
+    let s = "}";
+    _comment_!("Not replaced");
+    if true { _blank_!(); }
+}
+
+#[rustfmt::skip]
+const ARR: [u8; 2] = [1, 2];
+_comment_!("Replaced");
+
+#[ rustfmt :: skip ]
+mod m { _blank_!(); }
+
+fn main() {
+    #[rustfmt::skip]
+    let x = { _blank_!(); 1 };
+    _blank_!();
+    #[doc = " Replaced"]
+    fn inner() {}
+}
+"#;
+
+        let actual = replace_markers(
+            source,
+            PostProcess::ReplaceMarkersAndDocBlocksRespectingSkip,
+        )
+        .unwrap();
+        let expected = r#"#[rustfmt::skip]
+fn skipped() {
+    let s = "}";
+    _comment_!("Not replaced");
+    if true { _blank_!(); }
+}
+
+#[rustfmt::skip]
+const ARR: [u8; 2] = [1, 2];
+// Replaced
+
+#[ rustfmt :: skip ]
+mod m { _blank_!(); }
+
+fn main() {
+    #[rustfmt::skip]
+    let x = { _blank_!(); 1 };
+
+    /// Replaced
+    fn inner() {}
+}
+"#;
+
+        assert_eq!(expected, actual);
+
+        // The attribute is ignored unless requested
+        let actual = replace_markers(source, PostProcess::ReplaceMarkers).unwrap();
+        assert!(actual.contains("// Not replaced"));
+    }
+
+    #[test]
+    fn replace_selected_markers() {
+        let source = r#"_blank_!();
+_comment_!("comment");
+_doc_!("doc");
+#[doc = " doc block"]
+fn main() {}
+"#;
+
+        let actual = replace_markers(source, PostProcess::BLANKS | PostProcess::DOCS).unwrap();
+        let expected = r#"
+_comment_!("comment");
+/// doc
+#[doc = " doc block"]
+fn main() {}
+"#;
+        assert_eq!(expected, actual);
+
+        let actual = replace_markers(source, PostProcess::DOC_BLOCKS).unwrap();
+        let expected = r#"_blank_!();
+_comment_!("comment");
+_doc_!("doc");
+/// doc block
+fn main() {}
+"#;
+        assert_eq!(expected, actual);
+    }
+
     #[test]
     fn replace_crlf() {
         let source = "_blank_!(2);\r\n";
-        let actual = replace_markers(source, false).unwrap();
+        let actual = replace_markers(source, PostProcess::ReplaceMarkers).unwrap();
 
         let expected = "\r\n\r\n";
         assert_eq!(expected, actual);
     }
 
+    #[test]
+    fn replace_non_ascii() {
+        // Non-ASCII text in the source and the markers is copied as is
+        let source = "fn crab() {\n    _comment_!(\"Ferris 🦀\");\n    let s = \"héllo\";\n}\n";
+        let actual = replace_markers(source, PostProcess::ReplaceMarkers).unwrap();
+
+        let expected = "fn crab() {\n    // Ferris 🦀\n    let s = \"héllo\";\n}\n";
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn replace_mixed_line_endings() {
+        // Every inserted line uses the line ending most lines of the source use
+        let source = "fn main() {\r\n    _comment_!(\"a\\nb\");\n    _blank_!();\r\n}\r\n";
+        let actual = replace_markers(source, PostProcess::ReplaceMarkers).unwrap();
+
+        let expected = "fn main() {\r\n    // a\r\n    // b\r\n\r\n}\r\n";
+        assert_eq!(expected, actual);
+
+        let source = "_blank_!(2);\r\n_blank_!();\nfn main() {}\n";
+        let actual = replace_markers(source, PostProcess::ReplaceMarkers).unwrap();
+        assert_eq!("\n\n\nfn main() {}\n", actual);
+    }
+
     #[test]
     fn marker_end_after_prefix() {
         assert!(matches!(
-            replace_markers("_blank_!(", false),
-            Err(Error::BadSourceCode(_))
+            replace_markers("_blank_!(", PostProcess::ReplaceMarkers).map_err(Error::into_kind),
+            Err(ErrorKind::BadSourceCode(_))
         ));
     }
 
     #[test]
     fn marker_param_not_string() {
         assert!(matches!(
-            replace_markers("_comment_!(blah);\n", false),
-            Err(Error::BadSourceCode(_))
+            replace_markers("_comment_!(blah);\n", PostProcess::ReplaceMarkers)
+                .map_err(Error::into_kind),
+            Err(ErrorKind::BadSourceCode(_))
+        ));
+    }
+
+    #[test]
+    fn marker_second_param_not_string() {
+        assert!(matches!(
+            replace_markers("_comment_!(\"a\", 1);\n", PostProcess::ReplaceMarkers)
+                .map_err(Error::into_kind),
+            Err(ErrorKind::BadSourceCode(_))
+        ));
+    }
+
+    #[test]
+    fn raw_marker_param_not_string() {
+        assert!(matches!(
+            replace_markers("_raw_!();\n", PostProcess::ReplaceMarkers).map_err(Error::into_kind),
+            Err(ErrorKind::BadSourceCode(_))
         ));
     }
 
     #[test]
     fn marker_bad_suffix() {
         assert!(matches!(
-            replace_markers("_comment_!(\"blah\"];\n", false),
-            Err(Error::BadSourceCode(_))
+            replace_markers("_comment_!(\"blah\"];\n", PostProcess::ReplaceMarkers)
+                .map_err(Error::into_kind),
+            Err(ErrorKind::BadSourceCode(_))
         ));
     }
 
     #[test]
     fn doc_block_string_not_closed() {
         assert!(matches!(
-            replace_markers("#[doc = \"test]\n", true),
-            Err(Error::BadSourceCode(_))
+            replace_markers("#[doc = \"test]\n", PostProcess::ReplaceMarkersAndDocBlocks)
+                .map_err(Error::into_kind),
+            Err(ErrorKind::BadSourceCode(_))
+        ));
+    }
+
+    #[test]
+    fn stats() {
+        let source = r####"// _blank_!();
+#[doc = " Main"]
+fn main() {
+    _comment_!("comment 1", "comment 2");
+    _blank_!(3);
+    println!("_comment_!(\"in a string\");");
+    _doc_!("doc");
+    _inner_doc_!("inner doc");
+    _raw_!("raw");
+}
+"####;
+
+        let mut stats = PostProcessStats::default();
+        replace_markers_counting(
+            source,
+            PostProcess::ReplaceMarkers,
+            &Markers::NONE,
+            &mut stats,
+        )
+        .unwrap();
+        let expected = PostProcessStats {
+            blanks: 1,
+            comments: 1,
+            docs: 2,
+            raw: 1,
+            doc_blocks: 0,
+            custom: 0,
+        };
+        assert_eq!(expected, stats);
+
+        // Counts accumulate
+        replace_markers_counting(
+            source,
+            PostProcess::ReplaceMarkersAndDocBlocks,
+            &Markers::NONE,
+            &mut stats,
+        )
+        .unwrap();
+        let expected = PostProcessStats {
+            blanks: 2,
+            comments: 2,
+            docs: 4,
+            raw: 2,
+            doc_blocks: 1,
+            custom: 0,
+        };
+        assert_eq!(expected, stats);
+    }
+
+    #[test]
+    fn custom_markers() {
+        let mut markers = Markers::default();
+        markers.push(MarkerFn::new("todo", |arg, indent| match arg {
+            Some(arg) => Ok(format!("{:indent$}// TODO: {arg}", "")),
+            None => Err("A description is required".to_string()),
+        }));
+        markers.push(MarkerFn::new("nothing", |_, _| Ok(String::new())));
+        // Built-in markers can't be replaced
+        markers.push(MarkerFn::new("blank", |_, _| Ok("blank".to_string())));
+
+        let source = r####"fn main() {
+    _todo_!("Write main");
+    println!("_todo_!(\"in a string\")"); _nothing_!();
+    _blank_!();
+    _todo_ = 1;
+}
+"####;
+        let expected = r####"fn main() {
+    // TODO: Write main
+    println!("_todo_!(\"in a string\")");
+
+    _todo_ = 1;
+}
+"####;
+
+        let mut stats = PostProcessStats::default();
+        let actual =
+            replace_markers_counting(source, PostProcess::ReplaceMarkers, &markers, &mut stats)
+                .unwrap();
+        assert_eq!(expected, actual);
+        assert_eq!((2, 1), (stats.custom, stats.blanks));
+
+        // Only replaced along with the other markers
+        let actual = replace_markers_counting(source, PostProcess::None, &markers, &mut stats);
+        assert!(matches!(actual.unwrap(), Cow::Borrowed(_)));
+
+        assert!(matches!(
+            replace_markers_counting(
+                "_todo_!();\n",
+                PostProcess::ReplaceMarkers,
+                &markers,
+                &mut stats
+            )
+            .map_err(Error::into_kind),
+            Err(ErrorKind::BadSourceCode(_))
         ));
     }
 }
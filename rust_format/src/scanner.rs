@@ -0,0 +1,162 @@
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum State {
+    Code,
+    Str,
+    RawStr(usize),
+    BlockComment(usize),
+}
+
+// Tracks just enough of the Rust syntax to know if a line ends inside a literal, comment, or
+// delimited group (where splitting or changing the code would change its meaning or how it is post
+// processed)
+#[derive(Debug)]
+pub(crate) struct Scanner {
+    state: State,
+    depth: usize,
+}
+
+impl Scanner {
+    #[inline]
+    pub(crate) fn new() -> Self {
+        Self {
+            state: State::Code,
+            depth: 0,
+        }
+    }
+
+    // Returns true if the code can be split at the end of the last line scanned
+    #[cfg(not(target_arch = "wasm32"))]
+    #[inline]
+    pub(crate) fn at_top_level(&self) -> bool {
+        self.in_code() && self.depth == 0
+    }
+
+    // Returns true if the last line scanned didn't end inside a literal or block comment
+    #[inline]
+    pub(crate) fn in_code(&self) -> bool {
+        self.state == State::Code
+    }
+
+    pub(crate) fn scan_line(&mut self, line: &str) {
+        let bytes = line.as_bytes();
+        let mut idx = 0;
+
+        while idx < bytes.len() {
+            let ch = bytes[idx];
+            let next = bytes.get(idx + 1).copied();
+
+            match self.state {
+                State::Code => match ch {
+                    // The rest of the line is a comment
+                    b'/' if next == Some(b'/') => return,
+                    b'/' if next == Some(b'*') => {
+                        self.state = State::BlockComment(1);
+                        idx += 1;
+                    }
+                    b'"' => self.state = State::Str,
+                    b'\'' => idx = skip_char_literal(bytes, idx),
+                    b'{' | b'[' | b'(' => self.depth += 1,
+                    b'}' | b']' | b')' => self.depth = self.depth.saturating_sub(1),
+                    ch if ch == b'_' || ch.is_ascii_alphanumeric() => {
+                        let start = idx;
+                        while idx + 1 < bytes.len()
+                            && (bytes[idx + 1] == b'_' || bytes[idx + 1].is_ascii_alphanumeric())
+                        {
+                            idx += 1;
+                        }
+                        // Raw strings: r"..", r#".."#, br"..", cr".."
+                        if matches!(&line[start..=idx], "r" | "br" | "cr") {
+                            let hashes =
+                                bytes[idx + 1..].iter().take_while(|&&b| b == b'#').count();
+                            if bytes.get(idx + 1 + hashes) == Some(&b'"') {
+                                self.state = State::RawStr(hashes);
+                                idx += 1 + hashes;
+                            }
+                        }
+                    }
+                    _ => {}
+                },
+                State::Str => match ch {
+                    b'\\' => idx += 1,
+                    b'"' => self.state = State::Code,
+                    _ => {}
+                },
+                State::RawStr(hashes) => {
+                    let end = idx + 1 + hashes;
+                    if ch == b'"'
+                        && end <= bytes.len()
+                        && bytes[idx + 1..end].iter().all(|&b| b == b'#')
+                    {
+                        self.state = State::Code;
+                        idx = end - 1;
+                    }
+                }
+                State::BlockComment(depth) => match (ch, next) {
+                    (b'/', Some(b'*')) => {
+                        self.state = State::BlockComment(depth + 1);
+                        idx += 1;
+                    }
+                    (b'*', Some(b'/')) => {
+                        self.state = if depth == 1 {
+                            State::Code
+                        } else {
+                            State::BlockComment(depth - 1)
+                        };
+                        idx += 1;
+                    }
+                    _ => {}
+                },
+            }
+
+            idx += 1;
+        }
+    }
+}
+
+// Skip a char literal (returning the index of its closing quote), or nothing if it is a lifetime
+fn skip_char_literal(bytes: &[u8], idx: usize) -> usize {
+    match bytes.get(idx + 1) {
+        Some(b'\\') => bytes[idx + 2..]
+            .iter()
+            .position(|&b| b == b'\'')
+            .map_or(idx, |pos| idx + 2 + pos),
+        Some(_) => {
+            // A multi-byte char is still a single char
+            let len = std::str::from_utf8(&bytes[idx + 1..])
+                .ok()
+                .and_then(|s| s.chars().next())
+                .map_or(1, char::len_utf8);
+            match bytes.get(idx + 1 + len) {
+                Some(b'\'') => idx + 1 + len,
+                _ => idx,
+            }
+        }
+        None => idx,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::scanner::Scanner;
+
+    #[test]
+    fn scanner() {
+        let top_level = |source: &str| {
+            let mut scanner = Scanner::new();
+            for line in source.split_inclusive('\n') {
+                scanner.scan_line(line);
+            }
+            scanner.at_top_level()
+        };
+
+        assert!(top_level("fn main() {\n    let x = 'a';\n}\n"));
+        assert!(top_level("fn f<'a>(s: &'a str) -> char { '\\'' }\n"));
+        assert!(top_level(
+            "// {\nconst S: &str = r#\"\"\"#; /* { /* } */ } */\n"
+        ));
+        assert!(!top_level("fn main() {\n\n"));
+        assert!(!top_level("const S: &str = \"\n\n"));
+        assert!(!top_level("const S: &str = r#\"\"\n\n"));
+        assert!(!top_level("/* /* */\n\n"));
+    }
+}
@@ -0,0 +1,170 @@
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::{Check, Error, FormatOutput, Formatter};
+
+// *** Shared Formatter ***
+
+/// A cheaply cloneable handle to a formatter that can be shared between threads (such as
+/// `rayon` or `tokio` workers). Every clone uses the same formatter instance, so the formatter
+/// (and any state it holds, such as the results memoized by `Cached`) is only built once instead
+/// of once per file
+///
+/// ```
+/// use rust_format::{Formatter, RustFmt, SharedFormatter};
+///
+/// let fmt = SharedFormatter::new(RustFmt::default());
+///
+/// let handles: Vec<_> = (0..2)
+///     .map(|_| {
+///         let fmt = fmt.clone();
+///         std::thread::spawn(move || fmt.format_str("fn main(){}").unwrap())
+///     })
+///     .collect();
+///
+/// for handle in handles {
+///     assert_eq!("fn main() {}\n", handle.join().unwrap());
+/// }
+/// ```
+#[derive(Debug, Default)]
+pub struct SharedFormatter<F> {
+    fmt: Arc<F>,
+}
+
+impl<F: Formatter> SharedFormatter<F> {
+    /// Creates a new shared handle to the given formatter
+    #[inline]
+    pub fn new(fmt: F) -> Self {
+        Self { fmt: Arc::new(fmt) }
+    }
+
+    /// Returns the shared formatter
+    #[inline]
+    pub fn inner(&self) -> &F {
+        &self.fmt
+    }
+}
+
+// Derived `Clone` would require `F: Clone`
+impl<F> Clone for SharedFormatter<F> {
+    #[inline]
+    fn clone(&self) -> Self {
+        Self {
+            fmt: Arc::clone(&self.fmt),
+        }
+    }
+}
+
+impl<F: Formatter> From<F> for SharedFormatter<F> {
+    #[inline]
+    fn from(fmt: F) -> Self {
+        Self::new(fmt)
+    }
+}
+
+impl<F: Formatter> From<Arc<F>> for SharedFormatter<F> {
+    #[inline]
+    fn from(fmt: Arc<F>) -> Self {
+        Self { fmt }
+    }
+}
+
+impl<F: Formatter> Formatter for SharedFormatter<F> {
+    #[inline]
+    fn format_str(&self, source: impl AsRef<str>) -> Result<String, Error> {
+        self.fmt.format_str(source)
+    }
+
+    #[inline]
+    fn format_str_output(&self, source: impl AsRef<str>) -> Result<FormatOutput, Error> {
+        self.fmt.format_str_output(source)
+    }
+
+    #[inline]
+    fn format_file(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        self.fmt.format_file(path)
+    }
+
+    #[inline]
+    fn format_to_writer(
+        &self,
+        source: impl AsRef<str>,
+        writer: &mut impl Write,
+    ) -> Result<(), Error> {
+        self.fmt.format_to_writer(source, writer)
+    }
+
+    #[cfg(feature = "token_stream")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "token_stream")))]
+    #[inline]
+    fn format_tokens(&self, tokens: proc_macro2::TokenStream) -> Result<String, Error> {
+        self.fmt.format_tokens(tokens)
+    }
+
+    #[cfg(feature = "token_stream")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "token_stream")))]
+    #[inline]
+    fn format_tokens_ref(&self, tokens: &proc_macro2::TokenStream) -> Result<String, Error> {
+        self.fmt.format_tokens_ref(tokens)
+    }
+
+    #[cfg(feature = "pretty_please")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "pretty_please")))]
+    #[inline]
+    fn format_syn_file(&self, file: &syn::File) -> Result<String, Error> {
+        self.fmt.format_syn_file(file)
+    }
+
+    #[inline]
+    fn check_str(&self, source: impl AsRef<str>) -> Result<Check, Error> {
+        self.fmt.check_str(source)
+    }
+
+    #[inline]
+    fn check_file(&self, path: impl AsRef<Path>) -> Result<Check, Error> {
+        self.fmt.check_file(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::{CommandFormatter, Formatter, RustFmt, SharedFormatter, RUST_FMT};
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn thread_safe() {
+        assert_send_sync::<RustFmt>();
+        assert_send_sync::<CommandFormatter>();
+        #[cfg(feature = "pretty_please")]
+        assert_send_sync::<crate::PrettyPlease>();
+        assert_send_sync::<SharedFormatter<RustFmt>>();
+    }
+
+    #[test]
+    fn shared_between_threads() {
+        let fmt = SharedFormatter::new(CommandFormatter::new(RUST_FMT));
+        let clone = fmt.clone();
+        // Clones share the same instance
+        assert!(std::ptr::eq(fmt.inner(), clone.inner()));
+
+        let results: Vec<_> = thread::scope(|scope| {
+            let handles: Vec<_> = (0..4)
+                .map(|idx| {
+                    let fmt = fmt.clone();
+                    scope.spawn(move || fmt.format_str(format!("fn f{idx}(){{}}")))
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+
+        for (idx, result) in results.into_iter().enumerate() {
+            assert_eq!(format!("fn f{idx}() {{}}\n"), result.unwrap());
+        }
+    }
+}
@@ -0,0 +1,191 @@
+use std::ops::Range;
+
+// *** Source Map ***
+
+/// Maps each line of source code given to a formatter to the range of lines it became in the
+/// formatted (and post processed) output. Line indexes start at 0
+///
+/// The mapping is found by diffing the non-whitespace chars of the input and output, so it stays
+/// accurate when lines are joined or split (as long as the formatter mostly moves code around
+/// rather than rewriting it). Input lines with nothing left in the output (such as blank lines or
+/// replaced markers) map to `None`
+///
+/// ```
+/// use rust_format::SourceMap;
+///
+/// let input = "fn main() {\nlet x = 1; let y = 2;\n}\n";
+/// let output = "fn main() {\n    let x = 1;\n    let y = 2;\n}\n";
+///
+/// let map = SourceMap::new(input, output);
+/// assert_eq!(Some(1..3), map.output_lines(1));
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SourceMap {
+    lines: Vec<Option<Range<usize>>>,
+}
+
+impl SourceMap {
+    /// Creates a new source map from the input given to a formatter and the output it returned
+    pub fn new(input: &str, output: &str) -> Self {
+        let input_chars = code_chars(input);
+        let output_chars = code_chars(output);
+
+        let mut lines = vec![None; input.lines().count()];
+
+        for (input_idx, output_idx) in matches(&input_chars, &output_chars, same_char) {
+            let input_line = input_chars[input_idx].1;
+            let output_line = output_chars[output_idx].1;
+
+            let range: &mut Option<Range<usize>> = &mut lines[input_line];
+            match range {
+                Some(range) => {
+                    range.start = range.start.min(output_line);
+                    range.end = range.end.max(output_line + 1);
+                }
+                None => *range = Some(output_line..output_line + 1),
+            }
+        }
+
+        Self { lines }
+    }
+
+    /// Returns the range of output lines the given input line became (if any)
+    #[inline]
+    pub fn output_lines(&self, input_line: usize) -> Option<Range<usize>> {
+        self.lines.get(input_line).cloned().flatten()
+    }
+
+    /// Returns the range of output lines for each input line (indexed by input line)
+    #[inline]
+    pub fn lines(&self) -> &[Option<Range<usize>>] {
+        &self.lines
+    }
+}
+
+// Every non-whitespace char along with the line it is on
+fn code_chars(s: &str) -> Vec<(char, usize)> {
+    s.lines()
+        .enumerate()
+        .flat_map(|(line, text)| text.chars().map(move |ch| (ch, line)))
+        .filter(|(ch, _)| !ch.is_whitespace())
+        .collect()
+}
+
+#[inline]
+fn same_char(a: &(char, usize), b: &(char, usize)) -> bool {
+    a.0 == b.0
+}
+
+// *** Diff ***
+
+// Returns the index pairs of the longest common subsequence (Myers' diff algorithm). This is fast
+// when the sequences are mostly the same, which is the case for formatted code
+pub(crate) fn matches<T>(a: &[T], b: &[T], eq: impl Fn(&T, &T) -> bool) -> Vec<(usize, usize)> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = n + m;
+    let offset = max + 1;
+    let mut v = vec![0isize; (2 * max + 3) as usize];
+    // The furthest reaching paths before each step (only the diagonals that step can use)
+    let mut trace = Vec::new();
+
+    'outer: for d in 0..=max {
+        trace.push(v[(offset - d - 1) as usize..=(offset + d + 1) as usize].to_vec());
+
+        for k in (-d..=d).step_by(2) {
+            let idx = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && v[idx - 1] < v[idx + 1]) {
+                v[idx + 1]
+            } else {
+                v[idx - 1] + 1
+            };
+            let mut y = x - k;
+
+            while x < n && y < m && eq(&a[x as usize], &b[y as usize]) {
+                x += 1;
+                y += 1;
+            }
+            v[idx] = x;
+
+            if x >= n && y >= m {
+                break 'outer;
+            }
+        }
+    }
+
+    // Walk backwards through each step collecting the diagonals (matches)
+    let mut pairs = Vec::new();
+    let (mut x, mut y) = (n, m);
+
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let get = |k: isize| v[(k + d + 1) as usize];
+        let k = x - y;
+
+        let prev_k = if k == -d || (k != d && get(k - 1) < get(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = get(prev_k);
+        let prev_y = prev_x - prev_k;
+
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+            pairs.push((x as usize, y as usize));
+        }
+
+        if d > 0 {
+            x = prev_x;
+            y = prev_y;
+        }
+    }
+
+    pairs.reverse();
+    pairs
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::source_map::{code_chars, matches, same_char};
+    use crate::SourceMap;
+
+    #[test]
+    fn diff() {
+        let a = code_chars("abcabba");
+        let b = code_chars("cbabac");
+
+        let pairs = matches(&a, &b, same_char);
+        assert_eq!(4, pairs.len());
+        for (a_idx, b_idx) in pairs {
+            assert_eq!(a[a_idx].0, b[b_idx].0);
+        }
+
+        assert_eq!(Vec::<(usize, usize)>::new(), matches(&[], &b, same_char));
+        assert_eq!(vec![(0, 0), (1, 1)], matches(&a[..2], &a[..2], same_char));
+    }
+
+    #[test]
+    fn source_map() {
+        let input = r#"use std::io;
+fn main() { let x = [1, 2]; println!("{x:?}"); }
+
+struct Foo { a: u8 }
+"#;
+        let output = r#"use std::io;
+fn main() {
+    let x = [1, 2];
+    println!("{x:?}");
+}
+
+struct Foo {
+    a: u8,
+}
+"#;
+
+        let expected = vec![Some(0..1), Some(1..5), None, Some(6..9)];
+        assert_eq!(expected, SourceMap::new(input, output).lines());
+    }
+}
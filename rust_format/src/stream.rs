@@ -0,0 +1,154 @@
+use std::io::{BufRead, Write};
+
+use crate::error::Context;
+use crate::marker::Markers;
+use crate::scanner::Scanner;
+use crate::{post_process, post_process_code, Error, Operation, PostProcess};
+
+// *** Chunked Post Processor ***
+
+// Post processes code a chunk at a time as it is written. Chunks are only split after a run of
+// blank lines between top level items, so each chunk is post processed exactly as it would have
+// been as part of the whole file
+pub(crate) struct ChunkedPostProcessor<W> {
+    writer: W,
+    post_proc: PostProcess,
+    markers: Markers,
+    chunk_size: usize,
+    chunk: String,
+    scanner: Scanner,
+    first: bool,
+    // The last line was blank and between top level items
+    after_blank: bool,
+}
+
+impl<W: Write> ChunkedPostProcessor<W> {
+    #[inline]
+    pub(crate) fn new(
+        writer: W,
+        post_proc: PostProcess,
+        markers: &Markers,
+        chunk_size: usize,
+    ) -> Self {
+        Self {
+            writer,
+            post_proc,
+            markers: markers.clone(),
+            chunk_size,
+            chunk: String::with_capacity(chunk_size),
+            scanner: Scanner::new(),
+            first: true,
+            after_blank: false,
+        }
+    }
+
+    fn flush_chunk(&mut self) -> Result<(), Error> {
+        let chunk = std::mem::take(&mut self.chunk);
+        // Only the start of the file can have a prologue (shebang and/or frontmatter)
+        let result = if self.first {
+            post_process(self.post_proc, &self.markers, chunk)?
+        } else {
+            post_process_code(self.post_proc, &self.markers, chunk)
+                .context(Operation::PostProcess)?
+        };
+        self.first = false;
+
+        self.writer
+            .write_all(result.as_bytes())
+            .context(Operation::WriteFile)
+    }
+
+    pub(crate) fn write_line(&mut self, line: &str) -> Result<(), Error> {
+        let blank = line.trim().is_empty();
+
+        // Splitting before the first line after the run (instead of after the first blank line)
+        // keeps runs of blank lines whole, so they can be collapsed
+        if self.chunk.len() >= self.chunk_size && self.after_blank && !blank {
+            self.flush_chunk()?;
+        }
+
+        self.chunk.push_str(line);
+        self.scanner.scan_line(line);
+        self.after_blank = blank && self.scanner.at_top_level();
+        Ok(())
+    }
+
+    // Post process each line read until the end of the reader
+    pub(crate) fn write_lines(&mut self, mut reader: impl BufRead) -> Result<(), Error> {
+        let mut line = String::new();
+        while reader.read_line(&mut line).context(Operation::Wait)? > 0 {
+            self.write_line(&line)?;
+            line.clear();
+        }
+        Ok(())
+    }
+
+    pub(crate) fn finish(mut self) -> Result<W, Error> {
+        if !self.chunk.is_empty() || self.first {
+            self.flush_chunk()?;
+        }
+        self.writer.flush().context(Operation::WriteFile)?;
+        Ok(self.writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    #[cfg(feature = "post_process")]
+    use crate::marker::Markers;
+    use crate::stream::ChunkedPostProcessor;
+    use crate::PostProcess;
+
+    #[cfg(feature = "post_process")]
+    #[test]
+    fn chunked_post_process() {
+        let source = r#"#[doc = " Docs"]
+fn a() {
+    _blank_!();
+
+    _comment_!("Comment");
+}
+
+const S: &str = "
+";
+
+#[doc = r" Raw"]
+fn b() {}
+
+_blank_!();
+fn c() {
+    _blank_!();
+}
+"#;
+        let post_proc = PostProcess::ReplaceMarkersAndDocBlocks;
+        let expected = crate::post_process(post_proc, &Markers::NONE, source.to_string()).unwrap();
+
+        // Every chunk size must give the same results as processing the whole file at once
+        for chunk_size in [0, 1, 16, 64, source.len()] {
+            let mut processor =
+                ChunkedPostProcessor::new(Vec::new(), post_proc, &Markers::NONE, chunk_size);
+            processor.write_lines(source.as_bytes()).unwrap();
+            let actual = String::from_utf8(processor.finish().unwrap()).unwrap();
+            assert_eq!(expected, actual);
+        }
+    }
+
+    #[cfg(feature = "post_process")]
+    #[test]
+    fn chunked_max_blank_lines() {
+        let source = "fn a() {}\n\n\n\nfn b() {}\n_blank_!(3);\n\nfn c() {}\n";
+        let post_proc = PostProcess::ReplaceMarkers.max_consecutive_blank_lines(2);
+        let expected = "fn a() {}\n\n\nfn b() {}\n\n\nfn c() {}\n";
+
+        // Runs of blank lines are never split between chunks, so they are always fully collapsed
+        for chunk_size in [0, 1, 12, source.len()] {
+            let mut processor =
+                ChunkedPostProcessor::new(Vec::new(), post_proc, &Markers::NONE, chunk_size);
+            processor.write_lines(source.as_bytes()).unwrap();
+            let actual = String::from_utf8(processor.finish().unwrap()).unwrap();
+            assert_eq!(expected, actual);
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use std::fmt;
+
+// *** Channel ***
+
+/// The release channel a `rustfmt` binary was built for
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Channel {
+    /// A stable release
+    Stable,
+    /// A beta release
+    Beta,
+    /// A nightly release (required for unstable options)
+    Nightly,
+    /// A local or otherwise unknown build
+    Dev,
+}
+
+impl Channel {
+    fn parse(s: &str) -> Self {
+        match s {
+            "stable" => Channel::Stable,
+            s if s.starts_with("beta") => Channel::Beta,
+            "nightly" => Channel::Nightly,
+            _ => Channel::Dev,
+        }
+    }
+
+    /// Returns the channel as a string (as shown by `rustfmt --version`)
+    #[inline]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Channel::Stable => "stable",
+            Channel::Beta => "beta",
+            Channel::Nightly => "nightly",
+            Channel::Dev => "dev",
+        }
+    }
+}
+
+impl fmt::Display for Channel {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+// *** RustFmt Version ***
+
+/// The version of `rustfmt` as reported by `rustfmt --version`
+///
+/// ```
+/// use rust_format::{Channel, RustFmtVersion};
+///
+/// let version = RustFmtVersion::parse("rustfmt 1.7.1-nightly (3f5fd8dd41 2024-08-06)").unwrap();
+/// assert_eq!((1, 7, 1), (version.major, version.minor, version.patch));
+/// assert_eq!(Channel::Nightly, version.channel);
+/// assert_eq!(Some("2024-08-06"), version.commit_date.as_deref());
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RustFmtVersion {
+    /// The major version
+    pub major: u32,
+    /// The minor version
+    pub minor: u32,
+    /// The patch version
+    pub patch: u32,
+    /// The release channel
+    pub channel: Channel,
+    /// The (abbreviated) hash of the commit it was built from, if reported
+    pub commit_hash: Option<String>,
+    /// The date of the commit it was built from, if reported
+    pub commit_date: Option<String>,
+}
+
+impl RustFmtVersion {
+    /// Parse the output of `rustfmt --version` (such as `rustfmt 1.7.1-stable (3f5fd8dd41
+    /// 2024-08-06)`). Returns `None` if it isn't in the expected format
+    pub fn parse(s: &str) -> Option<Self> {
+        let rest = s.trim().strip_prefix("rustfmt ")?;
+
+        let (version, commit) = match rest.split_once(' ') {
+            Some((version, commit)) => (version, Some(commit)),
+            None => (rest, None),
+        };
+
+        let (number, channel) = match version.split_once('-') {
+            Some((number, channel)) => (number, Channel::parse(channel)),
+            None => (version, Channel::Dev),
+        };
+
+        let mut parts = number.split('.').map(str::parse::<u32>);
+        let (major, minor, patch) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(Ok(major)), Some(Ok(minor)), Some(Ok(patch)), None) => (major, minor, patch),
+            _ => return None,
+        };
+
+        // (<hash> <date>)
+        let mut commit = commit
+            .and_then(|commit| commit.strip_prefix('('))
+            .and_then(|commit| commit.strip_suffix(')'))
+            .unwrap_or_default()
+            .split_whitespace()
+            .map(ToString::to_string);
+
+        Some(Self {
+            major,
+            minor,
+            patch,
+            channel,
+            commit_hash: commit.next(),
+            commit_date: commit.next(),
+        })
+    }
+
+    /// Returns true if this `rustfmt` accepts unstable options (`--unstable-features`)
+    #[inline]
+    pub fn is_nightly(&self) -> bool {
+        matches!(self.channel, Channel::Nightly | Channel::Dev)
+    }
+}
+
+impl fmt::Display for RustFmtVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}.{}.{}-{}",
+            self.major, self.minor, self.patch, self.channel
+        )?;
+
+        match (&self.commit_hash, &self.commit_date) {
+            (Some(hash), Some(date)) => write!(f, " ({hash} {date})"),
+            (Some(hash), None) => write!(f, " ({hash})"),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::{Channel, RustFmtVersion};
+
+    #[test]
+    fn parse_version() {
+        let expected = RustFmtVersion {
+            major: 1,
+            minor: 9,
+            patch: 0,
+            channel: Channel::Stable,
+            commit_hash: Some("59807616e1".to_string()),
+            commit_date: Some("2026-04-14".to_string()),
+        };
+        let version = "rustfmt 1.9.0-stable (59807616e1 2026-04-14)\n";
+        assert_eq!(Some(expected), RustFmtVersion::parse(version));
+        assert_eq!(
+            version.trim_end(),
+            format!("rustfmt {}", RustFmtVersion::parse(version).unwrap())
+        );
+
+        let version = RustFmtVersion::parse("rustfmt 1.4.38-beta.2").unwrap();
+        assert_eq!(Channel::Beta, version.channel);
+        assert_eq!(None, version.commit_hash);
+        assert!(!version.is_nightly());
+
+        let version = RustFmtVersion::parse("rustfmt 1.5.0").unwrap();
+        assert_eq!(Channel::Dev, version.channel);
+
+        assert_eq!(None, RustFmtVersion::parse("rustc 1.80.0"));
+        assert_eq!(None, RustFmtVersion::parse("rustfmt 1.x.0-stable"));
+    }
+}
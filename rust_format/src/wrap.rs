@@ -0,0 +1,327 @@
+#![cfg(feature = "post_process")]
+
+use std::borrow::Cow;
+
+const OUTER_DOC: &str = "///";
+const INNER_DOC: &str = "//!";
+
+// A single `///` or `//!` doc comment line
+#[derive(Clone, Copy)]
+pub(crate) struct DocLine<'a> {
+    // The full line including the line ending
+    pub(crate) line: &'a str,
+    pub(crate) indent: &'a str,
+    pub(crate) prefix: &'static str,
+    // The comment text (minus the single space after the prefix, if any)
+    pub(crate) text: &'a str,
+    pub(crate) ending: &'a str,
+}
+
+impl<'a> DocLine<'a> {
+    pub(crate) fn parse(line: &'a str) -> Option<Self> {
+        let content = line.trim_end_matches(['\r', '\n']);
+        let ending = &line[content.len()..];
+        let code = content.trim_start();
+        let indent = &content[..content.len() - code.len()];
+
+        let (prefix, rest) = match code {
+            // Four or more slashes is a regular comment
+            code if code.starts_with("////") => return None,
+            code if code.starts_with(OUTER_DOC) => (OUTER_DOC, &code[OUTER_DOC.len()..]),
+            code if code.starts_with(INNER_DOC) => (INNER_DOC, &code[INNER_DOC.len()..]),
+            _ => return None,
+        };
+
+        Some(Self {
+            line,
+            indent,
+            prefix,
+            text: rest.strip_prefix(' ').unwrap_or(rest),
+            ending,
+        })
+    }
+
+    #[inline]
+    fn width(&self) -> usize {
+        self.line.trim_end_matches(['\r', '\n']).chars().count()
+    }
+}
+
+// Returns the length of the list item marker (including any indent and the following space) if
+// the text starts a list item
+fn list_marker(text: &str) -> Option<usize> {
+    let rest = text.trim_start_matches(' ');
+    let indent = text.len() - rest.len();
+
+    if rest.starts_with("- ") || rest.starts_with("* ") || rest.starts_with("+ ") {
+        return Some(indent + 2);
+    }
+
+    let digits = rest.chars().take_while(char::is_ascii_digit).count();
+    match &rest[digits..] {
+        rest if (1..=9).contains(&digits) && (rest.starts_with(". ") || rest.starts_with(") ")) => {
+            Some(indent + digits + 2)
+        }
+        _ => None,
+    }
+}
+
+#[inline]
+pub(crate) fn is_fence(text: &str) -> bool {
+    let text = text.trim_start();
+    text.starts_with("```") || text.starts_with("~~~")
+}
+
+// Lines that are never joined with others (headings, tables, quotes, HTML, link references, and
+// thematic breaks/setext underlines)
+fn is_verbatim(text: &str) -> bool {
+    let text = text.trim();
+
+    text.is_empty()
+        || text.starts_with(['#', '|', '>', '<'])
+        || (text.starts_with('[') && text.contains("]:"))
+        || text.chars().all(|ch| matches!(ch, '-' | '=' | '*' | '_'))
+}
+
+// A paragraph or list item made up of one or more lines
+struct Paragraph<'a> {
+    lines: Vec<DocLine<'a>>,
+    // The text before the first word on the first line (indent and list marker)
+    lead: &'a str,
+    is_list: bool,
+}
+
+impl<'a> Paragraph<'a> {
+    // Emit the paragraph and return true if it was re-wrapped
+    fn write(&self, width: usize, result: &mut String) -> bool {
+        // Only paragraphs that don't fit are re-wrapped (so intentional line breaks are preserved)
+        if self.lines.iter().all(|line| line.width() <= width) {
+            for line in &self.lines {
+                result.push_str(line.line);
+            }
+            return false;
+        }
+
+        // Panic safety: a paragraph always has at least one line
+        let first = self.lines[0];
+        let last = self.lines[self.lines.len() - 1];
+        let start = format!("{}{} ", first.indent, first.prefix);
+        let cont_lead = " ".repeat(self.lead.chars().count());
+        let hard_break = last.text.ends_with("  ");
+
+        let mut line = format!("{start}{}", self.lead);
+        let mut line_width = line.chars().count();
+        let mut empty = true;
+
+        let words = self.lines.iter().enumerate().flat_map(|(idx, line)| {
+            let text = if idx == 0 {
+                &line.text[self.lead.len()..]
+            } else {
+                line.text
+            };
+            text.split_whitespace()
+        });
+
+        for word in words {
+            let word_width = word.chars().count();
+
+            if !empty && line_width + 1 + word_width > width {
+                result.push_str(&line);
+                result.push_str(first.ending);
+                line = format!("{start}{cont_lead}");
+                line_width = line.chars().count();
+                empty = true;
+            }
+
+            if !empty {
+                line.push(' ');
+                line_width += 1;
+            }
+            line.push_str(word);
+            line_width += word_width;
+            empty = false;
+        }
+
+        result.push_str(&line);
+        if hard_break {
+            result.push_str("  ");
+        }
+        result.push_str(last.ending);
+        true
+    }
+}
+
+// Emit a block of consecutive doc comment lines (with the same indent and prefix) and return true
+// if any part of it was re-wrapped
+fn wrap_block(block: &[DocLine], width: usize, result: &mut String) -> bool {
+    let mut changed = false;
+    let mut para: Option<Paragraph> = None;
+    let mut in_fence = false;
+
+    let mut flush = |para: &mut Option<Paragraph>, result: &mut String| {
+        if let Some(para) = para.take() {
+            changed |= para.write(width, result);
+        }
+    };
+
+    for &line in block {
+        let text = line.text;
+
+        if in_fence || is_fence(text) || is_verbatim(text) {
+            flush(&mut para, result);
+            if is_fence(text) {
+                in_fence = !in_fence;
+            }
+            result.push_str(line.line);
+            continue;
+        }
+
+        let indent = text.len() - text.trim_start_matches(' ').len();
+
+        if let Some(marker_len) = list_marker(text) {
+            flush(&mut para, result);
+            para = Some(Paragraph {
+                lines: vec![line],
+                lead: &text[..marker_len],
+                is_list: true,
+            });
+        } else if let Some(p) = &mut para {
+            // List item continuations must be indented under the item
+            if p.is_list && indent < p.lead.len() {
+                flush(&mut para, result);
+                para = Some(Paragraph {
+                    lines: vec![line],
+                    lead: &text[..indent],
+                    is_list: false,
+                });
+            } else {
+                p.lines.push(line);
+            }
+        } else if indent >= 4 {
+            // Indented code block
+            result.push_str(line.line);
+            continue;
+        } else {
+            para = Some(Paragraph {
+                lines: vec![line],
+                lead: &text[..indent],
+                is_list: false,
+            });
+        }
+
+        // Hard line break
+        if text.ends_with("  ") || text.ends_with('\\') {
+            flush(&mut para, result);
+        }
+    }
+
+    flush(&mut para, result);
+    changed
+}
+
+pub(crate) fn wrap_doc_comments(s: &str, width: usize) -> Cow<'_, str> {
+    let lines: Vec<_> = s.split_inclusive('\n').collect();
+    let mut result = String::with_capacity(s.len());
+    let mut changed = false;
+    let mut idx = 0;
+
+    while idx < lines.len() {
+        match DocLine::parse(lines[idx]) {
+            Some(first) => {
+                let mut block = vec![first];
+                idx += 1;
+
+                while let Some(line) = lines.get(idx).and_then(|line| DocLine::parse(line)) {
+                    if line.indent != first.indent || line.prefix != first.prefix {
+                        break;
+                    }
+                    block.push(line);
+                    idx += 1;
+                }
+
+                changed |= wrap_block(&block, width, &mut result);
+            }
+            None => {
+                result.push_str(lines[idx]);
+                idx += 1;
+            }
+        }
+    }
+
+    if changed {
+        Cow::Owned(result)
+    } else {
+        Cow::Borrowed(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::wrap::wrap_doc_comments;
+
+    #[test]
+    fn wrap_paragraphs() {
+        let source = r#"//! A crate level doc comment that is far too long to fit
+/// Short line
+/// kept as is
+fn a() {}
+
+    /// This paragraph is much too long so it is re-wrapped, including the short
+    /// line after it.
+    ///
+    /// ```
+    /// let code_in_a_fence = "is never wrapped regardless of how long it is";
+    /// ```
+    ///
+    /// # A heading that is far too long to fit but is never wrapped
+    /// - A list item that is much too long to fit on one line
+    ///   continued here
+    /// 10. https://a.very.long.link/that/can/not/be/split/up/at/all
+    fn b() {}
+"#;
+
+        let actual = wrap_doc_comments(source, 40);
+        let expected = r#"//! A crate level doc comment that is
+//! far too long to fit
+/// Short line
+/// kept as is
+fn a() {}
+
+    /// This paragraph is much too long
+    /// so it is re-wrapped, including
+    /// the short line after it.
+    ///
+    /// ```
+    /// let code_in_a_fence = "is never wrapped regardless of how long it is";
+    /// ```
+    ///
+    /// # A heading that is far too long to fit but is never wrapped
+    /// - A list item that is much too
+    ///   long to fit on one line
+    ///   continued here
+    /// 10. https://a.very.long.link/that/can/not/be/split/up/at/all
+    fn b() {}
+"#;
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn wrap_crlf() {
+        let source = "/// one two three four\r\nfn main() {}\r\n";
+
+        let actual = wrap_doc_comments(source, 14);
+        let expected = "/// one two\r\n/// three four\r\nfn main() {}\r\n";
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn nothing_to_wrap() {
+        let source = "/// short\n//// not a doc comment that is long\nfn main() {}\n";
+        assert!(matches!(wrap_doc_comments(source, 20), Cow::Borrowed(_)));
+    }
+}